@@ -0,0 +1,14 @@
+mod openapi;
+mod postman;
+mod schema;
+mod template;
+
+#[cfg(test)]
+mod openapi_test;
+#[cfg(test)]
+mod postman_test;
+
+pub use openapi::*;
+pub use postman::*;
+pub use schema::*;
+pub use template::*;
@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+
+use hyper::{StatusCode, Uri};
+use serde_json::{json, Map, Value};
+
+use super::{infer_templates, PathTemplate, SchemaBuilder};
+use crate::har::form_params;
+use crate::mitm::{BodyCapture, Flow, FlowKind};
+
+pub const OPENAPI_VERSION: &str = "3.0.3";
+
+/// A flow's URL split into what the documents need.
+pub(super) struct Endpoint {
+    /// `scheme://host[:port]`
+    pub origin: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+}
+
+impl Endpoint {
+    /// Tunnels and requests without an absolute URL have none.
+    pub fn of(flow: &Flow) -> Option<Self> {
+        if flow.kind == FlowKind::Tunnel {
+            return None;
+        }
+        let uri: Uri = flow.request.uri.parse().ok()?;
+        let (scheme, authority) = (uri.scheme_str()?, uri.authority()?);
+        Some(Self {
+            origin: format!("{}://{}", scheme, authority),
+            path: uri.path().to_string(),
+            query: form_params(uri.query().unwrap_or_default())
+                .into_iter()
+                .map(|param| (param.name, param.value))
+                .collect(),
+        })
+    }
+}
+
+/// The media type of a `Content-Type` header, lowercased and without
+/// parameters.
+pub(super) fn media_type(headers: &[(String, String)]) -> Option<String> {
+    headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        .and_then(|(_, value)| value.split(';').next())
+        .map(|media_type| media_type.trim().to_ascii_lowercase())
+        .filter(|media_type| !media_type.is_empty())
+}
+
+pub(super) fn is_json(media_type: &str) -> bool {
+    media_type == "application/json" || media_type.ends_with("+json")
+}
+
+#[derive(Default)]
+struct Parameter {
+    schema: SchemaBuilder,
+    example: Option<String>,
+    seen: usize,
+}
+
+impl Parameter {
+    fn add(&mut self, value: &str) {
+        self.schema.add(&scalar(value));
+        self.example.get_or_insert_with(|| value.to_string());
+        self.seen += 1;
+    }
+}
+
+#[derive(Default)]
+struct Operation {
+    samples: usize,
+    path_params: Vec<(String, Parameter)>,
+    query: Vec<(String, Parameter)>,
+    request: BTreeMap<String, Option<SchemaBuilder>>,
+    responses: BTreeMap<u16, BTreeMap<String, Option<SchemaBuilder>>>,
+}
+
+/// An OpenAPI 3 document inferred from the HTTP flows among `flows`.
+/// Paths are templated across all of them, schemas are merged from every
+/// JSON body seen for an operation.
+pub fn export_openapi(flows: &[Flow], title: &str) -> Value {
+    let observed: Vec<(&Flow, Endpoint)> = flows
+        .iter()
+        .filter_map(|flow| Some((flow, Endpoint::of(flow)?)))
+        .collect();
+    let paths: Vec<&str> = observed
+        .iter()
+        .map(|(_, endpoint)| endpoint.path.as_str())
+        .collect();
+    let templates = infer_templates(&paths);
+
+    let mut servers: Vec<&str> = vec![];
+    let mut operations: BTreeMap<(String, String), (PathTemplate, Operation)> = BTreeMap::new();
+    for ((flow, endpoint), template) in observed.iter().zip(templates) {
+        if !servers.contains(&endpoint.origin.as_str()) {
+            servers.push(&endpoint.origin);
+        }
+
+        let key = (
+            template.template.clone(),
+            flow.request.method.to_ascii_lowercase(),
+        );
+        let values = template.values(&endpoint.path);
+        let (template, operation) = operations
+            .entry(key)
+            .or_insert_with(|| (template, Operation::default()));
+        operation.samples += 1;
+        for (name, value) in template.params.iter().zip(values) {
+            parameter(&mut operation.path_params, name).add(value);
+        }
+        for (name, value) in &endpoint.query {
+            parameter(&mut operation.query, name).add(value);
+        }
+
+        let request = &flow.request;
+        add_body(
+            &mut operation.request,
+            &request.headers,
+            flow.request_body.as_ref(),
+        );
+        if let Some(response) = &flow.response {
+            let content = operation.responses.entry(response.status).or_default();
+            add_body(content, &response.headers, flow.response_body.as_ref());
+        }
+    }
+
+    let mut document_paths = Map::new();
+    let mut operation_ids = vec![];
+    for ((path, method), (template, operation)) in operations {
+        let base = operation_id(&method, &template);
+        let mut operation_id = base.clone();
+        let mut n = 2;
+        while operation_ids.contains(&operation_id) {
+            operation_id = format!("{}{}", base, n);
+            n += 1;
+        }
+        operation_ids.push(operation_id.clone());
+
+        let item = document_paths
+            .entry(path)
+            .or_insert_with(|| Value::Object(Map::new()));
+        item[method.as_str()] = operation_object(operation_id, operation);
+    }
+
+    json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": title,
+            "version": "1.0.0",
+            "description": format!(
+                "Inferred by devya {} from {} captured requests.",
+                env!("CARGO_PKG_VERSION"),
+                observed.len()
+            ),
+        },
+        "servers": servers
+            .iter()
+            .map(|url| json!({ "url": url }))
+            .collect::<Vec<_>>(),
+        "paths": document_paths,
+    })
+}
+
+fn parameter<'a>(params: &'a mut Vec<(String, Parameter)>, name: &str) -> &'a mut Parameter {
+    let index = match params.iter().position(|(known, _)| known == name) {
+        Some(index) => index,
+        None => {
+            params.push((name.to_string(), Parameter::default()));
+            params.len() - 1
+        }
+    };
+    &mut params[index].1
+}
+
+/// Notes the media type of a body and, for JSON, its shape. Empty bodies
+/// aren't content.
+fn add_body(
+    content: &mut BTreeMap<String, Option<SchemaBuilder>>,
+    headers: &[(String, String)],
+    body: Option<&BodyCapture>,
+) {
+    let Some(body) = body.filter(|body| body.size > 0) else {
+        return;
+    };
+    let media_type = media_type(headers).unwrap_or_else(|| "application/octet-stream".to_string());
+
+    let sample = match body.decoded(headers) {
+        Ok(data) if !body.truncated => {
+            if is_json(&media_type) {
+                serde_json::from_slice(&data).ok()
+            } else if media_type == "application/x-www-form-urlencoded" {
+                let fields = form_params(&String::from_utf8_lossy(&data))
+                    .into_iter()
+                    .map(|param| (param.name, Value::String(param.value)))
+                    .collect::<Map<_, _>>();
+                Some(Value::Object(fields))
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+
+    let schema = content.entry(media_type).or_insert(None);
+    if let Some(sample) = sample {
+        schema.get_or_insert_with(SchemaBuilder::new).add(&sample);
+    }
+}
+
+fn operation_object(operation_id: String, operation: Operation) -> Value {
+    let mut parameters = vec![];
+    for (name, param) in &operation.path_params {
+        parameters.push(parameter_object(name, "path", true, param));
+    }
+    for (name, param) in &operation.query {
+        let required = param.seen >= operation.samples;
+        parameters.push(parameter_object(name, "query", required, param));
+    }
+
+    let mut object = Map::new();
+    object.insert("operationId".to_string(), json!(operation_id));
+    if !parameters.is_empty() {
+        object.insert("parameters".to_string(), json!(parameters));
+    }
+    if !operation.request.is_empty() {
+        object.insert(
+            "requestBody".to_string(),
+            json!({ "content": content_object(&operation.request) }),
+        );
+    }
+
+    let mut responses = Map::new();
+    for (status, content) in &operation.responses {
+        let description = StatusCode::from_u16(*status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or("Response");
+        let mut response = json!({ "description": description });
+        if !content.is_empty() {
+            response["content"] = content_object(content);
+        }
+        responses.insert(status.to_string(), response);
+    }
+    // required by the spec, requests that failed have no response
+    if responses.is_empty() {
+        responses.insert(
+            "default".to_string(),
+            json!({ "description": "No response was observed" }),
+        );
+    }
+    object.insert("responses".to_string(), responses.into());
+    object.into()
+}
+
+fn parameter_object(name: &str, location: &str, required: bool, param: &Parameter) -> Value {
+    let mut object = json!({
+        "name": name,
+        "in": location,
+        "required": required,
+        "schema": param.schema.build(),
+    });
+    if let Some(example) = &param.example {
+        object["example"] = scalar(example);
+    }
+    object
+}
+
+/// Media types without a known shape are described by their kind only.
+fn content_object(content: &BTreeMap<String, Option<SchemaBuilder>>) -> Value {
+    content
+        .iter()
+        .map(|(media_type, schema)| {
+            let schema = match schema {
+                Some(schema) => schema.build(),
+                None if media_type.starts_with("text/") => json!({ "type": "string" }),
+                None => json!({ "type": "string", "format": "binary" }),
+            };
+            (media_type.clone(), json!({ "schema": schema }))
+        })
+        .collect::<Map<_, _>>()
+        .into()
+}
+
+/// `get /users/{userId}/posts` becomes `getUsersByUserIdPosts`.
+fn operation_id(method: &str, template: &PathTemplate) -> String {
+    let mut id = method.to_string();
+    for segment in template.template.split('/') {
+        let (prefix, word) = match segment.strip_prefix('{') {
+            Some(param) => ("By", param.trim_end_matches('}')),
+            None => ("", segment),
+        };
+        id.push_str(prefix);
+        let mut upper = true;
+        for c in word.chars() {
+            if !c.is_ascii_alphanumeric() {
+                upper = true;
+            } else if upper {
+                id.push(c.to_ascii_uppercase());
+                upper = false;
+            } else {
+                id.push(c);
+            }
+        }
+    }
+    id
+}
+
+/// Query and path values are text, numbers and booleans are told apart so
+/// the schema gets the right type. Zero-padded numbers stay text.
+fn scalar(value: &str) -> Value {
+    let digits = value.trim_start_matches('-');
+    if digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.") {
+        return value.into();
+    }
+    if let Ok(integer) = value.parse::<i64>() {
+        return integer.into();
+    }
+    match value {
+        "true" => true.into(),
+        "false" => false.into(),
+        _ => match value.parse::<f64>() {
+            Ok(number) if number.is_finite() => number.into(),
+            _ => value.into(),
+        },
+    }
+}
@@ -0,0 +1,195 @@
+use serde_json::{json, Value};
+
+use super::{export_openapi, infer_templates, SchemaBuilder};
+use crate::mitm::Flow;
+
+pub(super) fn flow(
+    method: &str,
+    uri: &str,
+    request: Option<(&str, &str)>,
+    response: Option<(u16, &str, &str)>,
+) -> Flow {
+    let mut flow = Flow::test(method, uri);
+    if let Some((media_type, body)) = request {
+        flow = flow
+            .with_request_headers(&[("content-type", media_type)])
+            .with_request_body(body);
+    }
+    if let Some((status, media_type, body)) = response {
+        flow = flow
+            .with_response(status, &[("content-type", media_type)])
+            .with_response_body(body);
+    }
+    flow
+}
+
+#[test]
+fn test_infer_templates() {
+    let paths = [
+        "/users/42",
+        "/users/42/posts/9f1c2a7e-3b4d-4c5e-8f60-718293a4b5c6",
+        "/users/me",
+        "/files/5d41402abc4b2a76b9719d911017c592",
+        "/",
+        "/tags/rust",
+        "/tags/go",
+        "/tags/zig",
+        "/tags/c",
+        "/v1/order-items/7/notes",
+    ];
+    let templates: Vec<String> = infer_templates(&paths)
+        .into_iter()
+        .map(|template| template.template)
+        .collect();
+    assert_eq!(
+        templates,
+        [
+            "/users/{id}",
+            "/users/{userId}/posts/{id}",
+            "/users/me",
+            "/files/{id}",
+            "/",
+            "/tags/{id}",
+            "/tags/{id}",
+            "/tags/{id}",
+            "/tags/{id}",
+            "/v1/order-items/{id}/notes",
+        ]
+    );
+
+    let template = &infer_templates(&["/a/1/b/2"])[0];
+    assert_eq!(template.params, ["aId", "id"]);
+    assert_eq!(template.values("/a/1/b/2"), ["1", "2"]);
+}
+
+#[test]
+fn test_infer_schema() {
+    let mut schema = SchemaBuilder::new();
+    schema.add(&json!({
+        "id": 1,
+        "name": "a",
+        "tags": ["x"],
+        "created": "2024-05-01T12:00:00Z",
+    }));
+    schema.add(&json!({
+        "id": 2,
+        "name": null,
+        "score": 1.5,
+        "tags": [],
+        "created": "2024-05-02T08:30:00.123+02:00",
+    }));
+
+    assert_eq!(
+        schema.build(),
+        json!({
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "name": { "type": "string", "nullable": true },
+                "tags": { "type": "array", "items": { "type": "string" } },
+                "created": { "type": "string", "format": "date-time" },
+                "score": { "type": "number" },
+            },
+            "required": ["created", "id", "name", "tags"],
+        })
+    );
+
+    let mut mixed = SchemaBuilder::new();
+    mixed.add(&json!(1));
+    mixed.add(&json!("one"));
+    assert_eq!(
+        mixed.build(),
+        json!({ "anyOf": [{ "type": "integer" }, { "type": "string" }] })
+    );
+}
+
+#[test]
+fn test_export_openapi() {
+    let flows = [
+        flow(
+            "GET",
+            "https://api.test/users/1?expand=posts&limit=10",
+            None,
+            Some((200, "application/json", r#"{"id":1,"name":"a"}"#)),
+        ),
+        flow(
+            "GET",
+            "https://api.test/users/2?limit=5",
+            None,
+            Some((200, "application/json; charset=utf-8", r#"{"id":2}"#)),
+        ),
+        flow(
+            "GET",
+            "https://api.test/users/3",
+            None,
+            Some((404, "text/plain", "missing")),
+        ),
+        flow(
+            "POST",
+            "https://api.test/users",
+            Some(("application/json", r#"{"name":"b"}"#)),
+            Some((201, "application/json", r#"{"id":4,"name":"b"}"#)),
+        ),
+        flow("DELETE", "http://other.test:8080/users/4", None, None),
+    ];
+
+    let document = export_openapi(&flows, "Users");
+    assert_eq!(document["openapi"], "3.0.3");
+    assert_eq!(document["info"]["title"], "Users");
+    assert_eq!(
+        document["servers"],
+        json!([{ "url": "https://api.test" }, { "url": "http://other.test:8080" }])
+    );
+
+    let get = &document["paths"]["/users/{id}"]["get"];
+    assert_eq!(get["operationId"], "getUsersById");
+    assert_eq!(
+        get["parameters"],
+        json!([
+            {
+                "name": "id",
+                "in": "path",
+                "required": true,
+                "schema": { "type": "integer" },
+                "example": 1,
+            },
+            {
+                "name": "expand",
+                "in": "query",
+                "required": false,
+                "schema": { "type": "string" },
+                "example": "posts",
+            },
+            {
+                "name": "limit",
+                "in": "query",
+                "required": false,
+                "schema": { "type": "integer" },
+                "example": 10,
+            },
+        ])
+    );
+    assert_eq!(
+        get["responses"]["200"]["content"]["application/json"]["schema"]["required"],
+        json!(["id"])
+    );
+    assert_eq!(get["responses"]["404"]["description"], "Not Found");
+    assert_eq!(
+        get["responses"]["404"]["content"]["text/plain"]["schema"],
+        json!({ "type": "string" })
+    );
+
+    let post = &document["paths"]["/users"]["post"];
+    assert_eq!(
+        post["requestBody"]["content"]["application/json"]["schema"],
+        json!({
+            "type": "object",
+            "properties": { "name": { "type": "string" } },
+            "required": ["name"],
+        })
+    );
+    assert_eq!(post.get("parameters"), None::<&Value>);
+
+    let delete = &document["paths"]["/users/{id}"]["delete"];
+    assert!(delete["responses"]["default"].is_object());
+}
@@ -0,0 +1,180 @@
+use hyper::{StatusCode, Uri};
+use serde_json::{json, Value};
+
+use super::{is_json, media_type, Endpoint};
+use crate::har::form_params;
+use crate::mitm::{BodyCapture, Flow};
+
+pub const POSTMAN_SCHEMA: &str =
+    "https://schema.getpostman.com/json/collection/v2.1.0/collection.json";
+
+/// Postman works these out itself when sending.
+const SKIPPED_HEADERS: &[&str] = &["host", "content-length", "connection", "transfer-encoding"];
+
+/// A Postman 2.1 collection with one request per HTTP flow, in a folder per
+/// host. Recorded responses are attached as examples.
+pub fn export_postman(flows: &[Flow], name: &str) -> Value {
+    let mut folders: Vec<(String, Vec<Value>)> = vec![];
+    for flow in flows {
+        let Some(endpoint) = Endpoint::of(flow) else {
+            continue;
+        };
+        let host = endpoint
+            .origin
+            .split_once("://")
+            .map_or(endpoint.origin.as_str(), |(_, host)| host)
+            .to_string();
+        let item = postman_item(flow, &endpoint);
+        match folders.iter_mut().find(|(known, _)| *known == host) {
+            Some((_, items)) => items.push(item),
+            None => folders.push((host, vec![item])),
+        }
+    }
+
+    json!({
+        "info": {
+            "name": name,
+            "description": format!("Captured with devya {}.", env!("CARGO_PKG_VERSION")),
+            "schema": POSTMAN_SCHEMA,
+        },
+        "item": folders
+            .into_iter()
+            .map(|(host, items)| json!({ "name": host, "item": items }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn postman_item(flow: &Flow, endpoint: &Endpoint) -> Value {
+    let request = postman_request(flow, endpoint);
+    let responses: Vec<Value> = flow
+        .response
+        .iter()
+        .map(|response| {
+            let status = StatusCode::from_u16(response.status)
+                .ok()
+                .and_then(|status| status.canonical_reason())
+                .unwrap_or_default();
+            let mut example = json!({
+                "name": format!("{} {}", response.status, status).trim_end(),
+                "originalRequest": request,
+                "status": status,
+                "code": response.status,
+            });
+
+            // the body is decoded, so its encoding header no longer applies
+            let decoded = flow
+                .response_body
+                .as_ref()
+                .and_then(|body| text(body, &response.headers, true));
+            let headers: Vec<&(String, String)> = response
+                .headers
+                .iter()
+                .filter(|(name, _)| {
+                    decoded.is_none() || !name.eq_ignore_ascii_case("content-encoding")
+                })
+                .collect();
+            example["header"] = key_values(headers);
+            if let Some(body) = decoded {
+                example["body"] = body.into();
+                example["_postman_previewlanguage"] =
+                    language(media_type(&response.headers).as_deref()).into();
+            }
+            example
+        })
+        .collect();
+
+    json!({
+        "name": format!("{} {}", flow.request.method, endpoint.path),
+        "request": request,
+        "response": responses,
+    })
+}
+
+fn postman_request(flow: &Flow, endpoint: &Endpoint) -> Value {
+    let request = &flow.request;
+    let uri: Uri = request.uri.parse().unwrap_or_default();
+    let mut url = json!({
+        "raw": request.uri,
+        "protocol": uri.scheme_str().unwrap_or("http"),
+        "host": uri.host().unwrap_or_default().split('.').collect::<Vec<_>>(),
+        "path": endpoint.path.trim_start_matches('/').split('/').collect::<Vec<_>>(),
+    });
+    if let Some(port) = uri.port_u16() {
+        url["port"] = port.to_string().into();
+    }
+    if !endpoint.query.is_empty() {
+        url["query"] = key_values(&endpoint.query);
+    }
+
+    let headers: Vec<&(String, String)> = request
+        .headers
+        .iter()
+        .filter(|(name, _)| {
+            !SKIPPED_HEADERS
+                .iter()
+                .any(|skipped| name.eq_ignore_ascii_case(skipped))
+        })
+        .collect();
+    let mut object = json!({
+        "method": request.method,
+        "header": key_values(headers),
+        "url": url,
+    });
+
+    let media_type = media_type(&request.headers);
+    let body = flow
+        .request_body
+        .as_ref()
+        .filter(|body| body.size > 0)
+        .and_then(|body| text(body, &request.headers, false));
+    object["body"] = match (body, media_type.as_deref()) {
+        (Some(body), Some("application/x-www-form-urlencoded")) => json!({
+            "mode": "urlencoded",
+            "urlencoded": key_values(
+                &form_params(&body)
+                    .into_iter()
+                    .map(|param| (param.name, param.value))
+                    .collect::<Vec<_>>()
+            ),
+        }),
+        (Some(body), media_type) => json!({
+            "mode": "raw",
+            "raw": body,
+            "options": { "raw": { "language": language(media_type) } },
+        }),
+        (None, _) => return object,
+    };
+    object
+}
+
+/// Postman keeps bodies as text, binary and cut off ones are left out.
+/// Request bodies are kept as sent, response bodies are shown decoded.
+fn text(body: &BodyCapture, headers: &[(String, String)], decode: bool) -> Option<String> {
+    if body.truncated {
+        return None;
+    }
+    let data = if decode {
+        body.decoded(headers).ok()?.to_vec()
+    } else {
+        body.data.clone()
+    };
+    String::from_utf8(data).ok()
+}
+
+fn key_values<'a>(pairs: impl IntoIterator<Item = &'a (String, String)>) -> Value {
+    pairs
+        .into_iter()
+        .map(|(key, value)| json!({ "key": key, "value": value }))
+        .collect::<Vec<_>>()
+        .into()
+}
+
+fn language(media_type: Option<&str>) -> &'static str {
+    match media_type {
+        Some(media_type) if is_json(media_type) => "json",
+        Some(media_type) if media_type.contains("xml") => "xml",
+        Some("text/html") => "html",
+        Some("application/javascript" | "text/javascript") => "javascript",
+        _ => "text",
+    }
+}
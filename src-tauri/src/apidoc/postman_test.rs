@@ -0,0 +1,86 @@
+use serde_json::json;
+
+use super::openapi_test::flow;
+use super::{export_postman, POSTMAN_SCHEMA};
+use crate::mitm::{encode_body, ContentEncoding};
+
+#[test]
+fn test_export_postman() {
+    let mut gzipped = flow(
+        "GET",
+        "https://api.test/items?q=a%20b",
+        None,
+        Some((200, "application/json", "")),
+    );
+    let response = gzipped.response.as_mut().unwrap();
+    response
+        .headers
+        .push(("content-encoding".to_string(), "gzip".to_string()));
+    let body = gzipped.response_body.as_mut().unwrap();
+    body.data = encode_body(b"[1]", &[ContentEncoding::Gzip])
+        .unwrap()
+        .to_vec();
+    body.size = body.data.len() as u64;
+
+    let mut binary = flow(
+        "PUT",
+        "http://files.test:8080/upload",
+        Some(("application/octet-stream", "")),
+        None,
+    );
+    let body = binary.request_body.as_mut().unwrap();
+    body.data = vec![0xff, 0];
+    body.size = 2;
+
+    let flows = [
+        gzipped,
+        flow(
+            "POST",
+            "https://api.test/login",
+            Some(("application/x-www-form-urlencoded", "user=a&pass=b+c")),
+            None,
+        ),
+        binary,
+    ];
+    let collection = export_postman(&flows, "Capture");
+
+    assert_eq!(collection["info"]["name"], "Capture");
+    assert_eq!(collection["info"]["schema"], POSTMAN_SCHEMA);
+    let folders = collection["item"].as_array().unwrap();
+    assert_eq!(folders.len(), 2);
+    assert_eq!(folders[0]["name"], "api.test");
+    assert_eq!(folders[1]["name"], "files.test:8080");
+
+    let get = &folders[0]["item"][0];
+    assert_eq!(get["name"], "GET /items");
+    assert_eq!(
+        get["request"]["url"],
+        json!({
+            "raw": "https://api.test/items?q=a%20b",
+            "protocol": "https",
+            "host": ["api", "test"],
+            "path": ["items"],
+            "query": [{ "key": "q", "value": "a b" }],
+        })
+    );
+    let example = &get["response"][0];
+    assert_eq!(example["name"], "200 OK");
+    assert_eq!(example["code"], 200);
+    assert_eq!(example["body"], "[1]");
+    assert_eq!(example["_postman_previewlanguage"], "json");
+    assert_eq!(
+        example["header"],
+        json!([{ "key": "content-type", "value": "application/json" }])
+    );
+
+    let login = &folders[0]["item"][1]["request"];
+    assert_eq!(login["body"]["mode"], "urlencoded");
+    assert_eq!(
+        login["body"]["urlencoded"],
+        json!([{ "key": "user", "value": "a" }, { "key": "pass", "value": "b c" }])
+    );
+
+    let upload = &folders[1]["item"][0]["request"];
+    assert_eq!(upload["url"]["port"], "8080");
+    assert_eq!(upload.get("body"), None);
+}
@@ -0,0 +1,155 @@
+use serde_json::{json, Map, Value};
+
+use super::is_uuid;
+
+/// A JSON schema built up from sample values. Every kind of value seen is
+/// kept, so mixed samples end up as `anyOf`.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaBuilder {
+    nullable: bool,
+    boolean: bool,
+    integer: bool,
+    number: bool,
+    strings: usize,
+    /// Kept while every string sampled has it.
+    format: Option<&'static str>,
+    items: Option<Box<SchemaBuilder>>,
+    /// Properties in the order first seen, with the objects they were in.
+    properties: Option<Vec<(String, SchemaBuilder, usize)>>,
+    objects: usize,
+}
+
+impl SchemaBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, value: &Value) {
+        match value {
+            Value::Null => self.nullable = true,
+            Value::Bool(_) => self.boolean = true,
+            Value::Number(number) if number.is_i64() || number.is_u64() => self.integer = true,
+            Value::Number(_) => self.number = true,
+            Value::String(text) => {
+                let format = string_format(text);
+                if self.strings == 0 {
+                    self.format = format;
+                } else if self.format != format {
+                    self.format = None;
+                }
+                self.strings += 1;
+            }
+            Value::Array(items) => {
+                let schema = self.items.get_or_insert_with(Default::default);
+                for item in items {
+                    schema.add(item);
+                }
+            }
+            Value::Object(object) => {
+                let properties = self.properties.get_or_insert_with(Vec::new);
+                for (name, value) in object {
+                    match properties.iter_mut().find(|(known, ..)| known == name) {
+                        Some((_, schema, seen)) => {
+                            schema.add(value);
+                            *seen += 1;
+                        }
+                        None => {
+                            let mut schema = SchemaBuilder::new();
+                            schema.add(value);
+                            properties.push((name.clone(), schema, 1));
+                        }
+                    }
+                }
+                self.objects += 1;
+            }
+        }
+    }
+
+    /// An OpenAPI 3.0 schema object. Properties present in every sampled
+    /// object are required.
+    pub fn build(&self) -> Value {
+        let mut variants = vec![];
+        if self.boolean {
+            variants.push(json!({ "type": "boolean" }));
+        }
+        match (self.integer, self.number) {
+            (_, true) => variants.push(json!({ "type": "number" })),
+            (true, false) => variants.push(json!({ "type": "integer" })),
+            _ => {}
+        }
+        if self.strings > 0 {
+            variants.push(match self.format {
+                Some(format) => json!({ "type": "string", "format": format }),
+                None => json!({ "type": "string" }),
+            });
+        }
+        if let Some(items) = &self.items {
+            variants.push(json!({ "type": "array", "items": items.build() }));
+        }
+        if let Some(properties) = &self.properties {
+            let mut object = Map::new();
+            object.insert("type".to_string(), json!("object"));
+            object.insert(
+                "properties".to_string(),
+                properties
+                    .iter()
+                    .map(|(name, schema, _)| (name.clone(), schema.build()))
+                    .collect::<Map<_, _>>()
+                    .into(),
+            );
+            let required: Vec<&str> = properties
+                .iter()
+                .filter(|(_, _, seen)| *seen == self.objects)
+                .map(|(name, ..)| name.as_str())
+                .collect();
+            if !required.is_empty() {
+                object.insert("required".to_string(), json!(required));
+            }
+            variants.push(object.into());
+        }
+
+        let mut schema = match variants.len() {
+            // nothing but nulls, or an array that was always empty
+            0 => json!({}),
+            1 => variants.remove(0),
+            _ => json!({ "anyOf": variants }),
+        };
+        if self.nullable {
+            schema["nullable"] = json!(true);
+        }
+        schema
+    }
+}
+
+fn string_format(text: &str) -> Option<&'static str> {
+    if is_uuid(text) {
+        Some("uuid")
+    } else if is_date_time(text) {
+        Some("date-time")
+    } else {
+        None
+    }
+}
+
+/// RFC 3339 timestamps like `2024-05-01T12:00:00Z`, checked by shape.
+fn is_date_time(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    let digits = |range: std::ops::Range<usize>| {
+        bytes
+            .get(range)
+            .is_some_and(|part| part.iter().all(u8::is_ascii_digit))
+    };
+    bytes.len() >= 20
+        && digits(0..4)
+        && bytes[4] == b'-'
+        && digits(5..7)
+        && bytes[7] == b'-'
+        && digits(8..10)
+        && matches!(bytes[10], b'T' | b't')
+        && digits(11..13)
+        && bytes[13] == b':'
+        && digits(14..16)
+        && bytes[16] == b':'
+        && digits(17..19)
+        && matches!(bytes[bytes.len() - 1], b'Z' | b'z' | b'0'..=b'9')
+}
@@ -0,0 +1,170 @@
+use std::collections::{HashMap, HashSet};
+
+/// A path with its variable segments replaced by named parameters, as in
+/// `/users/{userId}/posts/{id}`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PathTemplate {
+    pub template: String,
+    /// Parameter names in path order.
+    pub params: Vec<String>,
+}
+
+impl PathTemplate {
+    /// The value of each parameter in `path`, which has to be one of the
+    /// paths the template was inferred from.
+    pub fn values<'a>(&self, path: &'a str) -> Vec<&'a str> {
+        self.template
+            .split('/')
+            .zip(path.split('/'))
+            .filter(|(segment, _)| segment.starts_with('{'))
+            .map(|(_, value)| value)
+            .collect()
+    }
+}
+
+/// Distinct literals at one position, among paths otherwise alike, that
+/// make the position a parameter even when the values don't look like ids.
+const VARIANTS_FOR_PARAM: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Segment {
+    Literal(String),
+    Param,
+}
+
+/// Templates for `paths`, in the same order. A segment becomes a parameter
+/// when it looks like an id, or when enough paths differ only there.
+pub fn infer_templates(paths: &[&str]) -> Vec<PathTemplate> {
+    let mut shapes: Vec<Vec<Segment>> = paths
+        .iter()
+        .map(|path| {
+            path.trim_start_matches('/')
+                .split('/')
+                .map(|segment| {
+                    if is_identifier(segment) {
+                        Segment::Param
+                    } else {
+                        Segment::Literal(segment.to_string())
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    let longest = shapes.iter().map(Vec::len).max().unwrap_or_default();
+    for position in 0..longest {
+        let mut variants: HashMap<Vec<Segment>, HashSet<String>> = HashMap::new();
+        for shape in shapes.iter().filter(|shape| shape.len() > position) {
+            if let Segment::Literal(literal) = &shape[position] {
+                let mut key = shape.clone();
+                key[position] = Segment::Param;
+                variants.entry(key).or_default().insert(literal.clone());
+            }
+        }
+        for shape in shapes.iter_mut().filter(|shape| shape.len() > position) {
+            let mut key = shape.clone();
+            key[position] = Segment::Param;
+            if variants.get(&key).map_or(0, HashSet::len) >= VARIANTS_FOR_PARAM {
+                shape[position] = Segment::Param;
+            }
+        }
+    }
+
+    shapes.iter().map(|shape| template(shape)).collect()
+}
+
+/// The last parameter is `id`, earlier ones are named after the resource
+/// before them, so `/users/1/posts/2` becomes `/users/{userId}/posts/{id}`.
+fn template(shape: &[Segment]) -> PathTemplate {
+    let last_param = shape.iter().rposition(|segment| *segment == Segment::Param);
+    let mut params: Vec<String> = vec![];
+    let mut segments = vec![];
+
+    for (position, segment) in shape.iter().enumerate() {
+        if let Segment::Literal(literal) = segment {
+            segments.push(literal.clone());
+            continue;
+        }
+
+        let resource = match position.checked_sub(1).map(|before| &shape[before]) {
+            Some(Segment::Literal(literal)) if !literal.is_empty() => Some(literal.as_str()),
+            _ => None,
+        };
+        let base = match resource {
+            _ if Some(position) == last_param => "id".to_string(),
+            Some(resource) => format!("{}Id", camel_case(&singular(resource))),
+            None => "param".to_string(),
+        };
+
+        let mut name = base.clone();
+        let mut n = 2;
+        while params.contains(&name) {
+            name = format!("{}{}", base, n);
+            n += 1;
+        }
+        segments.push(format!("{{{}}}", name));
+        params.push(name);
+    }
+
+    PathTemplate {
+        template: format!("/{}", segments.join("/")),
+        params,
+    }
+}
+
+/// Numbers, UUIDs, hashes and long opaque tokens.
+fn is_identifier(segment: &str) -> bool {
+    let bytes = segment.as_bytes();
+    if bytes.is_empty() {
+        return false;
+    }
+    let has_digit = bytes.iter().any(u8::is_ascii_digit);
+    if bytes.iter().all(u8::is_ascii_digit) || is_uuid(segment) {
+        return true;
+    }
+    if bytes.len() >= 16 && has_digit && bytes.iter().all(u8::is_ascii_hexdigit) {
+        return true;
+    }
+    bytes.len() >= 20
+        && has_digit
+        && bytes.iter().any(u8::is_ascii_alphabetic)
+        && bytes
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+pub fn is_uuid(value: &str) -> bool {
+    let groups: Vec<&str> = value.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+fn singular(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if word.ends_with("ss") {
+        word.to_string()
+    } else {
+        word.strip_suffix('s').unwrap_or(word).to_string()
+    }
+}
+
+/// `order-items` and `order_items` become `orderItems`.
+fn camel_case(word: &str) -> String {
+    let mut out = String::with_capacity(word.len());
+    let mut upper = false;
+    for c in word.chars() {
+        if !c.is_ascii_alphanumeric() {
+            upper = !out.is_empty();
+        } else if upper {
+            out.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
@@ -0,0 +1,512 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use hyper::Version;
+use tauri::State;
+
+use crate::apidoc;
+use crate::diff::{self, FlowDiff};
+use crate::grpc::{decode_call, fetch_descriptors, GrpcCall, GrpcSchemas};
+use crate::har;
+use crate::mitm::{
+    parse_version, replay_request, BreakpointAction, BreakpointRule, Breakpoints, ClientReplay,
+    Connection, EditableRequest, Flow, FlowId, FlowKind, FlowStore, NetworkPreset, NetworkProfile,
+    PendingBreakpoint, ReplayOptions, ReplayReport, Rule, Rules, ServerReplay, ServerReplayConfig,
+    Throttle, ThrottleConfig, TimeoutPolicy, TransparentRules, WsDirection, WsInjector,
+    WsMessageKind,
+};
+use crate::mitmproxy;
+use crate::search::{self, SearchIndex};
+use crate::session::{self, OpenSession, SessionIndex};
+use crate::snippet::{self, CurlCommand, SnippetFormat};
+
+#[tauri::command]
+pub fn get_breakpoint_rules(breakpoints: State<'_, Arc<Breakpoints>>) -> Vec<BreakpointRule> {
+    breakpoints.rules()
+}
+
+#[tauri::command]
+pub fn set_breakpoint_rules(breakpoints: State<'_, Arc<Breakpoints>>, rules: Vec<BreakpointRule>) {
+    breakpoints.set_rules(rules);
+}
+
+#[tauri::command]
+pub fn get_breakpoint_timeout(breakpoints: State<'_, Arc<Breakpoints>>) -> TimeoutPolicy {
+    breakpoints.timeout_policy()
+}
+
+#[tauri::command]
+pub fn set_breakpoint_timeout(breakpoints: State<'_, Arc<Breakpoints>>, policy: TimeoutPolicy) {
+    breakpoints.set_timeout_policy(policy);
+}
+
+#[tauri::command]
+pub fn list_pending_breakpoints(
+    breakpoints: State<'_, Arc<Breakpoints>>,
+) -> Vec<PendingBreakpoint> {
+    breakpoints.pending()
+}
+
+#[tauri::command]
+pub fn resolve_breakpoint(
+    breakpoints: State<'_, Arc<Breakpoints>>,
+    id: u64,
+    action: BreakpointAction,
+) -> Result<(), String> {
+    breakpoints.resolve(id, action).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_rules(rules: State<'_, Arc<Rules>>) -> Vec<Rule> {
+    rules.rules()
+}
+
+#[tauri::command]
+pub fn set_rules(rules: State<'_, Arc<Rules>>, new_rules: Vec<Rule>) {
+    rules.set_rules(new_rules);
+}
+
+#[tauri::command]
+pub fn get_network_presets() -> Vec<(NetworkPreset, NetworkProfile)> {
+    NetworkPreset::ALL
+        .iter()
+        .map(|preset| (*preset, preset.profile()))
+        .collect()
+}
+
+#[tauri::command]
+pub fn get_network_conditions(throttle: State<'_, Arc<Throttle>>) -> ThrottleConfig {
+    throttle.config()
+}
+
+#[tauri::command]
+pub fn set_network_conditions(throttle: State<'_, Arc<Throttle>>, config: ThrottleConfig) {
+    throttle.set_config(config);
+}
+
+#[tauri::command]
+pub fn list_flows(flow_store: State<'_, Arc<FlowStore>>) -> Vec<Flow> {
+    flow_store.list()
+}
+
+#[tauri::command]
+pub fn get_flow(flow_store: State<'_, Arc<FlowStore>>, id: FlowId) -> Option<Flow> {
+    flow_store.get(id)
+}
+
+/// The request line and header block as the client sent them, recorded
+/// for HTTP/1 requests in header fidelity mode.
+#[tauri::command]
+pub fn get_raw_head(
+    flow_store: State<'_, Arc<FlowStore>>,
+    id: FlowId,
+) -> Result<Option<String>, String> {
+    let flow = flow_store
+        .get(id)
+        .ok_or_else(|| format!("No flow {}", id))?;
+    Ok(flow
+        .request
+        .raw_head
+        .map(|head| String::from_utf8_lossy(&head).into_owned()))
+}
+
+#[tauri::command]
+pub fn list_connections(flow_store: State<'_, Arc<FlowStore>>) -> Vec<Connection> {
+    flow_store.connections()
+}
+
+#[tauri::command]
+pub fn clear_flows(flow_store: State<'_, Arc<FlowStore>>) {
+    flow_store.clear();
+}
+
+#[tauri::command]
+pub fn get_capture_limit(flow_store: State<'_, Arc<FlowStore>>) -> usize {
+    flow_store.capture_limit()
+}
+
+#[tauri::command]
+pub fn set_capture_limit(flow_store: State<'_, Arc<FlowStore>>, limit: usize) {
+    flow_store.set_capture_limit(limit);
+}
+
+#[tauri::command]
+pub fn list_ws_sessions(ws_injector: State<'_, Arc<WsInjector>>) -> Vec<FlowId> {
+    ws_injector.sessions()
+}
+
+#[tauri::command]
+pub fn inject_ws_message(
+    ws_injector: State<'_, Arc<WsInjector>>,
+    id: FlowId,
+    direction: WsDirection,
+    kind: WsMessageKind,
+    payload: Vec<u8>,
+) -> Result<(), String> {
+    ws_injector
+        .inject(id, direction, kind, payload)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn list_grpc_services(grpc_schemas: State<'_, Arc<GrpcSchemas>>) -> Vec<String> {
+    grpc_schemas.services()
+}
+
+#[tauri::command]
+pub fn clear_grpc_schemas(grpc_schemas: State<'_, Arc<GrpcSchemas>>) {
+    grpc_schemas.clear();
+}
+
+#[tauri::command]
+pub async fn load_descriptor_set(
+    grpc_schemas: State<'_, Arc<GrpcSchemas>>,
+    path: PathBuf,
+) -> Result<Vec<String>, String> {
+    let data = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+    grpc_schemas
+        .load_descriptor_set(&data)
+        .map_err(|e| e.to_string())?;
+    Ok(grpc_schemas.services())
+}
+
+#[tauri::command]
+pub async fn load_proto_files(
+    grpc_schemas: State<'_, Arc<GrpcSchemas>>,
+    paths: Vec<PathBuf>,
+    include_dirs: Vec<PathBuf>,
+) -> Result<Vec<String>, String> {
+    grpc_schemas
+        .load_proto_files(&paths, &include_dirs)
+        .await
+        .map_err(|e| format!("{:#}", e))?;
+    Ok(grpc_schemas.services())
+}
+
+#[tauri::command]
+pub async fn load_grpc_reflection(
+    grpc_schemas: State<'_, Arc<GrpcSchemas>>,
+    endpoint: String,
+) -> Result<Vec<String>, String> {
+    let endpoint = endpoint.parse().map_err(|e| format!("{}", e))?;
+    let files = fetch_descriptors(&endpoint)
+        .await
+        .map_err(|e| format!("{:#}", e))?;
+    grpc_schemas.add_files(files).map_err(|e| e.to_string())?;
+    Ok(grpc_schemas.services())
+}
+
+#[tauri::command]
+pub fn decode_grpc_flow(
+    flow_store: State<'_, Arc<FlowStore>>,
+    grpc_schemas: State<'_, Arc<GrpcSchemas>>,
+    id: FlowId,
+) -> Option<GrpcCall> {
+    decode_call(&flow_store.get(id)?, &grpc_schemas)
+}
+
+#[tauri::command]
+pub fn get_transparent_rules(rules: Option<TransparentRules>) -> String {
+    rules.unwrap_or_default().script()
+}
+
+#[tauri::command]
+pub async fn export_har(
+    flow_store: State<'_, Arc<FlowStore>>,
+    path: PathBuf,
+    ids: Option<Vec<FlowId>>,
+) -> Result<usize, String> {
+    let flows = selected_flows(&flow_store, ids);
+    let har = har::export_har(&flows);
+    let data = serde_json::to_vec_pretty(&har).map_err(|e| e.to_string())?;
+    tokio::fs::write(&path, data)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(har.log.entries.len())
+}
+
+#[tauri::command]
+pub async fn import_har(
+    flow_store: State<'_, Arc<FlowStore>>,
+    path: PathBuf,
+) -> Result<Vec<FlowId>, String> {
+    let data = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+    let har: har::Har = serde_json::from_slice(&data).map_err(|e| e.to_string())?;
+    Ok(har::import_har(&har)
+        .into_iter()
+        .map(|flow| flow_store.import(flow))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn export_postman(
+    flow_store: State<'_, Arc<FlowStore>>,
+    path: PathBuf,
+    ids: Option<Vec<FlowId>>,
+    name: String,
+) -> Result<(), String> {
+    let flows = selected_flows(&flow_store, ids);
+    let collection = apidoc::export_postman(&flows, &name);
+    let data = serde_json::to_vec_pretty(&collection).map_err(|e| e.to_string())?;
+    tokio::fs::write(&path, data)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn export_openapi(
+    flow_store: State<'_, Arc<FlowStore>>,
+    path: PathBuf,
+    ids: Option<Vec<FlowId>>,
+    title: String,
+) -> Result<(), String> {
+    let flows = selected_flows(&flow_store, ids);
+    let document = apidoc::export_openapi(&flows, &title);
+    let data = serde_json::to_vec_pretty(&document).map_err(|e| e.to_string())?;
+    tokio::fs::write(&path, data)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn diff_flows(
+    flow_store: State<'_, Arc<FlowStore>>,
+    left: FlowId,
+    right: FlowId,
+) -> Result<FlowDiff, String> {
+    let [left, right] =
+        [left, right].map(|id| flow_store.get(id).ok_or_else(|| format!("No flow {}", id)));
+    let (left, right) = (left?, right?);
+    tokio::task::spawn_blocking(move || diff::diff_flows(&left, &right))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn search_flows(
+    flow_store: State<'_, Arc<FlowStore>>,
+    search_index: State<'_, Arc<SearchIndex>>,
+    query: String,
+) -> Result<Vec<FlowId>, String> {
+    let query = search::parse_query(&query).map_err(|e| e.to_string())?;
+    let (flow_store, search_index) = (flow_store.inner().clone(), search_index.inner().clone());
+    tokio::task::spawn_blocking(move || search_index.search(&flow_store, &query))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+fn selected_flows(flow_store: &FlowStore, ids: Option<Vec<FlowId>>) -> Vec<Flow> {
+    match ids {
+        Some(ids) => ids
+            .into_iter()
+            .filter_map(|id| flow_store.get(id))
+            .collect(),
+        None => flow_store.list(),
+    }
+}
+
+#[tauri::command]
+pub async fn export_mitmproxy(
+    flow_store: State<'_, Arc<FlowStore>>,
+    path: PathBuf,
+    ids: Option<Vec<FlowId>>,
+) -> Result<usize, String> {
+    let flows = selected_flows(&flow_store, ids);
+    let data = mitmproxy::export_mitmproxy(&flows, &flow_store.connections());
+    tokio::fs::write(&path, data)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(flows
+        .iter()
+        .filter(|flow| flow.kind == FlowKind::Http)
+        .count())
+}
+
+#[tauri::command]
+pub async fn import_mitmproxy(
+    flow_store: State<'_, Arc<FlowStore>>,
+    path: PathBuf,
+) -> Result<Vec<FlowId>, String> {
+    let data = tokio::fs::read(&path).await.map_err(|e| e.to_string())?;
+    mitmproxy::load_mitmproxy(&data, &flow_store).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_server_replay(server_replay: State<'_, Arc<ServerReplay>>) -> ServerReplayConfig {
+    server_replay.config()
+}
+
+#[tauri::command]
+pub fn set_server_replay(server_replay: State<'_, Arc<ServerReplay>>, config: ServerReplayConfig) {
+    server_replay.set_config(config);
+}
+
+#[tauri::command]
+pub fn load_server_replay(
+    flow_store: State<'_, Arc<FlowStore>>,
+    server_replay: State<'_, Arc<ServerReplay>>,
+    ids: Option<Vec<FlowId>>,
+) -> usize {
+    let flows = match ids {
+        Some(ids) => ids
+            .into_iter()
+            .filter_map(|id| flow_store.get(id))
+            .collect(),
+        None => flow_store
+            .list()
+            .into_iter()
+            .filter(|flow| flow.imported)
+            .collect(),
+    };
+    server_replay.load(flows)
+}
+
+#[tauri::command]
+pub fn clear_server_replay(server_replay: State<'_, Arc<ServerReplay>>) {
+    server_replay.clear();
+}
+
+#[tauri::command]
+pub fn get_replay_request(
+    flow_store: State<'_, Arc<FlowStore>>,
+    id: FlowId,
+) -> Result<EditableRequest, String> {
+    let flow = flow_store
+        .get(id)
+        .ok_or_else(|| format!("No flow {}", id))?;
+    replay_request(&flow).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn replay_flow(
+    flow_store: State<'_, Arc<FlowStore>>,
+    client_replay: State<'_, Arc<ClientReplay>>,
+    id: FlowId,
+    request: Option<EditableRequest>,
+    options: Option<ReplayOptions>,
+) -> Result<ReplayReport, String> {
+    let flow = flow_store
+        .get(id)
+        .ok_or_else(|| format!("No flow {}", id))?;
+    let request = match request {
+        Some(request) => request,
+        None => replay_request(&flow).map_err(|e| e.to_string())?,
+    };
+    client_replay
+        .replay(
+            id,
+            request,
+            parse_version(&flow.request.version),
+            options.unwrap_or_default(),
+        )
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn generate_snippet(
+    flow_store: State<'_, Arc<FlowStore>>,
+    id: FlowId,
+    format: SnippetFormat,
+) -> Result<String, String> {
+    let flow = flow_store
+        .get(id)
+        .ok_or_else(|| format!("No flow {}", id))?;
+    let request = replay_request(&flow).map_err(|e| e.to_string())?;
+    Ok(snippet::generate_snippet(&request, format))
+}
+
+#[tauri::command]
+pub fn parse_curl(command: String) -> Result<CurlCommand, String> {
+    snippet::parse_curl(&command).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn send_request(
+    client_replay: State<'_, Arc<ClientReplay>>,
+    request: EditableRequest,
+    options: Option<ReplayOptions>,
+) -> Result<ReplayReport, String> {
+    client_replay
+        .send(request, Version::HTTP_11, options.unwrap_or_default())
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn save_session(
+    flow_store: State<'_, Arc<FlowStore>>,
+    rules: State<'_, Arc<Rules>>,
+    breakpoints: State<'_, Arc<Breakpoints>>,
+    path: PathBuf,
+) -> Result<usize, String> {
+    let flow_store = flow_store.inner().clone();
+    let (rules, breakpoint_rules) = (rules.rules(), breakpoints.rules());
+    tokio::task::spawn_blocking(move || {
+        let file = BufWriter::new(File::create(path)?);
+        session::save_session(file, &flow_store, &rules, &breakpoint_rules)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn load_session(
+    flow_store: State<'_, Arc<FlowStore>>,
+    rules: State<'_, Arc<Rules>>,
+    breakpoints: State<'_, Arc<Breakpoints>>,
+    path: PathBuf,
+    restore_rules: Option<bool>,
+) -> Result<Vec<FlowId>, String> {
+    let store = flow_store.inner().clone();
+    let loaded = tokio::task::spawn_blocking(move || {
+        session::load_session(BufReader::new(File::open(path)?), &store)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+    .map_err(|e| e.to_string())?;
+
+    if restore_rules.unwrap_or(false) {
+        if let Some(new_rules) = loaded.rules {
+            rules.set_rules(new_rules);
+        }
+        if let Some(breakpoint_rules) = loaded.breakpoint_rules {
+            breakpoints.set_rules(breakpoint_rules);
+        }
+    }
+    Ok(loaded.flows)
+}
+
+#[tauri::command]
+pub async fn open_session(
+    open_session: State<'_, Arc<OpenSession>>,
+    path: PathBuf,
+) -> Result<SessionIndex, String> {
+    let open_session = open_session.inner().clone();
+    tokio::task::spawn_blocking(move || open_session.open(&path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_session_flow(
+    open_session: State<'_, Arc<OpenSession>>,
+    id: FlowId,
+) -> Result<Flow, String> {
+    let open_session = open_session.inner().clone();
+    tokio::task::spawn_blocking(move || open_session.flow(id))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn close_session(open_session: State<'_, Arc<OpenSession>>) {
+    open_session.close();
+}
@@ -0,0 +1,242 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{diff_sequences, Change, Edit};
+
+/// Unchanged lines kept around each run of changes.
+const CONTEXT_LINES: usize = 3;
+
+/// Bytes per row of a hex diff.
+const HEX_ROW: usize = 16;
+
+/// Differing hex rows reported, the rest are left out.
+const MAX_HEX_ROWS: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BodyDiff {
+    /// The same bytes on both sides, which includes two missing bodies.
+    Same {
+        size: u64,
+    },
+    /// Both bodies are JSON, only the values that differ are listed.
+    Json {
+        changes: Vec<JsonChange>,
+    },
+    Text {
+        hunks: Vec<TextHunk>,
+    },
+    /// Rows of bytes at the same offsets that differ.
+    #[serde(rename_all = "camelCase")]
+    Binary {
+        left_size: u64,
+        right_size: u64,
+        rows: Vec<HexRow>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JsonChange {
+    /// JSON Pointer to the value, empty for the whole document.
+    pub path: String,
+    pub change: Change,
+    pub left: Option<Value>,
+    pub right: Option<Value>,
+}
+
+/// A run of changed lines with some unchanged ones around it. Line numbers
+/// start at 1.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextHunk {
+    pub left_start: usize,
+    pub right_start: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLine {
+    /// `unchanged`, `removed` or `added`.
+    pub change: Change,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HexRow {
+    pub offset: usize,
+    /// Space separated hex bytes, empty past the end of a side.
+    pub left: String,
+    pub right: String,
+}
+
+/// Compares JSON structurally when both sides parse, text line by line when
+/// both are UTF-8, and anything else as hex.
+pub fn diff_bodies(left: &[u8], right: &[u8]) -> BodyDiff {
+    if left == right {
+        return BodyDiff::Same {
+            size: left.len() as u64,
+        };
+    }
+
+    if let (Ok(left), Ok(right)) = (
+        serde_json::from_slice::<Value>(left),
+        serde_json::from_slice::<Value>(right),
+    ) {
+        let mut changes = vec![];
+        diff_json(&mut String::new(), &left, &right, &mut changes);
+        return BodyDiff::Json { changes };
+    }
+
+    match (std::str::from_utf8(left), std::str::from_utf8(right)) {
+        (Ok(left), Ok(right)) if !left.contains('\0') && !right.contains('\0') => BodyDiff::Text {
+            hunks: diff_text(left, right),
+        },
+        _ => BodyDiff::Binary {
+            left_size: left.len() as u64,
+            right_size: right.len() as u64,
+            rows: diff_hex(left, right),
+        },
+    }
+}
+
+/// Objects are compared by key and arrays by position.
+fn diff_json(path: &mut String, left: &Value, right: &Value, changes: &mut Vec<JsonChange>) {
+    match (left, right) {
+        (Value::Object(left), Value::Object(right)) => {
+            let mut keys: Vec<&String> = left.keys().chain(right.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let len = path.len();
+                path.push('/');
+                path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+                diff_json_member(path, left.get(key), right.get(key), changes);
+                path.truncate(len);
+            }
+        }
+        (Value::Array(left), Value::Array(right)) => {
+            for i in 0..left.len().max(right.len()) {
+                let len = path.len();
+                path.push('/');
+                path.push_str(&i.to_string());
+                diff_json_member(path, left.get(i), right.get(i), changes);
+                path.truncate(len);
+            }
+        }
+        (left, right) if left != right => changes.push(JsonChange {
+            path: path.clone(),
+            change: Change::Modified,
+            left: Some(left.clone()),
+            right: Some(right.clone()),
+        }),
+        _ => {}
+    }
+}
+
+fn diff_json_member(
+    path: &mut String,
+    left: Option<&Value>,
+    right: Option<&Value>,
+    changes: &mut Vec<JsonChange>,
+) {
+    match (left, right) {
+        (Some(left), Some(right)) => diff_json(path, left, right, changes),
+        (left, right) => changes.push(JsonChange {
+            path: path.clone(),
+            change: Change::of(left, right),
+            left: left.cloned(),
+            right: right.cloned(),
+        }),
+    }
+}
+
+fn diff_text(left: &str, right: &str) -> Vec<TextHunk> {
+    let left: Vec<&str> = left.lines().collect();
+    let right: Vec<&str> = right.lines().collect();
+    let edits = diff_sequences(&left, &right);
+
+    let mut hunks: Vec<TextHunk> = vec![];
+    // edits up to here are in a hunk already
+    let mut done = 0;
+    let changed: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Keep(..)))
+        .map(|(i, _)| i)
+        .collect();
+    for &i in &changed {
+        let start = i.saturating_sub(CONTEXT_LINES).max(done);
+        let end = (i + CONTEXT_LINES + 1).min(edits.len());
+        let extends = start == done && done > 0;
+        if !extends {
+            let (left_start, right_start) = line_numbers(&edits[start..], &left, &right);
+            hunks.push(TextHunk {
+                left_start,
+                right_start,
+                lines: vec![],
+            });
+        }
+        let hunk = hunks.last_mut().unwrap();
+        for edit in &edits[start..end] {
+            hunk.lines.push(match *edit {
+                Edit::Keep(x, _) => DiffLine {
+                    change: Change::Unchanged,
+                    text: left[x].to_string(),
+                },
+                Edit::Remove(x) => DiffLine {
+                    change: Change::Removed,
+                    text: left[x].to_string(),
+                },
+                Edit::Insert(y) => DiffLine {
+                    change: Change::Added,
+                    text: right[y].to_string(),
+                },
+            });
+        }
+        done = done.max(end);
+    }
+    hunks
+}
+
+/// 1-based numbers of the first lines on each side from `edits` on.
+fn line_numbers(edits: &[Edit], left: &[&str], right: &[&str]) -> (usize, usize) {
+    let left_start = edits
+        .iter()
+        .find_map(|edit| match *edit {
+            Edit::Keep(x, _) | Edit::Remove(x) => Some(x + 1),
+            Edit::Insert(_) => None,
+        })
+        .unwrap_or(left.len() + 1);
+    let right_start = edits
+        .iter()
+        .find_map(|edit| match *edit {
+            Edit::Keep(_, y) | Edit::Insert(y) => Some(y + 1),
+            Edit::Remove(_) => None,
+        })
+        .unwrap_or(right.len() + 1);
+    (left_start, right_start)
+}
+
+fn diff_hex(left: &[u8], right: &[u8]) -> Vec<HexRow> {
+    let hex = |data: &[u8], offset: usize| {
+        let row = data.get(offset..).unwrap_or_default();
+        row[..row.len().min(HEX_ROW)]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    (0..left.len().max(right.len()))
+        .step_by(HEX_ROW)
+        .map(|offset| HexRow {
+            offset,
+            left: hex(left, offset),
+            right: hex(right, offset),
+        })
+        .filter(|row| row.left != row.right)
+        .take(MAX_HEX_ROWS)
+        .collect()
+}
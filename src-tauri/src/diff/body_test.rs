@@ -0,0 +1,157 @@
+use serde_json::json;
+
+use super::{diff_bodies, diff_sequences, BodyDiff, Change, DiffLine, Edit, HexRow, JsonChange};
+
+#[test]
+fn test_diff_sequences() {
+    let left: Vec<char> = "abcabba".chars().collect();
+    let right: Vec<char> = "cbabac".chars().collect();
+    let edits = diff_sequences(&left, &right);
+    assert_eq!(
+        edits
+            .iter()
+            .filter(|e| !matches!(e, Edit::Keep(..)))
+            .count(),
+        5
+    );
+
+    // replaying the script has to give the right side
+    let mut rebuilt = vec![];
+    let (mut x, mut y) = (0, 0);
+    for edit in edits {
+        match edit {
+            Edit::Keep(i, j) => {
+                assert_eq!((i, j), (x, y));
+                assert_eq!(left[i], right[j]);
+                rebuilt.push(left[i]);
+                (x, y) = (x + 1, y + 1);
+            }
+            Edit::Remove(i) => {
+                assert_eq!(i, x);
+                x += 1;
+            }
+            Edit::Insert(j) => {
+                assert_eq!(j, y);
+                rebuilt.push(right[j]);
+                y += 1;
+            }
+        }
+    }
+    assert_eq!(rebuilt, right);
+    assert_eq!(diff_sequences::<u8>(&[], &[]), []);
+}
+
+#[test]
+fn test_diff_json_bodies() {
+    let left = br#"{"user":{"id":1,"roles":["a","b"]},"token":"x","a/b":1}"#;
+    let right = br#"{ "user": { "roles": ["a"], "id": 2 }, "expires": 60, "a/b": 1 }"#;
+    let BodyDiff::Json { changes } = diff_bodies(left, right) else {
+        panic!("not a JSON diff");
+    };
+    assert_eq!(
+        changes,
+        [
+            JsonChange {
+                path: "/expires".to_string(),
+                change: Change::Added,
+                left: None,
+                right: Some(json!(60)),
+            },
+            JsonChange {
+                path: "/token".to_string(),
+                change: Change::Removed,
+                left: Some(json!("x")),
+                right: None,
+            },
+            JsonChange {
+                path: "/user/id".to_string(),
+                change: Change::Modified,
+                left: Some(json!(1)),
+                right: Some(json!(2)),
+            },
+            JsonChange {
+                path: "/user/roles/1".to_string(),
+                change: Change::Removed,
+                left: Some(json!("b")),
+                right: None,
+            },
+        ]
+    );
+
+    assert_eq!(
+        diff_bodies(br#"{"a":1}"#, br#"{ "a": 1 }"#),
+        BodyDiff::Json { changes: vec![] }
+    );
+    assert_eq!(diff_bodies(b"", b""), BodyDiff::Same { size: 0 });
+}
+
+#[test]
+fn test_diff_text_bodies() {
+    let left: String = (1..=20).map(|i| format!("line {}\n", i)).collect();
+    let right = left
+        .replace("line 2\n", "line two\n")
+        .replace("line 15\n", "")
+        + "line 21\n";
+    let BodyDiff::Text { hunks } = diff_bodies(left.as_bytes(), right.as_bytes()) else {
+        panic!("not a text diff");
+    };
+
+    assert_eq!(hunks.len(), 2);
+    assert_eq!((hunks[0].left_start, hunks[0].right_start), (1, 1));
+    let line = |change, text: &str| DiffLine {
+        change,
+        text: text.to_string(),
+    };
+    assert_eq!(
+        hunks[0].lines,
+        [
+            line(Change::Unchanged, "line 1"),
+            line(Change::Removed, "line 2"),
+            line(Change::Added, "line two"),
+            line(Change::Unchanged, "line 3"),
+            line(Change::Unchanged, "line 4"),
+            line(Change::Unchanged, "line 5"),
+        ]
+    );
+    // the removal and the addition are close enough to share a hunk
+    assert_eq!((hunks[1].left_start, hunks[1].right_start), (12, 12));
+    assert_eq!(
+        hunks[1]
+            .lines
+            .iter()
+            .filter(|line| line.change != Change::Unchanged)
+            .collect::<Vec<_>>(),
+        [
+            &line(Change::Removed, "line 15"),
+            &line(Change::Added, "line 21")
+        ]
+    );
+}
+
+#[test]
+fn test_diff_binary_bodies() {
+    let left: Vec<u8> = (0..40).collect();
+    let mut right = left.clone();
+    right[17] = 0xff;
+    right.truncate(34);
+
+    assert_eq!(
+        diff_bodies(&left, &right),
+        BodyDiff::Binary {
+            left_size: 40,
+            right_size: 34,
+            rows: vec![
+                HexRow {
+                    offset: 16,
+                    left: "10 11 12 13 14 15 16 17 18 19 1a 1b 1c 1d 1e 1f".to_string(),
+                    right: "10 ff 12 13 14 15 16 17 18 19 1a 1b 1c 1d 1e 1f".to_string(),
+                },
+                HexRow {
+                    offset: 32,
+                    left: "20 21 22 23 24 25 26 27".to_string(),
+                    right: "20 21".to_string(),
+                },
+            ],
+        }
+    );
+}
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+
+use hyper::Uri;
+use serde::Serialize;
+
+use super::{diff_bodies, BodyDiff};
+use crate::har::form_params;
+use crate::mitm::{BodyCapture, Flow, FlowId};
+
+/// How a value on the right compares to the one on the left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Change {
+    Unchanged,
+    /// Only on the right.
+    Added,
+    /// Only on the left.
+    Removed,
+    Modified,
+}
+
+impl Change {
+    pub fn of<T: PartialEq>(left: Option<T>, right: Option<T>) -> Self {
+        match (left, right) {
+            (None, None) => Change::Unchanged,
+            (None, Some(_)) => Change::Added,
+            (Some(_), None) => Change::Removed,
+            (Some(left), Some(right)) if left == right => Change::Unchanged,
+            (Some(_), Some(_)) => Change::Modified,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldDiff {
+    pub name: &'static str,
+    pub change: Change,
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+/// Every value of a name that may repeat, sorted so that order doesn't
+/// count.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntryDiff {
+    pub name: String,
+    pub change: Change,
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+}
+
+/// Everything that differs between two flows, plus what doesn't for the
+/// lists so they can be shown side by side.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowDiff {
+    pub left: FlowId,
+    pub right: FlowId,
+    /// Method, scheme, host, path and version.
+    pub request_line: Vec<FieldDiff>,
+    /// Status, version and the error that ended the flow.
+    pub status_line: Vec<FieldDiff>,
+    /// Names are lowercased.
+    pub request_headers: Vec<EntryDiff>,
+    pub response_headers: Vec<EntryDiff>,
+    pub query: Vec<EntryDiff>,
+    /// From `Cookie` request headers.
+    pub cookies: Vec<EntryDiff>,
+    /// From `Set-Cookie` response headers, with their attributes.
+    pub set_cookies: Vec<EntryDiff>,
+    /// Bodies are compared with their `Content-Encoding` undone.
+    pub request_body: BodyDiff,
+    pub response_body: BodyDiff,
+}
+
+pub fn diff_flows(left: &Flow, right: &Flow) -> FlowDiff {
+    let request_line = |flow: &Flow| {
+        let request = &flow.request;
+        let uri = request.uri.parse::<Uri>().ok();
+        [
+            Some(request.method.clone()),
+            uri.as_ref().and_then(Uri::scheme_str).map(str::to_string),
+            uri.as_ref()
+                .and_then(Uri::authority)
+                .map(|authority| authority.to_string()),
+            match &uri {
+                // none for the `host:port` of a CONNECT
+                Some(uri) => Some(uri.path().to_string()).filter(|path| !path.is_empty()),
+                None => Some(request.uri.clone()),
+            },
+            Some(request.version.clone()),
+        ]
+    };
+    let status_line = |flow: &Flow| {
+        [
+            flow.response
+                .as_ref()
+                .map(|response| response.status.to_string()),
+            flow.response
+                .as_ref()
+                .map(|response| response.version.clone()),
+            flow.error.clone(),
+        ]
+    };
+    let response_headers = |flow: &Flow| {
+        flow.response
+            .as_ref()
+            .map_or(&[][..], |response| &response.headers)
+            .to_vec()
+    };
+
+    FlowDiff {
+        left: left.id,
+        right: right.id,
+        request_line: diff_fields(
+            ["method", "scheme", "host", "path", "version"],
+            request_line(left),
+            request_line(right),
+        ),
+        status_line: diff_fields(
+            ["status", "version", "error"],
+            status_line(left),
+            status_line(right),
+        ),
+        request_headers: diff_entries(
+            headers(&left.request.headers),
+            headers(&right.request.headers),
+        ),
+        response_headers: diff_entries(
+            headers(&response_headers(left)),
+            headers(&response_headers(right)),
+        ),
+        query: diff_entries(query(&left.request.uri), query(&right.request.uri)),
+        cookies: diff_entries(
+            cookies(&left.request.headers, "cookie"),
+            cookies(&right.request.headers, "cookie"),
+        ),
+        set_cookies: diff_entries(
+            cookies(&response_headers(left), "set-cookie"),
+            cookies(&response_headers(right), "set-cookie"),
+        ),
+        request_body: diff_bodies(
+            &body(left.request_body.as_ref(), &left.request.headers),
+            &body(right.request_body.as_ref(), &right.request.headers),
+        ),
+        response_body: diff_bodies(
+            &body(left.response_body.as_ref(), &response_headers(left)),
+            &body(right.response_body.as_ref(), &response_headers(right)),
+        ),
+    }
+}
+
+fn diff_fields<const N: usize>(
+    names: [&'static str; N],
+    left: [Option<String>; N],
+    right: [Option<String>; N],
+) -> Vec<FieldDiff> {
+    names
+        .into_iter()
+        .zip(left.into_iter().zip(right))
+        .map(|(name, (left, right))| FieldDiff {
+            name,
+            change: Change::of(left.as_ref(), right.as_ref()),
+            left,
+            right,
+        })
+        .collect()
+}
+
+type Entries = BTreeMap<String, Vec<String>>;
+
+fn diff_entries(mut left: Entries, mut right: Entries) -> Vec<EntryDiff> {
+    let mut names: Vec<String> = left.keys().chain(right.keys()).cloned().collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| {
+            let mut left = left.remove(&name).unwrap_or_default();
+            let mut right = right.remove(&name).unwrap_or_default();
+            left.sort();
+            right.sort();
+            let change = Change::of(
+                Some(&left).filter(|values| !values.is_empty()),
+                Some(&right).filter(|values| !values.is_empty()),
+            );
+            EntryDiff {
+                name,
+                change,
+                left,
+                right,
+            }
+        })
+        .collect()
+}
+
+fn entries(pairs: impl IntoIterator<Item = (String, String)>) -> Entries {
+    let mut entries = Entries::new();
+    for (name, value) in pairs {
+        entries.entry(name).or_default().push(value);
+    }
+    entries
+}
+
+fn headers(headers: &[(String, String)]) -> Entries {
+    entries(
+        headers
+            .iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.clone())),
+    )
+}
+
+fn query(uri: &str) -> Entries {
+    let query = uri
+        .split_once('?')
+        .map(|(_, query)| query.split('#').next().unwrap_or_default())
+        .unwrap_or_default();
+    entries(
+        form_params(query)
+            .into_iter()
+            .map(|param| (param.name, param.value)),
+    )
+}
+
+/// `Cookie` headers hold `name=value` pairs split by `;`, a `Set-Cookie`
+/// header is one cookie followed by its attributes.
+fn cookies(headers: &[(String, String)], header: &str) -> Entries {
+    let values = headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(header))
+        .map(|(_, value)| value.as_str());
+    let pairs: Vec<&str> = if header == "cookie" {
+        values.flat_map(|value| value.split(';')).collect()
+    } else {
+        values.collect()
+    };
+    entries(pairs.into_iter().filter_map(|pair| {
+        let (name, value) = pair.trim().split_once('=')?;
+        Some((name.trim().to_string(), value.trim().to_string()))
+    }))
+}
+
+fn body(body: Option<&BodyCapture>, headers: &[(String, String)]) -> Vec<u8> {
+    match body {
+        Some(body) => match body.decoded(headers) {
+            Ok(data) => data.to_vec(),
+            Err(_) => body.data.clone(),
+        },
+        None => vec![],
+    }
+}
@@ -0,0 +1,146 @@
+use super::{diff_flows, BodyDiff, Change, EntryDiff, FieldDiff};
+use crate::mitm::{encode_body, BodyCapture, ContentEncoding, Flow};
+
+fn flow(uri: &str, headers: &[(&str, &str)], response_headers: &[(&str, &str)]) -> Flow {
+    Flow::test("GET", uri)
+        .with_request_headers(headers)
+        .with_response(200, response_headers)
+}
+
+fn entry(name: &str, change: Change, left: &[&str], right: &[&str]) -> EntryDiff {
+    let values = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+    EntryDiff {
+        name: name.to_string(),
+        change,
+        left: values(left),
+        right: values(right),
+    }
+}
+
+#[test]
+fn test_diff_flows() {
+    let mut left = flow(
+        "https://staging.test/api/items?page=2&tag=a&tag=b",
+        &[
+            ("Accept", "application/json"),
+            ("Cookie", "session=1; theme=dark"),
+            ("X-Env", "staging"),
+        ],
+        &[("Set-Cookie", "session=1; Path=/; Secure")],
+    );
+    left.id = 1;
+    let mut right = flow(
+        "https://prod.test/api/items?tag=b&tag=a&page=3",
+        &[
+            ("Cookie", "theme=dark"),
+            ("accept", "application/json"),
+            ("x-trace", "abc"),
+        ],
+        &[
+            ("Set-Cookie", "session=2; Path=/"),
+            ("content-encoding", "gzip"),
+        ],
+    );
+    right.id = 2;
+    right.response.as_mut().unwrap().status = 500;
+    right.error = Some("upstream reset".to_string());
+
+    left.response_body = Some(BodyCapture::complete(br#"{"items":[]}"#));
+    let gzipped = encode_body(br#"{"items": []}"#, &[ContentEncoding::Gzip]).unwrap();
+    right.response_body = Some(BodyCapture::complete(&gzipped[..]));
+    right.request_body = Some(BodyCapture::complete(b"a\n"));
+
+    let diff = diff_flows(&left, &right);
+    assert_eq!((diff.left, diff.right), (1, 2));
+    let field = |name, change, left: Option<&str>, right: Option<&str>| FieldDiff {
+        name,
+        change,
+        left: left.map(str::to_string),
+        right: right.map(str::to_string),
+    };
+    assert_eq!(
+        diff.request_line,
+        [
+            field("method", Change::Unchanged, Some("GET"), Some("GET")),
+            field("scheme", Change::Unchanged, Some("https"), Some("https")),
+            field(
+                "host",
+                Change::Modified,
+                Some("staging.test"),
+                Some("prod.test")
+            ),
+            field(
+                "path",
+                Change::Unchanged,
+                Some("/api/items"),
+                Some("/api/items")
+            ),
+            field(
+                "version",
+                Change::Unchanged,
+                Some("HTTP/1.1"),
+                Some("HTTP/1.1")
+            ),
+        ]
+    );
+    assert_eq!(
+        diff.status_line,
+        [
+            field("status", Change::Modified, Some("200"), Some("500")),
+            field(
+                "version",
+                Change::Unchanged,
+                Some("HTTP/1.1"),
+                Some("HTTP/1.1")
+            ),
+            field("error", Change::Added, None, Some("upstream reset")),
+        ]
+    );
+
+    assert_eq!(
+        diff.request_headers,
+        [
+            entry(
+                "accept",
+                Change::Unchanged,
+                &["application/json"],
+                &["application/json"]
+            ),
+            entry(
+                "cookie",
+                Change::Modified,
+                &["session=1; theme=dark"],
+                &["theme=dark"]
+            ),
+            entry("x-env", Change::Removed, &["staging"], &[]),
+            entry("x-trace", Change::Added, &[], &["abc"]),
+        ]
+    );
+    assert_eq!(
+        diff.query,
+        [
+            entry("page", Change::Modified, &["2"], &["3"]),
+            entry("tag", Change::Unchanged, &["a", "b"], &["a", "b"]),
+        ]
+    );
+    assert_eq!(
+        diff.cookies,
+        [
+            entry("session", Change::Removed, &["1"], &[]),
+            entry("theme", Change::Unchanged, &["dark"], &["dark"]),
+        ]
+    );
+    assert_eq!(
+        diff.set_cookies,
+        [entry(
+            "session",
+            Change::Modified,
+            &["1; Path=/; Secure"],
+            &["2; Path=/"]
+        )]
+    );
+
+    // gzip is undone and the JSON only differs in whitespace
+    assert_eq!(diff.response_body, BodyDiff::Json { changes: vec![] });
+    assert!(matches!(diff.request_body, BodyDiff::Text { ref hunks } if hunks.len() == 1));
+}
@@ -0,0 +1,110 @@
+/// One step of turning the left sequence into the right one, with indexes
+/// into the sides it touches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Keep(usize, usize),
+    Remove(usize),
+    Insert(usize),
+}
+
+/// Past this many removals and insertions the differing middle counts as
+/// replaced wholesale, the search costs the square of it.
+const MAX_EDIT_DISTANCE: usize = 1024;
+
+/// A shortest edit script from `left` to `right`, after Myers' "An O(ND)
+/// Difference Algorithm and Its Variations".
+pub fn diff_sequences<T: PartialEq>(left: &[T], right: &[T]) -> Vec<Edit> {
+    let prefix = left.iter().zip(right).take_while(|(a, b)| a == b).count();
+    let suffix = left[prefix..]
+        .iter()
+        .rev()
+        .zip(right[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|i| Edit::Keep(i, i)).collect();
+    let a = &left[prefix..left.len() - suffix];
+    let b = &right[prefix..right.len() - suffix];
+    match shortest_edit(a, b) {
+        Some(middle) => edits.extend(middle.into_iter().map(|edit| match edit {
+            Edit::Keep(x, y) => Edit::Keep(x + prefix, y + prefix),
+            Edit::Remove(x) => Edit::Remove(x + prefix),
+            Edit::Insert(y) => Edit::Insert(y + prefix),
+        })),
+        None => {
+            edits.extend((0..a.len()).map(|x| Edit::Remove(x + prefix)));
+            edits.extend((0..b.len()).map(|y| Edit::Insert(y + prefix)));
+        }
+    }
+    let (left_end, right_end) = (left.len() - suffix, right.len() - suffix);
+    edits.extend((0..suffix).map(|i| Edit::Keep(left_end + i, right_end + i)));
+    edits
+}
+
+fn shortest_edit<T: PartialEq>(a: &[T], b: &[T]) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    // furthest x reached on each diagonal k = x - y, for -d..=d, per step d
+    let mut trace: Vec<Vec<isize>> = vec![];
+
+    for d in 0..=(n + m).min(MAX_EDIT_DISTANCE as isize) {
+        let mut xs = vec![0; 2 * d as usize + 1];
+        for k in (-d..=d).step_by(2) {
+            let mut x = match trace.last() {
+                None => 0,
+                Some(prev) => {
+                    let at = |k: isize| prev[(k + d - 1) as usize];
+                    if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                        at(k + 1)
+                    } else {
+                        at(k - 1) + 1
+                    }
+                }
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            xs[(k + d) as usize] = x;
+            if x >= n && y >= m {
+                trace.push(xs);
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+        trace.push(xs);
+    }
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Edit> {
+    let mut edits = vec![];
+    let (mut x, mut y) = (n, m);
+    for d in (1..trace.len() as isize).rev() {
+        let prev = &trace[d as usize - 1];
+        let at = |k: isize| prev[(k + d - 1) as usize];
+        let k = x - y;
+        let inserted = k == -d || (k != d && at(k - 1) < at(k + 1));
+        let prev_x = at(if inserted { k + 1 } else { k - 1 });
+        let prev_y = prev_x - if inserted { k + 1 } else { k - 1 };
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Keep(x as usize, y as usize));
+        }
+        if inserted {
+            y -= 1;
+            edits.push(Edit::Insert(y as usize));
+        } else {
+            x -= 1;
+            edits.push(Edit::Remove(x as usize));
+        }
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        edits.push(Edit::Keep(x as usize, y as usize));
+    }
+    edits.reverse();
+    edits
+}
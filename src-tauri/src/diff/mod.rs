@@ -0,0 +1,12 @@
+mod body;
+mod flow;
+mod lines;
+
+#[cfg(test)]
+mod body_test;
+#[cfg(test)]
+mod flow_test;
+
+pub use body::*;
+pub use flow::*;
+pub use lines::*;
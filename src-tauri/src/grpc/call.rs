@@ -0,0 +1,160 @@
+use hyper::Uri;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    decode_raw, decode_web_text, parse_frames, parse_web_trailers, GrpcProtocol, GrpcSchemas,
+};
+use crate::mitm::{decode_body, BodyCapture, ContentEncoding, Flow, MAX_DECODED_SIZE};
+use crate::util::percent_decode;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GrpcMessage {
+    pub compressed: bool,
+    pub size: usize,
+    /// Decoded with the schema when known, raw wire format otherwise.
+    pub json: Option<Value>,
+    pub schema: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GrpcCall {
+    pub protocol: GrpcProtocol,
+    pub service: String,
+    pub method: String,
+    pub requests: Vec<GrpcMessage>,
+    pub responses: Vec<GrpcMessage>,
+    pub status: Option<i32>,
+    pub message: Option<String>,
+    pub trailers: Vec<(String, String)>,
+    /// Some message was cut off by the capture limit.
+    pub truncated: bool,
+}
+
+/// Splits the captured bodies of a gRPC or gRPC-Web flow into messages,
+/// `None` if the flow is not a gRPC call.
+pub fn decode_call(flow: &Flow, schemas: &GrpcSchemas) -> Option<GrpcCall> {
+    let protocol = GrpcProtocol::from_content_type(header(&flow.request.headers, "content-type")?)?;
+    let uri: Uri = flow.request.uri.parse().ok()?;
+    let path = uri.path();
+    let (service, method) = path.trim_start_matches('/').split_once('/')?;
+
+    let mut call = GrpcCall {
+        protocol,
+        service: service.to_string(),
+        method: method.to_string(),
+        requests: vec![],
+        responses: vec![],
+        status: None,
+        message: None,
+        trailers: vec![],
+        truncated: false,
+    };
+
+    let encoding = header(&flow.request.headers, "grpc-encoding");
+    if let Some(body) = &flow.request_body {
+        let (messages, _, truncated) = decode_messages(body, protocol, encoding, |data| {
+            schemas.decode(path, false, data)
+        });
+        call.requests = messages;
+        call.truncated |= truncated;
+    }
+
+    if let Some(response) = &flow.response {
+        let encoding = header(&response.headers, "grpc-encoding");
+        if let Some(body) = &flow.response_body {
+            let (messages, trailers, truncated) =
+                decode_messages(body, protocol, encoding, |data| {
+                    schemas.decode(path, true, data)
+                });
+            call.responses = messages;
+            call.trailers = trailers;
+            call.truncated |= truncated;
+        }
+
+        // trailers-only responses carry the status in the headers
+        let status_headers = match header(&call.trailers, "grpc-status") {
+            Some(_) => &call.trailers,
+            None => &response.headers,
+        };
+        call.status = header(status_headers, "grpc-status").and_then(|s| s.parse().ok());
+        call.message = header(status_headers, "grpc-message").map(percent_decode);
+    }
+
+    Some(call)
+}
+
+type Decoded = (Vec<GrpcMessage>, Vec<(String, String)>, bool);
+
+fn decode_messages<F>(
+    body: &BodyCapture,
+    protocol: GrpcProtocol,
+    encoding: Option<&str>,
+    decode: F,
+) -> Decoded
+where
+    F: Fn(&[u8]) -> Option<anyhow::Result<Value>>,
+{
+    let mut trailers = body.trailers.clone().unwrap_or_default();
+
+    let data = match protocol {
+        GrpcProtocol::GrpcWebText => match decode_web_text(&body.data) {
+            Ok(data) => data,
+            Err(_) => return (vec![], trailers, body.truncated),
+        },
+        _ => body.data.clone(),
+    };
+    let (frames, partial) = parse_frames(&data);
+
+    let mut messages = vec![];
+    for frame in frames {
+        if frame.trailers {
+            trailers.extend(parse_web_trailers(&frame.data));
+            continue;
+        }
+
+        let mut message = GrpcMessage {
+            compressed: frame.compressed,
+            size: frame.data.len(),
+            json: None,
+            schema: false,
+            error: None,
+        };
+
+        let data = match (frame.compressed, encoding.and_then(ContentEncoding::parse)) {
+            (false, _) => Ok(frame.data.into()),
+            (true, Some(encoding)) => decode_body(&frame.data, &[encoding], MAX_DECODED_SIZE),
+            (true, None) => Err(anyhow::anyhow!(
+                "Unsupported grpc-encoding {:?}",
+                encoding.unwrap_or_default()
+            )),
+        };
+
+        match data {
+            Ok(data) => match decode(&data) {
+                Some(Ok(json)) => {
+                    message.json = Some(json);
+                    message.schema = true;
+                }
+                schema_result => {
+                    message.error = schema_result.and_then(|r| r.err()).map(|e| e.to_string());
+                    message.json = decode_raw(&data);
+                }
+            },
+            Err(e) => message.error = Some(e.to_string()),
+        }
+        messages.push(message);
+    }
+
+    (messages, trailers, partial || body.truncated)
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
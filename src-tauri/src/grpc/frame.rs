@@ -0,0 +1,113 @@
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GrpcProtocol {
+    Grpc,
+    GrpcWeb,
+    /// gRPC-Web with base64 encoded frames.
+    GrpcWebText,
+}
+
+impl GrpcProtocol {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        let (base, _codec) = mime.split_once('+').unwrap_or((&mime, ""));
+
+        match base {
+            "application/grpc" => Some(Self::Grpc),
+            "application/grpc-web" => Some(Self::GrpcWeb),
+            "application/grpc-web-text" => Some(Self::GrpcWebText),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrpcFrame {
+    pub compressed: bool,
+    /// gRPC-Web sends its trailers as a frame at the end of the body.
+    pub trailers: bool,
+    pub data: Vec<u8>,
+}
+
+/// Splits a body into its length-prefixed frames. The flag is set when
+/// the body ends in the middle of a frame.
+pub fn parse_frames(mut body: &[u8]) -> (Vec<GrpcFrame>, bool) {
+    let mut frames = vec![];
+
+    while !body.is_empty() {
+        let Some((&[flags, a, b, c, d], rest)) = body.split_first_chunk::<5>() else {
+            return (frames, true);
+        };
+        let len = u32::from_be_bytes([a, b, c, d]) as usize;
+        if rest.len() < len {
+            return (frames, true);
+        }
+
+        frames.push(GrpcFrame {
+            compressed: flags & 0x01 != 0,
+            trailers: flags & 0x80 != 0,
+            data: rest[..len].to_vec(),
+        });
+        body = &rest[len..];
+    }
+
+    (frames, false)
+}
+
+pub fn encode_frame(data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(data.len() + 5);
+    frame.push(0);
+    frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    frame.extend_from_slice(data);
+    frame
+}
+
+/// `grpc-web-text` bodies may be a concatenation of separately padded
+/// base64 chunks.
+pub fn decode_web_text(body: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let text: Vec<u8> = body
+        .iter()
+        .copied()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+
+    let mut decoded = vec![];
+    let mut rest = &text[..];
+    while !rest.is_empty() {
+        let end = match rest.iter().position(|b| *b == b'=') {
+            Some(pad) => pad + rest[pad..].iter().take_while(|b| **b == b'=').count(),
+            None => rest.len(),
+        };
+        // a partially captured body can end in the middle of a quantum
+        let end = if end == rest.len() {
+            end - end % 4
+        } else {
+            end
+        };
+        if end == 0 {
+            break;
+        }
+
+        decoded.extend(
+            STANDARD
+                .decode(&rest[..end])
+                .map_err(|e| anyhow!("Invalid grpc-web-text body: {}", e))?,
+        );
+        rest = &rest[end..];
+    }
+
+    Ok(decoded)
+}
+
+/// Trailers of a gRPC-Web trailer frame, written like HTTP/1 headers.
+pub fn parse_web_trailers(data: &[u8]) -> Vec<(String, String)> {
+    String::from_utf8_lossy(data)
+        .split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect()
+}
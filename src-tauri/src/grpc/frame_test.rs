@@ -0,0 +1,82 @@
+use super::{
+    decode_call, decode_web_text, encode_frame, parse_frames, parse_web_trailers, GrpcProtocol,
+    GrpcSchemas,
+};
+use crate::mitm::Flow;
+
+#[test]
+fn test_protocol() {
+    assert_eq!(
+        GrpcProtocol::from_content_type("application/grpc+proto"),
+        Some(GrpcProtocol::Grpc)
+    );
+    assert_eq!(
+        GrpcProtocol::from_content_type("application/grpc-web-text; charset=utf-8"),
+        Some(GrpcProtocol::GrpcWebText)
+    );
+    assert_eq!(GrpcProtocol::from_content_type("application/json"), None);
+}
+
+#[test]
+fn test_frames() {
+    let mut body = encode_frame(b"first");
+    body.extend(encode_frame(b""));
+    let trailers = b"grpc-status: 0\r\ngrpc-message:\r\n";
+    body.push(0x80);
+    body.extend((trailers.len() as u32).to_be_bytes());
+    body.extend(trailers);
+
+    let (frames, partial) = parse_frames(&body);
+    assert!(!partial);
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0].data, b"first");
+    assert!(frames[2].trailers);
+    assert_eq!(
+        parse_web_trailers(&frames[2].data),
+        vec![
+            ("grpc-status".to_string(), "0".to_string()),
+            ("grpc-message".to_string(), "".to_string())
+        ]
+    );
+
+    let (frames, partial) = parse_frames(&body[..12]);
+    assert_eq!(frames.len(), 1);
+    assert!(partial);
+}
+
+#[test]
+fn test_web_text() {
+    // two separately padded chunks
+    let decoded = decode_web_text(b"AAAAAAJoaQ==\r\ngAAAAA5ncnBjLXN0YXR1czogMA==").unwrap();
+    let (frames, partial) = parse_frames(&decoded);
+
+    assert!(!partial);
+    assert_eq!(frames[0].data, b"hi");
+    assert_eq!(frames[1].data, b"grpc-status: 0");
+}
+
+#[test]
+fn test_decode_call() {
+    let mut response = encode_frame(&[0x08, 0x2a]);
+    let trailers = b"grpc-status: 5\r\ngrpc-message: not%20found\r\n";
+    response.push(0x80);
+    response.extend((trailers.len() as u32).to_be_bytes());
+    response.extend(trailers);
+
+    let content_type = [("content-type", "application/grpc-web+proto")];
+    let flow = Flow::test("POST", "https://api.example.com/shop.v1.Orders/GetOrder")
+        .with_request_headers(&content_type)
+        .with_request_body(encode_frame(&[0x0a, 0x01, b'7']))
+        .with_response(200, &content_type)
+        .with_response_body(response);
+
+    let call = decode_call(&flow, &GrpcSchemas::new()).unwrap();
+    assert_eq!(call.service, "shop.v1.Orders");
+    assert_eq!(call.method, "GetOrder");
+    assert_eq!(call.requests[0].json, Some(serde_json::json!({"1": "7"})));
+    assert_eq!(call.responses[0].json, Some(serde_json::json!({"1": 42})));
+    assert!(!call.responses[0].schema);
+    assert_eq!(call.status, Some(5));
+    assert_eq!(call.message.as_deref(), Some("not found"));
+    assert!(!call.truncated);
+}
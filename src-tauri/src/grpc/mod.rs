@@ -0,0 +1,22 @@
+mod call;
+mod frame;
+mod parser;
+mod reflection;
+mod schema;
+mod wire;
+
+#[cfg(test)]
+mod frame_test;
+#[cfg(test)]
+mod parser_test;
+#[cfg(test)]
+mod reflection_test;
+#[cfg(test)]
+mod wire_test;
+
+pub use call::*;
+pub use frame::*;
+pub use parser::*;
+pub use reflection::*;
+pub use schema::*;
+pub use wire::*;
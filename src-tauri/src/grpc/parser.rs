@@ -0,0 +1,860 @@
+use anyhow::{anyhow, bail};
+use prost_types::{
+    descriptor_proto::{ExtensionRange, ReservedRange},
+    enum_descriptor_proto::EnumReservedRange,
+    field_descriptor_proto::{Label, Type},
+    DescriptorProto, EnumDescriptorProto, EnumOptions, EnumValueDescriptorProto,
+    FieldDescriptorProto, FieldOptions, FileDescriptorProto, MessageOptions, MethodDescriptorProto,
+    OneofDescriptorProto, ServiceDescriptorProto,
+};
+
+/// Exclusive upper bound for field numbers.
+const MAX_FIELD_NUMBER: i32 = 536_870_912;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(String),
+    Str(String),
+    Symbol(char),
+}
+
+/// Parses the source of a `.proto` file into a descriptor. Type names are
+/// left as written, the descriptor pool resolves them.
+///
+/// Only the syntax is handled here, which is why this is not protox: name
+/// resolution, imports and validation are left to prost-reflect's
+/// `DescriptorPool`, which also satisfies imports with files that came
+/// from server reflection or descriptor sets rather than from source.
+pub fn parse_proto(name: &str, source: &str) -> anyhow::Result<FileDescriptorProto> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        proto3: false,
+    };
+
+    parser
+        .file(name)
+        .map_err(|e| anyhow!("{}:{}: {}", name, parser.line(), e))
+}
+
+fn tokenize(source: &str) -> anyhow::Result<Vec<(Token, usize)>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                if chars[i] == '\n' {
+                    line += 1;
+                }
+                i += 1;
+            }
+            i += 2;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), line));
+        } else if c.is_ascii_digit() || (c == '.' && next.is_some_and(|n| n.is_ascii_digit())) {
+            let start = i;
+            while i < chars.len() {
+                let d = chars[i];
+                let exponent_sign = (d == '-' || d == '+')
+                    && matches!(chars[i - 1], 'e' | 'E')
+                    && !chars[start..i].iter().any(|c| matches!(c, 'x' | 'X'));
+                if !(d.is_ascii_alphanumeric() || d == '.' || exponent_sign) {
+                    break;
+                }
+                i += 1;
+            }
+            tokens.push((Token::Number(chars[start..i].iter().collect()), line));
+        } else if c == '"' || c == '\'' {
+            let (value, end) = string_literal(&chars, i)?;
+            i = end;
+            // adjacent literals are concatenated
+            match tokens.last_mut() {
+                Some((Token::Str(previous), _)) => previous.push_str(&value),
+                _ => tokens.push((Token::Str(value), line)),
+            }
+        } else {
+            tokens.push((Token::Symbol(c), line));
+            i += 1;
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn string_literal(chars: &[char], start: usize) -> anyhow::Result<(String, usize)> {
+    let quote = chars[start];
+    let mut bytes = vec![];
+    let mut i = start + 1;
+
+    loop {
+        let c = *chars.get(i).ok_or_else(|| anyhow!("Unterminated string"))?;
+        i += 1;
+
+        match c {
+            '\n' => bail!("Unterminated string"),
+            c if c == quote => break,
+            '\\' => {
+                let escape = *chars.get(i).ok_or_else(|| anyhow!("Invalid escape"))?;
+                i += 1;
+                match escape {
+                    'n' => bytes.push(b'\n'),
+                    'r' => bytes.push(b'\r'),
+                    't' => bytes.push(b'\t'),
+                    'a' => bytes.push(0x07),
+                    'b' => bytes.push(0x08),
+                    'f' => bytes.push(0x0c),
+                    'v' => bytes.push(0x0b),
+                    'x' | 'X' => {
+                        let digits = take_digits(chars, &mut i, 2, 16);
+                        bytes.push(u8::from_str_radix(&digits, 16)?);
+                    }
+                    '0'..='7' => {
+                        i -= 1;
+                        let digits = take_digits(chars, &mut i, 3, 8);
+                        bytes.push(u8::from_str_radix(&digits, 8)?);
+                    }
+                    'u' | 'U' => {
+                        let len = if escape == 'u' { 4 } else { 8 };
+                        let digits = take_digits(chars, &mut i, len, 16);
+                        let c = u32::from_str_radix(&digits, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| anyhow!("Invalid unicode escape"))?;
+                        bytes.extend(c.to_string().bytes());
+                    }
+                    c => bytes.extend(c.to_string().bytes()),
+                }
+            }
+            c => bytes.extend(c.to_string().bytes()),
+        }
+    }
+
+    Ok((String::from_utf8_lossy(&bytes).into_owned(), i))
+}
+
+fn take_digits(chars: &[char], i: &mut usize, max: usize, radix: u32) -> String {
+    let start = *i;
+    while *i < chars.len() && *i - start < max && chars[*i].is_digit(radix) {
+        *i += 1;
+    }
+    chars[start..*i].iter().collect()
+}
+
+fn scalar_type(name: &str) -> Option<Type> {
+    Some(match name {
+        "double" => Type::Double,
+        "float" => Type::Float,
+        "int32" => Type::Int32,
+        "int64" => Type::Int64,
+        "uint32" => Type::Uint32,
+        "uint64" => Type::Uint64,
+        "sint32" => Type::Sint32,
+        "sint64" => Type::Sint64,
+        "fixed32" => Type::Fixed32,
+        "fixed64" => Type::Fixed64,
+        "sfixed32" => Type::Sfixed32,
+        "sfixed64" => Type::Sfixed64,
+        "bool" => Type::Bool,
+        "string" => Type::String,
+        "bytes" => Type::Bytes,
+        _ => return None,
+    })
+}
+
+/// `foo_bar` becomes `FooBarEntry`, like protoc names map entries.
+fn map_entry_name(field: &str) -> String {
+    let mut name = String::with_capacity(field.len() + 5);
+    let mut upper = true;
+    for c in field.chars() {
+        if c == '_' {
+            upper = true;
+        } else if upper {
+            name.push(c.to_ascii_uppercase());
+            upper = false;
+        } else {
+            name.push(c);
+        }
+    }
+    name + "Entry"
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    proto3: bool,
+}
+
+impl Parser {
+    /// Line of the last token looked at.
+    fn line(&self) -> usize {
+        let pos = self
+            .pos
+            .saturating_sub(1)
+            .min(self.tokens.len().saturating_sub(1));
+        self.tokens.get(pos).map_or(1, |(_, line)| *line)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn peek_ident(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Ident(ident)) => Some(ident),
+            _ => None,
+        }
+    }
+
+    fn next(&mut self) -> anyhow::Result<Token> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| anyhow!("Unexpected end of file"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        let found = self.peek() == Some(&Token::Symbol(symbol));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_ident(&mut self, keyword: &str) -> bool {
+        let found = self.peek_ident() == Some(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: char) -> anyhow::Result<()> {
+        match self.next()? {
+            Token::Symbol(c) if c == symbol => Ok(()),
+            token => bail!("Expected '{}', found {:?}", symbol, token),
+        }
+    }
+
+    fn expect_ident(&mut self, keyword: &str) -> anyhow::Result<()> {
+        match self.next()? {
+            Token::Ident(ident) if ident == keyword => Ok(()),
+            token => bail!("Expected '{}', found {:?}", keyword, token),
+        }
+    }
+
+    fn ident(&mut self) -> anyhow::Result<String> {
+        match self.next()? {
+            Token::Ident(ident) => Ok(ident),
+            token => bail!("Expected identifier, found {:?}", token),
+        }
+    }
+
+    fn full_ident(&mut self) -> anyhow::Result<String> {
+        let mut ident = self.ident()?;
+        while self.eat('.') {
+            ident.push('.');
+            ident.push_str(&self.ident()?);
+        }
+        Ok(ident)
+    }
+
+    fn type_name(&mut self) -> anyhow::Result<String> {
+        let absolute = self.eat('.');
+        let name = self.full_ident()?;
+        Ok(if absolute { format!(".{}", name) } else { name })
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        match self.next()? {
+            Token::Str(value) => Ok(value),
+            token => bail!("Expected string, found {:?}", token),
+        }
+    }
+
+    fn int(&mut self) -> anyhow::Result<i64> {
+        let negative = self.eat('-');
+        let value = match self.next()? {
+            Token::Number(number) => parse_int(&number)?,
+            token => bail!("Expected integer, found {:?}", token),
+        };
+        Ok(if negative { -value } else { value })
+    }
+
+    fn field_number(&mut self) -> anyhow::Result<i32> {
+        let number = self.int()?;
+        i32::try_from(number)
+            .ok()
+            .filter(|n| (1..MAX_FIELD_NUMBER).contains(n))
+            .ok_or_else(|| anyhow!("Invalid field number {}", number))
+    }
+
+    /// Option values as text, aggregates are skipped.
+    fn constant(&mut self) -> anyhow::Result<String> {
+        match self.next()? {
+            Token::Str(value) => Ok(value),
+            Token::Number(number) => Ok(number),
+            Token::Symbol(sign @ ('-' | '+')) => match self.next()? {
+                Token::Number(value) | Token::Ident(value) => Ok(format!("{}{}", sign, value)),
+                token => bail!("Expected number, found {:?}", token),
+            },
+            Token::Ident(mut ident) => {
+                while self.eat('.') {
+                    ident.push('.');
+                    ident.push_str(&self.ident()?);
+                }
+                Ok(ident)
+            }
+            Token::Symbol('{') => {
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.next()? {
+                        Token::Symbol('{') => depth += 1,
+                        Token::Symbol('}') => depth -= 1,
+                        _ => {}
+                    }
+                }
+                Ok(String::new())
+            }
+            token => bail!("Expected constant, found {:?}", token),
+        }
+    }
+
+    fn option_name(&mut self) -> anyhow::Result<String> {
+        let mut name = if self.eat('(') {
+            let name = self.type_name()?;
+            self.expect(')')?;
+            format!("({})", name)
+        } else {
+            self.ident()?
+        };
+        while self.eat('.') {
+            name.push('.');
+            name.push_str(&self.ident()?);
+        }
+        Ok(name)
+    }
+
+    /// `option name = value;`, the keyword already consumed.
+    fn option_statement(&mut self) -> anyhow::Result<(String, String)> {
+        let name = self.option_name()?;
+        self.expect('=')?;
+        let value = self.constant()?;
+        self.expect(';')?;
+        Ok((name, value))
+    }
+
+    /// `[name = value, ...]` after fields and enum values.
+    fn compact_options(&mut self) -> anyhow::Result<Vec<(String, String)>> {
+        let mut options = vec![];
+        if !self.eat('[') {
+            return Ok(options);
+        }
+        loop {
+            let name = self.option_name()?;
+            self.expect('=')?;
+            options.push((name, self.constant()?));
+            if !self.eat(',') {
+                break;
+            }
+        }
+        self.expect(']')?;
+        Ok(options)
+    }
+
+    fn file(&mut self, name: &str) -> anyhow::Result<FileDescriptorProto> {
+        let mut file = FileDescriptorProto {
+            name: Some(name.to_string()),
+            ..Default::default()
+        };
+
+        while let Some(token) = self.peek().cloned() {
+            let Token::Ident(keyword) = token else {
+                self.expect(';')?;
+                continue;
+            };
+            self.pos += 1;
+
+            match keyword.as_str() {
+                "syntax" => {
+                    self.expect('=')?;
+                    let syntax = self.string()?;
+                    self.expect(';')?;
+                    match syntax.as_str() {
+                        "proto2" => {}
+                        "proto3" => {
+                            self.proto3 = true;
+                            file.syntax = Some(syntax);
+                        }
+                        _ => bail!("Unsupported syntax {:?}", syntax),
+                    }
+                }
+                "edition" => bail!("Editions are not supported"),
+                "package" => {
+                    file.package = Some(self.full_ident()?);
+                    self.expect(';')?;
+                }
+                "import" => {
+                    let public = self.eat_ident("public");
+                    let weak = !public && self.eat_ident("weak");
+                    let dependency = file.dependency.len() as i32;
+                    file.dependency.push(self.string()?);
+                    if public {
+                        file.public_dependency.push(dependency);
+                    }
+                    if weak {
+                        file.weak_dependency.push(dependency);
+                    }
+                    self.expect(';')?;
+                }
+                "option" => {
+                    self.option_statement()?;
+                }
+                "message" => file.message_type.push(self.message()?),
+                "enum" => file.enum_type.push(self.enumeration()?),
+                "service" => file.service.push(self.service()?),
+                "extend" => file.extension.extend(self.extend()?),
+                _ => bail!("Unexpected '{}'", keyword),
+            }
+        }
+
+        Ok(file)
+    }
+
+    fn message(&mut self) -> anyhow::Result<DescriptorProto> {
+        let mut message = DescriptorProto {
+            name: Some(self.ident()?),
+            ..Default::default()
+        };
+        // proto3 optional fields get a synthetic oneof after the real ones
+        let mut optional_fields = vec![];
+        self.expect('{')?;
+
+        while !self.eat('}') {
+            if self.eat(';') {
+                continue;
+            }
+
+            match self.peek_ident() {
+                Some("message") => {
+                    self.pos += 1;
+                    message.nested_type.push(self.message()?);
+                }
+                Some("enum") => {
+                    self.pos += 1;
+                    message.enum_type.push(self.enumeration()?);
+                }
+                Some("extend") => {
+                    self.pos += 1;
+                    message.extension.extend(self.extend()?);
+                }
+                Some("option") => {
+                    self.pos += 1;
+                    self.option_statement()?;
+                }
+                Some("oneof") => {
+                    self.pos += 1;
+                    self.oneof(&mut message)?;
+                }
+                Some("reserved") => {
+                    self.pos += 1;
+                    if matches!(self.peek(), Some(Token::Str(_))) {
+                        message.reserved_name.extend(self.reserved_names()?);
+                    } else {
+                        for (start, end) in self.ranges(MAX_FIELD_NUMBER - 1)? {
+                            message.reserved_range.push(ReservedRange {
+                                start: Some(start),
+                                end: Some(end + 1),
+                            });
+                        }
+                        self.expect(';')?;
+                    }
+                }
+                Some("extensions") => {
+                    self.pos += 1;
+                    for (start, end) in self.ranges(MAX_FIELD_NUMBER - 1)? {
+                        message.extension_range.push(ExtensionRange {
+                            start: Some(start),
+                            end: Some(end + 1),
+                            options: None,
+                        });
+                    }
+                    self.compact_options()?;
+                    self.expect(';')?;
+                }
+                Some("map")
+                    if self.tokens.get(self.pos + 1).map(|(t, _)| t)
+                        == Some(&Token::Symbol('<')) =>
+                {
+                    self.pos += 1;
+                    self.map_field(&mut message)?;
+                }
+                _ => {
+                    let field = self.field(true)?;
+                    if field.proto3_optional == Some(true) {
+                        optional_fields.push(message.field.len());
+                    }
+                    message.field.push(field);
+                }
+            }
+        }
+
+        for index in optional_fields {
+            let field = &mut message.field[index];
+            field.oneof_index = Some(message.oneof_decl.len() as i32);
+            message.oneof_decl.push(OneofDescriptorProto {
+                name: Some(format!("_{}", field.name())),
+                options: None,
+            });
+        }
+
+        Ok(message)
+    }
+
+    fn field(&mut self, with_label: bool) -> anyhow::Result<FieldDescriptorProto> {
+        let label = match self.peek_ident() {
+            Some("optional") if with_label => Some(Label::Optional),
+            Some("required") if with_label => Some(Label::Required),
+            Some("repeated") if with_label => Some(Label::Repeated),
+            _ => None,
+        };
+        if label.is_some() {
+            self.pos += 1;
+        }
+        if self.peek_ident() == Some("group") {
+            bail!("Groups are not supported");
+        }
+
+        let type_name = self.type_name()?;
+        let name = self.ident()?;
+        self.expect('=')?;
+        let number = self.field_number()?;
+        let options = self.compact_options()?;
+        self.expect(';')?;
+
+        let mut field = FieldDescriptorProto {
+            name: Some(name),
+            number: Some(number),
+            ..Default::default()
+        };
+        field.set_label(label.unwrap_or(Label::Optional));
+        if self.proto3 && label == Some(Label::Optional) {
+            field.proto3_optional = Some(true);
+        }
+        match scalar_type(&type_name) {
+            Some(ty) => field.set_type(ty),
+            None => field.type_name = Some(type_name),
+        }
+
+        for (name, value) in options {
+            match name.as_str() {
+                "default" => field.default_value = Some(value),
+                "json_name" => field.json_name = Some(value),
+                "packed" => {
+                    field
+                        .options
+                        .get_or_insert_with(FieldOptions::default)
+                        .packed = Some(value == "true")
+                }
+                "deprecated" => {
+                    field
+                        .options
+                        .get_or_insert_with(FieldOptions::default)
+                        .deprecated = Some(value == "true")
+                }
+                _ => {}
+            }
+        }
+
+        Ok(field)
+    }
+
+    fn map_field(&mut self, message: &mut DescriptorProto) -> anyhow::Result<()> {
+        self.expect('<')?;
+        let key_type = self.type_name()?;
+        self.expect(',')?;
+        let value_type = self.type_name()?;
+        self.expect('>')?;
+        let name = self.ident()?;
+        self.expect('=')?;
+        let number = self.field_number()?;
+        self.compact_options()?;
+        self.expect(';')?;
+
+        let entry_field = |name: &str, number: i32, type_name: String| {
+            let mut field = FieldDescriptorProto {
+                name: Some(name.to_string()),
+                number: Some(number),
+                ..Default::default()
+            };
+            field.set_label(Label::Optional);
+            match scalar_type(&type_name) {
+                Some(ty) => field.set_type(ty),
+                None => field.type_name = Some(type_name),
+            }
+            field
+        };
+
+        let entry_name = map_entry_name(&name);
+        message.nested_type.push(DescriptorProto {
+            name: Some(entry_name.clone()),
+            field: vec![
+                entry_field("key", 1, key_type),
+                entry_field("value", 2, value_type),
+            ],
+            options: Some(MessageOptions {
+                map_entry: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+
+        let mut field = FieldDescriptorProto {
+            name: Some(name),
+            number: Some(number),
+            type_name: Some(entry_name),
+            ..Default::default()
+        };
+        field.set_label(Label::Repeated);
+        field.set_type(Type::Message);
+        message.field.push(field);
+
+        Ok(())
+    }
+
+    fn oneof(&mut self, message: &mut DescriptorProto) -> anyhow::Result<()> {
+        let index = message.oneof_decl.len() as i32;
+        message.oneof_decl.push(OneofDescriptorProto {
+            name: Some(self.ident()?),
+            options: None,
+        });
+        self.expect('{')?;
+
+        while !self.eat('}') {
+            if self.eat(';') {
+                continue;
+            }
+            if self.eat_ident("option") {
+                self.option_statement()?;
+                continue;
+            }
+
+            let mut field = self.field(false)?;
+            field.oneof_index = Some(index);
+            message.field.push(field);
+        }
+
+        Ok(())
+    }
+
+    fn enumeration(&mut self) -> anyhow::Result<EnumDescriptorProto> {
+        let mut enumeration = EnumDescriptorProto {
+            name: Some(self.ident()?),
+            ..Default::default()
+        };
+        self.expect('{')?;
+
+        while !self.eat('}') {
+            if self.eat(';') {
+                continue;
+            }
+
+            if self.eat_ident("option") {
+                let (name, value) = self.option_statement()?;
+                if name == "allow_alias" {
+                    enumeration.options = Some(EnumOptions {
+                        allow_alias: Some(value == "true"),
+                        ..Default::default()
+                    });
+                }
+            } else if self.eat_ident("reserved") {
+                if matches!(self.peek(), Some(Token::Str(_))) {
+                    enumeration.reserved_name.extend(self.reserved_names()?);
+                } else {
+                    for (start, end) in self.ranges(i32::MAX)? {
+                        enumeration.reserved_range.push(EnumReservedRange {
+                            start: Some(start),
+                            end: Some(end),
+                        });
+                    }
+                    self.expect(';')?;
+                }
+            } else {
+                let name = self.ident()?;
+                self.expect('=')?;
+                let number = self.int()?;
+                self.compact_options()?;
+                self.expect(';')?;
+
+                enumeration.value.push(EnumValueDescriptorProto {
+                    name: Some(name),
+                    number: Some(
+                        i32::try_from(number)
+                            .map_err(|_| anyhow!("Invalid enum value {}", number))?,
+                    ),
+                    options: None,
+                });
+            }
+        }
+
+        Ok(enumeration)
+    }
+
+    fn service(&mut self) -> anyhow::Result<ServiceDescriptorProto> {
+        let mut service = ServiceDescriptorProto {
+            name: Some(self.ident()?),
+            ..Default::default()
+        };
+        self.expect('{')?;
+
+        while !self.eat('}') {
+            if self.eat(';') {
+                continue;
+            }
+            if self.eat_ident("option") {
+                self.option_statement()?;
+                continue;
+            }
+
+            self.expect_ident("rpc")?;
+            let name = self.ident()?;
+            let (client_streaming, input_type) = self.rpc_type()?;
+            self.expect_ident("returns")?;
+            let (server_streaming, output_type) = self.rpc_type()?;
+
+            if self.eat('{') {
+                while !self.eat('}') {
+                    if !self.eat(';') {
+                        self.expect_ident("option")?;
+                        self.option_statement()?;
+                    }
+                }
+            } else {
+                self.expect(';')?;
+            }
+
+            service.method.push(MethodDescriptorProto {
+                name: Some(name),
+                input_type: Some(input_type),
+                output_type: Some(output_type),
+                options: None,
+                client_streaming: Some(client_streaming),
+                server_streaming: Some(server_streaming),
+            });
+        }
+
+        Ok(service)
+    }
+
+    fn rpc_type(&mut self) -> anyhow::Result<(bool, String)> {
+        self.expect('(')?;
+        // `stream` is only a keyword when a type name follows
+        let streaming = self.peek_ident() == Some("stream")
+            && matches!(
+                self.tokens.get(self.pos + 1),
+                Some((Token::Ident(_) | Token::Symbol('.'), _))
+            );
+        if streaming {
+            self.pos += 1;
+        }
+        let type_name = self.type_name()?;
+        self.expect(')')?;
+        Ok((streaming, type_name))
+    }
+
+    fn extend(&mut self) -> anyhow::Result<Vec<FieldDescriptorProto>> {
+        let extendee = self.type_name()?;
+        let mut fields = vec![];
+        self.expect('{')?;
+
+        while !self.eat('}') {
+            if self.eat(';') {
+                continue;
+            }
+            let mut field = self.field(true)?;
+            field.extendee = Some(extendee.clone());
+            // extensions are never part of a synthetic oneof
+            field.proto3_optional = None;
+            fields.push(field);
+        }
+
+        Ok(fields)
+    }
+
+    fn ranges(&mut self, max: i32) -> anyhow::Result<Vec<(i32, i32)>> {
+        let mut ranges = vec![];
+        loop {
+            let start = self.range_bound(max)?;
+            let end = if self.eat_ident("to") {
+                if self.eat_ident("max") {
+                    max
+                } else {
+                    self.range_bound(max)?
+                }
+            } else {
+                start
+            };
+            ranges.push((start, end));
+
+            if !self.eat(',') {
+                return Ok(ranges);
+            }
+        }
+    }
+
+    fn range_bound(&mut self, max: i32) -> anyhow::Result<i32> {
+        let value = self.int()?;
+        i32::try_from(value)
+            .ok()
+            .filter(|v| *v <= max)
+            .ok_or_else(|| anyhow!("Invalid range bound {}", value))
+    }
+
+    fn reserved_names(&mut self) -> anyhow::Result<Vec<String>> {
+        let mut names = vec![self.string()?];
+        while self.eat(',') {
+            names.push(self.string()?);
+        }
+        self.expect(';')?;
+        Ok(names)
+    }
+}
+
+fn parse_int(number: &str) -> anyhow::Result<i64> {
+    let value = if let Some(hex) = number
+        .strip_prefix("0x")
+        .or_else(|| number.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16)
+    } else if number.len() > 1 && number.starts_with('0') {
+        u64::from_str_radix(&number[1..], 8)
+    } else {
+        number.parse()
+    }
+    .map_err(|_| anyhow!("Invalid integer {:?}", number))?;
+
+    i64::try_from(value).map_err(|_| anyhow!("Integer {} out of range", number))
+}
@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+
+use prost::encoding;
+use serde_json::json;
+
+use super::{parse_proto, GrpcSchemas};
+
+const SHOP_PROTO: &str = r#"
+syntax = "proto3";
+
+package shop.v1;
+
+import "google/protobuf/timestamp.proto";
+
+option go_package = "example.com/shop;shop";
+
+/* Orders and
+   what is in them. */
+message Order {
+  enum Status {
+    option allow_alias = true;
+    STATUS_UNSPECIFIED = 0;
+    PENDING = 1;
+    WAITING = 1;
+    SHIPPED = 2 [deprecated = true];
+    reserved 10 to max;
+  }
+
+  string id = 1;
+  repeated Item items = 2 [packed = false];
+  map<string, int64> totals = 3;
+  Status status = 4;
+  optional string note = 5;
+  oneof payment {
+    string card = 6;
+    string voucher = 7;
+  }
+  google.protobuf.Timestamp created_at = 8 [json_name = "created"];
+  reserved 9, 20 to 30;
+  reserved "legacy";
+}
+
+message Item {
+  string sku = 1;
+  uint32 quantity = 2;
+}
+
+message GetOrderRequest { string id = 1; }
+
+service Orders {
+  rpc GetOrder(GetOrderRequest) returns (Order);
+  rpc Watch(GetOrderRequest) returns (stream .shop.v1.Order) {
+    option deprecated = false;
+  }
+}
+"#;
+
+#[test]
+fn test_parse_proto() {
+    let file = parse_proto("shop.proto", SHOP_PROTO).unwrap();
+
+    assert_eq!(file.package(), "shop.v1");
+    assert_eq!(file.dependency, vec!["google/protobuf/timestamp.proto"]);
+
+    let order = &file.message_type[0];
+    assert_eq!(order.nested_type[0].name(), "TotalsEntry");
+    assert_eq!(
+        order
+            .oneof_decl
+            .iter()
+            .map(|o| o.name())
+            .collect::<Vec<_>>(),
+        vec!["payment", "_note"]
+    );
+    assert_eq!(order.reserved_range.len(), 2);
+    assert_eq!(order.reserved_name, vec!["legacy"]);
+
+    let methods = &file.service[0].method;
+    assert!(!methods[0].server_streaming());
+    assert!(methods[1].server_streaming());
+    assert_eq!(methods[1].output_type(), ".shop.v1.Order");
+}
+
+#[test]
+fn test_decode_with_schema() {
+    let schemas = GrpcSchemas::new();
+    schemas
+        .add_files(vec![parse_proto("shop.proto", SHOP_PROTO).unwrap()])
+        .unwrap();
+    assert!(schemas.services().contains(&"shop.v1.Orders".to_string()));
+
+    let mut item = vec![];
+    encoding::string::encode(1, &"A-1".to_string(), &mut item);
+    encoding::uint32::encode(2, &3, &mut item);
+
+    let mut order = vec![];
+    encoding::string::encode(1, &"o-7".to_string(), &mut order);
+    encoding::bytes::encode(2, &item, &mut order);
+    encoding::int32::encode(4, &2, &mut order);
+    encoding::string::encode(7, &"SPRING".to_string(), &mut order);
+
+    let decoded = schemas
+        .decode("/shop.v1.Orders/GetOrder", true, &order)
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        decoded,
+        json!({
+            "id": "o-7",
+            "items": [{"sku": "A-1", "quantity": 3}],
+            "status": "SHIPPED",
+            "voucher": "SPRING",
+        })
+    );
+
+    assert!(schemas
+        .decode("/shop.v1.Orders/Missing", true, &order)
+        .is_none());
+    assert!(schemas
+        .decode("/shop.v1.Orders/GetOrder", false, &[0x0a, 0x05])
+        .unwrap()
+        .is_err());
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = parse_proto(
+        "bad.proto",
+        "syntax = \"proto3\";\nmessage A {\n  string a = ;\n}",
+    )
+    .unwrap_err();
+    assert!(error.to_string().starts_with("bad.proto:3:"), "{}", error);
+
+    assert!(parse_proto("bad.proto", "message A { string a = 0; }").is_err());
+    assert!(parse_proto("bad.proto", "message A { string a = 1 }").is_err());
+}
+
+#[tokio::test]
+async fn test_load_proto_files() {
+    let dir = std::env::temp_dir().join(format!("devya-proto-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("shop")).unwrap();
+    std::fs::write(
+        dir.join("shop/item.proto"),
+        "syntax = \"proto3\";\npackage shop;\nmessage Item { string sku = 1; }\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("cart.proto"),
+        "syntax = \"proto3\";\nimport \"shop/item.proto\";\nservice Cart { rpc Add(shop.Item) returns (shop.Item); }\n",
+    )
+    .unwrap();
+
+    let schemas = GrpcSchemas::new();
+    let result = schemas
+        .load_proto_files(&[dir.join("cart.proto")], &[PathBuf::from(&dir)])
+        .await;
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    result.unwrap();
+    assert!(schemas.services().contains(&"Cart".to_string()));
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::Bytes,
+    header::{CONTENT_TYPE, TE},
+    HeaderMap, Method, Request, Uri,
+};
+use hyper_rustls::ConfigBuilderExt;
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+use prost::{encoding, Message};
+use prost_types::FileDescriptorProto;
+use rustls::ClientConfig;
+use tokio::time::timeout;
+use tracing::debug;
+
+use super::{encode_frame, parse_frames, read_fields, WireValue};
+use crate::util::percent_decode;
+
+const REFLECTION_SERVICES: [&str; 2] = [
+    "grpc.reflection.v1.ServerReflection",
+    "grpc.reflection.v1alpha.ServerReflection",
+];
+/// Covers connecting and every reflection round, a server that never
+/// answers would otherwise keep the command waiting.
+pub const REFLECTION_TIMEOUT: Duration = Duration::from_secs(15);
+/// Rounds of fetching dependencies the server did not send along.
+const MAX_DEPENDENCY_ROUNDS: usize = 16;
+
+type ReflectionClient = Client<
+    hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>,
+    Full<Bytes>,
+>;
+
+/// Fetches the descriptors of every service `endpoint` exposes through
+/// server reflection, e.g. `http://localhost:50051`. Gives up after
+/// [`REFLECTION_TIMEOUT`].
+pub async fn fetch_descriptors(endpoint: &Uri) -> anyhow::Result<Vec<FileDescriptorProto>> {
+    timeout(REFLECTION_TIMEOUT, fetch_all(endpoint))
+        .await
+        .map_err(|_| anyhow!("Server reflection timed out"))?
+}
+
+async fn fetch_all(endpoint: &Uri) -> anyhow::Result<Vec<FileDescriptorProto>> {
+    let client = make_client();
+
+    let mut error = anyhow!("Server reflection is not available");
+    for service in REFLECTION_SERVICES {
+        match fetch_with(&client, endpoint, service).await {
+            Ok(files) => return Ok(files),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+fn make_client() -> ReflectionClient {
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
+    let client_config = ClientConfig::builder()
+        .with_webpki_roots()
+        .with_no_client_auth();
+    let https = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(client_config)
+        .https_or_http()
+        .enable_http2()
+        .build();
+
+    Client::builder(TokioExecutor::new())
+        .http2_only(true)
+        .build(https)
+}
+
+async fn fetch_with(
+    client: &ReflectionClient,
+    endpoint: &Uri,
+    service: &str,
+) -> anyhow::Result<Vec<FileDescriptorProto>> {
+    let mut services = vec![];
+    for response in call(client, endpoint, service, vec![request(7, "")]).await? {
+        if let ReflectionResponse::Services(names) = parse_response(&response)? {
+            services.extend(names);
+        }
+    }
+
+    let mut files: HashMap<String, FileDescriptorProto> = HashMap::new();
+    let mut requested = HashSet::new();
+    let mut requests: Vec<_> = services
+        .iter()
+        .filter(|s| !REFLECTION_SERVICES.contains(&s.as_str()))
+        .map(|s| request(4, s))
+        .collect();
+
+    for _ in 0..MAX_DEPENDENCY_ROUNDS {
+        if requests.is_empty() {
+            break;
+        }
+
+        for response in call(client, endpoint, service, requests).await? {
+            match parse_response(&response)? {
+                ReflectionResponse::Files(descriptors) => {
+                    for descriptor in descriptors {
+                        let file = FileDescriptorProto::decode(descriptor)
+                            .context("Invalid file descriptor")?;
+                        files.entry(file.name().to_string()).or_insert(file);
+                    }
+                }
+                // a missing dependency may still be known, e.g. well-known types
+                ReflectionResponse::Error(message) => debug!("Reflection error: {}", message),
+                ReflectionResponse::Services(_) => {}
+            }
+        }
+
+        let missing: HashSet<String> = files
+            .values()
+            .flat_map(|f| f.dependency.iter())
+            .filter(|d| !files.contains_key(*d) && !requested.contains(*d))
+            .cloned()
+            .collect();
+        requests = missing.iter().map(|name| request(3, name)).collect();
+        requested.extend(missing);
+    }
+
+    Ok(files.into_values().collect())
+}
+
+/// A `ServerReflectionRequest` with a single string field set.
+fn request(field: u32, value: &str) -> Vec<u8> {
+    let mut buf = vec![];
+    encoding::string::encode(field, &value.to_string(), &mut buf);
+    buf
+}
+
+enum ReflectionResponse<'a> {
+    Files(Vec<&'a [u8]>),
+    Services(Vec<String>),
+    Error(String),
+}
+
+fn parse_response(data: &[u8]) -> anyhow::Result<ReflectionResponse<'_>> {
+    let fields = read_fields(data).ok_or_else(|| anyhow!("Invalid reflection response"))?;
+
+    for (number, value) in &fields {
+        let Some(inner) = nested(value) else {
+            continue;
+        };
+
+        match number {
+            4 => {
+                return Ok(ReflectionResponse::Files(
+                    inner
+                        .into_iter()
+                        .filter_map(|(n, v)| match (n, v) {
+                            (1, WireValue::Bytes(bytes)) => Some(bytes),
+                            _ => None,
+                        })
+                        .collect(),
+                ))
+            }
+            6 => {
+                return Ok(ReflectionResponse::Services(
+                    inner
+                        .iter()
+                        .filter(|(n, _)| *n == 1)
+                        .filter_map(|(_, v)| nested(v))
+                        .flat_map(|service| service.into_iter())
+                        .filter_map(|(n, v)| match (n, v) {
+                            (1, WireValue::Bytes(name)) => {
+                                Some(String::from_utf8_lossy(name).into_owned())
+                            }
+                            _ => None,
+                        })
+                        .collect(),
+                ))
+            }
+            7 => {
+                let message = inner.iter().find_map(|(n, v)| match (n, v) {
+                    (2, WireValue::Bytes(message)) => {
+                        Some(String::from_utf8_lossy(message).into_owned())
+                    }
+                    _ => None,
+                });
+                return Ok(ReflectionResponse::Error(message.unwrap_or_default()));
+            }
+            _ => {}
+        }
+    }
+
+    bail!("Unexpected reflection response")
+}
+
+fn nested<'a>(value: &WireValue<'a>) -> Option<Vec<(u32, WireValue<'a>)>> {
+    match value {
+        WireValue::Bytes(bytes) => read_fields(bytes),
+        _ => None,
+    }
+}
+
+/// One streaming call carrying all `messages`, returns the response messages.
+async fn call(
+    client: &ReflectionClient,
+    endpoint: &Uri,
+    service: &str,
+    messages: Vec<Vec<u8>>,
+) -> anyhow::Result<Vec<Vec<u8>>> {
+    let uri: Uri = format!(
+        "{}/{}/ServerReflectionInfo",
+        endpoint.to_string().trim_end_matches('/'),
+        service
+    )
+    .parse()?;
+    let body: Vec<u8> = messages.iter().flat_map(|m| encode_frame(m)).collect();
+
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header(CONTENT_TYPE, "application/grpc")
+        .header(TE, "trailers")
+        .body(Full::new(Bytes::from(body)))?;
+
+    let res = client.request(req).await?;
+    let headers = res.headers().clone();
+    let collected = res.into_body().collect().await?;
+    let trailers = collected.trailers().cloned().unwrap_or_default();
+
+    check_status(&trailers).and_then(|_| check_status(&headers))?;
+
+    let (frames, _) = parse_frames(&collected.to_bytes());
+    Ok(frames.into_iter().map(|f| f.data).collect())
+}
+
+fn check_status(headers: &HeaderMap) -> anyhow::Result<()> {
+    let status = headers
+        .get("grpc-status")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("0");
+    if status == "0" {
+        return Ok(());
+    }
+
+    let message = headers
+        .get("grpc-message")
+        .and_then(|v| v.to_str().ok())
+        .map(percent_decode)
+        .unwrap_or_default();
+    bail!("Reflection failed with grpc-status {}: {}", status, message)
+}
@@ -0,0 +1,200 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use http_body_util::BodyExt;
+use hyper::{body::Incoming, service::service_fn, HeaderMap, Request, Response};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use prost::{encoding, Message};
+use tokio::{io::AsyncReadExt, net::TcpListener, time::Instant};
+
+use super::{
+    decode_call, encode_frame, fetch_descriptors, parse_frames, parse_proto, read_fields,
+    GrpcSchemas, WireValue, REFLECTION_TIMEOUT,
+};
+use crate::mitm::{full_body, Flow};
+
+const TYPES_PROTO: &str = r#"
+syntax = "proto3";
+package echo.v1;
+import "google/protobuf/timestamp.proto";
+
+message Note {
+  string text = 1;
+  google.protobuf.Timestamp at = 2;
+}
+"#;
+
+const ECHO_PROTO: &str = r#"
+syntax = "proto3";
+package echo.v1;
+import "types.proto";
+
+service Echo {
+  rpc Say(Note) returns (Note);
+}
+"#;
+
+/// The reflection requests a server got, as field number and value, and
+/// the paths of calls it didn't implement as field 0.
+type Requests = Arc<Mutex<Vec<(u32, String)>>>;
+
+fn nested(field: u32, inner: &[u8], buf: &mut Vec<u8>) {
+    encoding::bytes::encode(field, &inner.to_vec(), buf);
+}
+
+/// The answer of a v1alpha server to one `ServerReflectionRequest`. Only
+/// `types.proto` has to be asked for by name, the well-known types are
+/// reported missing.
+fn answer(field: u32, value: &str) -> Vec<u8> {
+    let file = |name: &str, source: &str| {
+        let mut files = vec![];
+        nested(
+            1,
+            &parse_proto(name, source).unwrap().encode_to_vec(),
+            &mut files,
+        );
+        let mut response = vec![];
+        nested(4, &files, &mut response);
+        response
+    };
+
+    match (field, value) {
+        (7, _) => {
+            let mut list = vec![];
+            for name in ["echo.v1.Echo", "grpc.reflection.v1alpha.ServerReflection"] {
+                let mut service = vec![];
+                encoding::string::encode(1, &name.to_string(), &mut service);
+                nested(1, &service, &mut list);
+            }
+            let mut response = vec![];
+            nested(6, &list, &mut response);
+            response
+        }
+        (4, "echo.v1.Echo") => file("echo.proto", ECHO_PROTO),
+        (3, "types.proto") => file("types.proto", TYPES_PROTO),
+        _ => {
+            let mut error = vec![];
+            encoding::int32::encode(1, &5, &mut error);
+            encoding::string::encode(2, &"not found".to_string(), &mut error);
+            let mut response = vec![];
+            nested(7, &error, &mut response);
+            response
+        }
+    }
+}
+
+/// Answers v1 reflection as unimplemented and v1alpha from [`answer`].
+async fn serve_reflection(listener: TcpListener, requests: Requests) {
+    loop {
+        let (stream, _) = listener.accept().await.unwrap();
+        let requests = requests.clone();
+        let service = service_fn(move |req: Request<Incoming>| {
+            let requests = requests.clone();
+            async move {
+                let path = req.uri().path().to_string();
+                let body = req.into_body().collect().await?.to_bytes();
+                let mut trailers = HeaderMap::new();
+
+                let mut reply = vec![];
+                if path == "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo" {
+                    let (frames, _) = parse_frames(&body);
+                    for frame in frames {
+                        let fields = read_fields(&frame.data).unwrap();
+                        let [(field, WireValue::Bytes(value))] = &fields[..] else {
+                            panic!("expected a single string field");
+                        };
+                        let value = String::from_utf8(value.to_vec()).unwrap();
+                        reply.extend(encode_frame(&answer(*field, &value)));
+                        requests.lock().unwrap().push((*field, value));
+                    }
+                    trailers.insert("grpc-status", "0".parse().unwrap());
+                } else {
+                    requests.lock().unwrap().push((0, path));
+                    trailers.insert("grpc-status", "12".parse().unwrap());
+                }
+
+                let body = full_body(reply).with_trailers(async move { Some(Ok(trailers)) });
+                let res = Response::builder()
+                    .header("content-type", "application/grpc")
+                    .body(body)
+                    .unwrap();
+                Ok::<_, hyper::Error>(res)
+            }
+        });
+        tokio::spawn(async move {
+            let _ = hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
+
+#[tokio::test]
+async fn test_fetch_descriptors() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    let requests = Requests::default();
+    tokio::spawn(serve_reflection(listener, requests.clone()));
+
+    let files = fetch_descriptors(&endpoint).await.unwrap();
+    let mut names: Vec<_> = files.iter().map(|f| f.name().to_string()).collect();
+    names.sort();
+    assert_eq!(names, ["echo.proto", "types.proto"]);
+
+    // v1 first, then one round per level of dependencies; the reflection
+    // service itself isn't asked for
+    assert_eq!(
+        *requests.lock().unwrap(),
+        [
+            (
+                0,
+                "/grpc.reflection.v1.ServerReflection/ServerReflectionInfo".to_string()
+            ),
+            (7, String::new()),
+            (4, "echo.v1.Echo".to_string()),
+            (3, "types.proto".to_string()),
+            (3, "google/protobuf/timestamp.proto".to_string()),
+        ]
+    );
+
+    let schemas = GrpcSchemas::new();
+    schemas.add_files(files).unwrap();
+    assert!(schemas.services().contains(&"echo.v1.Echo".to_string()));
+
+    let mut note = vec![];
+    encoding::string::encode(1, &"hello".to_string(), &mut note);
+    let flow = Flow::test("POST", "http://localhost:50051/echo.v1.Echo/Say")
+        .with_request_headers(&[("content-type", "application/grpc")])
+        .with_request_body(encode_frame(&note));
+
+    let call = decode_call(&flow, &schemas).unwrap();
+    assert!(call.requests[0].schema);
+    assert_eq!(
+        call.requests[0].json,
+        Some(serde_json::json!({"text": "hello"}))
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_fetch_timeout() {
+    // accepts the connection but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = vec![0; 1024];
+        while stream.read(&mut buf).await.is_ok_and(|n| n > 0) {}
+    });
+
+    let start = Instant::now();
+    let error = fetch_descriptors(&endpoint).await.unwrap_err();
+    assert!(error.to_string().contains("timed out"), "{}", error);
+    assert!(start.elapsed() >= REFLECTION_TIMEOUT);
+    assert!(start.elapsed() < REFLECTION_TIMEOUT + Duration::from_secs(1));
+}
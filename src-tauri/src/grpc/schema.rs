@@ -0,0 +1,155 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use anyhow::{anyhow, Context};
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage};
+use prost_types::{FileDescriptorProto, FileDescriptorSet};
+use serde_json::Value;
+
+use super::parse_proto;
+
+/// Message types known from descriptor sets, `.proto` files and server
+/// reflection. Starts out with the well-known types.
+pub struct GrpcSchemas {
+    pool: RwLock<DescriptorPool>,
+}
+
+impl Default for GrpcSchemas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GrpcSchemas {
+    pub fn new() -> Self {
+        Self {
+            pool: RwLock::new(DescriptorPool::global()),
+        }
+    }
+
+    pub fn services(&self) -> Vec<String> {
+        self.pool
+            .read()
+            .unwrap()
+            .services()
+            .map(|s| s.full_name().to_string())
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        *self.pool.write().unwrap() = DescriptorPool::global();
+    }
+
+    /// Adds files that are not known yet, nothing is added if any fails.
+    pub fn add_files(&self, files: Vec<FileDescriptorProto>) -> anyhow::Result<()> {
+        let mut pool = self.pool.read().unwrap().clone();
+
+        let files: Vec<_> = files
+            .into_iter()
+            .filter(|f| pool.get_file_by_name(f.name()).is_none())
+            .collect();
+        pool.add_file_descriptor_protos(files)?;
+
+        *self.pool.write().unwrap() = pool;
+        Ok(())
+    }
+
+    pub fn load_descriptor_set(&self, data: &[u8]) -> anyhow::Result<()> {
+        let set = FileDescriptorSet::decode(data).context("Invalid descriptor set")?;
+        self.add_files(set.file)
+    }
+
+    /// Parses `paths` along with their imports, looked up in `include_dirs`
+    /// or next to the files when none are given.
+    pub async fn load_proto_files(
+        &self,
+        paths: &[PathBuf],
+        include_dirs: &[PathBuf],
+    ) -> anyhow::Result<()> {
+        let include_dirs: Vec<PathBuf> = match include_dirs.is_empty() {
+            false => include_dirs.to_vec(),
+            true => paths
+                .iter()
+                .filter_map(|p| p.parent().map(Path::to_path_buf))
+                .collect(),
+        };
+
+        let mut files = vec![];
+        let mut seen = HashSet::new();
+        let mut pending: Vec<(String, PathBuf)> = paths
+            .iter()
+            .map(|path| (proto_name(path, &include_dirs), path.clone()))
+            .collect();
+
+        while let Some((name, path)) = pending.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+
+            let source = tokio::fs::read_to_string(&path)
+                .await
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let file = parse_proto(&name, &source)?;
+
+            for dependency in &file.dependency {
+                if seen.contains(dependency) || self.has_file(dependency) {
+                    continue;
+                }
+                let path = include_dirs
+                    .iter()
+                    .map(|dir| dir.join(dependency))
+                    .find(|path| path.is_file())
+                    .ok_or_else(|| anyhow!("Import {:?} not found in {}", dependency, name))?;
+                pending.push((dependency.clone(), path));
+            }
+            files.push(file);
+        }
+
+        self.add_files(files)
+    }
+
+    fn has_file(&self, name: &str) -> bool {
+        self.pool.read().unwrap().get_file_by_name(name).is_some()
+    }
+
+    /// Decodes a message of the method at `path` (`/package.Service/Method`),
+    /// `None` when the method is unknown.
+    pub fn decode(&self, path: &str, response: bool, data: &[u8]) -> Option<anyhow::Result<Value>> {
+        let (service, method) = path.trim_start_matches('/').split_once('/')?;
+
+        let pool = self.pool.read().unwrap();
+        let method = pool
+            .get_service_by_name(service)?
+            .methods()
+            .find(|m| m.name() == method)?;
+        let descriptor = match response {
+            true => method.output(),
+            false => method.input(),
+        };
+
+        Some(
+            DynamicMessage::decode(descriptor, data)
+                .map_err(anyhow::Error::from)
+                .and_then(|message| Ok(serde_json::to_value(&message)?)),
+        )
+    }
+}
+
+/// The import name of `path`, relative to the include dir containing it.
+fn proto_name(path: &Path, include_dirs: &[PathBuf]) -> String {
+    let relative = include_dirs
+        .iter()
+        .find_map(|dir| path.strip_prefix(dir).ok())
+        .or_else(|| path.file_name().map(Path::new))
+        .unwrap_or(path);
+
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,123 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{Map, Value};
+
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Group(Vec<(u32, WireValue<'a>)>),
+    Fixed32(u32),
+}
+
+/// Splits an encoded message into its fields, `None` if it is malformed.
+pub fn read_fields(buf: &[u8]) -> Option<Vec<(u32, WireValue<'_>)>> {
+    let mut pos = 0;
+    let fields = read_until(buf, &mut pos, None, 0)?;
+    (pos == buf.len()).then_some(fields)
+}
+
+fn read_until<'a>(
+    buf: &'a [u8],
+    pos: &mut usize,
+    group: Option<u32>,
+    depth: usize,
+) -> Option<Vec<(u32, WireValue<'a>)>> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+
+    let mut fields = vec![];
+    while *pos < buf.len() {
+        let key = read_varint(buf, pos)?;
+        let number = u32::try_from(key >> 3).ok().filter(|n| *n > 0)?;
+
+        let value = match key & 0x7 {
+            0 => WireValue::Varint(read_varint(buf, pos)?),
+            1 => WireValue::Fixed64(u64::from_le_bytes(take(buf, pos, 8)?.try_into().ok()?)),
+            2 => {
+                let len = usize::try_from(read_varint(buf, pos)?).ok()?;
+                WireValue::Bytes(take(buf, pos, len)?)
+            }
+            3 => WireValue::Group(read_until(buf, pos, Some(number), depth + 1)?),
+            4 if group == Some(number) => return Some(fields),
+            5 => WireValue::Fixed32(u32::from_le_bytes(take(buf, pos, 4)?.try_into().ok()?)),
+            _ => return None,
+        };
+        fields.push((number, value));
+    }
+
+    // a group has to be closed by its end marker
+    group.is_none().then_some(fields)
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos)?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn take<'a>(buf: &'a [u8], pos: &mut usize, len: usize) -> Option<&'a [u8]> {
+    let end = pos.checked_add(len)?;
+    let slice = buf.get(*pos..end)?;
+    *pos = end;
+    Some(slice)
+}
+
+/// Best effort JSON view of a message without its schema, keyed by field
+/// number like `protoc --decode_raw`. Length-delimited fields show up as
+/// text, nested messages or base64, whichever fits first.
+pub fn decode_raw(buf: &[u8]) -> Option<Value> {
+    read_fields(buf).map(|fields| fields_to_json(fields, 0))
+}
+
+fn fields_to_json(fields: Vec<(u32, WireValue<'_>)>, depth: usize) -> Value {
+    let mut object = Map::new();
+
+    for (number, value) in fields {
+        let value = match value {
+            WireValue::Varint(v) | WireValue::Fixed64(v) => Value::from(v),
+            WireValue::Fixed32(v) => Value::from(v),
+            WireValue::Group(fields) => fields_to_json(fields, depth + 1),
+            WireValue::Bytes(bytes) => bytes_to_json(bytes, depth),
+        };
+
+        match object.get_mut(&number.to_string()) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+            None => {
+                object.insert(number.to_string(), value);
+            }
+        }
+    }
+
+    Value::Object(object)
+}
+
+fn bytes_to_json(bytes: &[u8], depth: usize) -> Value {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        if text
+            .chars()
+            .all(|c| !c.is_control() || c.is_ascii_whitespace())
+        {
+            return Value::from(text);
+        }
+    }
+
+    if depth < MAX_DEPTH {
+        if let Some(fields) = read_fields(bytes) {
+            return fields_to_json(fields, depth + 1);
+        }
+    }
+
+    Value::from(STANDARD.encode(bytes))
+}
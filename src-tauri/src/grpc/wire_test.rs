@@ -0,0 +1,44 @@
+use serde_json::json;
+
+use super::{decode_raw, read_fields, WireValue};
+
+#[test]
+fn test_decode_raw() {
+    // 1: 150, 2: "testing", 3: {1: 1}, 3: {1: 2}, 4: fixed32 1, 5: bytes ff
+    let data = [
+        0x08, 0x96, 0x01, 0x12, 0x07, b't', b'e', b's', b't', b'i', b'n', b'g', 0x1a, 0x02, 0x08,
+        0x01, 0x1a, 0x02, 0x08, 0x02, 0x25, 0x01, 0x00, 0x00, 0x00, 0x2a, 0x01, 0xff,
+    ];
+
+    assert_eq!(
+        decode_raw(&data).unwrap(),
+        json!({
+            "1": 150,
+            "2": "testing",
+            "3": [{"1": 1}, {"1": 2}],
+            "4": 1,
+            "5": "/w==",
+        })
+    );
+}
+
+#[test]
+fn test_groups() {
+    // group 1 { 2: 5 }
+    let data = [0x0b, 0x10, 0x05, 0x0c];
+    assert_eq!(
+        read_fields(&data).unwrap(),
+        vec![(1, WireValue::Group(vec![(2, WireValue::Varint(5))]))]
+    );
+
+    // unterminated group
+    assert!(read_fields(&data[..3]).is_none());
+}
+
+#[test]
+fn test_malformed() {
+    assert!(decode_raw(&[0x12, 0x05, b'a']).is_none());
+    assert!(decode_raw(&[0x08, 0xff]).is_none());
+    assert!(decode_raw(&[0x07]).is_none());
+    assert_eq!(decode_raw(&[]).unwrap(), json!({}));
+}
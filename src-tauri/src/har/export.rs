@@ -0,0 +1,325 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hyper::StatusCode;
+
+use super::{
+    format_iso8601, parse_http_date, Har, HarCache, HarContent, HarCookie, HarCreator, HarEntry,
+    HarLog, HarNameValue, HarParam, HarPostData, HarRequest, HarResponse, HarTimings,
+    HarWebSocketMessage, HAR_VERSION,
+};
+use crate::mitm::{BodyCapture, Flow, FlowKind, WsDirection, WsMessage, WsMessageKind};
+use crate::util::form_decode;
+
+/// Tunnels carry no HTTP exchange and are left out.
+pub fn export_har(flows: &[Flow]) -> Har {
+    Har {
+        log: HarLog {
+            version: HAR_VERSION.to_string(),
+            creator: HarCreator {
+                name: "devya".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            browser: None,
+            entries: flows.iter().filter_map(har_entry).collect(),
+            comment: None,
+        },
+    }
+}
+
+pub fn har_entry(flow: &Flow) -> Option<HarEntry> {
+    if flow.kind == FlowKind::Tunnel {
+        return None;
+    }
+
+    let timings = timings(flow);
+    Some(HarEntry {
+        pageref: None,
+        started_date_time: format_iso8601(flow.request.timestamp),
+        time: timings.send + timings.wait + timings.receive,
+        request: har_request(flow),
+        response: har_response(flow),
+        cache: HarCache::default(),
+        timings,
+        server_ip_address: None,
+        connection: flow.connection_id.map(|id| id.to_string()),
+        web_socket_messages: flow
+            .websocket
+            .as_ref()
+            .map(|messages| messages.iter().map(ws_message).collect()),
+        comment: None,
+    })
+}
+
+/// Only the time to the response head and the time to the end of its body
+/// are known, everything before the request is sent counts as waiting.
+fn timings(flow: &Flow) -> HarTimings {
+    let start = flow.request.timestamp;
+    let response_at = flow.response.as_ref().map(|res| res.timestamp);
+    let upgraded = flow.websocket.is_some();
+
+    let wait = response_at
+        .or(flow.finished_at)
+        .map_or(0, |at| at.saturating_sub(start));
+    let receive = match (response_at, flow.finished_at) {
+        (Some(response_at), Some(end)) if !upgraded => end.saturating_sub(response_at),
+        _ => 0,
+    };
+
+    HarTimings {
+        wait: wait as f64,
+        receive: receive as f64,
+        ..Default::default()
+    }
+}
+
+fn har_request(flow: &Flow) -> HarRequest {
+    let request = &flow.request;
+    HarRequest {
+        method: request.method.clone(),
+        url: request.uri.clone(),
+        http_version: request.version.clone(),
+        cookies: request_cookies(&request.headers),
+        headers: name_values(&request.headers),
+        query_string: query_string(&request.uri),
+        post_data: flow
+            .request_body
+            .as_ref()
+            .filter(|body| body.size > 0)
+            .map(|body| post_data(&request.headers, body)),
+        headers_size: request
+            .raw_head
+            .as_ref()
+            .map_or(-1, |head| head.len() as i64),
+        body_size: flow
+            .request_body
+            .as_ref()
+            .map_or(0, |body| body.size as i64),
+    }
+}
+
+fn har_response(flow: &Flow) -> HarResponse {
+    let Some(response) = &flow.response else {
+        return HarResponse {
+            status: 0,
+            status_text: String::new(),
+            http_version: String::new(),
+            cookies: vec![],
+            headers: vec![],
+            content: HarContent::default(),
+            redirect_url: String::new(),
+            headers_size: -1,
+            body_size: -1,
+            error: flow.error.clone(),
+        };
+    };
+
+    HarResponse {
+        status: response.status,
+        status_text: StatusCode::from_u16(response.status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or_default()
+            .to_string(),
+        http_version: response.version.clone(),
+        cookies: response_cookies(&response.headers, response.timestamp),
+        headers: name_values(&response.headers),
+        content: content(&response.headers, flow.response_body.as_ref()),
+        redirect_url: header(&response.headers, "location")
+            .unwrap_or_default()
+            .to_string(),
+        headers_size: -1,
+        body_size: flow
+            .response_body
+            .as_ref()
+            .map_or(-1, |body| body.size as i64),
+        error: flow.error.clone(),
+    }
+}
+
+fn content(headers: &[(String, String)], body: Option<&BodyCapture>) -> HarContent {
+    let mime_type = header(headers, "content-type")
+        .unwrap_or_default()
+        .to_string();
+    let Some(body) = body else {
+        return HarContent {
+            mime_type,
+            ..Default::default()
+        };
+    };
+
+    match body.decoded(headers) {
+        // without an encoding the full size is known even when truncated
+        Ok(decoded) if decoded[..] == body.data[..] => {
+            let (text, encoding) = text_or_base64(&decoded);
+            HarContent {
+                size: body.size as i64,
+                compression: None,
+                mime_type,
+                text: Some(text),
+                encoding,
+            }
+        }
+        Ok(decoded) => {
+            let (text, encoding) = text_or_base64(&decoded);
+            HarContent {
+                size: decoded.len() as i64,
+                compression: Some(decoded.len() as i64 - body.size as i64),
+                mime_type,
+                text: Some(text),
+                encoding,
+            }
+        }
+        // leaving the text out beats passing off encoded bytes as content
+        Err(_) => HarContent {
+            size: body.size as i64,
+            compression: None,
+            mime_type,
+            text: None,
+            encoding: None,
+        },
+    }
+}
+
+fn post_data(headers: &[(String, String)], body: &BodyCapture) -> HarPostData {
+    let mime_type = header(headers, "content-type")
+        .unwrap_or_default()
+        .to_string();
+    let data = body
+        .decoded(headers)
+        .unwrap_or_else(|_| body.data.clone().into());
+    let (text, encoding) = text_or_base64(&data);
+
+    let params = match encoding {
+        None if mime_type.starts_with("application/x-www-form-urlencoded") => form_params(&text)
+            .into_iter()
+            .map(|param| HarParam {
+                name: param.name,
+                value: Some(param.value),
+                file_name: None,
+                content_type: None,
+            })
+            .collect(),
+        _ => vec![],
+    };
+
+    HarPostData {
+        mime_type,
+        params,
+        text,
+        encoding,
+    }
+}
+
+fn ws_message(message: &WsMessage) -> HarWebSocketMessage {
+    let (opcode, data) = match message.kind {
+        WsMessageKind::Text => (1, String::from_utf8_lossy(&message.payload).into_owned()),
+        WsMessageKind::Binary => (2, STANDARD.encode(&message.payload)),
+        WsMessageKind::Close => (8, STANDARD.encode(&message.payload)),
+        WsMessageKind::Ping => (9, STANDARD.encode(&message.payload)),
+        WsMessageKind::Pong => (10, STANDARD.encode(&message.payload)),
+    };
+    HarWebSocketMessage {
+        kind: match message.direction {
+            WsDirection::ClientToServer => "send",
+            WsDirection::ServerToClient => "receive",
+        }
+        .to_string(),
+        time: message.timestamp as f64 / 1000.0,
+        opcode,
+        data,
+    }
+}
+
+fn text_or_base64(data: &[u8]) -> (String, Option<String>) {
+    match std::str::from_utf8(data) {
+        Ok(text) => (text.to_string(), None),
+        Err(_) => (STANDARD.encode(data), Some("base64".to_string())),
+    }
+}
+
+fn name_values(headers: &[(String, String)]) -> Vec<HarNameValue> {
+    headers
+        .iter()
+        .map(|(name, value)| HarNameValue {
+            name: name.clone(),
+            value: value.clone(),
+        })
+        .collect()
+}
+
+fn query_string(uri: &str) -> Vec<HarNameValue> {
+    let query = uri
+        .split_once('?')
+        .map(|(_, query)| query.split('#').next().unwrap_or_default())
+        .unwrap_or_default();
+    form_params(query)
+}
+
+/// Pairs of an `application/x-www-form-urlencoded` string, decoded.
+pub fn form_params(encoded: &str) -> Vec<HarNameValue> {
+    form_decode(encoded)
+        .into_iter()
+        .map(|(name, value)| HarNameValue { name, value })
+        .collect()
+}
+
+fn request_cookies(headers: &[(String, String)]) -> Vec<HarCookie> {
+    headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("cookie"))
+        .flat_map(|(_, value)| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .map(|(name, value)| HarCookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// `received_at` turns `Max-Age` into an expiry date.
+fn response_cookies(headers: &[(String, String)], received_at: u64) -> Vec<HarCookie> {
+    headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("set-cookie"))
+        .filter_map(|(_, value)| parse_set_cookie(value, received_at))
+        .collect()
+}
+
+fn parse_set_cookie(value: &str, received_at: u64) -> Option<HarCookie> {
+    let mut attributes = value.split(';');
+    let (name, value) = attributes.next()?.trim().split_once('=')?;
+    let mut cookie = HarCookie {
+        name: name.to_string(),
+        value: value.to_string(),
+        ..Default::default()
+    };
+
+    let mut max_age = None;
+    for attribute in attributes {
+        let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "path" => cookie.path = Some(value.to_string()),
+            "domain" => cookie.domain = Some(value.to_string()),
+            "expires" => cookie.expires = parse_http_date(value).map(format_iso8601),
+            "max-age" => max_age = value.parse::<i64>().ok(),
+            "httponly" => cookie.http_only = Some(true),
+            "secure" => cookie.secure = Some(true),
+            _ => {}
+        }
+    }
+    // Max-Age wins over Expires
+    if let Some(max_age) = max_age {
+        let expires = received_at.saturating_add_signed(max_age.saturating_mul(1000));
+        cookie.expires = Some(format_iso8601(expires));
+    }
+
+    Some(cookie)
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
@@ -0,0 +1,200 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use super::{export_har, format_iso8601, parse_http_date, HarCookie};
+use crate::mitm::{
+    encode_body, BodyCapture, ContentEncoding, Flow, FlowKind, FlowRequest, FlowResponse,
+    WsDirection, WsMessage, WsMessageKind,
+};
+
+fn pairs(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+fn flow(request: FlowRequest, response: Option<FlowResponse>) -> Flow {
+    let mut flow = Flow::test(&request.method, &request.uri);
+    flow.id = 1;
+    flow.connection_id = Some(3);
+    flow.request = request;
+    flow.response = response;
+    flow
+}
+
+#[test]
+fn test_dates() {
+    assert_eq!(format_iso8601(0), "1970-01-01T00:00:00.000Z");
+    assert_eq!(format_iso8601(1445412480123), "2015-10-21T07:28:00.123Z");
+    assert_eq!(
+        parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT"),
+        Some(1445412480000)
+    );
+    assert_eq!(
+        parse_http_date("Thu, 29-Feb-24 23:59:59 GMT"),
+        Some(1709251199000)
+    );
+    assert_eq!(parse_http_date("tomorrow"), None);
+    assert_eq!(
+        parse_http_date("Wed, 21 Oct 2015 18446744073709551615:00:00 GMT"),
+        None
+    );
+}
+
+#[test]
+fn test_export_exchange() {
+    let html = "<p>hello</p>".repeat(50);
+    let gzipped = encode_body(html.as_bytes(), &[ContentEncoding::Gzip]).unwrap();
+
+    let mut exchange = flow(
+        FlowRequest {
+            method: "POST".to_string(),
+            uri: "https://example.com/login?next=%2Fhome&q=a+b#top".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: pairs(&[
+                ("content-type", "application/octet-stream"),
+                ("cookie", "sid=1; theme=dark"),
+            ]),
+            raw_head: None,
+            timestamp: 1_000,
+        },
+        Some(FlowResponse {
+            status: 302,
+            version: "HTTP/1.1".to_string(),
+            headers: pairs(&[
+                ("content-type", "text/html"),
+                ("content-encoding", "gzip"),
+                ("location", "/home"),
+                ("set-cookie", "sid=2; Path=/; Max-Age=60; HttpOnly; Secure"),
+                (
+                    "set-cookie",
+                    "theme=light; Domain=example.com; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+                ),
+            ]),
+            timestamp: 1_250,
+        }),
+    );
+    exchange.request_body = Some(BodyCapture::complete([0xff, 0x00, 0x7f]));
+    exchange.response_body = Some(BodyCapture::complete(&gzipped[..]));
+    exchange.finished_at = Some(1_300);
+
+    let mut tunnel = exchange.clone();
+    tunnel.kind = FlowKind::Tunnel;
+
+    let har = export_har(&[exchange, tunnel]);
+    assert_eq!(har.log.version, "1.2");
+    assert_eq!(har.log.entries.len(), 1);
+
+    let entry = &har.log.entries[0];
+    assert_eq!(entry.started_date_time, "1970-01-01T00:00:01.000Z");
+    assert_eq!(entry.time, 300.0);
+    assert_eq!((entry.timings.wait, entry.timings.receive), (250.0, 50.0));
+    assert_eq!(entry.connection.as_deref(), Some("3"));
+
+    let request = &entry.request;
+    assert_eq!(request.query_string[0].value, "/home");
+    assert_eq!(request.query_string[1].value, "a b");
+    assert_eq!(request.cookies.len(), 2);
+    let post_data = request.post_data.as_ref().unwrap();
+    assert_eq!(post_data.encoding.as_deref(), Some("base64"));
+    assert_eq!(
+        STANDARD.decode(&post_data.text).unwrap(),
+        [0xff, 0x00, 0x7f]
+    );
+    assert_eq!(request.body_size, 3);
+
+    let response = &entry.response;
+    assert_eq!(response.status_text, "Found");
+    assert_eq!(response.redirect_url, "/home");
+    assert_eq!(response.body_size, gzipped.len() as i64);
+    assert_eq!(response.content.size, html.len() as i64);
+    assert_eq!(
+        response.content.compression,
+        Some(html.len() as i64 - gzipped.len() as i64)
+    );
+    assert_eq!(response.content.text.as_deref(), Some(html.as_str()));
+    assert_eq!(
+        response.cookies,
+        vec![
+            HarCookie {
+                name: "sid".to_string(),
+                value: "2".to_string(),
+                path: Some("/".to_string()),
+                expires: Some("1970-01-01T00:01:01.250Z".to_string()),
+                http_only: Some(true),
+                secure: Some(true),
+                ..Default::default()
+            },
+            HarCookie {
+                name: "theme".to_string(),
+                value: "light".to_string(),
+                domain: Some("example.com".to_string()),
+                expires: Some("2015-10-21T07:28:00.000Z".to_string()),
+                ..Default::default()
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_export_failures_and_websockets() {
+    let request = FlowRequest {
+        method: "GET".to_string(),
+        uri: "https://example.com/form".to_string(),
+        version: "HTTP/1.1".to_string(),
+        headers: vec![],
+        raw_head: Some(b"GET /form HTTP/1.1\r\n\r\n".to_vec()),
+        timestamp: 0,
+    };
+
+    let mut failed = flow(request.clone(), None);
+    failed.error = Some("connection refused".to_string());
+
+    let mut socket = flow(
+        request,
+        Some(FlowResponse {
+            status: 101,
+            version: "HTTP/1.1".to_string(),
+            headers: vec![],
+            timestamp: 10,
+        }),
+    );
+    socket.finished_at = Some(60_000);
+    socket.websocket = Some(vec![
+        WsMessage {
+            direction: WsDirection::ClientToServer,
+            kind: WsMessageKind::Text,
+            payload: b"hi".to_vec(),
+            compressed: false,
+            injected: false,
+            timestamp: 1_500,
+        },
+        WsMessage {
+            direction: WsDirection::ServerToClient,
+            kind: WsMessageKind::Binary,
+            payload: vec![1, 2],
+            compressed: false,
+            injected: false,
+            timestamp: 2_000,
+        },
+    ]);
+
+    let har = export_har(&[failed, socket]);
+    let failed = &har.log.entries[0];
+    assert_eq!(failed.response.status, 0);
+    assert_eq!(failed.response.error.as_deref(), Some("connection refused"));
+    assert_eq!(failed.request.headers_size, 22);
+    assert!(failed.request.post_data.is_none());
+
+    let socket = &har.log.entries[1];
+    assert_eq!(socket.time, 10.0);
+    let messages = socket.web_socket_messages.as_ref().unwrap();
+    assert_eq!((messages[0].kind.as_str(), messages[0].opcode), ("send", 1));
+    assert_eq!(messages[0].data, "hi");
+    assert_eq!(messages[1].data, "AQI=");
+    assert_eq!(messages[1].time, 2.0);
+
+    let json = serde_json::to_value(&har).unwrap();
+    assert!(json["log"]["entries"][0]["response"]["redirectURL"].is_string());
+    assert!(json["log"]["entries"][1]["_webSocketMessages"].is_array());
+}
@@ -0,0 +1,199 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use super::{
+    parse_iso8601, Har, HarEntry, HarNameValue, HarPostData, HarResponse, HarWebSocketMessage,
+};
+use crate::mitm::{
+    encode_body, BodyCapture, ContentEncoding, Flow, FlowKind, FlowRequest, FlowResponse,
+    FlowTimings, WsDirection, WsMessage, WsMessageKind,
+};
+
+/// Flows for the entries of `har`, ids are left for the store to assign.
+pub fn import_har(har: &Har) -> Vec<Flow> {
+    har.log.entries.iter().map(har_flow).collect()
+}
+
+pub fn har_flow(entry: &HarEntry) -> Flow {
+    let start = parse_iso8601(&entry.started_date_time).unwrap_or_default();
+    let request = &entry.request;
+    let headers = pairs(&request.headers);
+
+    let mut flow = Flow {
+        id: 0,
+        kind: FlowKind::Http,
+        imported: true,
+        replay_of: None,
+        connection_id: None,
+        request: FlowRequest {
+            method: request.method.clone(),
+            uri: request.url.clone(),
+            version: http_version(&request.http_version),
+            headers,
+            raw_head: None,
+            timestamp: start,
+        },
+        response: None,
+        request_body: request.post_data.as_ref().map(post_body),
+        response_body: None,
+        events: None,
+        websocket: entry
+            .web_socket_messages
+            .as_ref()
+            .map(|messages| messages.iter().map(ws_message).collect()),
+        tunnel: None,
+        rule_hits: vec![],
+        breakpoints: vec![],
+        timings: FlowTimings::default(),
+        error: None,
+        finished_at: Some(start + millis(entry.time)),
+    };
+
+    let response = &entry.response;
+    if response.status == 0 {
+        flow.error = Some(
+            response
+                .error
+                .clone()
+                .unwrap_or_else(|| "No response".to_string()),
+        );
+        return flow;
+    }
+
+    // everything up to the first byte of the response, ssl is part of connect
+    let timings = &entry.timings;
+    let response_after = [
+        timings.blocked,
+        timings.dns,
+        timings.connect,
+        timings.send,
+        timings.wait,
+    ]
+    .into_iter()
+    .map(millis)
+    .sum::<u64>();
+
+    let (headers, body) = response_body(response);
+    flow.response = Some(FlowResponse {
+        status: response.status,
+        version: http_version(&response.http_version),
+        headers,
+        timestamp: start + response_after,
+    });
+    flow.response_body = body;
+    flow.error = response.error.clone();
+    flow
+}
+
+fn post_body(post_data: &HarPostData) -> BodyCapture {
+    let data = match post_data.encoding.as_deref() {
+        Some("base64") => STANDARD
+            .decode(&post_data.text)
+            .unwrap_or_else(|_| post_data.text.clone().into_bytes()),
+        _ => post_data.text.clone().into_bytes(),
+    };
+    capture(data)
+}
+
+/// HAR keeps the decoded content, it is encoded again so that the body
+/// matches its `Content-Encoding` like a captured one. Encodings that can't
+/// be reproduced are dropped from the headers instead.
+fn response_body(response: &HarResponse) -> (Vec<(String, String)>, Option<BodyCapture>) {
+    let mut headers = pairs(&response.headers);
+    let Some(text) = &response.content.text else {
+        return (headers, None);
+    };
+
+    let data = match response.content.encoding.as_deref() {
+        Some("base64") => STANDARD
+            .decode(text)
+            .unwrap_or_else(|_| text.clone().into_bytes()),
+        _ => text.clone().into_bytes(),
+    };
+
+    let encodings: Option<Vec<ContentEncoding>> = headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("content-encoding"))
+        .flat_map(|(_, value)| value.split(','))
+        .map(str::trim)
+        .filter(|token| !token.is_empty() && !token.eq_ignore_ascii_case("identity"))
+        .map(ContentEncoding::parse)
+        .collect();
+
+    let data = match encodings.map(|encodings| encode_body(&data, &encodings)) {
+        Some(Ok(encoded)) => encoded.to_vec(),
+        _ => {
+            headers.retain(|(name, _)| !name.eq_ignore_ascii_case("content-encoding"));
+            data
+        }
+    };
+    (headers, Some(capture(data)))
+}
+
+fn ws_message(message: &HarWebSocketMessage) -> WsMessage {
+    let kind = match message.opcode {
+        1 => WsMessageKind::Text,
+        8 => WsMessageKind::Close,
+        9 => WsMessageKind::Ping,
+        10 => WsMessageKind::Pong,
+        _ => WsMessageKind::Binary,
+    };
+    let payload = match kind {
+        WsMessageKind::Text => message.data.clone().into_bytes(),
+        _ => STANDARD
+            .decode(&message.data)
+            .unwrap_or_else(|_| message.data.clone().into_bytes()),
+    };
+    WsMessage {
+        direction: match message.kind.as_str() {
+            "send" => WsDirection::ClientToServer,
+            _ => WsDirection::ServerToClient,
+        },
+        kind,
+        payload,
+        compressed: false,
+        injected: false,
+        timestamp: millis(message.time * 1000.0),
+    }
+}
+
+fn capture(data: Vec<u8>) -> BodyCapture {
+    BodyCapture {
+        size: data.len() as u64,
+        data,
+        truncated: false,
+        trailers: None,
+        complete: true,
+    }
+}
+
+/// Pseudo headers that browsers list for HTTP/2 are already part of the
+/// request line or status.
+fn pairs(headers: &[HarNameValue]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|header| !header.name.starts_with(':'))
+        .map(|header| (header.name.clone(), header.value.clone()))
+        .collect()
+}
+
+/// Browsers write `h2` or `http/2.0`, flows use hyper's spelling.
+fn http_version(version: &str) -> String {
+    match version.to_ascii_lowercase().as_str() {
+        "" | "http/1.1" => "HTTP/1.1",
+        "http/1.0" => "HTTP/1.0",
+        "http/0.9" => "HTTP/0.9",
+        "h2" | "http/2" | "http/2.0" => "HTTP/2.0",
+        "h3" | "http/3" | "http/3.0" => "HTTP/3.0",
+        _ => return version.to_string(),
+    }
+    .to_string()
+}
+
+/// Timings are -1 when unknown.
+fn millis(value: f64) -> u64 {
+    if value > 0.0 {
+        value.round() as u64
+    } else {
+        0
+    }
+}
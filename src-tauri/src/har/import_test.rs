@@ -0,0 +1,147 @@
+use super::{export_har, import_har, parse_iso8601, Har};
+use crate::mitm::{encode_body, ContentEncoding, Flow};
+
+#[test]
+fn test_parse_iso8601() {
+    assert_eq!(parse_iso8601("1970-01-01T00:00:00Z"), Some(0));
+    assert_eq!(
+        parse_iso8601("2015-10-21T07:28:00.123Z"),
+        Some(1445412480123)
+    );
+    assert_eq!(
+        parse_iso8601("2015-10-21T09:28:00.1+02:00"),
+        Some(1445412480100)
+    );
+    assert_eq!(parse_iso8601("2015-10-21T07:28-0030"), Some(1445414280000));
+    assert_eq!(parse_iso8601("yesterday"), None);
+    // out of range instead of overflowing
+    assert_eq!(parse_iso8601("9223372036854775807-01-01T00:00:00Z"), None);
+    assert_eq!(parse_iso8601("2015-10-21T9223372036854775807:00:00Z"), None);
+    assert_eq!(parse_iso8601("2015-13-21T07:28:00Z"), None);
+}
+
+#[test]
+fn test_round_trip() {
+    let json = "{\"ok\":true}".repeat(20);
+    let gzipped = encode_body(json.as_bytes(), &[ContentEncoding::Gzip]).unwrap();
+    let mut flow = Flow::test("PUT", "https://example.com/data?x=1")
+        .with_request_headers(&[("content-type", "application/octet-stream")])
+        .with_request_body([0, 159, 146, 150])
+        .with_response(
+            200,
+            &[
+                ("content-type", "application/json"),
+                ("content-encoding", "gzip"),
+            ],
+        )
+        .with_response_body(&gzipped[..]);
+    flow.id = 7;
+    flow.connection_id = Some(1);
+    flow.request.version = "HTTP/2.0".to_string();
+    flow.request.timestamp = 1_000;
+    let response = flow.response.as_mut().unwrap();
+    response.version = "HTTP/2.0".to_string();
+    response.timestamp = 1_040;
+    flow.finished_at = Some(1_100);
+
+    let har: Har =
+        serde_json::from_slice(&serde_json::to_vec(&export_har(&[flow])).unwrap()).unwrap();
+    let flows = import_har(&har);
+    assert_eq!(flows.len(), 1);
+
+    let imported = &flows[0];
+    assert!(imported.imported);
+    assert_eq!(imported.request.method, "PUT");
+    assert_eq!(imported.request.uri, "https://example.com/data?x=1");
+    assert_eq!(imported.request.timestamp, 1_000);
+    assert_eq!(
+        imported.request_body.as_ref().unwrap().data,
+        [0, 159, 146, 150]
+    );
+
+    let response = imported.response.as_ref().unwrap();
+    assert_eq!((response.status, response.timestamp), (200, 1_040));
+    assert_eq!(imported.finished_at, Some(1_100));
+    let body = imported.response_body.as_ref().unwrap();
+    assert_eq!(
+        &body.decoded(&response.headers).unwrap()[..],
+        json.as_bytes()
+    );
+}
+
+#[test]
+fn test_import_browser_entries() {
+    let har: Har = serde_json::from_str(
+        r#"{
+          "log": {
+            "version": "1.2",
+            "creator": { "name": "WebInspector", "version": "537.36" },
+            "entries": [
+              {
+                "startedDateTime": "2024-03-01T10:00:00.500+01:00",
+                "time": 120.4,
+                "request": {
+                  "method": "GET",
+                  "url": "https://example.com/app.js",
+                  "httpVersion": "h2",
+                  "headers": [
+                    { "name": ":authority", "value": "example.com" },
+                    { "name": "accept", "value": "*/*" }
+                  ],
+                  "queryString": [],
+                  "cookies": [],
+                  "headersSize": -1,
+                  "bodySize": 0
+                },
+                "response": {
+                  "status": 200,
+                  "statusText": "",
+                  "httpVersion": "h2",
+                  "headers": [
+                    { "name": "content-encoding", "value": "br" },
+                    { "name": "content-type", "value": "text/javascript" }
+                  ],
+                  "cookies": [],
+                  "content": { "size": 9, "mimeType": "text/javascript", "text": "let a = 1" },
+                  "redirectURL": "",
+                  "headersSize": -1,
+                  "bodySize": -1
+                },
+                "cache": {},
+                "timings": {
+                  "blocked": 10.2, "dns": -1, "ssl": -1, "connect": -1,
+                  "send": 0.3, "wait": 80.1, "receive": 29.8
+                }
+              },
+              {
+                "startedDateTime": "2024-03-01T09:00:01Z",
+                "time": 5,
+                "request": { "method": "GET", "url": "https://blocked.test/" },
+                "response": { "status": 0, "_error": "net::ERR_BLOCKED_BY_CLIENT" },
+                "timings": { "send": 0, "wait": 0, "receive": 0 }
+              }
+            ]
+          }
+        }"#,
+    )
+    .unwrap();
+
+    let flows = import_har(&har);
+    let script = &flows[0];
+    assert_eq!(script.request.version, "HTTP/2.0");
+    assert_eq!(script.request.headers.len(), 1);
+    assert_eq!(script.request.timestamp, 1709283600500);
+    assert!(script.request_body.is_none());
+
+    let response = script.response.as_ref().unwrap();
+    assert_eq!(response.timestamp, 1709283600500 + 90);
+    assert_eq!(script.finished_at, Some(1709283600500 + 120));
+    let body = script.response_body.as_ref().unwrap();
+    assert_ne!(body.data, b"let a = 1");
+    assert_eq!(&body.decoded(&response.headers).unwrap()[..], b"let a = 1");
+
+    let blocked = &flows[1];
+    assert!(blocked.response.is_none());
+    assert_eq!(blocked.error.as_deref(), Some("net::ERR_BLOCKED_BY_CLIENT"));
+    assert_eq!(blocked.request.timestamp, 1709283601000);
+}
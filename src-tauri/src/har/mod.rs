@@ -0,0 +1,14 @@
+mod export;
+mod import;
+mod model;
+mod time;
+
+#[cfg(test)]
+mod export_test;
+#[cfg(test)]
+mod import_test;
+
+pub use export::*;
+pub use import::*;
+pub use model::*;
+pub use time::*;
@@ -0,0 +1,126 @@
+/// Formats milliseconds since the epoch as an ISO 8601 UTC date-time.
+pub fn format_iso8601(millis: u64) -> String {
+    let secs = millis / 1000;
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let time = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60,
+        millis % 1000
+    )
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian date, after Howard
+/// Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Parses an IMF-fixdate like `Wed, 21 Oct 2015 07:28:00 GMT`, as used in
+/// `Expires`, into milliseconds since the epoch. Cookies also see dashes
+/// between the date parts.
+pub fn parse_http_date(value: &str) -> Option<u64> {
+    let value = value.split_once(',').map_or(value, |(_, date)| date);
+    let parts: Vec<&str> = value
+        .split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|p| !p.is_empty())
+        .collect();
+    let [day, month, year, time, ..] = parts[..] else {
+        return None;
+    };
+
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))? as u32 + 1;
+    let year: i64 = match year.parse().ok()? {
+        year @ 0..=69 => year + 2000,
+        year @ 70..=99 => year + 1900,
+        year => year,
+    };
+    let mut time = time.split(':').map(|p| p.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+
+    let days = u64::try_from(days_from_civil(year, month, day)?).ok()?;
+    days.checked_mul(86_400)?
+        .checked_add(hour.checked_mul(3600)?)?
+        .checked_add(minute.checked_mul(60)?)?
+        .checked_add(second)?
+        .checked_mul(1000)
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// `None` for dates out of range, or too far out to count the days.
+fn days_from_civil(year: i64, month: u32, day: u32) -> Option<i64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let year = year.checked_sub(i64::from(month <= 2))?;
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era.checked_mul(146_097)?.checked_add(doe - 719_468)
+}
+
+/// Parses an ISO 8601 date-time with a `Z` or `±hh:mm` offset into
+/// milliseconds since the epoch.
+pub fn parse_iso8601(value: &str) -> Option<u64> {
+    let (date, time) = value.trim().split_once('T')?;
+    let mut date = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+
+    let (time, offset_secs) = match time.find(['Z', 'z', '+', '-']) {
+        Some(at) => {
+            let (time, offset) = time.split_at(at);
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let offset = offset[1..].replace(':', "");
+            let offset_secs = match offset.len() {
+                0 => 0,
+                4 => {
+                    sign * (offset[..2].parse::<i64>().ok()? * 3600
+                        + offset[2..].parse::<i64>().ok()? * 60)
+                }
+                _ => return None,
+            };
+            (time, offset_secs)
+        }
+        None => (time, 0),
+    };
+
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.split(':').map(|p| p.parse::<i64>().ok());
+    let (hour, minute, second) = (
+        time.next()??,
+        time.next()??,
+        time.next().flatten().unwrap_or(0),
+    );
+    let millis = format!("{:0<3}", fraction.get(..3).unwrap_or(fraction))
+        .parse::<i64>()
+        .ok()?;
+
+    let days = days_from_civil(year, u32::try_from(month).ok()?, u32::try_from(day).ok()?)?;
+    let secs = days
+        .checked_mul(86_400)?
+        .checked_add(hour.checked_mul(3600)?)?
+        .checked_add(minute.checked_mul(60)?)?
+        .checked_add(second)?
+        .checked_sub(offset_secs)?;
+    u64::try_from(secs.checked_mul(1000)?.checked_add(millis)?).ok()
+}
@@ -1,10 +1,28 @@
-use std::io::{Read, Write};
+use std::sync::Arc;
 
-use flate2::{read::GzDecoder, write::GzEncoder, Compression};
-use mitm::{HttpHandler, MitmProxy, RootCA};
+use grpc::GrpcSchemas;
+use mitm::{
+    Breakpoints, ClientReplay, FlowStore, HttpHandler, MitmProxy, RootCA, Rules, ServerReplay,
+    Throttle, WsInjector,
+};
 use quick_cache::sync::Cache;
+use search::SearchIndex;
+use serde::Serialize;
+use session::OpenSession;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
 
-mod mitm;
+pub mod apidoc;
+mod commands;
+pub mod diff;
+pub mod grpc;
+pub mod har;
+pub mod mitm;
+pub mod mitmproxy;
+pub mod search;
+pub mod session;
+pub mod snippet;
+pub mod util;
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 #[tauri::command]
@@ -27,18 +45,15 @@ impl HttpHandler for Proxy {
         &self,
         res: hyper::Response<mitm::Body>,
     ) -> anyhow::Result<hyper::Response<mitm::Body>> {
-        // let (parts, body) = res.into_parts();
+        // collecting waits for the whole body, use mitm::TeeBody to look at
+        // streamed responses without holding them back
+        // let (mut parts, body) = res.into_parts();
 
-        // let collected_body = body.collect().await?;
+        // let captured = mitm::CapturedBody::collect(body, &parts.headers).await?;
 
-        // let body_bytes = collected_body.to_bytes();
+        // // println!("{:?}", String::from_utf8_lossy(&captured.decoded()?));
 
-        // // println!(
-        // //     "{:?}",
-        // //     String::from_utf8_lossy(&decompress_gzip_data(&body_bytes).unwrap())
-        // // );
-
-        // let new_body = mitm::full_body(compress_gzip_data(b"<div>12345</div>").unwrap());
+        // let new_body = captured.rewrite(&mut parts.headers, "<div>12345</div>")?;
 
         // let new_res = Response::from_parts(parts, new_body);
 
@@ -47,25 +62,103 @@ impl HttpHandler for Proxy {
     }
 }
 
-fn compress_gzip_data(original_data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
-    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-    encoder.write_all(original_data)?;
-    encoder.finish() // finish() 返回 Result<Vec<u8>, io::Error>
-}
-
-fn decompress_gzip_data(compressed_data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
-    let mut decoder = GzDecoder::new(compressed_data);
-    let mut decompressed_data = Vec::new();
-    decoder.read_to_end(&mut decompressed_data)?;
-    Ok(decompressed_data)
+fn forward_events<T>(app_handle: AppHandle, event: &'static str, mut events: Receiver<T>)
+where
+    T: Serialize + Clone + Send + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(payload) => {
+                    let _ = app_handle.emit(event, payload);
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    let breakpoints = Arc::new(Breakpoints::new());
+    let rules = Arc::new(Rules::new());
+    let throttle = Arc::new(Throttle::new());
+    let flow_store = Arc::new(FlowStore::new());
+    let ws_injector = Arc::new(WsInjector::new());
+    let grpc_schemas = Arc::new(GrpcSchemas::new());
+    let server_replay = Arc::new(ServerReplay::new());
+    let client_replay = Arc::new(ClientReplay::new());
+    let search_index = Arc::new(SearchIndex::new(&flow_store));
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .invoke_handler(tauri::generate_handler![greet])
-        .setup(|_| {
+        .manage(breakpoints.clone())
+        .manage(rules.clone())
+        .manage(throttle.clone())
+        .manage(flow_store.clone())
+        .manage(ws_injector.clone())
+        .manage(grpc_schemas)
+        .manage(server_replay.clone())
+        .manage(client_replay.clone())
+        .manage(search_index)
+        .manage(Arc::new(OpenSession::default()))
+        .invoke_handler(tauri::generate_handler![
+            greet,
+            commands::get_breakpoint_rules,
+            commands::set_breakpoint_rules,
+            commands::get_breakpoint_timeout,
+            commands::set_breakpoint_timeout,
+            commands::list_pending_breakpoints,
+            commands::resolve_breakpoint,
+            commands::get_rules,
+            commands::set_rules,
+            commands::get_network_presets,
+            commands::get_network_conditions,
+            commands::set_network_conditions,
+            commands::list_flows,
+            commands::get_flow,
+            commands::get_raw_head,
+            commands::list_connections,
+            commands::clear_flows,
+            commands::get_capture_limit,
+            commands::set_capture_limit,
+            commands::list_ws_sessions,
+            commands::inject_ws_message,
+            commands::list_grpc_services,
+            commands::clear_grpc_schemas,
+            commands::load_descriptor_set,
+            commands::load_proto_files,
+            commands::load_grpc_reflection,
+            commands::decode_grpc_flow,
+            commands::get_transparent_rules,
+            commands::export_har,
+            commands::import_har,
+            commands::export_mitmproxy,
+            commands::import_mitmproxy,
+            commands::export_postman,
+            commands::export_openapi,
+            commands::search_flows,
+            commands::diff_flows,
+            commands::get_server_replay,
+            commands::set_server_replay,
+            commands::load_server_replay,
+            commands::clear_server_replay,
+            commands::get_replay_request,
+            commands::replay_flow,
+            commands::generate_snippet,
+            commands::parse_curl,
+            commands::send_request,
+            commands::save_session,
+            commands::load_session,
+            commands::open_session,
+            commands::get_session_flow,
+            commands::close_session,
+        ])
+        .setup(move |app| {
+            forward_events(app.handle().clone(), "breakpoint", breakpoints.subscribe());
+            forward_events(app.handle().clone(), "flow", flow_store.subscribe());
+
             tokio::spawn(async move {
                 let root_ca = RootCA::read_from_file("./ca.crt", "./ca.key")
                     .await
@@ -74,7 +167,18 @@ pub fn run() {
                     .with_handler(Proxy)
                     .with_root_ca(root_ca)
                     .with_cert_cache(Cache::new(128))
+                    .with_breakpoints(breakpoints)
+                    .with_rules(rules)
+                    .with_throttle(throttle)
+                    .with_flow_store(flow_store)
+                    .with_ws_injector(ws_injector)
+                    .with_server_replay(server_replay)
+                    .with_client_replay(client_replay)
+                    .with_header_fidelity(true)
                     .with_addr("127.0.0.1:7777")
+                    .with_socks_addr("127.0.0.1:7778")
+                    // what TransparentRules::default sends traffic to
+                    .with_transparent_addr("127.0.0.1:7779")
                     .build();
                 let _ = proxy.start().await;
             });
@@ -0,0 +1,597 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, RwLock,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use http_body_util::BodyExt;
+use hyper::{
+    body::{Body as HttpBody, Bytes, Frame, SizeHint},
+    header::{HeaderName, HeaderValue, CONTENT_LENGTH},
+    HeaderMap, Method, Request, Response, StatusCode, Uri,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{broadcast, oneshot},
+    time::timeout,
+};
+use tracing::{debug, warn};
+
+use super::{
+    body_with_trailers, error_response, header_pairs, now_millis, Body, RequestMatcher,
+    RequestOrResponse,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BreakpointPhase {
+    Request,
+    Response,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreakpointRule {
+    pub matcher: RequestMatcher,
+    pub request: bool,
+    pub response: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TimeoutAction {
+    Resume,
+    Abort,
+}
+
+/// What happens to a paused exchange nobody resolves. `timeout_ms: None`
+/// waits forever.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeoutPolicy {
+    pub timeout_ms: Option<u64>,
+    pub action: TimeoutAction,
+}
+
+impl Default for TimeoutPolicy {
+    fn default() -> Self {
+        Self {
+            timeout_ms: Some(300_000),
+            action: TimeoutAction::Resume,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditableRequest {
+    pub method: String,
+    pub uri: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    #[serde(default)]
+    pub trailers: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditableResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    #[serde(default)]
+    pub trailers: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingBreakpoint {
+    pub id: u64,
+    pub phase: BreakpointPhase,
+    pub request: EditableRequest,
+    pub response: Option<EditableResponse>,
+    pub paused_at: u64,
+}
+
+/// How the UI resolves a pending breakpoint. `Respond` short-circuits a
+/// paused request, or replaces a paused response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BreakpointAction {
+    Resume,
+    ResumeWithRequest { request: EditableRequest },
+    Respond { response: EditableResponse },
+    Abort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BreakpointOutcome {
+    Resumed,
+    /// Resumed with an edited request.
+    Edited,
+    Responded,
+    Aborted,
+}
+
+/// A pause at a breakpoint as recorded on the flow. What the flow would no
+/// longer show is kept: the request as it arrived when it was edited, and
+/// the response as it arrived when it was replaced or aborted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreakpointRecord {
+    pub phase: BreakpointPhase,
+    pub outcome: BreakpointOutcome,
+    pub paused_at: u64,
+    pub resumed_at: u64,
+    #[serde(default)]
+    pub original_request: Option<EditableRequest>,
+    #[serde(default)]
+    pub original_response: Option<EditableResponse>,
+}
+
+impl BreakpointRecord {
+    fn new(phase: BreakpointPhase, action: &BreakpointAction, paused_at: u64) -> Self {
+        let outcome = match action {
+            BreakpointAction::Resume => BreakpointOutcome::Resumed,
+            BreakpointAction::ResumeWithRequest { .. } => match phase {
+                BreakpointPhase::Request => BreakpointOutcome::Edited,
+                BreakpointPhase::Response => BreakpointOutcome::Resumed,
+            },
+            BreakpointAction::Respond { .. } => BreakpointOutcome::Responded,
+            BreakpointAction::Abort => BreakpointOutcome::Aborted,
+        };
+        Self {
+            phase,
+            outcome,
+            paused_at,
+            resumed_at: now_millis(),
+            original_request: None,
+            original_response: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum BreakpointEvent {
+    Paused { breakpoint: Box<PendingBreakpoint> },
+    Resolved { id: u64 },
+}
+
+struct Pending {
+    breakpoint: PendingBreakpoint,
+    resolve_tx: oneshot::Sender<BreakpointAction>,
+}
+
+/// Holds matching exchanges until the UI resolves them.
+///
+/// Every paused exchange only suspends its own service future, so other
+/// requests on the same connection, in particular other HTTP/2 streams,
+/// keep flowing.
+pub struct Breakpoints {
+    rules: RwLock<Vec<BreakpointRule>>,
+    timeout_policy: RwLock<TimeoutPolicy>,
+    pending: Mutex<BTreeMap<u64, Pending>>,
+    next_id: AtomicU64,
+    events_tx: broadcast::Sender<BreakpointEvent>,
+}
+
+impl Default for Breakpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self {
+            rules: RwLock::new(vec![]),
+            timeout_policy: RwLock::new(TimeoutPolicy::default()),
+            pending: Mutex::new(BTreeMap::new()),
+            next_id: AtomicU64::new(1),
+            events_tx: broadcast::channel(256).0,
+        }
+    }
+
+    pub fn rules(&self) -> Vec<BreakpointRule> {
+        self.rules.read().unwrap().clone()
+    }
+
+    pub fn set_rules(&self, rules: Vec<BreakpointRule>) {
+        *self.rules.write().unwrap() = rules;
+    }
+
+    pub fn timeout_policy(&self) -> TimeoutPolicy {
+        self.timeout_policy.read().unwrap().clone()
+    }
+
+    pub fn set_timeout_policy(&self, policy: TimeoutPolicy) {
+        *self.timeout_policy.write().unwrap() = policy;
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<BreakpointEvent> {
+        self.events_tx.subscribe()
+    }
+
+    /// Paused exchanges, oldest first.
+    pub fn pending(&self) -> Vec<PendingBreakpoint> {
+        self.pending
+            .lock()
+            .unwrap()
+            .values()
+            .map(|p| p.breakpoint.clone())
+            .collect()
+    }
+
+    pub fn resolve(&self, id: u64, action: BreakpointAction) -> anyhow::Result<()> {
+        let pending = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .ok_or_else(|| anyhow!("No pending breakpoint {}", id))?;
+
+        let _ = self.events_tx.send(BreakpointEvent::Resolved { id });
+
+        pending
+            .resolve_tx
+            .send(action)
+            .map_err(|_| anyhow!("Breakpoint {} is no longer waiting", id))
+    }
+
+    pub(super) fn should_break(&self, phase: BreakpointPhase, method: &Method, uri: &Uri) -> bool {
+        self.rules.read().unwrap().iter().any(|rule| {
+            let enabled = match phase {
+                BreakpointPhase::Request => rule.request,
+                BreakpointPhase::Response => rule.response,
+            };
+            enabled && rule.matcher.matches(method, uri)
+        })
+    }
+
+    /// Bodies over `limit` bytes are not held, they go on unchanged. The
+    /// result carries a [`BreakpointRecord`] in its extensions.
+    pub(super) async fn pause_request(
+        &self,
+        req: Request<Body>,
+        limit: usize,
+    ) -> RequestOrResponse {
+        let (parts, body) = req.into_parts();
+        let (body, trailers) = match buffer(body, limit).await {
+            Ok(Buffered::Whole(body, trailers)) => (body, trailers),
+            Ok(Buffered::Streaming(body)) => {
+                debug!("Request body too large to pause, forwarding");
+                return RequestOrResponse::Request(Request::from_parts(parts, body));
+            }
+            Err(e) => {
+                warn!("Failed to read request body at breakpoint: {}", e);
+                return RequestOrResponse::Response(error_response(
+                    StatusCode::BAD_GATEWAY,
+                    "Failed to read request body",
+                ));
+            }
+        };
+
+        let request = EditableRequest {
+            method: parts.method.to_string(),
+            uri: parts.uri.to_string(),
+            headers: header_pairs(&parts.headers),
+            body: body.to_vec(),
+            trailers: trailers.as_ref().map(header_pairs).unwrap_or_default(),
+        };
+
+        let (action, paused_at) = self
+            .wait(BreakpointPhase::Request, request.clone(), None)
+            .await;
+        let mut record = BreakpointRecord::new(BreakpointPhase::Request, &action, paused_at);
+
+        let mut result = match action {
+            BreakpointAction::Resume => RequestOrResponse::Request(Request::from_parts(
+                parts,
+                body_with_trailers(body, trailers),
+            )),
+            BreakpointAction::ResumeWithRequest { request: edited } => {
+                record.original_request = Some(request);
+                match edited.into_request(parts.version) {
+                    Ok(req) => RequestOrResponse::Request(req),
+                    Err(e) => {
+                        warn!("Invalid request edited at breakpoint: {}", e);
+                        RequestOrResponse::Response(error_response(
+                            StatusCode::BAD_REQUEST,
+                            "Invalid request edited at breakpoint",
+                        ))
+                    }
+                }
+            }
+            BreakpointAction::Respond { response } => {
+                RequestOrResponse::Response(response.into_response_or_error())
+            }
+            BreakpointAction::Abort => RequestOrResponse::Response(error_response(
+                StatusCode::BAD_GATEWAY,
+                "Aborted at breakpoint",
+            )),
+        };
+
+        match &mut result {
+            RequestOrResponse::Request(req) => req.extensions_mut().insert(record),
+            RequestOrResponse::Response(res) => res.extensions_mut().insert(record),
+        };
+        result
+    }
+
+    /// `request` is what was sent upstream, for display. Bodies over `limit`
+    /// bytes are not held, they go on unchanged. The result carries a
+    /// [`BreakpointRecord`] in its extensions.
+    pub(super) async fn pause_response(
+        &self,
+        request: EditableRequest,
+        res: Response<Body>,
+        limit: usize,
+    ) -> Response<Body> {
+        let (parts, body) = res.into_parts();
+        let (body, trailers) = match buffer(body, limit).await {
+            Ok(Buffered::Whole(body, trailers)) => (body, trailers),
+            Ok(Buffered::Streaming(body)) => {
+                debug!("Response body too large to pause, forwarding");
+                return Response::from_parts(parts, body);
+            }
+            Err(e) => {
+                warn!("Failed to read response body at breakpoint: {}", e);
+                return error_response(StatusCode::BAD_GATEWAY, "Failed to read response body");
+            }
+        };
+
+        let response = EditableResponse {
+            status: parts.status.as_u16(),
+            headers: header_pairs(&parts.headers),
+            body: body.to_vec(),
+            trailers: trailers.as_ref().map(header_pairs).unwrap_or_default(),
+        };
+
+        let (action, paused_at) = self
+            .wait(BreakpointPhase::Response, request, Some(response.clone()))
+            .await;
+        let mut record = BreakpointRecord::new(BreakpointPhase::Response, &action, paused_at);
+
+        let mut res = match action {
+            BreakpointAction::Resume | BreakpointAction::ResumeWithRequest { .. } => {
+                Response::from_parts(parts, body_with_trailers(body, trailers))
+            }
+            BreakpointAction::Respond {
+                response: replacement,
+            } => {
+                record.original_response = Some(response);
+                replacement.into_response_or_error()
+            }
+            BreakpointAction::Abort => {
+                record.original_response = Some(response);
+                error_response(StatusCode::BAD_GATEWAY, "Aborted at breakpoint")
+            }
+        };
+        res.extensions_mut().insert(record);
+        res
+    }
+
+    /// The action and when the exchange was paused.
+    async fn wait(
+        &self,
+        phase: BreakpointPhase,
+        request: EditableRequest,
+        response: Option<EditableResponse>,
+    ) -> (BreakpointAction, u64) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let paused_at = now_millis();
+        let breakpoint = PendingBreakpoint {
+            id,
+            phase,
+            request,
+            response,
+            paused_at,
+        };
+
+        let (resolve_tx, resolve_rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(
+            id,
+            Pending {
+                breakpoint: breakpoint.clone(),
+                resolve_tx,
+            },
+        );
+        let _ = self.events_tx.send(BreakpointEvent::Paused {
+            breakpoint: Box::new(breakpoint),
+        });
+        debug!("Paused at breakpoint {}", id);
+
+        // drops the entry when the client goes away while we are waiting
+        let _guard = PendingGuard {
+            breakpoints: self,
+            id,
+        };
+
+        let policy = self.timeout_policy();
+        let result = match policy.timeout_ms {
+            Some(ms) => timeout(Duration::from_millis(ms), resolve_rx).await.ok(),
+            None => Some(resolve_rx.await),
+        };
+
+        let action = match result {
+            Some(Ok(action)) => action,
+            Some(Err(_)) => BreakpointAction::Abort,
+            None => {
+                debug!("Breakpoint {} timed out", id);
+                match policy.action {
+                    TimeoutAction::Resume => BreakpointAction::Resume,
+                    TimeoutAction::Abort => BreakpointAction::Abort,
+                }
+            }
+        };
+        (action, paused_at)
+    }
+}
+
+struct PendingGuard<'a> {
+    breakpoints: &'a Breakpoints,
+    id: u64,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        let removed = self
+            .breakpoints
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&self.id)
+            .is_some();
+
+        if removed {
+            let _ = self
+                .breakpoints
+                .events_tx
+                .send(BreakpointEvent::Resolved { id: self.id });
+        }
+    }
+}
+
+impl EditableRequest {
+    pub(super) fn into_request(self, version: hyper::Version) -> anyhow::Result<Request<Body>> {
+        let mut builder = Request::builder()
+            .method(Method::from_bytes(self.method.as_bytes())?)
+            .uri(Uri::try_from(self.uri)?)
+            .version(version);
+
+        *builder.headers_mut().unwrap() = header_map(&self.headers, self.body.len())?;
+        let trailers = trailer_map(&self.trailers)?;
+
+        Ok(builder.body(body_with_trailers(self.body.into(), trailers))?)
+    }
+}
+
+impl EditableResponse {
+    fn into_response(self) -> anyhow::Result<Response<Body>> {
+        let mut builder = Response::builder().status(StatusCode::from_u16(self.status)?);
+
+        *builder.headers_mut().unwrap() = header_map(&self.headers, self.body.len())?;
+        let trailers = trailer_map(&self.trailers)?;
+
+        Ok(builder.body(body_with_trailers(self.body.into(), trailers))?)
+    }
+
+    fn into_response_or_error(self) -> Response<Body> {
+        self.into_response().unwrap_or_else(|e| {
+            warn!("Invalid response edited at breakpoint: {}", e);
+            error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Invalid response edited at breakpoint",
+            )
+        })
+    }
+}
+
+fn parse_headers(pairs: &[(String, String)]) -> anyhow::Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        headers.append(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    Ok(headers)
+}
+
+fn header_map(pairs: &[(String, String)], body_len: usize) -> anyhow::Result<HeaderMap> {
+    let mut headers = parse_headers(pairs)?;
+
+    // keep the framing consistent with an edited body
+    if headers.contains_key(CONTENT_LENGTH) {
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(body_len));
+    }
+
+    Ok(headers)
+}
+
+fn trailer_map(pairs: &[(String, String)]) -> anyhow::Result<Option<HeaderMap>> {
+    if pairs.is_empty() {
+        return Ok(None);
+    }
+    parse_headers(pairs).map(Some)
+}
+
+pub(super) enum Buffered {
+    Whole(Bytes, Option<HeaderMap>),
+    /// Too large to hold, the frames read so far go out ahead of the rest.
+    Streaming(Body),
+}
+
+/// Reads `body` whole if it is at most `limit` bytes.
+pub(super) async fn buffer(mut body: Body, limit: usize) -> anyhow::Result<Buffered> {
+    let mut frames = VecDeque::new();
+    let mut size = 0;
+    while let Some(frame) = body.frame().await {
+        let frame = frame?;
+        size += frame.data_ref().map_or(0, Bytes::len);
+        frames.push_back(frame);
+        if size > limit {
+            return Ok(Buffered::Streaming(
+                Prepended { frames, rest: body }.boxed(),
+            ));
+        }
+    }
+
+    let mut data = Vec::with_capacity(size);
+    let mut trailers = None;
+    for frame in frames {
+        match frame.into_data() {
+            Ok(chunk) => data.extend_from_slice(&chunk),
+            Err(frame) => trailers = frame.into_trailers().ok(),
+        }
+    }
+    Ok(Buffered::Whole(data.into(), trailers))
+}
+
+struct Prepended {
+    frames: VecDeque<Frame<Bytes>>,
+    rest: Body,
+}
+
+impl HttpBody for Prepended {
+    type Data = Bytes;
+    type Error = anyhow::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match self.frames.pop_front() {
+            Some(frame) => Poll::Ready(Some(Ok(frame))),
+            None => Pin::new(&mut self.rest).poll_frame(cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.frames.is_empty() && self.rest.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered: usize = self
+            .frames
+            .iter()
+            .filter_map(Frame::data_ref)
+            .map(Bytes::len)
+            .sum();
+        let rest = self.rest.size_hint();
+        let mut hint = SizeHint::new();
+        hint.set_lower(rest.lower() + buffered as u64);
+        if let Some(upper) = rest.upper() {
+            hint.set_upper(upper + buffered as u64);
+        }
+        hint
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use http_body_util::BodyExt;
+use hyper::{header::CONTENT_LENGTH, Method, Request, Response, Uri};
+use tokio::sync::broadcast;
+
+use super::{
+    full_body, BreakpointAction, BreakpointEvent, BreakpointOutcome, BreakpointPhase,
+    BreakpointRecord, BreakpointRule, Breakpoints, EditableRequest, EditableResponse,
+    PendingBreakpoint, RequestMatcher, RequestOrResponse, TimeoutAction, TimeoutPolicy,
+};
+
+fn request(body: &'static str) -> Request<super::Body> {
+    Request::post("https://api.test/upload")
+        .header(CONTENT_LENGTH, body.len())
+        .body(full_body(body))
+        .unwrap()
+}
+
+async fn paused(events: &mut broadcast::Receiver<BreakpointEvent>) -> PendingBreakpoint {
+    match events.recv().await.unwrap() {
+        BreakpointEvent::Paused { breakpoint } => *breakpoint,
+        event => panic!("expected a pause, got {:?}", event),
+    }
+}
+
+async fn body_text(body: super::Body) -> String {
+    let bytes = body.collect().await.unwrap().to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[test]
+fn test_should_break() {
+    let breakpoints = Breakpoints::new();
+    breakpoints.set_rules(vec![BreakpointRule {
+        matcher: RequestMatcher {
+            host: Some("api.*".to_string()),
+            method: Some("post".to_string()),
+            ..Default::default()
+        },
+        request: true,
+        response: false,
+    }]);
+
+    let uri: Uri = "https://api.test/upload".parse().unwrap();
+    assert!(breakpoints.should_break(BreakpointPhase::Request, &Method::POST, &uri));
+    assert!(!breakpoints.should_break(BreakpointPhase::Response, &Method::POST, &uri));
+    assert!(!breakpoints.should_break(BreakpointPhase::Request, &Method::GET, &uri));
+    let other: Uri = "https://www.test/upload".parse().unwrap();
+    assert!(!breakpoints.should_break(BreakpointPhase::Request, &Method::POST, &other));
+}
+
+#[tokio::test]
+async fn test_resume_with_edited_request() {
+    let breakpoints = Arc::new(Breakpoints::new());
+    let mut events = breakpoints.subscribe();
+    let paused_task = {
+        let breakpoints = breakpoints.clone();
+        tokio::spawn(async move { breakpoints.pause_request(request("hello"), 1024).await })
+    };
+
+    let breakpoint = paused(&mut events).await;
+    assert_eq!(breakpoint.phase, BreakpointPhase::Request);
+    assert_eq!(breakpoint.request.body, b"hello");
+    assert_eq!(breakpoints.pending().len(), 1);
+
+    let mut edited = breakpoint.request.clone();
+    edited.uri = "https://api.test/other".to_string();
+    edited.body = b"bye".to_vec();
+    breakpoints
+        .resolve(
+            breakpoint.id,
+            BreakpointAction::ResumeWithRequest { request: edited },
+        )
+        .unwrap();
+
+    let RequestOrResponse::Request(req) = paused_task.await.unwrap() else {
+        panic!("request was not resumed");
+    };
+    assert_eq!(req.uri(), "https://api.test/other");
+    // the length follows the edited body
+    assert_eq!(req.headers()[CONTENT_LENGTH], "3");
+    let record = req.extensions().get::<BreakpointRecord>().unwrap();
+    assert_eq!(record.outcome, BreakpointOutcome::Edited);
+    assert_eq!(record.paused_at, breakpoint.paused_at);
+    assert_eq!(record.original_request.as_ref().unwrap().body, b"hello");
+    assert_eq!(body_text(req.into_body()).await, "bye");
+    assert!(breakpoints.pending().is_empty());
+    assert!(breakpoints
+        .resolve(breakpoint.id, BreakpointAction::Resume)
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_respond_to_paused_response() {
+    let breakpoints = Arc::new(Breakpoints::new());
+    let mut events = breakpoints.subscribe();
+    let sent = EditableRequest {
+        method: "GET".to_string(),
+        uri: "https://api.test/items".to_string(),
+        headers: vec![("accept".to_string(), "application/json".to_string())],
+        body: vec![],
+        trailers: vec![],
+    };
+    let paused_task = {
+        let breakpoints = breakpoints.clone();
+        let res = Response::new(full_body("[]"));
+        tokio::spawn(async move { breakpoints.pause_response(sent, res, 1024).await })
+    };
+
+    let breakpoint = paused(&mut events).await;
+    assert_eq!(breakpoint.phase, BreakpointPhase::Response);
+    assert_eq!(breakpoint.request.headers[0].0, "accept");
+    assert_eq!(breakpoint.response.unwrap().body, b"[]");
+
+    let response = EditableResponse {
+        status: 503,
+        headers: vec![],
+        body: b"down".to_vec(),
+        trailers: vec![],
+    };
+    breakpoints
+        .resolve(breakpoint.id, BreakpointAction::Respond { response })
+        .unwrap();
+    let res = paused_task.await.unwrap();
+    assert_eq!(res.status(), 503);
+    let record = res.extensions().get::<BreakpointRecord>().unwrap();
+    assert_eq!(
+        (record.phase, record.outcome),
+        (BreakpointPhase::Response, BreakpointOutcome::Responded)
+    );
+    assert_eq!(record.original_response.as_ref().unwrap().body, b"[]");
+    assert_eq!(body_text(res.into_body()).await, "down");
+}
+
+#[tokio::test]
+async fn test_timeout_resumes() {
+    let breakpoints = Breakpoints::new();
+    breakpoints.set_timeout_policy(TimeoutPolicy {
+        timeout_ms: Some(10),
+        action: TimeoutAction::Resume,
+    });
+
+    let RequestOrResponse::Request(req) = breakpoints.pause_request(request("hello"), 1024).await
+    else {
+        panic!("request was not resumed");
+    };
+    assert_eq!(body_text(req.into_body()).await, "hello");
+    assert!(breakpoints.pending().is_empty());
+
+    breakpoints.set_timeout_policy(TimeoutPolicy {
+        timeout_ms: Some(10),
+        action: TimeoutAction::Abort,
+    });
+    let RequestOrResponse::Response(res) = breakpoints.pause_request(request("hello"), 1024).await
+    else {
+        panic!("request was not aborted");
+    };
+    assert_eq!(res.status(), 502);
+}
+
+#[tokio::test]
+async fn test_pending_dropped_with_exchange() {
+    let breakpoints = Arc::new(Breakpoints::new());
+    let mut events = breakpoints.subscribe();
+    let paused_task = {
+        let breakpoints = breakpoints.clone();
+        tokio::spawn(async move { breakpoints.pause_request(request("hello"), 1024).await })
+    };
+    let breakpoint = paused(&mut events).await;
+
+    // the client going away drops the service future
+    paused_task.abort();
+    assert!(paused_task.await.is_err_and(|e| e.is_cancelled()));
+
+    assert!(breakpoints.pending().is_empty());
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        BreakpointEvent::Resolved { id } if id == breakpoint.id
+    ));
+}
+
+#[tokio::test]
+async fn test_large_body_not_held() {
+    let breakpoints = Breakpoints::new();
+    let RequestOrResponse::Request(req) = breakpoints
+        .pause_request(request("more than the limit"), 4)
+        .await
+    else {
+        panic!("request was not forwarded");
+    };
+    assert_eq!(body_text(req.into_body()).await, "more than the limit");
+    assert!(breakpoints.pending().is_empty());
+}
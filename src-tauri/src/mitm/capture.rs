@@ -0,0 +1,179 @@
+use std::{
+    mem,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use hyper::{
+    body::{Body as HttpBody, Bytes, Frame, SizeHint},
+    header::{HeaderValue, CONTENT_ENCODING},
+    HeaderMap,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    content_encodings, decode_body, header_pairs, Body, ContentEncoding, StreamDecoder,
+    MAX_DECODED_SIZE,
+};
+
+pub const DEFAULT_CAPTURE_LIMIT: usize = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BodyCapture {
+    /// The first bytes of the body as sent on the wire, up to the capture limit.
+    pub data: Vec<u8>,
+    pub size: u64,
+    pub truncated: bool,
+    pub trailers: Option<Vec<(String, String)>>,
+    /// `false` when the stream failed or was abandoned before its end.
+    pub complete: bool,
+}
+
+impl BodyCapture {
+    /// The captured bytes with the `Content-Encoding` listed in `headers`
+    /// undone. Fails for truncated bodies of most encodings.
+    pub fn decoded(&self, headers: &[(String, String)]) -> anyhow::Result<Bytes> {
+        decode_body(&self.data, &encodings(headers)?, MAX_DECODED_SIZE)
+    }
+
+    /// The first `limit` bytes of [`Self::decoded`], without inflating the
+    /// rest. Truncated bodies give what decodes of them.
+    pub fn decoded_prefix(
+        &self,
+        headers: &[(String, String)],
+        limit: usize,
+    ) -> anyhow::Result<Vec<u8>> {
+        let encodings = encodings(headers)?;
+        if encodings.is_empty() {
+            return Ok(self.data[..self.data.len().min(limit)].to_vec());
+        }
+        Ok(StreamDecoder::new(&encodings, limit)?.decode(&self.data)?)
+    }
+}
+
+#[cfg(test)]
+impl BodyCapture {
+    /// A body read to its end within the capture limit.
+    pub fn complete(data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        Self {
+            size: data.len() as u64,
+            data,
+            complete: true,
+            ..Default::default()
+        }
+    }
+}
+
+fn encodings(headers: &[(String, String)]) -> anyhow::Result<Vec<ContentEncoding>> {
+    let mut map = HeaderMap::new();
+    for (_, value) in headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("content-encoding"))
+    {
+        map.append(CONTENT_ENCODING, HeaderValue::from_str(value)?);
+    }
+    content_encodings(&map)
+}
+
+type ChunkFn = Box<dyn FnMut(&Bytes) + Send + Sync>;
+type EndFn = Box<dyn FnOnce(BodyCapture) + Send + Sync>;
+
+/// Hands every frame on as soon as it arrives while keeping a copy of up to
+/// `limit` bytes. `on_end` gets the capture once the body ends, fails or is
+/// dropped.
+pub struct TeeBody {
+    inner: Body,
+    limit: usize,
+    capture: BodyCapture,
+    on_chunk: Option<ChunkFn>,
+    on_end: Option<EndFn>,
+}
+
+impl TeeBody {
+    pub fn new<F>(inner: Body, limit: usize, on_end: F) -> Self
+    where
+        F: FnOnce(BodyCapture) + Send + Sync + 'static,
+    {
+        Self {
+            inner,
+            limit,
+            capture: BodyCapture::default(),
+            on_chunk: None,
+            on_end: Some(Box::new(on_end)),
+        }
+    }
+
+    /// Also called for every data chunk, whether it is captured or not.
+    pub fn on_chunk<F>(mut self, on_chunk: F) -> Self
+    where
+        F: FnMut(&Bytes) + Send + Sync + 'static,
+    {
+        self.on_chunk = Some(Box::new(on_chunk));
+        self
+    }
+
+    fn record(&mut self, data: &Bytes) {
+        let room = self.limit.saturating_sub(self.capture.data.len());
+        if data.len() > room {
+            self.capture.truncated = true;
+        }
+        self.capture
+            .data
+            .extend_from_slice(&data[..data.len().min(room)]);
+        self.capture.size += data.len() as u64;
+
+        if let Some(on_chunk) = self.on_chunk.as_mut() {
+            on_chunk(data);
+        }
+    }
+
+    fn finish(&mut self, complete: bool) {
+        if let Some(on_end) = self.on_end.take() {
+            let mut capture = mem::take(&mut self.capture);
+            capture.complete = complete;
+            on_end(capture);
+        }
+    }
+}
+
+impl HttpBody for TeeBody {
+    type Data = Bytes;
+    type Error = anyhow::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    self.record(data);
+                } else if let Some(trailers) = frame.trailers_ref() {
+                    self.capture.trailers = Some(header_pairs(trailers));
+                }
+            }
+            Some(Err(_)) => self.finish(false),
+            None => self.finish(true),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for TeeBody {
+    fn drop(&mut self) {
+        // hyper stops polling once the body reports its end
+        let complete = self.inner.is_end_stream();
+        self.finish(complete);
+    }
+}
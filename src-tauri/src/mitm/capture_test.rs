@@ -0,0 +1,80 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use http_body_util::BodyExt;
+use hyper::body::{Body as HttpBody, Bytes, Frame};
+
+use super::{full_body, Body, BodyCapture, TeeBody};
+
+struct Chunks(VecDeque<&'static str>);
+
+impl HttpBody for Chunks {
+    type Data = Bytes;
+    type Error = anyhow::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Poll::Ready(self.0.pop_front().map(|c| Ok(Frame::data(c.into()))))
+    }
+}
+
+fn chunked(chunks: &[&'static str]) -> Body {
+    Chunks(chunks.iter().copied().collect()).boxed()
+}
+
+fn tee(body: Body, limit: usize) -> (TeeBody, Arc<Mutex<Option<BodyCapture>>>) {
+    let captured = Arc::new(Mutex::new(None));
+    let sink = captured.clone();
+    let body = TeeBody::new(body, limit, move |capture| {
+        *sink.lock().unwrap() = Some(capture);
+    });
+    (body, captured)
+}
+
+#[tokio::test]
+async fn test_tee_forwards_everything_and_caps_capture() {
+    let (body, captured) = tee(chunked(&["data: 1\n\n", "data: 2\n\n", "data: 3\n\n"]), 12);
+
+    let chunks = Arc::new(Mutex::new(vec![]));
+    let seen = chunks.clone();
+    let body = body.on_chunk(move |chunk| seen.lock().unwrap().push(chunk.clone()));
+
+    let forwarded = body.collect().await.unwrap().to_bytes();
+    assert_eq!(forwarded, "data: 1\n\ndata: 2\n\ndata: 3\n\n");
+    assert_eq!(chunks.lock().unwrap().len(), 3);
+
+    let capture = captured.lock().unwrap().take().unwrap();
+    assert_eq!(capture.data, b"data: 1\n\ndat");
+    assert_eq!(capture.size, 27);
+    assert!(capture.truncated);
+    assert!(capture.complete);
+}
+
+#[tokio::test]
+async fn test_tee_abandoned_stream() {
+    let (mut body, captured) = tee(chunked(&["first", "second"]), 1024);
+
+    let frame = body.frame().await.unwrap().unwrap();
+    assert_eq!(frame.into_data().unwrap(), "first");
+    drop(body);
+
+    let capture = captured.lock().unwrap().take().unwrap();
+    assert_eq!(capture.data, b"first");
+    assert!(!capture.complete);
+}
+
+#[tokio::test]
+async fn test_tee_full_body() {
+    let (body, captured) = tee(full_body("done"), 1024);
+    body.collect().await.unwrap();
+
+    let capture = captured.lock().unwrap().take().unwrap();
+    assert_eq!(capture.data, b"done");
+    assert!(capture.complete && !capture.truncated);
+}
@@ -0,0 +1,213 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::{anyhow, bail};
+use hyper::{Request, Version};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        oneshot, Semaphore,
+    },
+    task::{JoinError, JoinSet},
+};
+
+use super::{Body, EditableRequest, Flow, FlowId, FlowKind};
+
+/// The most times one replay sends its request.
+pub const MAX_REPEAT: usize = 1000;
+
+/// A request handed to the proxy to go through its pipeline again.
+pub(super) struct ReplayJob {
+    pub request: Request<Body>,
+    pub replay_of: Option<FlowId>,
+    pub done_tx: oneshot::Sender<anyhow::Result<FlowId>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReplayOptions {
+    /// At most [`MAX_REPEAT`].
+    pub repeat: usize,
+    /// How many of the repeats are in flight at once.
+    pub concurrency: usize,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            repeat: 1,
+            concurrency: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayReport {
+    /// The new flows in the order they were sent.
+    pub flows: Vec<FlowId>,
+    /// Requests that never made it into a flow.
+    pub errors: Vec<String>,
+    pub elapsed_ms: u64,
+}
+
+/// Re-sends recorded requests through a running proxy, so they see the
+/// same handler, rules and upstream client as captured traffic.
+pub struct ClientReplay {
+    jobs_tx: UnboundedSender<ReplayJob>,
+    jobs_rx: Mutex<Option<UnboundedReceiver<ReplayJob>>>,
+}
+
+impl Default for ClientReplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientReplay {
+    pub fn new() -> Self {
+        let (jobs_tx, jobs_rx) = unbounded_channel();
+        Self {
+            jobs_tx,
+            jobs_rx: Mutex::new(Some(jobs_rx)),
+        }
+    }
+
+    /// Sends `request` `options.repeat` times on behalf of flow `replay_of`.
+    pub async fn replay(
+        &self,
+        replay_of: FlowId,
+        request: EditableRequest,
+        version: Version,
+        options: ReplayOptions,
+    ) -> anyhow::Result<ReplayReport> {
+        self.send_jobs(Some(replay_of), request, version, options)
+            .await
+    }
+
+    /// Like [`Self::replay`] for a request that wasn't captured.
+    pub async fn send(
+        &self,
+        request: EditableRequest,
+        version: Version,
+        options: ReplayOptions,
+    ) -> anyhow::Result<ReplayReport> {
+        self.send_jobs(None, request, version, options).await
+    }
+
+    async fn send_jobs(
+        &self,
+        replay_of: Option<FlowId>,
+        request: EditableRequest,
+        version: Version,
+        options: ReplayOptions,
+    ) -> anyhow::Result<ReplayReport> {
+        if self.jobs_rx.lock().unwrap().is_some() {
+            bail!("No proxy is running to replay through");
+        }
+        if options.repeat > MAX_REPEAT {
+            bail!("Can't repeat a request more than {} times", MAX_REPEAT);
+        }
+        // fail early instead of once per repeat
+        request.clone().into_request(version)?;
+
+        let started = Instant::now();
+        let permits = Arc::new(Semaphore::new(options.concurrency.max(1)));
+        let mut sends = JoinSet::new();
+        let mut report = ReplayReport {
+            flows: vec![],
+            errors: vec![],
+            elapsed_ms: 0,
+        };
+        let mut record = |result: Result<anyhow::Result<FlowId>, JoinError>| match result
+            .map_err(anyhow::Error::from)
+            .and_then(|r| r)
+        {
+            Ok(id) => report.flows.push(id),
+            Err(e) => report.errors.push(e.to_string()),
+        };
+        // only as many sends as there are permits exist at a time
+        for _ in 0..options.repeat.max(1) {
+            let permit = loop {
+                tokio::select! {
+                    permit = permits.clone().acquire_owned() => break permit?,
+                    Some(result) = sends.join_next() => record(result),
+                }
+            };
+            let jobs_tx = self.jobs_tx.clone();
+            let request = request.clone();
+
+            sends.spawn(async move {
+                let _permit = permit;
+                let (done_tx, done_rx) = oneshot::channel();
+                jobs_tx
+                    .send(ReplayJob {
+                        request: request.into_request(version)?,
+                        replay_of,
+                        done_tx,
+                    })
+                    .map_err(|_| anyhow!("Proxy stopped"))?;
+                done_rx.await.map_err(|_| anyhow!("Proxy stopped"))?
+            });
+        }
+
+        while let Some(result) = sends.join_next().await {
+            record(result);
+        }
+        report.flows.sort_unstable();
+        report.elapsed_ms = started.elapsed().as_millis() as u64;
+        Ok(report)
+    }
+
+    /// Only the first proxy built with this gets the jobs.
+    pub(super) fn take_jobs(&self) -> Option<UnboundedReceiver<ReplayJob>> {
+        self.jobs_rx.lock().unwrap().take()
+    }
+}
+
+/// The request of `flow` as it went upstream, ready to be edited.
+pub fn replay_request(flow: &Flow) -> anyhow::Result<EditableRequest> {
+    if flow.kind == FlowKind::Tunnel {
+        bail!("Tunnels can't be replayed");
+    }
+    let body = match &flow.request_body {
+        Some(body) if body.truncated || !body.complete => {
+            bail!("The request body was only partly captured")
+        }
+        Some(body) => body.clone(),
+        // a body that was sent but never captured can't be made up
+        None if content_length(flow) != 0 => bail!("The request body wasn't captured"),
+        None => Default::default(),
+    };
+
+    Ok(EditableRequest {
+        method: flow.request.method.clone(),
+        uri: flow.request.uri.clone(),
+        headers: flow.request.headers.clone(),
+        body: body.data,
+        trailers: body.trailers.unwrap_or_default(),
+    })
+}
+
+fn content_length(flow: &Flow) -> u64 {
+    flow.request
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Parses a version as flows record it, e.g. `HTTP/2.0`.
+pub fn parse_version(version: &str) -> Version {
+    match version {
+        "HTTP/0.9" => Version::HTTP_09,
+        "HTTP/1.0" => Version::HTTP_10,
+        "HTTP/2.0" => Version::HTTP_2,
+        "HTTP/3.0" => Version::HTTP_3,
+        _ => Version::HTTP_11,
+    }
+}
@@ -0,0 +1,142 @@
+use std::{sync::Arc, time::Duration};
+
+use http_body_util::BodyExt;
+use hyper::{body::Incoming, service::service_fn, Request, Response};
+use hyper_util::rt::TokioIo;
+use tokio::{net::TcpListener, sync::broadcast, time::sleep};
+
+use super::{
+    full_body, replay_request, BodyCapture, ClientReplay, EditableRequest, FlowRequest, FlowStore,
+    HttpHandler, MitmProxy, ReplayOptions, MAX_REPEAT,
+};
+
+struct PassThrough;
+
+impl HttpHandler for PassThrough {}
+
+async fn serve_echo(listener: TcpListener) {
+    loop {
+        let (stream, _) = listener.accept().await.unwrap();
+        tokio::spawn(async move {
+            let service = service_fn(|req: Request<Incoming>| async move {
+                let method = req.method().to_string();
+                let body = req.into_body().collect().await?.to_bytes();
+                let reply = format!("{} {}", method, String::from_utf8_lossy(&body));
+                Ok::<_, hyper::Error>(Response::new(full_body(reply)))
+            });
+            let _ = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
+
+#[test]
+fn test_replay_request() {
+    let store = FlowStore::new();
+    let id = store.insert(
+        FlowRequest {
+            method: "POST".to_string(),
+            uri: "http://example.com/".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: vec![("content-length".to_string(), "4".to_string())],
+            raw_head: None,
+            timestamp: 0,
+        },
+        None,
+    );
+    // the body was sent but not captured
+    assert!(replay_request(&store.get(id).unwrap()).is_err());
+
+    let empty = store.insert(
+        FlowRequest {
+            method: "GET".to_string(),
+            uri: "http://example.com/".to_string(),
+            version: "HTTP/1.1".to_string(),
+            headers: vec![("Content-Length".to_string(), "0".to_string())],
+            raw_head: None,
+            timestamp: 0,
+        },
+        None,
+    );
+    assert!(replay_request(&store.get(empty).unwrap())
+        .unwrap()
+        .body
+        .is_empty());
+
+    store.set_request_body(
+        id,
+        BodyCapture {
+            data: b"pi".to_vec(),
+            size: 4,
+            truncated: true,
+            complete: true,
+            trailers: None,
+        },
+    );
+    assert!(replay_request(&store.get(id).unwrap()).is_err());
+}
+
+#[tokio::test]
+async fn test_replay_through_proxy() {
+    let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = upstream.local_addr().unwrap().port();
+    tokio::spawn(serve_echo(upstream));
+
+    let client_replay = Arc::new(ClientReplay::new());
+    let request = EditableRequest {
+        method: "POST".to_string(),
+        uri: format!("http://127.0.0.1:{}/echo", port),
+        headers: vec![("content-length".to_string(), "0".to_string())],
+        body: b"ping".to_vec(),
+        trailers: vec![],
+    };
+    let version = hyper::Version::HTTP_11;
+    assert!(client_replay
+        .replay(1, request.clone(), version, ReplayOptions::default())
+        .await
+        .is_err());
+
+    let (shutdown_tx, _) = broadcast::channel::<()>(1);
+    let flow_store = Arc::new(FlowStore::new());
+    let proxy = MitmProxy::builder()
+        .with_addr("127.0.0.1:8087")
+        .with_handler(PassThrough)
+        .with_flow_store(flow_store.clone())
+        .with_client_replay(client_replay.clone())
+        .with_shutdown(shutdown_tx.clone())
+        .build();
+    tokio::spawn(async move { proxy.start().await });
+    sleep(Duration::from_millis(200)).await;
+
+    let options = ReplayOptions {
+        repeat: 5,
+        concurrency: 2,
+    };
+    let report = client_replay
+        .replay(1, request.clone(), version, options)
+        .await
+        .unwrap();
+    assert!(report.errors.is_empty());
+    assert_eq!(report.flows.len(), 5);
+
+    let too_many = ReplayOptions {
+        repeat: MAX_REPEAT + 1,
+        concurrency: 1,
+    };
+    assert!(client_replay
+        .replay(1, request.clone(), version, too_many)
+        .await
+        .is_err());
+
+    for id in report.flows {
+        let flow = flow_store.get(id).unwrap();
+        assert_eq!(flow.replay_of, Some(1));
+        assert_eq!(flow.response.as_ref().unwrap().status, 200);
+        assert_eq!(flow.request_body.unwrap().data, b"ping");
+        assert_eq!(flow.response_body.unwrap().data, b"POST ping");
+        assert!(flow.finished_at.is_some());
+    }
+
+    let _ = shutdown_tx.send(());
+}
@@ -0,0 +1,316 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    mem,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, bail};
+use flate2::{
+    read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder},
+    write::{self, GzEncoder, ZlibEncoder},
+    Compression,
+};
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::{
+    body::Bytes,
+    header::{HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH},
+    HeaderMap,
+};
+
+use super::Body;
+
+/// Upper bound for decoded bodies, guards against decompression bombs.
+pub const MAX_DECODED_SIZE: usize = 64 * 1024 * 1024;
+
+/// Upper bound for bodies [`CapturedBody::collect`] buffers as received.
+pub const MAX_COLLECTED_SIZE: usize = 64 * 1024 * 1024;
+
+const BUFFER_SIZE: usize = 8 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentEncoding {
+    Gzip,
+    Deflate,
+    Brotli,
+    Zstd,
+}
+
+impl ContentEncoding {
+    pub fn parse(token: &str) -> Option<Self> {
+        match token.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            "br" => Some(Self::Brotli),
+            "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gzip",
+            Self::Deflate => "deflate",
+            Self::Brotli => "br",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+/// Encodings listed in `Content-Encoding`, in the order they were applied.
+pub fn content_encodings(headers: &HeaderMap) -> anyhow::Result<Vec<ContentEncoding>> {
+    let mut encodings = vec![];
+
+    for value in headers.get_all(CONTENT_ENCODING) {
+        for token in value.to_str()?.split(',').map(str::trim) {
+            if token.is_empty() || token.eq_ignore_ascii_case("identity") {
+                continue;
+            }
+            let encoding = ContentEncoding::parse(token)
+                .ok_or_else(|| anyhow!("Unsupported content encoding: {}", token))?;
+            encodings.push(encoding);
+        }
+    }
+
+    Ok(encodings)
+}
+
+/// Streams `data` through one decoder per encoding, failing once the output
+/// grows beyond `max_size`.
+pub fn decode_body(
+    data: &[u8],
+    encodings: &[ContentEncoding],
+    max_size: usize,
+) -> anyhow::Result<Bytes> {
+    let mut reader: Box<dyn Read + '_> = Box::new(data);
+    for encoding in encodings.iter().rev() {
+        reader = decoder(reader, *encoding)?;
+    }
+
+    let mut decoded = Vec::new();
+    reader
+        .take((max_size as u64).saturating_add(1))
+        .read_to_end(&mut decoded)?;
+
+    if decoded.len() > max_size {
+        bail!("Decoded body exceeds {} bytes", max_size);
+    }
+
+    Ok(decoded.into())
+}
+
+pub fn encode_body(data: &[u8], encodings: &[ContentEncoding]) -> anyhow::Result<Bytes> {
+    let mut encoded = data.to_vec();
+    for encoding in encodings {
+        encoded = encode_one(&encoded, *encoding)?;
+    }
+    Ok(encoded.into())
+}
+
+fn decoder<'a>(
+    reader: Box<dyn Read + 'a>,
+    encoding: ContentEncoding,
+) -> anyhow::Result<Box<dyn Read + 'a>> {
+    Ok(match encoding {
+        ContentEncoding::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        ContentEncoding::Deflate => {
+            // `deflate` is supposed to be zlib-wrapped, but plenty of servers send raw deflate
+            let mut reader = BufReader::new(reader);
+            if is_zlib_header(reader.fill_buf()?) {
+                Box::new(ZlibDecoder::new(reader))
+            } else {
+                Box::new(DeflateDecoder::new(reader))
+            }
+        }
+        ContentEncoding::Brotli => Box::new(brotli::Decompressor::new(reader, BUFFER_SIZE)),
+        ContentEncoding::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+    })
+}
+
+type BoxedWriter = Box<dyn Write + Send + Sync>;
+
+/// Decodes a body chunk by chunk, for content that is consumed while it
+/// is still streaming.
+pub struct StreamDecoder {
+    writer: BoxedWriter,
+    output: Arc<Mutex<Output>>,
+}
+
+impl StreamDecoder {
+    /// Decodes at most `limit` bytes over all calls to [`Self::decode`].
+    pub fn new(encodings: &[ContentEncoding], limit: usize) -> anyhow::Result<Self> {
+        let output = Arc::new(Mutex::new(Output {
+            data: Vec::new(),
+            remaining: limit,
+        }));
+
+        let mut writer: BoxedWriter = Box::new(SharedBuffer(output.clone()));
+        for encoding in encodings {
+            writer = stream_decoder(writer, *encoding)?;
+        }
+
+        Ok(Self { writer, output })
+    }
+
+    /// Everything decodable from the chunks fed so far. Decoding stops at
+    /// the limit, the output up to it is returned and later calls fail.
+    pub fn decode(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        let result = self
+            .writer
+            .write_all(chunk)
+            .and_then(|()| self.writer.flush());
+        let data = mem::take(&mut self.output.lock().unwrap().data);
+        match result {
+            Err(_) if !data.is_empty() && self.output.lock().unwrap().remaining == 0 => Ok(data),
+            Err(e) => Err(e),
+            Ok(()) => Ok(data),
+        }
+    }
+}
+
+/// Decoded bytes not taken yet, and how many more may be decoded.
+struct Output {
+    data: Vec<u8>,
+    remaining: usize,
+}
+
+/// Refuses writes past the limit, which stops the decoders feeding it
+/// before they inflate any further.
+struct SharedBuffer(Arc<Mutex<Output>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut output = self.0.lock().unwrap();
+        if output.remaining == 0 && !buf.is_empty() {
+            return Err(io::Error::other("Decoded body too large"));
+        }
+        let n = buf.len().min(output.remaining);
+        output.remaining -= n;
+        output.data.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn stream_decoder(writer: BoxedWriter, encoding: ContentEncoding) -> anyhow::Result<BoxedWriter> {
+    Ok(match encoding {
+        ContentEncoding::Gzip => Box::new(write::MultiGzDecoder::new(writer)),
+        // no way to peek at the header here, go with what the spec says
+        ContentEncoding::Deflate => Box::new(write::ZlibDecoder::new(writer)),
+        ContentEncoding::Brotli => Box::new(brotli::DecompressorWriter::new(writer, BUFFER_SIZE)),
+        ContentEncoding::Zstd => Box::new(zstd::stream::write::Decoder::new(writer)?),
+    })
+}
+
+fn encode_one(data: &[u8], encoding: ContentEncoding) -> anyhow::Result<Vec<u8>> {
+    Ok(match encoding {
+        ContentEncoding::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?
+        }
+        ContentEncoding::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?
+        }
+        ContentEncoding::Brotli => {
+            let mut encoder = brotli::CompressorWriter::new(Vec::new(), BUFFER_SIZE, 5, 22);
+            encoder.write_all(data)?;
+            encoder.flush()?;
+            encoder.into_inner()
+        }
+        ContentEncoding::Zstd => zstd::stream::encode_all(data, 0)?,
+    })
+}
+
+fn is_zlib_header(head: &[u8]) -> bool {
+    match head {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+/// A fully buffered body kept in its original wire form.
+///
+/// Handlers decode it for inspection; unless [`CapturedBody::rewrite`] is
+/// used the exact bytes received are forwarded.
+pub struct CapturedBody {
+    raw: Bytes,
+    trailers: Option<HeaderMap>,
+    encodings: anyhow::Result<Vec<ContentEncoding>>,
+}
+
+impl CapturedBody {
+    pub async fn collect(body: Body, headers: &HeaderMap) -> anyhow::Result<Self> {
+        Self::collect_with_limit(body, headers, MAX_COLLECTED_SIZE).await
+    }
+
+    /// Fails once more than `max_size` bytes arrive, without buffering them.
+    pub async fn collect_with_limit(
+        body: Body,
+        headers: &HeaderMap,
+        max_size: usize,
+    ) -> anyhow::Result<Self> {
+        let collected = Limited::new(body, max_size)
+            .collect()
+            .await
+            .map_err(|e| anyhow!(e))?;
+        let trailers = collected.trailers().cloned();
+
+        Ok(Self {
+            raw: collected.to_bytes(),
+            trailers,
+            encodings: content_encodings(headers),
+        })
+    }
+
+    pub fn raw(&self) -> &Bytes {
+        &self.raw
+    }
+
+    pub fn trailers(&self) -> Option<&HeaderMap> {
+        self.trailers.as_ref()
+    }
+
+    pub fn encodings(&self) -> anyhow::Result<&[ContentEncoding]> {
+        self.encodings
+            .as_deref()
+            .map_err(|e| anyhow!(e.to_string()))
+    }
+
+    pub fn decoded(&self) -> anyhow::Result<Bytes> {
+        self.decoded_with_limit(MAX_DECODED_SIZE)
+    }
+
+    pub fn decoded_with_limit(&self, max_size: usize) -> anyhow::Result<Bytes> {
+        decode_body(&self.raw, self.encodings()?, max_size)
+    }
+
+    pub fn into_body(self) -> Body {
+        body_with_trailers(self.raw, self.trailers)
+    }
+
+    /// Replaces the decoded content, re-applying the original encodings and
+    /// fixing up `Content-Length` in `headers`.
+    pub fn rewrite<T: Into<Bytes>>(self, headers: &mut HeaderMap, data: T) -> anyhow::Result<Body> {
+        let encoded = encode_body(&data.into(), self.encodings()?)?;
+
+        if headers.contains_key(CONTENT_LENGTH) {
+            headers.insert(CONTENT_LENGTH, HeaderValue::from(encoded.len()));
+        }
+
+        Ok(body_with_trailers(encoded, self.trailers))
+    }
+}
+
+pub(super) fn body_with_trailers(data: Bytes, trailers: Option<HeaderMap>) -> Body {
+    let body = Full::new(data).map_err(|never| match never {});
+
+    match trailers {
+        Some(trailers) => body.with_trailers(async { Some(Ok(trailers)) }).boxed(),
+        None => body.boxed(),
+    }
+}
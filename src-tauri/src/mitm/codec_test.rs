@@ -0,0 +1,101 @@
+use hyper::{
+    header::{HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH},
+    HeaderMap,
+};
+
+use super::{
+    content_encodings, decode_body, encode_body, full_body, CapturedBody, ContentEncoding,
+};
+
+#[test]
+fn test_stacked_encodings_round_trip() {
+    let data = b"<div>hello devya</div>".repeat(32);
+    let encodings = [
+        ContentEncoding::Gzip,
+        ContentEncoding::Brotli,
+        ContentEncoding::Zstd,
+        ContentEncoding::Deflate,
+    ];
+
+    let encoded = encode_body(&data, &encodings).unwrap();
+    let decoded = decode_body(&encoded, &encodings, usize::MAX).unwrap();
+
+    assert_eq!(decoded.as_ref(), data.as_slice());
+}
+
+#[test]
+fn test_raw_deflate() {
+    use flate2::{write::DeflateEncoder, Compression};
+    use std::io::Write;
+
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(b"raw deflate").unwrap();
+    let encoded = encoder.finish().unwrap();
+
+    let decoded = decode_body(&encoded, &[ContentEncoding::Deflate], usize::MAX).unwrap();
+
+    assert_eq!(decoded.as_ref(), b"raw deflate");
+}
+
+#[test]
+fn test_decompression_bomb() {
+    let data = vec![0u8; 1024 * 1024];
+    let encoded = encode_body(&data, &[ContentEncoding::Gzip]).unwrap();
+
+    assert!(decode_body(&encoded, &[ContentEncoding::Gzip], 1024).is_err());
+}
+
+#[test]
+fn test_content_encodings() {
+    let mut headers = HeaderMap::new();
+    headers.append(CONTENT_ENCODING, HeaderValue::from_static("gzip, identity"));
+    headers.append(CONTENT_ENCODING, HeaderValue::from_static("BR"));
+
+    assert_eq!(
+        content_encodings(&headers).unwrap(),
+        vec![ContentEncoding::Gzip, ContentEncoding::Brotli]
+    );
+
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("compress"));
+    assert!(content_encodings(&headers).is_err());
+}
+
+#[tokio::test]
+async fn test_captured_body_limit() {
+    let headers = HeaderMap::new();
+    let body = full_body(vec![0u8; 2048]);
+
+    assert!(CapturedBody::collect_with_limit(body, &headers, 1024)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_captured_body_rewrite() {
+    use http_body_util::BodyExt;
+
+    let encoded = encode_body(b"original", &[ContentEncoding::Gzip]).unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(encoded.len()));
+
+    let captured = CapturedBody::collect(full_body(encoded.clone()), &headers)
+        .await
+        .unwrap();
+    assert_eq!(captured.raw(), &encoded);
+    assert_eq!(captured.decoded().unwrap().as_ref(), b"original");
+
+    let body = captured.rewrite(&mut headers, "rewritten").unwrap();
+    let rewritten = body.collect().await.unwrap().to_bytes();
+
+    assert_eq!(
+        headers[CONTENT_LENGTH].to_str().unwrap(),
+        rewritten.len().to_string()
+    );
+    assert_eq!(
+        decode_body(&rewritten, &[ContentEncoding::Gzip], usize::MAX)
+            .unwrap()
+            .as_ref(),
+        b"rewritten"
+    );
+}
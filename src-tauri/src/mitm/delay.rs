@@ -0,0 +1,119 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use http_body_util::BodyExt;
+use hyper::body::{Body as HttpBody, Bytes, Frame, SizeHint};
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Sleep};
+
+use super::Body;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DelayMode {
+    /// Hold everything back, headers included.
+    #[default]
+    FirstByte,
+    /// Let the message start but hold back its last chunk.
+    FullBody,
+}
+
+/// A fixed delay of `min_ms`, or a random one up to `max_ms`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DelaySpec {
+    pub min_ms: u64,
+    #[serde(default)]
+    pub max_ms: Option<u64>,
+    #[serde(default)]
+    pub mode: DelayMode,
+}
+
+impl DelaySpec {
+    pub fn duration(&self) -> Duration {
+        let ms = match self.max_ms {
+            Some(max_ms) if max_ms > self.min_ms => rand::random_range(self.min_ms..=max_ms),
+            _ => self.min_ms,
+        };
+        Duration::from_millis(ms)
+    }
+}
+
+/// Applies `delay` to a message with `body`: [`DelayMode::FirstByte`] waits
+/// here before anything is sent, [`DelayMode::FullBody`] only holds back
+/// the end of the body.
+pub(super) async fn delay_body(body: Body, delay: &DelaySpec) -> Body {
+    match delay.mode {
+        DelayMode::FirstByte => {
+            sleep(delay.duration()).await;
+            body
+        }
+        DelayMode::FullBody => DelayedBody::new(body, delay.duration()).boxed(),
+    }
+}
+
+/// Keeps the latest frame back and only releases the last one, followed by
+/// the end of the stream, once `delay` has passed.
+pub(super) struct DelayedBody {
+    inner: Body,
+    delay: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    held: Option<Frame<Bytes>>,
+    finished: bool,
+}
+
+impl DelayedBody {
+    pub(super) fn new(inner: Body, delay: Duration) -> Self {
+        Self {
+            inner,
+            delay: Some(delay),
+            sleep: None,
+            held: None,
+            finished: false,
+        }
+    }
+}
+
+impl HttpBody for DelayedBody {
+    type Data = Bytes;
+    type Error = anyhow::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        while !self.finished {
+            match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => {
+                    if let Some(previous) = self.held.replace(frame) {
+                        return Poll::Ready(Some(Ok(previous)));
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => self.finished = true,
+            }
+        }
+
+        if let Some(delay) = self.delay.take() {
+            self.sleep = Some(Box::pin(sleep(delay)));
+        }
+        if let Some(sleep) = self.sleep.as_mut() {
+            ready!(sleep.as_mut().poll(cx));
+            self.sleep = None;
+        }
+
+        Poll::Ready(self.held.take().map(Ok))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.finished && self.sleep.is_none() && self.delay.is_none() && self.held.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
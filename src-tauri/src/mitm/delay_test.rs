@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use http_body_util::BodyExt;
+use hyper::{body::Body as _, HeaderMap};
+use tokio::time::Instant;
+
+use super::{delay_body, full_body, DelayMode, DelaySpec};
+
+fn spec(min_ms: u64, max_ms: Option<u64>, mode: DelayMode) -> DelaySpec {
+    DelaySpec {
+        min_ms,
+        max_ms,
+        mode,
+    }
+}
+
+#[test]
+fn test_delay_duration() {
+    let fixed = spec(250, None, DelayMode::FirstByte);
+    assert_eq!(fixed.duration(), Duration::from_millis(250));
+    // a maximum below the minimum is ignored
+    assert_eq!(
+        spec(250, Some(100), DelayMode::FirstByte).duration(),
+        Duration::from_millis(250)
+    );
+
+    let random = spec(100, Some(200), DelayMode::FirstByte);
+    for _ in 0..100 {
+        let duration = random.duration();
+        assert!(duration >= Duration::from_millis(100));
+        assert!(duration <= Duration::from_millis(200));
+    }
+
+    let parsed: DelaySpec = serde_json::from_str(r#"{"minMs":5}"#).unwrap();
+    assert_eq!(parsed, spec(5, None, DelayMode::FirstByte));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_first_byte_delay() {
+    let start = Instant::now();
+    let mut body = delay_body(full_body("hello"), &spec(300, None, DelayMode::FirstByte)).await;
+    assert_eq!(start.elapsed(), Duration::from_millis(300));
+
+    // nothing is held back once it has started
+    let frame = body.frame().await.unwrap().unwrap();
+    assert_eq!(frame.into_data().unwrap(), "hello");
+    assert_eq!(start.elapsed(), Duration::from_millis(300));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_full_body_delay() {
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", "0".parse().unwrap());
+    let body = full_body("hello")
+        .with_trailers(async move { Some(Ok(trailers)) })
+        .boxed();
+
+    let start = Instant::now();
+    let mut body = delay_body(body, &spec(300, None, DelayMode::FullBody)).await;
+    assert_eq!(start.elapsed(), Duration::ZERO);
+
+    // the data goes out right away, the last frame waits
+    let data = body.frame().await.unwrap().unwrap();
+    assert_eq!(data.into_data().unwrap(), "hello");
+    assert_eq!(start.elapsed(), Duration::ZERO);
+
+    let last = body.frame().await.unwrap().unwrap();
+    assert_eq!(last.into_trailers().unwrap()["grpc-status"], "0");
+    assert_eq!(start.elapsed(), Duration::from_millis(300));
+    assert!(body.frame().await.is_none());
+    assert!(body.is_end_stream());
+
+    // an empty body still ends late
+    let start = Instant::now();
+    let empty = delay_body(full_body(""), &spec(300, None, DelayMode::FullBody)).await;
+    empty.collect().await.unwrap();
+    assert_eq!(start.elapsed(), Duration::from_millis(300));
+}
@@ -0,0 +1,640 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        RwLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hyper::{HeaderMap, Request, Response};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use super::{BodyCapture, BreakpointRecord, RuleAction, SseEvent, DEFAULT_CAPTURE_LIMIT};
+
+pub type FlowId = u64;
+pub type ConnectionId = u64;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FlowKind {
+    #[default]
+    Http,
+    /// Raw TCP relayed without looking inside, the request names the target.
+    Tunnel,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TunnelStats {
+    /// Client to server.
+    pub bytes_sent: u64,
+    /// Server to client.
+    pub bytes_received: u64,
+}
+
+/// A client connection, its requests are the flows sharing its id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Connection {
+    pub id: ConnectionId,
+    pub client_addr: String,
+    /// Host the client asked to tunnel to, for intercepted TLS.
+    pub server_name: Option<String>,
+    /// Protocol picked through ALPN, for intercepted TLS.
+    pub alpn: Option<String>,
+    /// TLS version of the session with the client, e.g. `TLSv1.3`.
+    #[serde(default)]
+    pub tls_version: Option<String>,
+    /// Cipher suite of the session with the client.
+    #[serde(default)]
+    pub cipher: Option<String>,
+    pub opened_at: u64,
+    pub closed_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowRequest {
+    pub method: String,
+    pub uri: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    /// Request line and header block as read, in header fidelity mode.
+    #[serde(default)]
+    pub raw_head: Option<Vec<u8>>,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowResponse {
+    pub status: u16,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WsDirection {
+    ClientToServer,
+    ServerToClient,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WsMessageKind {
+    Text,
+    Binary,
+    Ping,
+    Pong,
+    Close,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WsMessage {
+    pub direction: WsDirection,
+    pub kind: WsMessageKind,
+    /// Payload after reassembly and permessage-deflate decompression.
+    pub payload: Vec<u8>,
+    pub compressed: bool,
+    /// Sent by the proxy rather than relayed.
+    #[serde(default)]
+    pub injected: bool,
+    pub timestamp: u64,
+}
+
+/// When an exchange got past the points its request and response
+/// timestamps don't cover, in milliseconds since the epoch like those.
+/// The request timestamp to `sent_at` is time spent in handlers,
+/// breakpoints and delays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FlowTimings {
+    /// Handed to the upstream connection.
+    pub sent_at: Option<u64>,
+    /// The request body was sent to its end.
+    pub request_ended_at: Option<u64>,
+    /// The response head came from upstream, before handlers and
+    /// breakpoints saw it.
+    pub response_started_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Flow {
+    pub id: FlowId,
+    #[serde(default)]
+    pub kind: FlowKind,
+    /// Loaded from a file rather than captured, nothing updates it.
+    #[serde(default)]
+    pub imported: bool,
+    /// The flow this one re-sends.
+    #[serde(default)]
+    pub replay_of: Option<FlowId>,
+    #[serde(default)]
+    pub connection_id: Option<ConnectionId>,
+    pub request: FlowRequest,
+    pub response: Option<FlowResponse>,
+    pub request_body: Option<BodyCapture>,
+    pub response_body: Option<BodyCapture>,
+    pub events: Option<Vec<SseEvent>>,
+    pub websocket: Option<Vec<WsMessage>>,
+    #[serde(default)]
+    pub tunnel: Option<TunnelStats>,
+    /// Actions of the rules that acted on the exchange, each once.
+    #[serde(default)]
+    pub rule_hits: Vec<RuleAction>,
+    /// Pauses at breakpoints, request phase first.
+    #[serde(default)]
+    pub breakpoints: Vec<BreakpointRecord>,
+    #[serde(default)]
+    pub timings: FlowTimings,
+    pub error: Option<String>,
+    pub finished_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum FlowEvent {
+    Created {
+        flow: Box<Flow>,
+    },
+    Response {
+        id: FlowId,
+        response: FlowResponse,
+    },
+    RequestBody {
+        id: FlowId,
+        size: u64,
+    },
+    ResponseBody {
+        id: FlowId,
+        size: u64,
+    },
+    ServerSentEvent {
+        id: FlowId,
+        event: SseEvent,
+    },
+    WebSocketMessage {
+        id: FlowId,
+        message: WsMessage,
+    },
+    Tunnel {
+        id: FlowId,
+        stats: TunnelStats,
+    },
+    RuleHit {
+        id: FlowId,
+        action: RuleAction,
+    },
+    Breakpoint {
+        id: FlowId,
+        record: Box<BreakpointRecord>,
+    },
+    Timings {
+        id: FlowId,
+        timings: FlowTimings,
+    },
+    Error {
+        id: FlowId,
+        error: String,
+    },
+    Finished {
+        id: FlowId,
+        timestamp: u64,
+    },
+    ConnectionOpened {
+        connection: Connection,
+    },
+    ConnectionClosed {
+        id: ConnectionId,
+        timestamp: u64,
+    },
+    Cleared,
+}
+
+/// Everything the proxy has seen, in the order it was seen.
+pub struct FlowStore {
+    flows: RwLock<BTreeMap<FlowId, Flow>>,
+    connections: RwLock<BTreeMap<ConnectionId, Connection>>,
+    next_id: AtomicU64,
+    next_connection_id: AtomicU64,
+    capture_limit: AtomicUsize,
+    events_tx: broadcast::Sender<FlowEvent>,
+}
+
+impl Default for FlowStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlowStore {
+    pub fn new() -> Self {
+        Self {
+            flows: RwLock::new(BTreeMap::new()),
+            connections: RwLock::new(BTreeMap::new()),
+            next_id: AtomicU64::new(1),
+            next_connection_id: AtomicU64::new(1),
+            capture_limit: AtomicUsize::new(DEFAULT_CAPTURE_LIMIT),
+            events_tx: broadcast::channel(1024).0,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<FlowEvent> {
+        self.events_tx.subscribe()
+    }
+
+    /// Bytes kept per body, what goes beyond is still forwarded.
+    pub fn capture_limit(&self) -> usize {
+        self.capture_limit.load(Ordering::Relaxed)
+    }
+
+    pub fn set_capture_limit(&self, limit: usize) {
+        self.capture_limit.store(limit, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, id: FlowId) -> Option<Flow> {
+        self.flows.read().unwrap().get(&id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<Flow> {
+        self.flows.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn ids(&self) -> Vec<FlowId> {
+        self.flows.read().unwrap().keys().copied().collect()
+    }
+
+    pub fn connections(&self) -> Vec<Connection> {
+        self.connections.read().unwrap().values().cloned().collect()
+    }
+
+    /// Drops all flows and the connections that are already closed.
+    pub fn clear(&self) {
+        self.flows.write().unwrap().clear();
+        self.connections
+            .write()
+            .unwrap()
+            .retain(|_, connection| connection.closed_at.is_none());
+        let _ = self.events_tx.send(FlowEvent::Cleared);
+    }
+
+    /// `tls` is the version and cipher suite negotiated with the client.
+    pub fn open_connection(
+        &self,
+        client_addr: SocketAddr,
+        server_name: Option<String>,
+        alpn: Option<String>,
+        tls: Option<(String, String)>,
+    ) -> ConnectionId {
+        let id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+        let (tls_version, cipher) = tls.unzip();
+        let connection = Connection {
+            id,
+            client_addr: client_addr.to_string(),
+            server_name,
+            alpn,
+            tls_version,
+            cipher,
+            opened_at: now_millis(),
+            closed_at: None,
+        };
+
+        self.connections
+            .write()
+            .unwrap()
+            .insert(id, connection.clone());
+        let _ = self
+            .events_tx
+            .send(FlowEvent::ConnectionOpened { connection });
+
+        id
+    }
+
+    /// Adds a connection read from a file under a new id.
+    pub fn import_connection(&self, mut connection: Connection) -> ConnectionId {
+        let id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+        connection.id = id;
+
+        self.connections
+            .write()
+            .unwrap()
+            .insert(id, connection.clone());
+        let _ = self
+            .events_tx
+            .send(FlowEvent::ConnectionOpened { connection });
+
+        id
+    }
+
+    pub fn close_connection(&self, id: ConnectionId) {
+        let timestamp = now_millis();
+        if let Some(connection) = self.connections.write().unwrap().get_mut(&id) {
+            connection.closed_at = Some(timestamp);
+        }
+        let _ = self
+            .events_tx
+            .send(FlowEvent::ConnectionClosed { id, timestamp });
+    }
+
+    pub fn insert(&self, request: FlowRequest, connection_id: Option<ConnectionId>) -> FlowId {
+        self.insert_kind(FlowKind::Http, request, connection_id, None)
+    }
+
+    /// Adds a flow the proxy sent by itself, `replay_of` is the flow it
+    /// repeats if any.
+    pub fn insert_replay(&self, request: FlowRequest, replay_of: Option<FlowId>) -> FlowId {
+        self.insert_kind(FlowKind::Http, request, None, replay_of)
+    }
+
+    pub fn insert_tunnel(
+        &self,
+        request: FlowRequest,
+        connection_id: Option<ConnectionId>,
+    ) -> FlowId {
+        self.insert_kind(FlowKind::Tunnel, request, connection_id, None)
+    }
+
+    fn insert_kind(
+        &self,
+        kind: FlowKind,
+        request: FlowRequest,
+        connection_id: Option<ConnectionId>,
+        replay_of: Option<FlowId>,
+    ) -> FlowId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let flow = Flow {
+            id,
+            kind,
+            imported: false,
+            replay_of,
+            connection_id,
+            request,
+            response: None,
+            request_body: None,
+            response_body: None,
+            events: None,
+            websocket: None,
+            tunnel: None,
+            rule_hits: vec![],
+            breakpoints: vec![],
+            timings: FlowTimings::default(),
+            error: None,
+            finished_at: None,
+        };
+
+        self.flows.write().unwrap().insert(id, flow.clone());
+        let _ = self.events_tx.send(FlowEvent::Created {
+            flow: Box::new(flow),
+        });
+
+        id
+    }
+
+    /// Adds a flow read from a file under a new id. Its links to other
+    /// flows and connections have to be ids of this store already.
+    pub fn import(&self, mut flow: Flow) -> FlowId {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        flow.id = id;
+        flow.imported = true;
+
+        self.flows.write().unwrap().insert(id, flow.clone());
+        let _ = self.events_tx.send(FlowEvent::Created {
+            flow: Box::new(flow),
+        });
+
+        id
+    }
+
+    pub fn set_response(&self, id: FlowId, response: FlowResponse) {
+        self.update(id, |flow| flow.response = Some(response.clone()));
+        let _ = self.events_tx.send(FlowEvent::Response { id, response });
+    }
+
+    /// Also marks the end of the request, the body is captured as it is
+    /// sent.
+    pub fn set_request_body(&self, id: FlowId, body: BodyCapture) {
+        let size = body.size;
+        self.update(id, |flow| flow.request_body = Some(body));
+        let _ = self.events_tx.send(FlowEvent::RequestBody { id, size });
+        self.update_timings(id, |timings| timings.request_ended_at = Some(now_millis()));
+    }
+
+    pub fn set_response_body(&self, id: FlowId, body: BodyCapture) {
+        let size = body.size;
+        self.update(id, |flow| flow.response_body = Some(body));
+        let _ = self.events_tx.send(FlowEvent::ResponseBody { id, size });
+    }
+
+    pub fn push_sse_event(&self, id: FlowId, event: SseEvent) {
+        self.update(id, |flow| {
+            flow.events.get_or_insert_with(Vec::new).push(event.clone())
+        });
+        let _ = self
+            .events_tx
+            .send(FlowEvent::ServerSentEvent { id, event });
+    }
+
+    pub fn push_ws_message(&self, id: FlowId, message: WsMessage) {
+        self.update(id, |flow| {
+            flow.websocket
+                .get_or_insert_with(Vec::new)
+                .push(message.clone())
+        });
+        let _ = self
+            .events_tx
+            .send(FlowEvent::WebSocketMessage { id, message });
+    }
+
+    pub fn set_tunnel_stats(&self, id: FlowId, stats: TunnelStats) {
+        self.update(id, |flow| flow.tunnel = Some(stats));
+        let _ = self.events_tx.send(FlowEvent::Tunnel { id, stats });
+    }
+
+    /// Records that a rule acted on the flow, once per action.
+    pub fn add_rule_hit(&self, id: FlowId, action: RuleAction) {
+        let mut added = false;
+        self.update(id, |flow| {
+            if !flow.rule_hits.contains(&action) {
+                flow.rule_hits.push(action.clone());
+                added = true;
+            }
+        });
+        if added {
+            let _ = self.events_tx.send(FlowEvent::RuleHit { id, action });
+        }
+    }
+
+    pub fn add_breakpoint(&self, id: FlowId, record: BreakpointRecord) {
+        self.update(id, |flow| flow.breakpoints.push(record.clone()));
+        let _ = self.events_tx.send(FlowEvent::Breakpoint {
+            id,
+            record: Box::new(record),
+        });
+    }
+
+    pub fn mark_sent(&self, id: FlowId) {
+        self.update_timings(id, |timings| timings.sent_at = Some(now_millis()));
+    }
+
+    pub fn mark_response_started(&self, id: FlowId) {
+        self.update_timings(id, |timings| {
+            timings.response_started_at = Some(now_millis())
+        });
+    }
+
+    fn update_timings(&self, id: FlowId, f: impl FnOnce(&mut FlowTimings)) {
+        let mut timings = None;
+        self.update(id, |flow| {
+            f(&mut flow.timings);
+            timings = Some(flow.timings);
+        });
+        if let Some(timings) = timings {
+            let _ = self.events_tx.send(FlowEvent::Timings { id, timings });
+        }
+    }
+
+    pub fn set_error(&self, id: FlowId, error: String) {
+        self.update(id, |flow| flow.error = Some(error.clone()));
+        let _ = self.events_tx.send(FlowEvent::Error { id, error });
+    }
+
+    pub fn finish(&self, id: FlowId) {
+        let timestamp = now_millis();
+        self.update(id, |flow| flow.finished_at = Some(timestamp));
+        let _ = self.events_tx.send(FlowEvent::Finished { id, timestamp });
+    }
+
+    fn update(&self, id: FlowId, f: impl FnOnce(&mut Flow)) {
+        if let Some(flow) = self.flows.write().unwrap().get_mut(&id) {
+            f(flow);
+        }
+    }
+}
+
+impl FlowRequest {
+    pub fn from_request<B>(req: &Request<B>) -> Self {
+        Self {
+            method: req.method().to_string(),
+            uri: req.uri().to_string(),
+            version: format!("{:?}", req.version()),
+            headers: header_pairs(req.headers()),
+            raw_head: None,
+            timestamp: now_millis(),
+        }
+    }
+
+    /// Stands in for the request of a tunnel flow, `target` is `host:port`.
+    pub fn tunnel(target: String, version: &str) -> Self {
+        Self {
+            method: "CONNECT".to_string(),
+            uri: target,
+            version: version.to_string(),
+            headers: vec![],
+            raw_head: None,
+            timestamp: now_millis(),
+        }
+    }
+}
+
+impl FlowResponse {
+    pub fn from_response<B>(res: &Response<B>) -> Self {
+        Self {
+            status: res.status().as_u16(),
+            version: format!("{:?}", res.version()),
+            headers: header_pairs(res.headers()),
+            timestamp: now_millis(),
+        }
+    }
+}
+
+/// Builds flows for tests, everything but the request line starts empty.
+#[cfg(test)]
+impl Flow {
+    pub fn test(method: &str, uri: &str) -> Self {
+        Self {
+            id: 0,
+            kind: FlowKind::Http,
+            imported: false,
+            replay_of: None,
+            connection_id: None,
+            request: FlowRequest {
+                method: method.to_string(),
+                uri: uri.to_string(),
+                version: "HTTP/1.1".to_string(),
+                headers: vec![],
+                raw_head: None,
+                timestamp: 0,
+            },
+            response: None,
+            request_body: None,
+            response_body: None,
+            events: None,
+            websocket: None,
+            tunnel: None,
+            rule_hits: vec![],
+            breakpoints: vec![],
+            timings: FlowTimings::default(),
+            error: None,
+            finished_at: None,
+        }
+    }
+
+    pub fn with_request_headers(mut self, headers: &[(&str, &str)]) -> Self {
+        self.request.headers = test_pairs(headers);
+        self
+    }
+
+    pub fn with_request_body(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.request_body = Some(BodyCapture::complete(data));
+        self
+    }
+
+    pub fn with_response(mut self, status: u16, headers: &[(&str, &str)]) -> Self {
+        self.response = Some(FlowResponse {
+            status,
+            version: self.request.version.clone(),
+            headers: test_pairs(headers),
+            timestamp: 0,
+        });
+        self
+    }
+
+    pub fn with_response_body(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.response_body = Some(BodyCapture::complete(data));
+        self
+    }
+}
+
+#[cfg(test)]
+fn test_pairs(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+pub(super) fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use hyper_util::{
+    client::legacy,
+    rt::{TokioExecutor, TokioTimer},
+    server::conn::auto,
+};
+use serde::{Deserialize, Serialize};
+
+/// HTTP/2 settings used on both sides of the proxy. Values left as `None`
+/// keep the defaults of the `h2` crate. Server push is never accepted from
+/// upstreams, nor offered to clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Http2Settings {
+    /// Offer `h2` in ALPN, to clients and to upstreams.
+    pub enabled: bool,
+    pub initial_stream_window_size: Option<u32>,
+    pub initial_connection_window_size: Option<u32>,
+    pub adaptive_window: bool,
+    pub max_frame_size: Option<u32>,
+    pub max_concurrent_streams: Option<u32>,
+    pub max_header_list_size: Option<u32>,
+    pub keep_alive_interval_ms: Option<u64>,
+    pub keep_alive_timeout_ms: u64,
+}
+
+impl Default for Http2Settings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            initial_stream_window_size: None,
+            initial_connection_window_size: None,
+            adaptive_window: false,
+            max_frame_size: None,
+            max_concurrent_streams: Some(200),
+            max_header_list_size: None,
+            keep_alive_interval_ms: None,
+            keep_alive_timeout_ms: 20_000,
+        }
+    }
+}
+
+impl Http2Settings {
+    pub fn alpn_protocols(&self) -> Vec<Vec<u8>> {
+        if self.enabled {
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        } else {
+            vec![b"http/1.1".to_vec()]
+        }
+    }
+
+    pub(super) fn configure_server(
+        &self,
+        mut builder: auto::Builder<TokioExecutor>,
+    ) -> auto::Builder<TokioExecutor> {
+        let mut http2 = builder.http2();
+        http2
+            .timer(TokioTimer::new())
+            .enable_connect_protocol()
+            .initial_stream_window_size(self.initial_stream_window_size)
+            .initial_connection_window_size(self.initial_connection_window_size)
+            .adaptive_window(self.adaptive_window)
+            .max_frame_size(self.max_frame_size)
+            .max_concurrent_streams(self.max_concurrent_streams)
+            .keep_alive_interval(self.keep_alive_interval_ms.map(Duration::from_millis))
+            .keep_alive_timeout(Duration::from_millis(self.keep_alive_timeout_ms));
+        if let Some(max) = self.max_header_list_size {
+            http2.max_header_list_size(max);
+        }
+
+        if !self.enabled {
+            builder = builder.http1_only();
+        }
+        builder
+    }
+
+    pub(super) fn configure_client(&self, builder: &mut legacy::Builder) {
+        builder
+            .timer(TokioTimer::new())
+            .http2_initial_stream_window_size(self.initial_stream_window_size)
+            .http2_initial_connection_window_size(self.initial_connection_window_size)
+            .http2_adaptive_window(self.adaptive_window)
+            .http2_max_frame_size(self.max_frame_size)
+            .http2_keep_alive_interval(self.keep_alive_interval_ms.map(Duration::from_millis))
+            .http2_keep_alive_timeout(Duration::from_millis(self.keep_alive_timeout_ms));
+        if let Some(max) = self.max_header_list_size {
+            builder.http2_max_header_list_size(max);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use hyper::{Method, Uri};
+use serde::{Deserialize, Serialize};
+
+/// Selects requests by host, path and method. Host and path accept `*`
+/// wildcards, empty fields match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestMatcher {
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub method: Option<String>,
+}
+
+impl RequestMatcher {
+    pub fn matches(&self, method: &Method, uri: &Uri) -> bool {
+        if let Some(expected) = &self.method {
+            if !expected.eq_ignore_ascii_case(method.as_str()) {
+                return false;
+            }
+        }
+
+        if let Some(pattern) = &self.path {
+            if !wildcard_match(pattern, uri.path()) {
+                return false;
+            }
+        }
+
+        self.matches_host(uri.host().unwrap_or_default())
+    }
+
+    pub fn matches_host(&self, host: &str) -> bool {
+        match &self.host {
+            Some(pattern) => {
+                wildcard_match(&pattern.to_ascii_lowercase(), &host.to_ascii_lowercase())
+            }
+            None => true,
+        }
+    }
+}
+
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
@@ -1,8 +1,74 @@
+mod breakpoint;
+mod capture;
 mod cert;
+mod client_replay;
+mod codec;
+mod delay;
+mod flow;
+mod http2;
+mod matcher;
 mod proxy;
+mod raw;
+mod reverse;
+mod rule;
+mod server_replay;
+mod sniff;
+mod socks;
+mod sse;
+mod throttle;
+mod transparent;
+mod tunnel;
+mod ws;
 
+#[cfg(test)]
+mod breakpoint_test;
+#[cfg(test)]
+mod capture_test;
+#[cfg(test)]
+mod client_replay_test;
+#[cfg(test)]
+mod codec_test;
+#[cfg(test)]
+mod delay_test;
 #[cfg(test)]
 mod proxy_test;
+#[cfg(test)]
+mod raw_test;
+#[cfg(test)]
+mod reverse_test;
+#[cfg(test)]
+mod rule_test;
+#[cfg(test)]
+mod server_replay_test;
+#[cfg(test)]
+mod socks_test;
+#[cfg(test)]
+mod sse_test;
+#[cfg(test)]
+mod throttle_test;
+#[cfg(test)]
+mod transparent_test;
+#[cfg(test)]
+mod ws_test;
 
+pub use breakpoint::*;
+pub use capture::*;
 pub use cert::*;
+pub use client_replay::*;
+pub use codec::*;
+pub use delay::*;
+pub use flow::*;
+pub use http2::*;
+pub use matcher::*;
 pub use proxy::*;
+pub use raw::*;
+pub use reverse::*;
+pub use rule::*;
+pub use server_replay::*;
+pub use sniff::*;
+pub use socks::*;
+pub use sse::*;
+pub use throttle::*;
+pub use transparent::*;
+pub use tunnel::*;
+pub use ws::*;
@@ -1,13 +1,24 @@
 use std::{net::SocketAddr, sync::Arc};
 
-use super::{RootCA, SignedCert};
-use anyhow::{anyhow, Context};
+use super::{
+    bind_transparent, blocked_response, bridge, delay_body, header_pairs, is_event_stream,
+    is_redirected, is_websocket_upgrade, malformed_response, offer_no_context_takeover,
+    original_dst, require_client_no_context_takeover, sniff, socks_handshake, tunnel,
+    BreakpointPhase, BreakpointRecord, Breakpoints, ClientReplay, ConnectionId, CountingStream,
+    DeflateParams, Direction, EditableRequest, FlowId, FlowRequest, FlowResponse, FlowStore,
+    HeadRecorder, Http2Settings, Limiter, RawHeads, ReplayJob, ReverseProxy, RootCA, RuleAction,
+    Rules, ServerReplay, SignedCert, Sniffed, SocksCredentials, SseRecorder, TargetAddr, TeeBody,
+    Throttle, ThrottledBody, WsInjector, WsMessage, WsRewriter, DEFAULT_CAPTURE_LIMIT,
+    SNIFF_TIMEOUT, TLS_HANDSHAKE_FAILURE_ALERT,
+};
+use anyhow::{anyhow, bail, Context};
 use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
 use hyper::{
     body::{Bytes, Incoming},
+    http::Extensions,
     service::service_fn,
-    upgrade::Upgraded,
-    Method, Request, Response, StatusCode, Uri, Version,
+    upgrade::OnUpgrade,
+    HeaderMap, Method, Request, Response, StatusCode, Uri, Version,
 };
 use hyper_rustls::{ConfigBuilderExt, HttpsConnector};
 use hyper_util::{
@@ -18,24 +29,68 @@ use hyper_util::{
 use quick_cache::sync::Cache;
 use rustls::{
     pki_types::{CertificateDer, PrivateKeyDer},
-    ClientConfig, ServerConfig,
+    ClientConfig, ProtocolVersion, ServerConfig,
 };
 use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
     net::{TcpListener, TcpStream, ToSocketAddrs},
-    sync::broadcast,
+    sync::{broadcast, mpsc::UnboundedReceiver, Notify},
+    time::sleep,
 };
-use tokio_rustls::TlsAcceptor;
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
 use tracing::{debug, error, info, warn};
 
 pub type Body = BoxBody<Bytes, anyhow::Error>;
 
+/// What the response side needs to know of the request that was sent.
+struct RequestHead {
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+}
+
 pub struct MitmProxy<A: ToSocketAddrs, H: HttpHandler> {
     bind_addr: Option<A>,
+    socks_addr: Option<A>,
+    socks_credentials: Option<SocksCredentials>,
+    transparent_addr: Option<A>,
+    reverse: Option<ReverseProxy>,
     root_cert: Option<RootCA>,
     cert_cache: Option<Cache<String, SignedCert>>,
     handler: Option<H>,
+    breakpoints: Option<Arc<Breakpoints>>,
+    rules: Option<Arc<Rules>>,
+    server_replay: Option<Arc<ServerReplay>>,
+    client_replay: Option<Arc<ClientReplay>>,
+    throttle: Option<Arc<Throttle>>,
+    flow_store: Option<Arc<FlowStore>>,
+    ws_injector: Option<Arc<WsInjector>>,
     shutdown_tx: Option<broadcast::Sender<()>>,
+    http2: Http2Settings,
+    header_fidelity: bool,
     http_client: Client<HttpsConnector<HttpConnector>, Body>,
+    http1_client: Client<HttpsConnector<HttpConnector>, Body>,
+    h2c_client: Client<HttpsConnector<HttpConnector>, Body>,
+}
+
+/// The request never reached the upstream server.
+#[derive(Debug)]
+struct UpstreamError(hyper_util::client::legacy::Error);
+
+impl std::fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for UpstreamError {}
+
+/// Shared by the requests of one client connection.
+struct ClientConn {
+    id: Option<ConnectionId>,
+    addr: SocketAddr,
+    raw_heads: Option<RawHeads>,
+    drop_signal: Notify,
 }
 
 impl<A, H> MitmProxy<A, H>
@@ -46,10 +101,23 @@ where
     pub fn builder() -> MitmProxyBuilder<A, H> {
         MitmProxyBuilder {
             bind_addr: None,
+            socks_addr: None,
+            socks_credentials: None,
+            transparent_addr: None,
+            reverse: None,
             root_ca: None,
             cert_cache: None,
             handler: None,
+            breakpoints: None,
+            rules: None,
+            server_replay: None,
+            client_replay: None,
+            throttle: None,
+            flow_store: None,
+            ws_injector: None,
             shutdown_tx: None,
+            http2: Http2Settings::default(),
+            header_fidelity: false,
         }
     }
 }
@@ -70,7 +138,32 @@ where
         };
 
         let listener = TcpListener::bind(addr).await?;
-        info!("Proxy listening on {}", listener.local_addr()?);
+        match &self.reverse {
+            Some(reverse) => info!(
+                "Reverse proxy listening on {} for {}",
+                listener.local_addr()?,
+                reverse.upstream()
+            ),
+            None => info!("Proxy listening on {}", listener.local_addr()?),
+        }
+
+        let socks_listener = match &self.socks_addr {
+            Some(addr) => {
+                let listener = TcpListener::bind(addr).await?;
+                info!("SOCKS proxy listening on {}", listener.local_addr()?);
+                Some(listener)
+            }
+            None => None,
+        };
+
+        let transparent_listener = match &self.transparent_addr {
+            Some(addr) => {
+                let listener = bind_transparent(addr).await?;
+                info!("Transparent proxy listening on {}", listener.local_addr()?);
+                Some(listener)
+            }
+            None => None,
+        };
 
         let (shutdown_tx, mut shutdown_rx) = match self.shutdown_tx {
             Some(ref tx) => (tx.clone(), tx.subscribe()),
@@ -79,26 +172,53 @@ where
 
         let proxy = Arc::new(self);
 
+        if let Some(jobs) = proxy.client_replay.as_ref().and_then(|r| r.take_jobs()) {
+            let proxy = proxy.clone();
+            let mut shutdown_rx = shutdown_tx.subscribe();
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = proxy.serve_replays(jobs) => {}
+                    _ = shutdown_rx.recv() => {}
+                }
+            });
+        }
+
         loop {
             tokio::select! {
                 accept_result = listener.accept() => {
                     match accept_result {
+                        Ok((stream, client_addr)) if proxy.reverse.is_some() => {
+                            let proxy = proxy.clone();
+                            let mut shutdown_rx = shutdown_tx.subscribe();
+
+                            tokio::spawn(async move {
+                                tokio::select! {
+                                    result = proxy.handle_reverse(stream, client_addr) => {
+                                        if let Err(err) = result {
+                                            error!("Error serving connection from {}: {}", client_addr, err)
+                                        }
+                                    }
+                                    _ = shutdown_rx.recv() => {
+                                        info!("Shutting down connection from {}", client_addr);
+                                    }
+                                }
+                            });
+                        }
                         Ok((stream, client_addr)) => {
                             let proxy = proxy.clone();
                             let mut shutdown_rx = shutdown_tx.subscribe();
+                            let stream = HeadRecorder::new(stream, proxy.header_fidelity);
+                            let conn = proxy.open_conn(client_addr, None, None, None, &stream);
 
                             tokio::spawn(async move {
                                 let io = TokioIo::new(stream);
-                                let mut server_builder = auto::Builder::new(TokioExecutor::new());
-                                server_builder.http1()
-                                    .preserve_header_case(true)
-                                    .title_case_headers(true)
-                                    .http2()
-                                    .enable_connect_protocol();
+                                let server_builder = proxy.server_builder();
+                                let service_proxy = proxy.clone();
+                                let service_conn = conn.clone();
                                 let connection = server_builder
                                 .serve_connection_with_upgrades(
                                     io,
-                                    service_fn(move |req| proxy.clone().handle_connection(req, client_addr)),
+                                    service_fn(move |req| service_proxy.clone().handle_connection(req, service_conn.clone())),
                                 );
 
                                 tokio::select! {
@@ -110,7 +230,14 @@ where
                                     _ = shutdown_rx.recv() => {
                                         info!("Shutting down connection from {}", client_addr);
                                     }
+                                    _ = conn.drop_signal.notified() => {
+                                        info!("Dropping connection from {}", client_addr);
+                                    }
                                 };
+
+                                if let (Some(store), Some(id)) = (&proxy.flow_store, conn.id) {
+                                    store.close_connection(id);
+                                }
                             });
                         }
                         Err(e) => {
@@ -118,6 +245,55 @@ where
                         }
                     }
                 }
+                accept_result = accept(socks_listener.as_ref()) => {
+                    match accept_result {
+                        Ok((stream, client_addr)) => {
+                            let proxy = proxy.clone();
+                            let mut shutdown_rx = shutdown_tx.subscribe();
+
+                            tokio::spawn(async move {
+                                tokio::select! {
+                                    result = proxy.handle_socks(stream, client_addr) => {
+                                        if let Err(err) = result {
+                                            error!("Error serving SOCKS connection from {}: {}", client_addr, err)
+                                        }
+                                    }
+                                    _ = shutdown_rx.recv() => {
+                                        info!("Shutting down SOCKS connection from {}", client_addr);
+                                    }
+                                }
+                            });
+                        }
+                        Err(e) => {
+                            error!("Failed to accept SOCKS connection: {}", e);
+                        }
+                    }
+                }
+                accept_result = accept(transparent_listener.as_ref()) => {
+                    match accept_result {
+                        Ok((stream, client_addr)) => {
+                            let proxy = proxy.clone();
+                            let mut shutdown_rx = shutdown_tx.subscribe();
+                            let listen_addr = transparent_listener.as_ref().and_then(|l| l.local_addr().ok());
+
+                            tokio::spawn(async move {
+                                tokio::select! {
+                                    result = proxy.handle_transparent(stream, client_addr, listen_addr) => {
+                                        if let Err(err) = result {
+                                            error!("Error serving transparent connection from {}: {}", client_addr, err)
+                                        }
+                                    }
+                                    _ = shutdown_rx.recv() => {
+                                        info!("Shutting down transparent connection from {}", client_addr);
+                                    }
+                                }
+                            });
+                        }
+                        Err(e) => {
+                            error!("Failed to accept transparent connection: {}", e);
+                        }
+                    }
+                }
                 _ = shutdown_rx.recv() => {
                     info!("Shutting down proxy");
                     break;
@@ -131,43 +307,376 @@ where
     async fn handle_connection(
         self: Arc<Self>,
         req: Request<Incoming>,
-        client_addr: SocketAddr,
+        conn: Arc<ClientConn>,
     ) -> Result<Response<Body>, anyhow::Error> {
         debug!(
             "Handling request from {}: {} {}",
-            client_addr,
+            conn.addr,
             req.method(),
             req.uri()
         );
 
         if req.method() == Method::CONNECT {
-            self.handle_connect(req).await
+            self.handle_connect(req, &conn).await
         } else {
-            self.handle_http(req).await
+            self.handle_http(req, &conn).await
         }
     }
 
     async fn handle_http(
         self: Arc<Self>,
         req: Request<Incoming>,
+        conn: &ClientConn,
     ) -> anyhow::Result<Response<Body>> {
-        let final_req = match self.get_final_req(req).await {
+        self.forward(req, conn).await
+    }
+
+    async fn forward(
+        self: &Arc<Self>,
+        mut req: Request<Incoming>,
+        conn: &ClientConn,
+    ) -> anyhow::Result<Response<Body>> {
+        // taken first so that short-circuited requests keep the queue in step
+        let raw_head = conn.raw_heads.as_ref().and_then(RawHeads::pop);
+        let client_upgrade = is_websocket_upgrade(&req).then(|| hyper::upgrade::on(&mut req));
+
+        // kept for answers that never go upstream, e.g. from server replay
+        let request = self
+            .flow_store
+            .as_ref()
+            .map(|_| FlowRequest::from_request(&req));
+
+        let mut final_req = match self.get_final_req(req).await {
             RequestOrResponse::Request(r) => r,
-            RequestOrResponse::Response(resp) => return Ok(resp),
+            RequestOrResponse::Response(mut res) => {
+                let (Some(store), Some(mut request)) = (&self.flow_store, request) else {
+                    return Ok(res);
+                };
+                request.raw_head = raw_head;
+                let id = store.insert(request, conn.id);
+                record_breakpoint(res.extensions_mut(), store, id);
+                store.set_response(id, FlowResponse::from_response(&res));
+                return Ok(capture_response(res, store.clone(), id));
+            }
+        };
+
+        let flow = self.flow_store.as_ref().map(|store| {
+            let mut request = FlowRequest::from_request(&final_req);
+            request.raw_head = raw_head;
+            let id = store.insert(request, conn.id);
+            record_breakpoint(final_req.extensions_mut(), store, id);
+            (store.clone(), id)
+        });
+
+        match self
+            .record_exchange(final_req, client_upgrade, flow, &conn.drop_signal)
+            .await
+        {
+            // the flow has the error, the client still gets an answer
+            Err(e) if e.is::<UpstreamError>() => Ok(error_response(
+                StatusCode::BAD_GATEWAY,
+                "Failed to send request to upstream",
+            )),
+            result => result,
+        }
+    }
+
+    async fn serve_replays(self: &Arc<Self>, mut jobs: UnboundedReceiver<ReplayJob>) {
+        while let Some(job) = jobs.recv().await {
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                let result = proxy.resend(job.request, job.replay_of).await;
+                let _ = job.done_tx.send(result);
+            });
+        }
+    }
+
+    /// Sends a replayed request like one from a client and waits for the
+    /// whole response, which ends up on a new flow linked to `replay_of`.
+    async fn resend(
+        self: &Arc<Self>,
+        req: Request<Body>,
+        replay_of: Option<FlowId>,
+    ) -> anyhow::Result<FlowId> {
+        let store = self
+            .flow_store
+            .clone()
+            .ok_or_else(|| anyhow!("No flow store to record the replay"))?;
+        let request = FlowRequest::from_request(&req);
+
+        let (id, res) = match self.intercept_req(req).await {
+            RequestOrResponse::Request(mut final_req) => {
+                let id = store.insert_replay(FlowRequest::from_request(&final_req), replay_of);
+                record_breakpoint(final_req.extensions_mut(), &store, id);
+                let flow = Some((store.clone(), id));
+                // failures end up on the flow
+                let Ok(res) = self
+                    .record_exchange(final_req, None, flow, &Notify::new())
+                    .await
+                else {
+                    return Ok(id);
+                };
+                (id, res)
+            }
+            // answered without going upstream, recorded all the same
+            RequestOrResponse::Response(mut res) => {
+                let id = store.insert_replay(request, replay_of);
+                record_breakpoint(res.extensions_mut(), &store, id);
+                store.set_response(id, FlowResponse::from_response(&res));
+                (id, capture_response(res, store.clone(), id))
+            }
         };
 
-        let res = match self.http_client.request(final_req).await {
+        // there is no client to hand an upgraded connection to
+        let upgraded = res.status() == StatusCode::SWITCHING_PROTOCOLS;
+        // nobody else reads the body, the capture finishes once it is drained
+        if let Err(e) = res.into_body().collect().await {
+            debug!("Replayed response of flow {} failed: {}", id, e);
+        }
+        if upgraded {
+            store.finish(id);
+        }
+        Ok(id)
+    }
+
+    /// Sends `final_req` upstream, recording the exchange on `flow`.
+    async fn record_exchange(
+        self: &Arc<Self>,
+        final_req: Request<Body>,
+        client_upgrade: Option<OnUpgrade>,
+        flow: Option<(Arc<FlowStore>, FlowId)>,
+        drop_signal: &Notify,
+    ) -> anyhow::Result<Response<Body>> {
+        let final_req = match &flow {
+            Some((store, id)) => {
+                let (store, id) = (store.clone(), *id);
+                let limit = store.capture_limit();
+                final_req.map(|b| {
+                    TeeBody::new(b, limit, move |capture| store.set_request_body(id, capture))
+                        .boxed()
+                })
+            }
+            None => final_req,
+        };
+
+        let result = self
+            .exchange(final_req, client_upgrade, flow.clone(), drop_signal)
+            .await;
+
+        let Some((store, id)) = flow else {
+            return result;
+        };
+
+        match result {
+            Ok(res) if res.status() == StatusCode::SWITCHING_PROTOCOLS => {
+                store.set_response(id, FlowResponse::from_response(&res));
+                Ok(res)
+            }
+            Ok(res) => {
+                store.set_response(id, FlowResponse::from_response(&res));
+                Ok(capture_response(res, store, id))
+            }
+            Err(e) => {
+                store.set_error(id, e.to_string());
+                store.finish(id);
+                Err(e)
+            }
+        }
+    }
+
+    async fn exchange(
+        self: &Arc<Self>,
+        final_req: Request<Body>,
+        client_upgrade: Option<OnUpgrade>,
+        flow: Option<(Arc<FlowStore>, FlowId)>,
+        drop_signal: &Notify,
+    ) -> anyhow::Result<Response<Body>> {
+        let (method, uri) = (final_req.method().clone(), final_req.uri().clone());
+        let request = RequestHead {
+            method: method.clone(),
+            uri: uri.clone(),
+            // only shown by response breakpoints
+            headers: match self.breakpoints {
+                Some(_) => final_req.headers().clone(),
+                None => HeaderMap::new(),
+            },
+        };
+
+        if let Some(action) = self.rules.as_ref().and_then(|r| r.find(&method, &uri)) {
+            debug!("Applying {:?} to {} {}", action, method, uri);
+            if let Some((store, id)) = &flow {
+                store.add_rule_hit(*id, action.clone());
+            }
+            match action {
+                RuleAction::Block { status } => return Ok(blocked_response(status)),
+                RuleAction::MalformedResponse => return Ok(malformed_response()),
+                RuleAction::DropConnection => {
+                    drop_signal.notify_one();
+                    return Err(anyhow!("Connection dropped by rule"));
+                }
+                // an error from the service resets the HTTP/2 stream
+                RuleAction::ResetStream => return Err(anyhow!("Stream reset by rule")),
+                RuleAction::FailTls | RuleAction::Delay { .. } | RuleAction::WsMessage { .. } => {}
+            }
+        }
+
+        let delay = self
+            .rules
+            .as_ref()
+            .and_then(|r| r.find_delay(&method, &uri));
+        if let (Some((request, response)), Some((store, id))) = (&delay, &flow) {
+            let action = RuleAction::Delay {
+                request: request.clone(),
+                response: response.clone(),
+            };
+            store.add_rule_hit(*id, action);
+        }
+        let (request_delay, response_delay) = delay.unwrap_or_default();
+
+        let final_req = match request_delay {
+            Some(delay) => {
+                let (parts, body) = final_req.into_parts();
+                Request::from_parts(parts, delay_body(body, &delay).await)
+            }
+            None => final_req,
+        };
+
+        let limiters = self.throttle.as_ref().map(|throttle| {
+            let host = uri.host().unwrap_or_default();
+            (
+                Limiter::new(throttle.clone(), host, Direction::Upload),
+                Limiter::new(throttle.clone(), host, Direction::Download),
+            )
+        });
+
+        let final_req = match &limiters {
+            Some((upload, _)) => {
+                sleep(upload.latency()).await;
+                final_req.map(|b| ThrottledBody::new(b, upload.clone()).boxed())
+            }
+            None => final_req,
+        };
+
+        let ws_rules = match (&client_upgrade, &self.rules) {
+            (Some(_), Some(rules)) => rules.find_ws(&method, &uri),
+            _ => vec![],
+        };
+        let rewrites_ws = client_upgrade.is_some()
+            && (!ws_rules.is_empty()
+                || self
+                    .handler
+                    .as_ref()
+                    .is_some_and(|h| h.rewrites_ws_messages()));
+
+        // the upgrade handshake needs an HTTP/1.1 upstream connection, and
+        // cleartext HTTP/2 can only be spoken with prior knowledge. Anything
+        // else goes out in the version the upstream picks through ALPN.
+        let (http_client, final_req) = match client_upgrade {
+            Some(_) => {
+                let mut final_req = with_version(final_req, Version::HTTP_11);
+                if rewrites_ws {
+                    offer_no_context_takeover(final_req.headers_mut());
+                }
+                (&self.http1_client, final_req)
+            }
+            None if final_req.version() == Version::HTTP_2
+                && self.http2.enabled
+                && uri.scheme_str() == Some("http") =>
+            {
+                (&self.h2c_client, final_req)
+            }
+            None => (&self.http_client, with_version(final_req, Version::HTTP_11)),
+        };
+
+        if let Some((store, id)) = &flow {
+            store.mark_sent(*id);
+        }
+        let mut res = match http_client.request(final_req).await {
             Ok(r) => r,
             Err(e) => {
                 error!("Failed to send request to upstream: {}", e);
-                return Ok(error_response(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to send request to upstream",
-                ));
+                return Err(UpstreamError(e).into());
+            }
+        };
+        if let Some((store, id)) = &flow {
+            store.mark_response_started(*id);
+        }
+
+        // taken before the handler sees the response, bridged only if it
+        // still switches protocols afterwards
+        let upstream_upgrade = (client_upgrade.is_some()
+            && res.status() == StatusCode::SWITCHING_PROTOCOLS)
+            .then(|| {
+                if rewrites_ws {
+                    require_client_no_context_takeover(res.headers_mut());
+                }
+                let deflate = DeflateParams::negotiated(res.headers());
+                (hyper::upgrade::on(&mut res), deflate)
+            });
+
+        let final_res = self.get_final_res(&request, flow.as_ref(), res).await;
+
+        if let (Some(client_upgrade), Some((upstream_upgrade, deflate))) =
+            (client_upgrade, upstream_upgrade)
+        {
+            if final_res.status() == StatusCode::SWITCHING_PROTOCOLS {
+                let proxy = self.clone();
+                let uri = uri.clone();
+                let flow = flow.clone();
+                let rules = ws_rules;
+
+                tokio::spawn(async move {
+                    match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                        Ok((client, upstream)) => {
+                            let handler = proxy
+                                .handler
+                                .as_ref()
+                                .filter(|h| h.rewrites_ws_messages())
+                                .map(|h| (h, &uri));
+                            let rewriter =
+                                (handler.is_some() || !rules.is_empty()).then_some(WsRewriter {
+                                    rules,
+                                    handler,
+                                    flow: flow.as_ref(),
+                                });
+                            let injector = proxy.ws_injector.as_deref();
+                            if let Err(e) = bridge(
+                                client,
+                                upstream,
+                                deflate,
+                                rewriter.as_ref(),
+                                flow.as_ref(),
+                                injector,
+                            )
+                            .await
+                            {
+                                debug!("WebSocket closed with error: {}", e);
+                            }
+                        }
+                        Err(e) => error!("Failed to upgrade WebSocket: {}", e),
+                    }
+                    if let Some((store, id)) = flow {
+                        store.finish(id);
+                    }
+                });
             }
+        }
+
+        let final_res = match limiters {
+            Some((_, download)) => {
+                sleep(download.latency()).await;
+                final_res.map(|b| ThrottledBody::new(b, download).boxed())
+            }
+            None => final_res,
         };
 
-        let final_res = self.get_final_res(res).await; // TODO: handle respones after handlin
+        let final_res = match response_delay {
+            Some(delay) => {
+                let (parts, body) = final_res.into_parts();
+                Response::from_parts(parts, delay_body(body, &delay).await)
+            }
+            None => final_res,
+        };
 
         Ok(final_res)
     }
@@ -175,51 +684,216 @@ where
     async fn handle_connect(
         self: Arc<Self>,
         req: Request<Incoming>,
+        conn: &ClientConn,
     ) -> anyhow::Result<Response<Body>> {
-        let host = req
-            .uri()
-            .host()
-            .ok_or_else(|| anyhow!("CONNECT request has no host"))?
-            .to_string();
-
-        if let Some(addr) = host_addr(req.uri()) {
-            tokio::spawn(async move {
-                match hyper::upgrade::on(req).await {
-                    Ok(upgraded) => {
-                        if self.root_cert.is_none() {
-                            if let Err(e) = tunnel(upgraded, addr).await {
-                                error!("Failed to tunnel: {}", e);
-                            }
-                        } else {
-                            if let Err(e) = self.handle_tls(upgraded, addr, host).await {
-                                error!("Failed to handle TLS: {}", e);
-                            }
-                        }
-                    }
-                    Err(e) => error!("Failed to upgrade: {}", e),
-                }
-            });
-            Ok(Response::new(empty_body()))
-        } else {
+        let Some(host) = req.uri().host() else {
             error!("CONNECT request has no host: {:?}", req.uri());
-            Ok(error_response(
+            return Ok(error_response(
                 StatusCode::BAD_REQUEST,
                 "CONNECT request has no host",
-            ))
+            ));
+        };
+        let target = TargetAddr {
+            host: host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            port: req.uri().port_u16().unwrap_or(443),
+        };
+        let request = FlowRequest::from_request(&req);
+        let (client_addr, connection_id) = (conn.addr, conn.id);
+
+        tokio::spawn(async move {
+            match hyper::upgrade::on(req).await {
+                Ok(upgraded) => {
+                    let upgraded = TokioIo::new(upgraded);
+                    if let Err(e) = self
+                        .route(upgraded, target, client_addr, request, connection_id, None)
+                        .await
+                    {
+                        error!("Failed to handle CONNECT tunnel: {}", e);
+                    }
+                }
+                Err(e) => error!("Failed to upgrade: {}", e),
+            }
+        });
+        Ok(Response::new(empty_body()))
+    }
+
+    async fn handle_socks(
+        self: Arc<Self>,
+        mut stream: TcpStream,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<()> {
+        let socks = socks_handshake(&mut stream, self.socks_credentials.as_ref()).await?;
+        let target = socks.target.clone();
+        debug!("SOCKS connection from {} to {}", client_addr, target.addr());
+
+        // dialed before answering so that the client learns when the target
+        // can't be reached. A relayed stream goes on over this connection,
+        // an intercepted one is sent by the HTTP clients and drops it.
+        let upstream = match TcpStream::connect(target.addr()).await {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                socks.reply(&mut stream, Some(&e)).await?;
+                return Err(anyhow!(e).context(format!("Failed to connect to {}", target.addr())));
+            }
+        };
+        socks.reply(&mut stream, None).await?;
+
+        let request = FlowRequest::tunnel(target.addr(), "SOCKS");
+        self.route(stream, target, client_addr, request, None, Some(upstream))
+            .await
+    }
+
+    async fn handle_transparent(
+        self: Arc<Self>,
+        stream: TcpStream,
+        client_addr: SocketAddr,
+        listen_addr: Option<SocketAddr>,
+    ) -> anyhow::Result<()> {
+        let dst = original_dst(&stream)?;
+        if listen_addr.is_some_and(|listen_addr| !is_redirected(dst, listen_addr)) {
+            bail!("Connection to {} was not redirected", dst);
         }
+        debug!("Transparent connection from {} to {}", client_addr, dst);
+
+        let target = TargetAddr {
+            host: dst.ip().to_string(),
+            port: dst.port(),
+        };
+        let request = FlowRequest::tunnel(target.addr(), "TCP");
+        self.route(stream, target, client_addr, request, None, None)
+            .await
     }
 
-    async fn handle_tls(
+    /// Intercepts a stream opened through CONNECT, SOCKS or a redirect as
+    /// TLS or plain HTTP when it starts like one, and relays it untouched otherwise.
+    /// A relayed stream goes over `upstream` when the target was already dialed.
+    async fn route<I>(
         self: Arc<Self>,
-        upgraded: Upgraded,
+        stream: I,
+        target: TargetAddr,
+        client_addr: SocketAddr,
+        request: FlowRequest,
+        connection_id: Option<ConnectionId>,
+        upstream: Option<TcpStream>,
+    ) -> anyhow::Result<()>
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (sniffed, stream) = sniff(stream, SNIFF_TIMEOUT).await?;
+        debug!("Sniffed {:?} towards {}", sniffed, target.addr());
+
+        match sniffed {
+            Sniffed::Tls { server_name } if self.root_cert.is_some() => {
+                // clients that resolve names themselves still send SNI
+                let host = server_name.unwrap_or_else(|| target.host.clone());
+                self.handle_tls(stream, target.addr(), host, client_addr)
+                    .await
+            }
+            Sniffed::Http => {
+                let stream = HeadRecorder::new(stream, self.header_fidelity);
+                let conn =
+                    self.open_conn(client_addr, Some(target.host.clone()), None, None, &stream);
+                self.serve_intercepted(stream, conn, "http", target.authority(80))
+                    .await;
+                Ok(())
+            }
+            _ => {
+                self.relay(stream, &target, request, connection_id, upstream)
+                    .await
+            }
+        }
+    }
+
+    /// Tunnels the stream and records it as a flow of its own.
+    async fn relay<I>(
+        &self,
+        stream: I,
+        target: &TargetAddr,
+        request: FlowRequest,
+        connection_id: Option<ConnectionId>,
+        upstream: Option<TcpStream>,
+    ) -> anyhow::Result<()>
+    where
+        I: AsyncRead + AsyncWrite + Unpin,
+    {
+        let flow = self
+            .flow_store
+            .as_ref()
+            .map(|store| (store.clone(), store.insert_tunnel(request, connection_id)));
+
+        let stream = CountingStream::new(stream);
+        let counts = stream.counts();
+        let result = tunnel(
+            stream,
+            target.addr(),
+            &target.host,
+            upstream,
+            self.throttle.clone(),
+        )
+        .await;
+
+        if let Some((store, id)) = flow {
+            store.set_tunnel_stats(id, counts.stats());
+            if let Err(e) = &result {
+                store.set_error(id, e.to_string());
+            }
+            store.finish(id);
+        }
+        result
+    }
+
+    async fn handle_tls<I>(
+        self: Arc<Self>,
+        mut client_io: I,
         target_addr: String,
         host_for_cert: String,
-    ) -> anyhow::Result<()> {
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<()>
+    where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
         debug!("Initiating TLS interception for {}", target_addr);
 
-        let signed_cert = self
-            .get_signed_cert(&host_for_cert)
-            .context("Failed to sign cert")?;
+        if self
+            .rules
+            .as_ref()
+            .is_some_and(|r| r.fails_tls(&host_for_cert))
+        {
+            debug!("Failing TLS handshake for {}", host_for_cert);
+            client_io.write_all(&TLS_HANDSHAKE_FAILURE_ALERT).await?;
+            client_io.shutdown().await?;
+            return Ok(());
+        }
+
+        let (client_tls_stream, conn) = self
+            .accept_tls(client_io, host_for_cert.clone(), client_addr)
+            .await?;
+
+        let authority = match target_addr.rsplit_once(':') {
+            Some((_, port)) if port != "443" => format!("{}:{}", host_for_cert, port),
+            _ => host_for_cert,
+        };
+        self.serve_intercepted(client_tls_stream, conn, "https", authority)
+            .await;
+
+        Ok(())
+    }
+
+    /// Terminates TLS with a certificate for `host` and opens the
+    /// connection record for what is served over it.
+    async fn accept_tls<I>(
+        &self,
+        client_io: I,
+        host: String,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<(HeadRecorder<TlsStream<I>>, Arc<ClientConn>)>
+    where
+        I: AsyncRead + AsyncWrite + Unpin,
+    {
+        let signed_cert = self.get_signed_cert(&host).context("Failed to sign cert")?;
 
         let server_cert = CertificateDer::from(signed_cert.cert);
         let server_key = PrivateKeyDer::Pkcs8(signed_cert.key_pair.into());
@@ -227,18 +901,98 @@ where
         let mut server_config = ServerConfig::builder()
             .with_no_client_auth()
             .with_single_cert(vec![server_cert.clone()], server_key)?;
-        server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        server_config.alpn_protocols = self.http2.alpn_protocols();
         let acceptor = TlsAcceptor::from(Arc::new(server_config));
 
-        let client_io = TokioIo::new(upgraded);
         let client_tls_stream = acceptor.accept(client_io).await?;
-        debug!("Client TLS handshake successful for {}", host_for_cert);
+        debug!("Client TLS handshake successful for {}", host);
+
+        let session = client_tls_stream.get_ref().1;
+        let alpn = session
+            .alpn_protocol()
+            .map(|p| String::from_utf8_lossy(p).into_owned());
+        let tls = session
+            .protocol_version()
+            .zip(session.negotiated_cipher_suite())
+            .map(|(version, suite)| (tls_version_name(version), format!("{:?}", suite.suite())));
+        // HTTP/2 heads are compressed frames, there is nothing raw to keep
+        let fidelity = self.header_fidelity && alpn.as_deref() != Some("h2");
+        let client_tls_stream = HeadRecorder::new(client_tls_stream, fidelity);
+        let conn = self.open_conn(client_addr, Some(host), alpn, tls, &client_tls_stream);
+
+        Ok((client_tls_stream, conn))
+    }
+
+    async fn handle_reverse(
+        self: Arc<Self>,
+        stream: TcpStream,
+        client_addr: SocketAddr,
+    ) -> anyhow::Result<()> {
+        let Some(reverse) = &self.reverse else {
+            return Ok(());
+        };
+        let upstream = reverse
+            .upstream()
+            .authority()
+            .map(|a| a.to_string())
+            .unwrap_or_default();
+
+        match reverse.tls_host() {
+            Some(host) => {
+                let (stream, conn) = self
+                    .accept_tls(stream, host.to_string(), client_addr)
+                    .await?;
+                self.serve_intercepted(stream, conn, "https", upstream)
+                    .await;
+            }
+            None => {
+                let stream = HeadRecorder::new(stream, self.header_fidelity);
+                let conn = self.open_conn(client_addr, None, None, None, &stream);
+                self.serve_intercepted(stream, conn, "http", upstream).await;
+            }
+        }
+        Ok(())
+    }
+
+    fn open_conn<S>(
+        &self,
+        client_addr: SocketAddr,
+        server_name: Option<String>,
+        alpn: Option<String>,
+        tls: Option<(String, String)>,
+        stream: &HeadRecorder<S>,
+    ) -> Arc<ClientConn> {
+        Arc::new(ClientConn {
+            id: self
+                .flow_store
+                .as_ref()
+                .map(|store| store.open_connection(client_addr, server_name, alpn, tls)),
+            addr: client_addr,
+            raw_heads: stream.heads(),
+            drop_signal: Notify::new(),
+        })
+    }
 
+    /// Serves requests read off an intercepted stream, origin-form targets
+    /// are completed with `scheme` and `authority`. In reverse proxy mode
+    /// every request is pointed at the upstream instead.
+    async fn serve_intercepted<I>(
+        self: Arc<Self>,
+        io: I,
+        conn: Arc<ClientConn>,
+        scheme: &'static str,
+        authority: String,
+    ) where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
         let proxy = self.clone();
+        let service_conn = conn.clone();
+        let default_authority = authority.clone();
 
         let service = service_fn(move |mut req: Request<Incoming>| {
             let proxy = proxy.clone();
-            let host_for_cert = host_for_cert.clone();
+            let authority = default_authority.clone();
+            let conn = service_conn.clone();
 
             async move {
                 let original_uri = req.uri().clone();
@@ -251,10 +1005,19 @@ where
                     original_uri
                 );
 
-                if original_uri.scheme().is_none() || original_uri.authority().is_none() {
+                if let Some(reverse) = &proxy.reverse {
+                    if let Err(e) = reverse.rewrite(&mut req, scheme, conn.addr.ip()) {
+                        error!("Failed to rewrite request for upstream: {}", e);
+                        return Ok(error_response(
+                            StatusCode::BAD_REQUEST,
+                            "Failed to parse URI",
+                        ));
+                    }
+                } else if original_uri.scheme().is_none() || original_uri.authority().is_none() {
                     let new_uri_string = format!(
-                        "https://{}{}",
-                        host_for_cert,
+                        "{}://{}{}",
+                        scheme,
+                        authority,
                         original_uri
                             .path_and_query()
                             .map(|pq| pq.as_str())
@@ -271,46 +1034,44 @@ where
                     }
                 }
 
-                let final_req = match proxy.get_final_req(req).await {
-                    RequestOrResponse::Request(r) => r,
-                    RequestOrResponse::Response(resp) => return Ok(resp),
-                };
-
-                let res = match proxy.http_client.request(final_req).await {
-                    Ok(r) => r,
-                    Err(e) => {
-                        error!("Failed to send request to upstream: {}", e);
-                        return Ok(error_response(
-                            StatusCode::INTERNAL_SERVER_ERROR,
-                            "Failed to send request to upstream",
-                        ));
-                    }
-                };
+                proxy.forward(req, &conn).await
+            }
+        });
 
-                let final_res = proxy.get_final_res(res).await;
+        let server_builder = self.server_builder();
+        let connection = server_builder.serve_connection_with_upgrades(TokioIo::new(io), service);
 
-                Ok(final_res)
+        tokio::select! {
+            result = connection => {
+                if let Err(err) = result {
+                    error!("Error serving intercepted connection to {}: {}", authority, err);
+                }
             }
-        });
+            _ = conn.drop_signal.notified() => {
+                info!("Dropping intercepted connection to {}", authority);
+            }
+        }
 
-        let client_tls_stream_io = TokioIo::new(client_tls_stream);
-        let mut server_builder = auto::Builder::new(TokioExecutor::new());
-        if let Err(err) = server_builder
-            .http1()
-            .preserve_header_case(true)
-            .title_case_headers(true)
-            .serve_connection_with_upgrades(client_tls_stream_io, service)
-            .await
-        {
-            error!("Error serving client TLS connection {}", err);
+        if let (Some(store), Some(id)) = (&self.flow_store, conn.id) {
+            store.close_connection(id);
         }
+    }
 
-        Ok(())
+    fn server_builder(&self) -> auto::Builder<TokioExecutor> {
+        let mut builder = auto::Builder::new(TokioExecutor::new());
+        builder
+            .http1()
+            .preserve_header_case(true)
+            .title_case_headers(!self.header_fidelity);
+        self.http2.configure_server(builder)
     }
 
     async fn get_final_req(&self, req: Request<Incoming>) -> RequestOrResponse {
-        let req = req.map(|b| b.map_err(|e| anyhow!(e)).boxed());
+        self.intercept_req(req.map(|b| b.map_err(|e| anyhow!(e)).boxed()))
+            .await
+    }
 
+    async fn intercept_req(&self, req: Request<Body>) -> RequestOrResponse {
         let final_req = if let Some(handler) = &self.handler {
             match handler.handle_request(req).await {
                 Ok(r) => r,
@@ -323,38 +1084,89 @@ where
                 }
             }
         } else {
-            return RequestOrResponse::Request(req);
+            RequestOrResponse::Request(req)
         };
 
-        match final_req {
-            RequestOrResponse::Request(mut r) => {
-                // reset version to enable optional HTTP2 upgrade
-                *r.version_mut() = Version::default();
-                RequestOrResponse::Request(r)
+        let final_req = match (final_req, &self.server_replay) {
+            (RequestOrResponse::Request(r), Some(replay)) if replay.is_active() => {
+                replay.respond(r, self.capture_limit()).await
             }
-            resp => resp,
-        }
+            (final_req, _) => final_req,
+        };
+
+        let final_req = match (final_req, &self.breakpoints) {
+            (RequestOrResponse::Request(r), Some(breakpoints))
+                if breakpoints.should_break(BreakpointPhase::Request, r.method(), r.uri()) =>
+            {
+                breakpoints.pause_request(r, self.capture_limit()).await
+            }
+            (final_req, _) => final_req,
+        };
+
+        final_req
     }
 
-    async fn get_final_res(&self, res: Response<Incoming>) -> Response<Body> {
+    async fn get_final_res(
+        &self,
+        request: &RequestHead,
+        flow: Option<&(Arc<FlowStore>, FlowId)>,
+        res: Response<Incoming>,
+    ) -> Response<Body> {
         let res = res.map(|b| b.map_err(|e| anyhow!(e)).boxed());
 
-        let Some(handler) = &self.handler else {
-            return res;
+        let res = match &self.handler {
+            Some(handler) => match handler.handle_response(res).await {
+                Ok(r) => r,
+                Err(e) => {
+                    error!("Failed to handle response: {}", e);
+                    error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Failed to handle response",
+                    )
+                }
+            },
+            None => res,
         };
 
-        match handler.handle_response(res).await {
-            Ok(r) => r,
-            Err(e) => {
-                error!("Failed to handle response: {}", e);
-                error_response(
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to handle response",
-                )
+        match &self.breakpoints {
+            Some(breakpoints)
+                if breakpoints.should_break(
+                    BreakpointPhase::Response,
+                    &request.method,
+                    &request.uri,
+                ) =>
+            {
+                // the body was streamed upstream, the capture has it
+                let body = flow
+                    .and_then(|(store, id)| store.get(*id))
+                    .and_then(|flow| flow.request_body)
+                    .unwrap_or_default();
+                let request = EditableRequest {
+                    method: request.method.to_string(),
+                    uri: request.uri.to_string(),
+                    headers: header_pairs(&request.headers),
+                    body: body.data,
+                    trailers: body.trailers.unwrap_or_default(),
+                };
+                let mut res = breakpoints
+                    .pause_response(request, res, self.capture_limit())
+                    .await;
+                if let Some((store, id)) = flow {
+                    record_breakpoint(res.extensions_mut(), store, *id);
+                }
+                res
             }
+            _ => res,
         }
     }
 
+    /// The most of a body that may be held in memory, for breakpoints.
+    fn capture_limit(&self) -> usize {
+        self.flow_store
+            .as_ref()
+            .map_or(DEFAULT_CAPTURE_LIMIT, |store| store.capture_limit())
+    }
+
     fn get_signed_cert(&self, host: &str) -> anyhow::Result<SignedCert> {
         let root_cert = self
             .root_cert
@@ -372,10 +1184,23 @@ where
 #[derive(Default)]
 pub struct MitmProxyBuilder<A: ToSocketAddrs, H: HttpHandler> {
     bind_addr: Option<A>,
+    socks_addr: Option<A>,
+    socks_credentials: Option<SocksCredentials>,
+    transparent_addr: Option<A>,
+    reverse: Option<ReverseProxy>,
     root_ca: Option<RootCA>,
     cert_cache: Option<Cache<String, SignedCert>>,
     handler: Option<H>,
+    breakpoints: Option<Arc<Breakpoints>>,
+    rules: Option<Arc<Rules>>,
+    server_replay: Option<Arc<ServerReplay>>,
+    client_replay: Option<Arc<ClientReplay>>,
+    throttle: Option<Arc<Throttle>>,
+    flow_store: Option<Arc<FlowStore>>,
+    ws_injector: Option<Arc<WsInjector>>,
     shutdown_tx: Option<broadcast::Sender<()>>,
+    http2: Http2Settings,
+    header_fidelity: bool,
 }
 
 impl<A, H> MitmProxyBuilder<A, H>
@@ -393,11 +1218,88 @@ where
         self
     }
 
+    pub fn with_breakpoints(mut self, breakpoints: Arc<Breakpoints>) -> Self {
+        self.breakpoints = Some(breakpoints);
+        self
+    }
+
+    pub fn with_rules(mut self, rules: Arc<Rules>) -> Self {
+        self.rules = Some(rules);
+        self
+    }
+
+    /// Answers matching requests with recorded responses.
+    pub fn with_server_replay(mut self, server_replay: Arc<ServerReplay>) -> Self {
+        self.server_replay = Some(server_replay);
+        self
+    }
+
+    /// Lets `client_replay` send requests through this proxy once started.
+    pub fn with_client_replay(mut self, client_replay: Arc<ClientReplay>) -> Self {
+        self.client_replay = Some(client_replay);
+        self
+    }
+
+    pub fn with_throttle(mut self, throttle: Arc<Throttle>) -> Self {
+        self.throttle = Some(throttle);
+        self
+    }
+
+    pub fn with_flow_store(mut self, flow_store: Arc<FlowStore>) -> Self {
+        self.flow_store = Some(flow_store);
+        self
+    }
+
+    pub fn with_ws_injector(mut self, ws_injector: Arc<WsInjector>) -> Self {
+        self.ws_injector = Some(ws_injector);
+        self
+    }
+
+    pub fn with_http2_settings(mut self, http2: Http2Settings) -> Self {
+        self.http2 = http2;
+        self
+    }
+
+    /// Sends HTTP/1 header names in the casing the peers used instead of
+    /// title case, and records raw HTTP/1 request heads on flows. Header
+    /// order is only kept per name since repeated names end up next to each
+    /// other, the raw head has the bytes as they were read.
+    pub fn with_header_fidelity(mut self, enabled: bool) -> Self {
+        self.header_fidelity = enabled;
+        self
+    }
+
     pub fn with_addr(mut self, addr: A) -> Self {
         self.bind_addr = Some(addr);
         self
     }
 
+    /// Also accepts SOCKS5 and SOCKS4a clients on `addr`.
+    pub fn with_socks_addr(mut self, addr: A) -> Self {
+        self.socks_addr = Some(addr);
+        self
+    }
+
+    /// Requires SOCKS5 username/password authentication, SOCKS4 clients
+    /// are turned away.
+    pub fn with_socks_credentials(mut self, credentials: SocksCredentials) -> Self {
+        self.socks_credentials = Some(credentials);
+        self
+    }
+
+    /// Also accepts connections redirected by netfilter on `addr`, see
+    /// [`TransparentRules`](super::TransparentRules).
+    pub fn with_transparent_addr(mut self, addr: A) -> Self {
+        self.transparent_addr = Some(addr);
+        self
+    }
+
+    /// Fronts a single backend instead of acting as a forward proxy.
+    pub fn with_reverse_proxy(mut self, reverse: ReverseProxy) -> Self {
+        self.reverse = Some(reverse);
+        self
+    }
+
     pub fn with_cert_cache(mut self, cert_cache: Cache<String, SignedCert>) -> Self {
         self.cert_cache = Some(cert_cache);
         self
@@ -409,17 +1311,40 @@ where
     }
 
     pub fn build(self) -> MitmProxy<A, H> {
+        let http_client = self.make_http_client(self.http2.enabled, false);
+        let http1_client = self.make_http_client(false, false);
+        let h2c_client = self.make_http_client(true, true);
+
         MitmProxy {
             bind_addr: self.bind_addr,
+            socks_addr: self.socks_addr,
+            socks_credentials: self.socks_credentials,
+            transparent_addr: self.transparent_addr,
+            reverse: self.reverse,
             root_cert: self.root_ca,
             cert_cache: self.cert_cache,
             handler: self.handler,
+            breakpoints: self.breakpoints,
+            rules: self.rules,
+            server_replay: self.server_replay,
+            client_replay: self.client_replay,
+            throttle: self.throttle,
+            flow_store: self.flow_store,
+            ws_injector: self.ws_injector,
             shutdown_tx: self.shutdown_tx,
-            http_client: Self::make_http_client(),
+            http2: self.http2,
+            header_fidelity: self.header_fidelity,
+            http_client,
+            http1_client,
+            h2c_client,
         }
     }
 
-    fn make_http_client() -> Client<HttpsConnector<HttpConnector>, Body> {
+    fn make_http_client(
+        &self,
+        enable_http2: bool,
+        http2_only: bool,
+    ) -> Client<HttpsConnector<HttpConnector>, Body> {
         let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
 
         let client_config = ClientConfig::builder()
@@ -429,11 +1354,19 @@ where
         let https = hyper_rustls::HttpsConnectorBuilder::new()
             .with_tls_config(client_config)
             .https_or_http()
-            .enable_http1()
-            .enable_http2()
-            .build();
+            .enable_http1();
+        let https = if enable_http2 {
+            https.enable_http2().build()
+        } else {
+            https.build()
+        };
 
-        Client::builder(TokioExecutor::new()).build(https)
+        let mut builder = Client::builder(TokioExecutor::new());
+        self.http2.configure_client(&mut builder);
+        builder
+            .http2_only(http2_only)
+            .http1_preserve_header_case(self.header_fidelity);
+        builder.build(https)
     }
 }
 
@@ -451,6 +1384,25 @@ pub trait HttpHandler {
     async fn handle_response(&self, res: Response<Body>) -> anyhow::Result<Response<Body>> {
         async { Ok(res) }
     }
+
+    /// Whether [`Self::handle_ws_message`] is overridden. WebSocket bytes
+    /// are relayed untouched unless a handler or a rule asks for messages.
+    fn rewrites_ws_messages(&self) -> bool {
+        false
+    }
+
+    /// Called for every WebSocket message when
+    /// [`Self::rewrites_ws_messages`] is set, `None` drops it. Changed
+    /// messages are forwarded uncompressed. Awaiting here delays the
+    /// message and the ones after it in the same direction, e.g. with
+    /// `tokio::time::sleep`, while the other direction keeps flowing.
+    async fn handle_ws_message(
+        &self,
+        _uri: &Uri,
+        message: WsMessage,
+    ) -> anyhow::Result<Option<WsMessage>> {
+        async { Ok(Some(message)) }
+    }
 }
 
 pub fn empty_body() -> Body {
@@ -465,7 +1417,7 @@ pub fn full_body<T: Into<Bytes>>(data: T) -> Body {
         .boxed()
 }
 
-fn error_response<'a>(status: StatusCode, message: &'a str) -> Response<Body> {
+pub(super) fn error_response(status: StatusCode, message: &str) -> Response<Body> {
     Response::builder()
         .status(status)
         .body(full_body(message.to_owned()))
@@ -477,16 +1429,58 @@ fn error_response<'a>(status: StatusCode, message: &'a str) -> Response<Body> {
         })
 }
 
-fn host_addr(uri: &Uri) -> Option<String> {
-    uri.authority().map(|auth| auth.to_string())
+/// Records the response body as it goes out, the flow finishes with it.
+fn capture_response(res: Response<Body>, store: Arc<FlowStore>, id: FlowId) -> Response<Body> {
+    let limit = store.capture_limit();
+    let sse = is_event_stream(res.headers())
+        .then(|| SseRecorder::new(res.headers(), limit))
+        .flatten();
+
+    let (parts, body) = res.into_parts();
+    let on_end = {
+        let store = store.clone();
+        move |capture| {
+            store.set_response_body(id, capture);
+            store.finish(id);
+        }
+    };
+    let body = TeeBody::new(body, limit, on_end);
+    let body = match sse {
+        Some(mut sse) => body.on_chunk(move |chunk| {
+            for event in sse.feed(chunk) {
+                store.push_sse_event(id, event);
+            }
+        }),
+        None => body,
+    };
+
+    Response::from_parts(parts, body.boxed())
+}
+
+/// Moves the [`BreakpointRecord`] a paused message carries onto its flow.
+fn record_breakpoint(extensions: &mut Extensions, store: &FlowStore, id: FlowId) {
+    if let Some(record) = extensions.remove::<BreakpointRecord>() {
+        store.add_breakpoint(id, record);
+    }
 }
 
-async fn tunnel(upgraded: Upgraded, addr: String) -> anyhow::Result<()> {
-    info!("Tunneling to {}", addr);
-    let mut server = TcpStream::connect(addr).await?;
-    let mut upgraded = TokioIo::new(upgraded);
+/// The name OpenSSL and mitmproxy use, e.g. `TLSv1.3`.
+fn tls_version_name(version: ProtocolVersion) -> String {
+    match version {
+        ProtocolVersion::TLSv1_2 => "TLSv1.2".to_string(),
+        ProtocolVersion::TLSv1_3 => "TLSv1.3".to_string(),
+        other => format!("{:?}", other),
+    }
+}
 
-    tokio::io::copy_bidirectional(&mut upgraded, &mut server).await?;
+fn with_version(mut req: Request<Body>, version: Version) -> Request<Body> {
+    *req.version_mut() = version;
+    req
+}
 
-    Ok(())
+async fn accept(listener: Option<&TcpListener>) -> std::io::Result<(TcpStream, SocketAddr)> {
+    match listener {
+        Some(listener) => listener.accept().await,
+        None => std::future::pending().await,
+    }
 }
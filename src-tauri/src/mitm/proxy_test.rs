@@ -1,9 +1,19 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
-use hyper::Response;
-use tokio::{sync::broadcast, time::sleep};
+use http_body_util::BodyExt;
+use hyper::{body::Incoming, service::service_fn, HeaderMap, Request, Response, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+    time::sleep,
+};
 
-use crate::mitm::{MitmProxy, RootCA};
+use crate::mitm::{
+    Flow, FlowKind, FlowStore, MitmProxy, RequestMatcher, RootCA, Rule, RuleAction, Rules,
+    ServerReplay, ServerReplayConfig, TunnelStats,
+};
 
 use super::{full_body, Body, HttpHandler, RequestOrResponse};
 
@@ -41,3 +51,485 @@ async fn test_proxy() {
 
     let _ = proxy_handle.await;
 }
+
+struct PassThrough;
+
+impl HttpHandler for PassThrough {}
+
+async fn serve_h2c_with_trailers(listener: TcpListener) {
+    let (stream, _) = listener.accept().await.unwrap();
+    let service = service_fn(|req: Request<Incoming>| async move {
+        let echoed = req.into_body().collect().await?.trailers().cloned();
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", "0".parse().unwrap());
+        if let Some(value) = echoed.and_then(|t| t.get("x-checksum").cloned()) {
+            trailers.insert("x-checksum", value);
+        }
+        let body = full_body("pong").with_trailers(async move { Some(Ok(trailers)) });
+        Ok::<_, hyper::Error>(Response::new(body))
+    });
+    hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+        .serve_connection(TokioIo::new(stream), service)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_h2c_trailers() {
+    let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    tokio::spawn(serve_h2c_with_trailers(upstream));
+
+    let (shutdown_tx, _) = broadcast::channel::<()>(1);
+    let flow_store = Arc::new(FlowStore::new());
+    let proxy = MitmProxy::builder()
+        .with_addr("127.0.0.1:8081")
+        .with_handler(PassThrough)
+        .with_flow_store(flow_store.clone())
+        .with_shutdown(shutdown_tx.clone())
+        .build();
+    tokio::spawn(async move { proxy.start().await });
+    sleep(Duration::from_millis(200)).await;
+
+    let stream = TcpStream::connect("127.0.0.1:8081").await.unwrap();
+    let (mut sender, connection) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+            .await
+            .unwrap();
+    tokio::spawn(connection);
+
+    let mut request_trailers = HeaderMap::new();
+    request_trailers.insert("x-checksum", "abc".parse().unwrap());
+    let req = Request::post(format!("http://{}/ping", upstream_addr))
+        .version(Version::HTTP_2)
+        .body(
+            full_body("ping")
+                .with_trailers(async move { Some(Ok(request_trailers)) })
+                .boxed(),
+        )
+        .unwrap();
+    let res = sender.send_request(req).await.unwrap();
+    assert_eq!(res.version(), Version::HTTP_2);
+
+    let collected = res.into_body().collect().await.unwrap();
+    let trailers = collected.trailers().cloned().unwrap();
+    assert_eq!(trailers["grpc-status"], "0");
+    assert_eq!(trailers["x-checksum"], "abc");
+    assert_eq!(collected.to_bytes(), "pong");
+
+    sleep(Duration::from_millis(50)).await;
+    let flows = flow_store.list();
+    let connections = flow_store.connections();
+    assert_eq!(flows.len(), 1);
+    assert_eq!(connections.len(), 1);
+    assert_eq!(flows[0].connection_id, Some(connections[0].id));
+    assert_eq!(flows[0].request.version, "HTTP/2.0");
+    assert_eq!(flows[0].response.as_ref().unwrap().version, "HTTP/2.0");
+    let response_body = flows[0].response_body.as_ref().unwrap();
+    assert!(response_body
+        .trailers
+        .as_ref()
+        .is_some_and(|t| t.contains(&("grpc-status".to_string(), "0".to_string()))));
+
+    let _ = shutdown_tx.send(());
+}
+
+#[tokio::test]
+async fn test_header_fidelity() {
+    let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    let upstream_task = tokio::spawn(async move {
+        let (mut stream, _) = upstream.accept().await.unwrap();
+        let mut received = vec![];
+        let mut buf = [0; 1024];
+        while !received.ends_with(b"\r\n\r\n") {
+            let n = stream.read(&mut buf).await.unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nx-lower: 1\r\nX-CuStOm: 2\r\nContent-Length: 0\r\n\r\n")
+            .await
+            .unwrap();
+        String::from_utf8(received).unwrap()
+    });
+
+    let (shutdown_tx, _) = broadcast::channel::<()>(1);
+    let flow_store = Arc::new(FlowStore::new());
+    let proxy = MitmProxy::builder()
+        .with_addr("127.0.0.1:8082")
+        .with_handler(PassThrough)
+        .with_flow_store(flow_store.clone())
+        .with_header_fidelity(true)
+        .with_shutdown(shutdown_tx.clone())
+        .build();
+    tokio::spawn(async move { proxy.start().await });
+    sleep(Duration::from_millis(200)).await;
+
+    let head = format!(
+        "GET http://{}/ HTTP/1.1\r\nHost: {}\r\nzz-last-alpha: 1\r\nX-MiXeD-Case: a\r\n\r\n",
+        upstream_addr, upstream_addr
+    );
+    let mut client = TcpStream::connect("127.0.0.1:8082").await.unwrap();
+    client.write_all(head.as_bytes()).await.unwrap();
+    let mut response = vec![];
+    let mut buf = [0; 1024];
+    while !response.ends_with(b"\r\n\r\n") {
+        let n = client.read(&mut buf).await.unwrap();
+        response.extend_from_slice(&buf[..n]);
+    }
+    let response = String::from_utf8(response).unwrap();
+
+    let received = upstream_task.await.unwrap();
+    let first = received.find("zz-last-alpha: 1").unwrap();
+    let second = received.find("X-MiXeD-Case: a").unwrap();
+    assert!(first < second);
+    assert!(response.contains("x-lower: 1\r\nX-CuStOm: 2\r\n"));
+
+    let flows = flow_store.list();
+    assert_eq!(flows[0].request.raw_head.as_deref(), Some(head.as_bytes()));
+
+    let _ = shutdown_tx.send(());
+}
+
+async fn connect_tunnel(proxy: &str, port: u16) -> TcpStream {
+    let mut stream = TcpStream::connect(proxy).await.unwrap();
+    let connect = format!("CONNECT 127.0.0.1:{port} HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\n\r\n");
+    stream.write_all(connect.as_bytes()).await.unwrap();
+    let mut response = vec![0; 1024];
+    let n = stream.read(&mut response).await.unwrap();
+    assert!(response[..n].starts_with(b"HTTP/1.1 200"));
+    stream
+}
+
+#[tokio::test]
+async fn test_connect_sniffing() {
+    let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = upstream.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        // plain HTTP inside the tunnel first, then a server speaking first
+        let (mut stream, _) = upstream.accept().await.unwrap();
+        let mut buf = [0; 1024];
+        let _ = stream.read(&mut buf).await.unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+            .await
+            .unwrap();
+
+        let (mut stream, _) = upstream.accept().await.unwrap();
+        stream.write_all(b"SSH-2.0-test\r\n").await.unwrap();
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+    });
+
+    let (shutdown_tx, _) = broadcast::channel::<()>(1);
+    let flow_store = Arc::new(FlowStore::new());
+    let proxy = MitmProxy::builder()
+        .with_addr("127.0.0.1:8085")
+        .with_handler(PassThrough)
+        .with_flow_store(flow_store.clone())
+        .with_shutdown(shutdown_tx.clone())
+        .build();
+    tokio::spawn(async move { proxy.start().await });
+    sleep(Duration::from_millis(200)).await;
+
+    let mut stream = connect_tunnel("127.0.0.1:8085", port).await;
+    stream
+        .write_all(b"GET /inner HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = vec![0; 1024];
+    let n = stream.read(&mut response).await.unwrap();
+    assert!(response[..n].ends_with(b"\r\n\r\nok"));
+
+    let flows = flow_store.list();
+    assert_eq!(flows.len(), 1);
+    assert_eq!(flows[0].kind, FlowKind::Http);
+    assert_eq!(
+        flows[0].request.uri,
+        format!("http://127.0.0.1:{}/inner", port)
+    );
+
+    let mut stream = connect_tunnel("127.0.0.1:8085", port).await;
+    let mut banner = [0; 14];
+    stream.read_exact(&mut banner).await.unwrap();
+    assert_eq!(&banner, b"SSH-2.0-test\r\n");
+    stream.write_all(b"ping").await.unwrap();
+    drop(stream);
+    sleep(Duration::from_millis(200)).await;
+
+    let tunnel = flow_store
+        .list()
+        .into_iter()
+        .find(|flow| flow.kind == FlowKind::Tunnel)
+        .unwrap();
+    assert_eq!(tunnel.request.method, "CONNECT");
+    assert!(tunnel.finished_at.is_some());
+    assert_eq!(
+        tunnel.tunnel,
+        Some(TunnelStats {
+            bytes_sent: 4,
+            bytes_received: 14
+        })
+    );
+
+    let _ = shutdown_tx.send(());
+}
+
+async fn read_response(stream: &mut TcpStream) -> Vec<u8> {
+    let mut response = vec![];
+    let mut buf = [0; 1024];
+    loop {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return response,
+            Ok(n) => response.extend_from_slice(&buf[..n]),
+        }
+        if response.windows(4).any(|w| w == b"\r\n\r\n") {
+            return response;
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_rule_actions() {
+    let rule = |matcher: RequestMatcher, action| Rule {
+        matcher,
+        action,
+        probability: 1.0,
+    };
+    let path = |path: &str| RequestMatcher {
+        path: Some(path.to_string()),
+        ..Default::default()
+    };
+    let rules = Arc::new(Rules::new());
+    rules.set_rules(vec![
+        rule(path("/blocked"), RuleAction::Block { status: 451 }),
+        rule(path("/drop"), RuleAction::DropConnection),
+        rule(
+            RequestMatcher {
+                host: Some("tls-fail.test".to_string()),
+                ..Default::default()
+            },
+            RuleAction::FailTls,
+        ),
+    ]);
+
+    let (shutdown_tx, _) = broadcast::channel::<()>(1);
+    let flow_store = Arc::new(FlowStore::new());
+    let root_ca = RootCA::read_from_file("./ca.crt", "./ca.key")
+        .await
+        .unwrap();
+    let ca_cert = root_ca.cert.der().clone();
+    let proxy = MitmProxy::builder()
+        .with_addr("127.0.0.1:8088")
+        .with_handler(PassThrough)
+        .with_root_ca(root_ca)
+        .with_rules(rules)
+        .with_flow_store(flow_store.clone())
+        .with_shutdown(shutdown_tx.clone())
+        .build();
+    tokio::spawn(async move { proxy.start().await });
+    sleep(Duration::from_millis(200)).await;
+
+    // nothing listens upstream, the rules answer first
+    let mut client = TcpStream::connect("127.0.0.1:8088").await.unwrap();
+    client
+        .write_all(b"GET http://127.0.0.1:1/blocked HTTP/1.1\r\nHost: 127.0.0.1:1\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(read_response(&mut client)
+        .await
+        .starts_with(b"HTTP/1.1 451"));
+
+    let mut client = TcpStream::connect("127.0.0.1:8088").await.unwrap();
+    client
+        .write_all(b"GET http://127.0.0.1:1/drop HTTP/1.1\r\nHost: 127.0.0.1:1\r\n\r\n")
+        .await
+        .unwrap();
+    assert_eq!(read_response(&mut client).await, b"");
+
+    // no rule applies, the client gets a 502 and the flow only the error
+    let mut client = TcpStream::connect("127.0.0.1:8088").await.unwrap();
+    client
+        .write_all(b"GET http://127.0.0.1:1/down HTTP/1.1\r\nHost: 127.0.0.1:1\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(read_response(&mut client)
+        .await
+        .starts_with(b"HTTP/1.1 502"));
+
+    let flows = flow_store.list();
+    assert_eq!(flows[0].response.as_ref().unwrap().status, 451);
+    assert!(flows[1].response.is_none());
+    assert!(flows[1].error.is_some());
+    assert!(flows[2].response.is_none());
+    assert!(flows[2].response_body.is_none());
+    assert!(flows[2].error.is_some());
+    assert_eq!(flows[0].rule_hits, [RuleAction::Block { status: 451 }]);
+    assert_eq!(flows[1].rule_hits, [RuleAction::DropConnection]);
+    assert!(flows[2].rule_hits.is_empty());
+    assert!(flows[2].timings.sent_at.is_some());
+    assert!(flows[2].timings.response_started_at.is_none());
+
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth();
+    let mut stream = TcpStream::connect("127.0.0.1:8088").await.unwrap();
+    stream
+        .write_all(b"CONNECT tls-fail.test:443 HTTP/1.1\r\nHost: tls-fail.test:443\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(read_response(&mut stream)
+        .await
+        .starts_with(b"HTTP/1.1 200"));
+    let name = rustls::pki_types::ServerName::try_from("tls-fail.test").unwrap();
+    let error = tokio_rustls::TlsConnector::from(Arc::new(config))
+        .connect(name, stream)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("HandshakeFailure"), "{}", error);
+
+    // intercepted TLS keeps what was negotiated with the client
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(ca_cert).unwrap();
+    let config = rustls::ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let mut stream = TcpStream::connect("127.0.0.1:8088").await.unwrap();
+    stream
+        .write_all(b"CONNECT secure.test:443 HTTP/1.1\r\nHost: secure.test:443\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(read_response(&mut stream)
+        .await
+        .starts_with(b"HTTP/1.1 200"));
+    let name = rustls::pki_types::ServerName::try_from("secure.test").unwrap();
+    let mut tls = tokio_rustls::TlsConnector::from(Arc::new(config))
+        .connect(name, stream)
+        .await
+        .unwrap();
+    tls.write_all(b"GET /blocked HTTP/1.1\r\nHost: secure.test\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = vec![0; 1024];
+    let n = tls.read(&mut response).await.unwrap();
+    assert!(response[..n].starts_with(b"HTTP/1.1 451"));
+
+    let connection = flow_store
+        .connections()
+        .into_iter()
+        .find(|c| c.server_name.as_deref() == Some("secure.test"))
+        .unwrap();
+    assert_eq!(connection.tls_version.as_deref(), Some("TLSv1.3"));
+    assert!(connection
+        .cipher
+        .is_some_and(|cipher| cipher.starts_with("TLS13_")));
+
+    let _ = shutdown_tx.send(());
+}
+
+struct RejectUpgrade;
+
+impl HttpHandler for RejectUpgrade {
+    async fn handle_response(&self, _res: Response<Body>) -> anyhow::Result<Response<Body>> {
+        let mut res = Response::new(full_body("no"));
+        *res.status_mut() = hyper::StatusCode::FORBIDDEN;
+        Ok(res)
+    }
+}
+
+#[tokio::test]
+async fn test_rejected_upgrade_not_bridged() {
+    let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = upstream.local_addr().unwrap().port();
+    let (upgraded_tx, upgraded_rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let (mut stream, _) = upstream.accept().await.unwrap();
+        let _ = read_response(&mut stream).await;
+        stream
+            .write_all(
+                b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        // the proxy hangs up instead of bridging
+        let mut buf = [0; 16];
+        let _ = upgraded_tx.send(stream.read(&mut buf).await.ok());
+    });
+
+    let (shutdown_tx, _) = broadcast::channel::<()>(1);
+    let flow_store = Arc::new(FlowStore::new());
+    let proxy = MitmProxy::builder()
+        .with_addr("127.0.0.1:8089")
+        .with_handler(RejectUpgrade)
+        .with_flow_store(flow_store.clone())
+        .with_shutdown(shutdown_tx.clone())
+        .build();
+    tokio::spawn(async move { proxy.start().await });
+    sleep(Duration::from_millis(200)).await;
+
+    let mut client = TcpStream::connect("127.0.0.1:8089").await.unwrap();
+    client
+        .write_all(
+            format!(
+                "GET http://127.0.0.1:{port}/ws HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+            )
+            .as_bytes(),
+        )
+        .await
+        .unwrap();
+    assert!(read_response(&mut client)
+        .await
+        .starts_with(b"HTTP/1.1 403"));
+    assert_eq!(upgraded_rx.await.unwrap(), Some(0));
+
+    let flows = flow_store.list();
+    assert_eq!(flows[0].response.as_ref().unwrap().status, 403);
+    assert!(flows[0].websocket.is_none());
+
+    let _ = shutdown_tx.send(());
+}
+
+#[tokio::test]
+async fn test_server_replay_recorded() {
+    let replay = Arc::new(ServerReplay::new());
+    let mut recorded = Flow::test("GET", "http://127.0.0.1:1/recorded")
+        .with_response(200, &[])
+        .with_response_body("recorded");
+    recorded.imported = true;
+    replay.load(vec![recorded]);
+    replay.set_config(ServerReplayConfig {
+        enabled: true,
+        ..Default::default()
+    });
+
+    let (shutdown_tx, _) = broadcast::channel::<()>(1);
+    let flow_store = Arc::new(FlowStore::new());
+    let proxy = MitmProxy::builder()
+        .with_addr("127.0.0.1:8092")
+        .with_handler(PassThrough)
+        .with_server_replay(replay)
+        .with_flow_store(flow_store.clone())
+        .with_shutdown(shutdown_tx.clone())
+        .build();
+    tokio::spawn(async move { proxy.start().await });
+    sleep(Duration::from_millis(200)).await;
+
+    let mut client = TcpStream::connect("127.0.0.1:8092").await.unwrap();
+    client
+        .write_all(b"GET http://127.0.0.1:1/recorded HTTP/1.1\r\nHost: 127.0.0.1:1\r\n\r\n")
+        .await
+        .unwrap();
+    let response = read_response(&mut client).await;
+    assert!(response.starts_with(b"HTTP/1.1 200"));
+    sleep(Duration::from_millis(100)).await;
+
+    // answered by the replay, recorded all the same
+    let flows = flow_store.list();
+    assert_eq!(flows.len(), 1);
+    assert_eq!(flows[0].request.uri, "http://127.0.0.1:1/recorded");
+    assert_eq!(flows[0].response.as_ref().unwrap().status, 200);
+    assert_eq!(flows[0].response_body.as_ref().unwrap().data, b"recorded");
+
+    let _ = shutdown_tx.send(());
+}
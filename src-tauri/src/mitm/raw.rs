@@ -0,0 +1,254 @@
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+const MAX_HEADERS: usize = 128;
+
+/// Wraps the client side of an HTTP/1 connection and keeps every request
+/// head exactly as it was read, request line and header block included.
+pub(super) struct HeadRecorder<S> {
+    inner: S,
+    parser: Option<Arc<Mutex<HeadParser>>>,
+}
+
+/// The heads read so far, in the order the requests arrived.
+#[derive(Clone)]
+pub(super) struct RawHeads(Arc<Mutex<HeadParser>>);
+
+impl<S> HeadRecorder<S> {
+    /// A disabled recorder passes the stream through untouched.
+    pub(super) fn new(inner: S, enabled: bool) -> Self {
+        Self {
+            inner,
+            parser: enabled.then(|| Arc::new(Mutex::new(HeadParser::default()))),
+        }
+    }
+
+    pub(super) fn heads(&self) -> Option<RawHeads> {
+        self.parser.clone().map(RawHeads)
+    }
+}
+
+impl RawHeads {
+    pub(super) fn pop(&self) -> Option<Vec<u8>> {
+        self.0.lock().unwrap().pop()
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for HeadRecorder<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(())), Some(parser)) = (&result, &self.parser) {
+            parser.lock().unwrap().feed(&buf.filled()[filled..]);
+        }
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for HeadRecorder<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Head(Vec<u8>),
+    Body(u64),
+    ChunkSize(Vec<u8>),
+    ChunkData(u64),
+    ChunkEnd,
+    Trailer(Vec<u8>),
+    /// Upgraded, HTTP/2 or something we could not follow.
+    Stopped,
+}
+
+/// Follows the message framing just enough to tell heads from bodies.
+#[derive(Debug)]
+pub struct HeadParser {
+    state: State,
+    heads: VecDeque<Vec<u8>>,
+}
+
+impl Default for HeadParser {
+    fn default() -> Self {
+        Self {
+            state: State::Head(Vec::new()),
+            heads: VecDeque::new(),
+        }
+    }
+}
+
+impl HeadParser {
+    pub fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            match &mut self.state {
+                State::Head(buf) => {
+                    buf.extend_from_slice(data);
+                    data = &[];
+                    self.parse_head();
+                }
+                State::Body(remaining) => {
+                    let n = (*remaining).min(data.len() as u64);
+                    data = &data[n as usize..];
+                    *remaining -= n;
+                    if *remaining == 0 {
+                        self.state = State::Head(Vec::new());
+                    }
+                }
+                State::ChunkSize(line) | State::Trailer(line) => {
+                    let Some(end) = data.iter().position(|&b| b == b'\n') else {
+                        line.extend_from_slice(data);
+                        return;
+                    };
+                    line.extend_from_slice(&data[..end]);
+                    data = &data[end + 1..];
+                    self.end_line();
+                }
+                State::ChunkData(remaining) => {
+                    let n = (*remaining).min(data.len() as u64);
+                    data = &data[n as usize..];
+                    *remaining -= n;
+                    if *remaining == 0 {
+                        self.state = State::ChunkEnd;
+                    }
+                }
+                State::ChunkEnd => match data.iter().position(|&b| b == b'\n') {
+                    Some(end) => {
+                        data = &data[end + 1..];
+                        self.state = State::ChunkSize(Vec::new());
+                    }
+                    None => return,
+                },
+                State::Stopped => return,
+            }
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.heads.pop_front()
+    }
+
+    fn parse_head(&mut self) {
+        loop {
+            let State::Head(buf) = &mut self.state else {
+                return;
+            };
+
+            let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+            let mut req = httparse::Request::new(&mut headers);
+            let len = match req.parse(buf) {
+                Ok(httparse::Status::Complete(len)) => len,
+                Ok(httparse::Status::Partial) if buf.len() <= MAX_HEAD_SIZE => return,
+                _ => {
+                    self.state = State::Stopped;
+                    return;
+                }
+            };
+
+            let next = framing(&req);
+            let rest = buf.split_off(len);
+            // blank lines ahead of a request line are not part of its head
+            let start = buf
+                .iter()
+                .position(|&b| b != b'\r' && b != b'\n')
+                .unwrap_or(0);
+            self.heads.push_back(buf[start..].to_vec());
+            self.state = next;
+
+            match &self.state {
+                State::Head(_) => self.state = State::Head(rest),
+                _ => {
+                    self.feed(&rest);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn end_line(&mut self) {
+        match &self.state {
+            State::ChunkSize(line) => {
+                let line = String::from_utf8_lossy(line);
+                let size = line.split(';').next().unwrap_or_default().trim();
+                self.state = match u64::from_str_radix(size, 16) {
+                    Ok(0) => State::Trailer(Vec::new()),
+                    Ok(size) => State::ChunkData(size),
+                    Err(_) => State::Stopped,
+                };
+            }
+            State::Trailer(line) if line.is_empty() || line == b"\r" => {
+                self.state = State::Head(Vec::new());
+            }
+            State::Trailer(_) => self.state = State::Trailer(Vec::new()),
+            _ => {}
+        }
+    }
+}
+
+fn framing(req: &httparse::Request) -> State {
+    if req.method == Some("CONNECT") {
+        return State::Stopped;
+    }
+
+    if header_values(req, "upgrade").next().is_some() {
+        return State::Stopped;
+    }
+    if header_values(req, "transfer-encoding").any(|v| v.trim_end().ends_with("chunked")) {
+        return State::ChunkSize(Vec::new());
+    }
+    match header_values(req, "content-length")
+        .next()
+        .map(|v| v.trim().parse())
+    {
+        Some(Ok(0)) | None => State::Head(Vec::new()),
+        Some(Ok(len)) => State::Body(len),
+        Some(Err(_)) => State::Stopped,
+    }
+}
+
+fn header_values<'a>(
+    req: &'a httparse::Request,
+    name: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    req.headers
+        .iter()
+        .filter(move |h| h.name.eq_ignore_ascii_case(name))
+        .map(|h| String::from_utf8_lossy(h.value).to_ascii_lowercase())
+}
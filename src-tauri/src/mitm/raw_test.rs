@@ -0,0 +1,48 @@
+use super::HeadParser;
+
+#[test]
+fn test_pipelined_heads() {
+    let first =
+        "POST /upload HTTP/1.1\r\nHost: example.com\r\nX-Trace-ID: 1\r\nContent-Length: 5\r\n\r\n";
+    let second = "PUT /chunks HTTP/1.1\r\nhost: example.com\r\nTransfer-Encoding: chunked\r\n\r\n";
+    let third = "GET /done HTTP/1.1\r\nHOST: example.com\r\naccept: */*\r\n\r\n";
+    let stream = [
+        first,
+        "hello",
+        second,
+        "4;ext=1\r\nGET \r\n3\r\n/x \r\n0\r\nX-Checksum: abc\r\n\r\n",
+        "\r\n",
+        third,
+    ]
+    .concat();
+
+    let mut parser = HeadParser::default();
+    for byte in stream.as_bytes() {
+        parser.feed(&[*byte]);
+    }
+
+    assert_eq!(parser.pop().unwrap(), first.as_bytes());
+    assert_eq!(parser.pop().unwrap(), second.as_bytes());
+    assert_eq!(parser.pop().unwrap(), third.as_bytes());
+    assert_eq!(parser.pop(), None);
+}
+
+#[test]
+fn test_stops_after_upgrade() {
+    let head = "GET /socket HTTP/1.1\r\nHost: example.com\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n";
+    let mut parser = HeadParser::default();
+    parser.feed(head.as_bytes());
+    parser.feed(b"GET /not-http HTTP/1.1\r\n\r\n");
+
+    assert_eq!(parser.pop().unwrap(), head.as_bytes());
+    assert_eq!(parser.pop(), None);
+}
+
+#[test]
+fn test_ignores_http2_preface() {
+    let mut parser = HeadParser::default();
+    parser.feed(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n");
+    parser.feed(b"GET / HTTP/1.1\r\n\r\n");
+
+    assert_eq!(parser.pop(), None);
+}
@@ -0,0 +1,100 @@
+use std::net::IpAddr;
+
+use anyhow::{anyhow, bail};
+use hyper::{
+    header::{HeaderValue, HOST},
+    Request, Uri,
+};
+
+/// Fronts a single backend: the listener takes origin-form requests and
+/// every one of them is sent to `upstream`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReverseProxy {
+    upstream: Uri,
+    tls_host: Option<String>,
+}
+
+impl ReverseProxy {
+    /// `upstream` is a base URL like `http://localhost:3000`, a path in it
+    /// is prepended to the paths of incoming requests.
+    pub fn new(upstream: &str) -> anyhow::Result<Self> {
+        let upstream = Uri::try_from(upstream)?;
+        match upstream.scheme_str() {
+            Some("http" | "https") => {}
+            _ => bail!(
+                "Reverse proxy upstream must be an http(s) URL: {}",
+                upstream
+            ),
+        }
+        if upstream.authority().is_none() {
+            bail!("Reverse proxy upstream has no host: {}", upstream);
+        }
+
+        Ok(Self {
+            upstream,
+            tls_host: None,
+        })
+    }
+
+    /// Terminates TLS on the listener with a certificate for `host` signed
+    /// by the root CA.
+    pub fn with_tls(mut self, host: impl Into<String>) -> Self {
+        self.tls_host = Some(host.into());
+        self
+    }
+
+    pub fn upstream(&self) -> &Uri {
+        &self.upstream
+    }
+
+    pub fn tls_host(&self) -> Option<&str> {
+        self.tls_host.as_deref()
+    }
+
+    /// Points `req` at the upstream and tells it where the request came
+    /// from, `scheme` is the one the client used to reach the proxy.
+    pub fn rewrite<B>(
+        &self,
+        req: &mut Request<B>,
+        scheme: &str,
+        client_ip: IpAddr,
+    ) -> anyhow::Result<()> {
+        let authority = self
+            .upstream
+            .authority()
+            .ok_or_else(|| anyhow!("Reverse proxy upstream has no host"))?;
+        let prefix = self.upstream.path().trim_end_matches('/');
+        let path = req
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str())
+            .unwrap_or("/");
+
+        let client_host = req.headers().get(HOST).cloned().or_else(|| {
+            let authority = req.uri().authority()?;
+            HeaderValue::from_str(authority.as_str()).ok()
+        });
+
+        *req.uri_mut() = Uri::builder()
+            .scheme(self.upstream.scheme_str().unwrap_or("http"))
+            .authority(authority.clone())
+            .path_and_query(format!("{}{}", prefix, path))
+            .build()?;
+
+        let headers = req.headers_mut();
+        if let Some(client_host) = client_host {
+            headers.insert("x-forwarded-host", client_host);
+        }
+        headers.insert("x-forwarded-proto", HeaderValue::from_str(scheme)?);
+        let forwarded_for = match headers.get("x-forwarded-for") {
+            Some(chain) => format!("{}, {}", chain.to_str()?, client_ip),
+            None => client_ip.to_string(),
+        };
+        headers.insert("x-forwarded-for", HeaderValue::from_str(&forwarded_for)?);
+        if headers.contains_key(HOST) {
+            headers.insert(HOST, HeaderValue::from_str(authority.as_str())?);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,94 @@
+use std::{sync::Arc, time::Duration};
+
+use hyper::Request;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+    time::sleep,
+};
+
+use super::{FlowStore, HttpHandler, MitmProxy, ReverseProxy};
+
+#[test]
+fn test_rewrite() {
+    assert!(ReverseProxy::new("localhost:3000").is_err());
+    assert!(ReverseProxy::new("ftp://localhost").is_err());
+
+    let reverse = ReverseProxy::new("http://localhost:3000/api/").unwrap();
+    let mut req = Request::get("/users?page=2")
+        .header("host", "app.test:8080")
+        .header("x-forwarded-for", "10.0.0.1")
+        .body(())
+        .unwrap();
+    reverse
+        .rewrite(&mut req, "https", "127.0.0.1".parse().unwrap())
+        .unwrap();
+
+    assert_eq!(req.uri(), "http://localhost:3000/api/users?page=2");
+    assert_eq!(req.headers()["host"], "localhost:3000");
+    assert_eq!(req.headers()["x-forwarded-host"], "app.test:8080");
+    assert_eq!(req.headers()["x-forwarded-proto"], "https");
+    assert_eq!(req.headers()["x-forwarded-for"], "10.0.0.1, 127.0.0.1");
+}
+
+struct PassThrough;
+
+impl HttpHandler for PassThrough {}
+
+#[tokio::test]
+async fn test_reverse_listener() {
+    let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    let upstream_task = tokio::spawn(async move {
+        let (mut stream, _) = upstream.accept().await.unwrap();
+        let mut received = vec![];
+        let mut buf = [0; 1024];
+        while !received.ends_with(b"\r\n\r\n") {
+            let n = stream.read(&mut buf).await.unwrap();
+            received.extend_from_slice(&buf[..n]);
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+            .await
+            .unwrap();
+        String::from_utf8(received).unwrap()
+    });
+
+    let (shutdown_tx, _) = broadcast::channel::<()>(1);
+    let flow_store = Arc::new(FlowStore::new());
+    let reverse = ReverseProxy::new(&format!("http://{}", upstream_addr)).unwrap();
+    let proxy = MitmProxy::builder()
+        .with_addr("127.0.0.1:8086")
+        .with_reverse_proxy(reverse)
+        .with_handler(PassThrough)
+        .with_flow_store(flow_store.clone())
+        .with_shutdown(shutdown_tx.clone())
+        .build();
+    tokio::spawn(async move { proxy.start().await });
+    sleep(Duration::from_millis(200)).await;
+
+    let mut client = TcpStream::connect("127.0.0.1:8086").await.unwrap();
+    client
+        .write_all(b"GET /hello HTTP/1.1\r\nHost: localhost:8086\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = vec![0; 1024];
+    let n = client.read(&mut response).await.unwrap();
+    assert!(response[..n].ends_with(b"\r\n\r\nok"));
+
+    let received = upstream_task.await.unwrap().to_lowercase();
+    assert!(received.starts_with("get /hello http/1.1\r\n"));
+    assert!(received.contains(&format!("host: {}\r\n", upstream_addr)));
+    assert!(received.contains("x-forwarded-host: localhost:8086\r\n"));
+
+    let flows = flow_store.list();
+    assert_eq!(flows.len(), 1);
+    assert_eq!(
+        flows[0].request.uri,
+        format!("http://{}/hello", upstream_addr)
+    );
+    assert_eq!(flow_store.connections().len(), 1);
+
+    let _ = shutdown_tx.send(());
+}
@@ -0,0 +1,209 @@
+use std::sync::RwLock;
+
+use hyper::{
+    header::{HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE},
+    Method, Response, StatusCode, Uri,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error_response, full_body, Body, DelaySpec, RequestMatcher, WsDirection, WsMessage,
+    WsMessageKind,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum RuleAction {
+    Block {
+        status: u16,
+    },
+    DropConnection,
+    ResetStream,
+    MalformedResponse,
+    FailTls,
+    Delay {
+        #[serde(default)]
+        request: Option<DelaySpec>,
+        #[serde(default)]
+        response: Option<DelaySpec>,
+    },
+    /// Rewrites the text WebSocket messages containing `find` on
+    /// connections upgraded from matching requests, or drops them without
+    /// a replacement. Rolled for every message.
+    WsMessage {
+        #[serde(default)]
+        direction: Option<WsDirection>,
+        find: String,
+        #[serde(default)]
+        replace: Option<String>,
+    },
+}
+
+impl RuleAction {
+    /// Actions that end the exchange, as opposed to only delaying it or
+    /// acting on the handshake.
+    fn is_terminal(&self) -> bool {
+        !matches!(
+            self,
+            RuleAction::FailTls | RuleAction::Delay { .. } | RuleAction::WsMessage { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rule {
+    pub matcher: RequestMatcher,
+    pub action: RuleAction,
+    /// Chance in `0.0..=1.0` that a matching request is affected.
+    #[serde(default = "default_probability")]
+    pub probability: f64,
+}
+
+fn default_probability() -> f64 {
+    1.0
+}
+
+impl Rule {
+    fn roll(&self) -> bool {
+        self.probability >= 1.0 || rand::random::<f64>() < self.probability
+    }
+}
+
+/// Rules are evaluated in order, the first matching rule that rolls wins.
+#[derive(Default)]
+pub struct Rules {
+    rules: RwLock<Vec<Rule>>,
+}
+
+impl Rules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn rules(&self) -> Vec<Rule> {
+        self.rules.read().unwrap().clone()
+    }
+
+    pub fn set_rules(&self, rules: Vec<Rule>) {
+        *self.rules.write().unwrap() = rules;
+    }
+
+    /// Request level action for an intercepted request.
+    pub(super) fn find(&self, method: &Method, uri: &Uri) -> Option<RuleAction> {
+        self.rules
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|rule| rule.action.is_terminal())
+            .find(|rule| rule.matcher.matches(method, uri) && rule.roll())
+            .map(|rule| rule.action.clone())
+    }
+
+    /// Request and response delays for an intercepted request.
+    pub(super) fn find_delay(
+        &self,
+        method: &Method,
+        uri: &Uri,
+    ) -> Option<(Option<DelaySpec>, Option<DelaySpec>)> {
+        self.rules
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|rule| matches!(rule.action, RuleAction::Delay { .. }))
+            .find(|rule| rule.matcher.matches(method, uri) && rule.roll())
+            .and_then(|rule| match &rule.action {
+                RuleAction::Delay { request, response } => {
+                    Some((request.clone(), response.clone()))
+                }
+                _ => None,
+            })
+    }
+
+    /// WebSocket rules for a connection upgraded from a matching request.
+    pub(super) fn find_ws(&self, method: &Method, uri: &Uri) -> Vec<Rule> {
+        self.rules
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|rule| matches!(rule.action, RuleAction::WsMessage { .. }))
+            .filter(|rule| rule.matcher.matches(method, uri))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether the client handshake for `host` should be failed. Only the
+    /// host part of the matcher applies since nothing else is known yet.
+    pub(super) fn fails_tls(&self, host: &str) -> bool {
+        self.rules
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|rule| rule.action == RuleAction::FailTls)
+            .any(|rule| rule.matcher.matches_host(host) && rule.roll())
+    }
+}
+
+/// The action of the first of `rules` that matches `message` and rolls.
+pub(super) fn find_ws_action<'a>(rules: &'a [Rule], message: &WsMessage) -> Option<&'a RuleAction> {
+    if message.kind != WsMessageKind::Text {
+        return None;
+    }
+    let text = std::str::from_utf8(&message.payload).ok()?;
+
+    rules
+        .iter()
+        .find(|rule| match &rule.action {
+            RuleAction::WsMessage {
+                direction, find, ..
+            } => {
+                direction.is_none_or(|d| d == message.direction)
+                    && text.contains(find.as_str())
+                    && rule.roll()
+            }
+            _ => false,
+        })
+        .map(|rule| &rule.action)
+}
+
+/// Applies what [`find_ws_action`] found for `message`, `None` when it
+/// drops the message.
+pub(super) fn apply_ws_action(
+    action: Option<&RuleAction>,
+    mut message: WsMessage,
+) -> Option<WsMessage> {
+    match action {
+        Some(RuleAction::WsMessage {
+            find,
+            replace: Some(replace),
+            ..
+        }) => {
+            let text = String::from_utf8_lossy(&message.payload);
+            message.payload = text.replace(find.as_str(), replace).into_bytes();
+            Some(message)
+        }
+        Some(_) => None,
+        None => Some(message),
+    }
+}
+
+pub(super) fn blocked_response(status: u16) -> Response<Body> {
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::FORBIDDEN);
+    error_response(status, "Blocked by devya")
+}
+
+/// Claims a gzip JSON body longer than what is sent, and what is sent is
+/// not gzip either.
+pub(super) fn malformed_response() -> Response<Body> {
+    let body = b"\x1f\x8b\x08\x00{\"truncated\": tr";
+
+    let mut res = Response::new(full_body(&body[..]));
+    let headers = res.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(body.len() * 4));
+    res
+}
+
+/// A fatal `handshake_failure` alert record.
+pub(super) const TLS_HANDSHAKE_FAILURE_ALERT: [u8; 7] = [0x15, 0x03, 0x03, 0x00, 0x02, 0x02, 0x28];
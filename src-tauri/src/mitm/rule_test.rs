@@ -0,0 +1,193 @@
+use http_body_util::BodyExt;
+use hyper::{
+    header::{CONTENT_ENCODING, CONTENT_LENGTH},
+    Method, Uri,
+};
+
+use super::{
+    apply_ws_action, blocked_response, find_ws_action, malformed_response, now_millis, DelayMode,
+    DelaySpec, RequestMatcher, Rule, RuleAction, Rules, WsDirection, WsMessage, WsMessageKind,
+    TLS_HANDSHAKE_FAILURE_ALERT,
+};
+
+fn rule(path: &str, action: RuleAction) -> Rule {
+    Rule {
+        matcher: RequestMatcher {
+            path: Some(path.to_string()),
+            ..Default::default()
+        },
+        action,
+        probability: 1.0,
+    }
+}
+
+#[test]
+fn test_find_rule() {
+    let delay = DelaySpec {
+        min_ms: 100,
+        max_ms: None,
+        mode: DelayMode::FirstByte,
+    };
+    let rules = Rules::new();
+    rules.set_rules(vec![
+        rule(
+            "/api/*",
+            RuleAction::Delay {
+                request: None,
+                response: Some(delay.clone()),
+            },
+        ),
+        Rule {
+            matcher: RequestMatcher {
+                host: Some("*.example.test".to_string()),
+                ..Default::default()
+            },
+            action: RuleAction::FailTls,
+            probability: 1.0,
+        },
+        rule("/api/users", RuleAction::Block { status: 451 }),
+        rule("/api/*", RuleAction::DropConnection),
+    ]);
+
+    let uri = |uri: &str| uri.parse::<Uri>().unwrap();
+    // delays and handshake failures never end an exchange by themselves,
+    // and the first matching rule wins
+    assert_eq!(
+        rules.find(&Method::GET, &uri("https://a.test/api/users")),
+        Some(RuleAction::Block { status: 451 })
+    );
+    assert_eq!(
+        rules.find(&Method::GET, &uri("https://a.test/api/items")),
+        Some(RuleAction::DropConnection)
+    );
+    assert_eq!(rules.find(&Method::GET, &uri("https://a.test/")), None);
+
+    assert_eq!(
+        rules.find_delay(&Method::GET, &uri("https://a.test/api/items")),
+        Some((None, Some(delay)))
+    );
+    assert_eq!(
+        rules.find_delay(&Method::GET, &uri("https://a.test/")),
+        None
+    );
+
+    assert!(rules.fails_tls("www.example.test"));
+    assert!(!rules.fails_tls("example.org"));
+}
+
+#[test]
+fn test_probability() {
+    let rules = Rules::new();
+    let mut never = rule("/*", RuleAction::Block { status: 500 });
+    never.probability = 0.0;
+    rules.set_rules(vec![never, rule("/*", RuleAction::ResetStream)]);
+
+    let uri: Uri = "http://a.test/".parse().unwrap();
+    for _ in 0..100 {
+        assert_eq!(
+            rules.find(&Method::GET, &uri),
+            Some(RuleAction::ResetStream)
+        );
+    }
+
+    // a missing probability means always
+    let parsed: Rule =
+        serde_json::from_str(r#"{"matcher":{},"action":{"type":"block","status":403}}"#).unwrap();
+    assert_eq!(parsed.probability, 1.0);
+}
+
+#[tokio::test]
+async fn test_canned_responses() {
+    assert_eq!(blocked_response(451).status(), 451);
+    // not a valid status code
+    assert_eq!(blocked_response(1000).status(), 403);
+
+    let res = malformed_response();
+    assert_eq!(res.headers()[CONTENT_ENCODING], "gzip");
+    let length: usize = res.headers()[CONTENT_LENGTH]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    assert!(length > body.len());
+    assert!(serde_json::from_slice::<serde_json::Value>(&body).is_err());
+
+    // alert record, TLS 1.2, fatal handshake_failure
+    assert_eq!(TLS_HANDSHAKE_FAILURE_ALERT[0], 0x15);
+    assert_eq!(TLS_HANDSHAKE_FAILURE_ALERT[5..], [2, 40]);
+}
+
+#[test]
+fn test_ws_rules() {
+    let rules = Rules::new();
+    rules.set_rules(vec![
+        rule(
+            "/ws",
+            RuleAction::WsMessage {
+                direction: Some(WsDirection::ServerToClient),
+                find: "secret".to_string(),
+                replace: None,
+            },
+        ),
+        rule(
+            "/ws",
+            RuleAction::WsMessage {
+                direction: None,
+                find: "ping".to_string(),
+                replace: Some("pong".to_string()),
+            },
+        ),
+        rule("/ws", RuleAction::Block { status: 403 }),
+    ]);
+
+    let uri: Uri = "http://example.com/ws".parse().unwrap();
+    let ws_rules = rules.find_ws(&Method::GET, &uri);
+    assert_eq!(ws_rules.len(), 2);
+    assert!(rules
+        .find_ws(&Method::GET, &"/other".parse().unwrap())
+        .is_empty());
+
+    let message = |direction, kind, payload: &[u8]| WsMessage {
+        direction,
+        kind,
+        payload: payload.to_vec(),
+        compressed: false,
+        injected: false,
+        timestamp: now_millis(),
+    };
+    let apply = |direction, kind, payload: &[u8]| {
+        let message = message(direction, kind, payload);
+        apply_ws_action(find_ws_action(&ws_rules, &message), message).map(|m| m.payload)
+    };
+
+    assert_eq!(
+        apply(
+            WsDirection::ServerToClient,
+            WsMessageKind::Text,
+            b"a secret"
+        ),
+        None
+    );
+    // the first rule only applies to messages from the server
+    assert_eq!(
+        apply(
+            WsDirection::ClientToServer,
+            WsMessageKind::Text,
+            b"a secret"
+        ),
+        Some(b"a secret".to_vec())
+    );
+    assert_eq!(
+        apply(
+            WsDirection::ClientToServer,
+            WsMessageKind::Text,
+            b"ping ping"
+        ),
+        Some(b"pong pong".to_vec())
+    );
+    assert_eq!(
+        apply(WsDirection::ServerToClient, WsMessageKind::Binary, b"ping"),
+        Some(b"ping".to_vec())
+    );
+}
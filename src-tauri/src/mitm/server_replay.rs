@@ -0,0 +1,300 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use hyper::{
+    body::Bytes,
+    header::{HeaderName, HeaderValue, CONTENT_LENGTH},
+    HeaderMap, Method, Request, Response, StatusCode, Uri,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use super::{
+    body_with_trailers, buffer, error_response, Body, Buffered, Flow, FlowId, RequestOrResponse,
+};
+
+/// Headers describing the connection the recorded response came over,
+/// rather than the response itself.
+const HOP_BY_HOP: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "transfer-encoding",
+    "upgrade",
+    "proxy-connection",
+];
+
+/// How closely a request has to resemble a recorded one to be answered
+/// with its response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReplayMatching {
+    /// Method, URL and body, query parameters in any order.
+    Exact,
+    /// Method and URL, query parameters in any order.
+    #[default]
+    Url,
+    /// Method, host and path, the query is ignored.
+    Path,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UnmatchedAction {
+    /// Send it on to the real server.
+    #[default]
+    Forward,
+    /// Answer 404 so nothing leaves the proxy.
+    NotFound,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ServerReplayConfig {
+    pub enabled: bool,
+    pub matching: ReplayMatching,
+    /// Request headers that have to be equal as well, e.g. `accept`.
+    pub match_headers: Vec<String>,
+    pub unmatched: UnmatchedAction,
+}
+
+/// Answers requests with recorded responses. When several flows match,
+/// they take turns in the order they were recorded.
+pub struct ServerReplay {
+    config: RwLock<ServerReplayConfig>,
+    flows: RwLock<Vec<Flow>>,
+    hits: RwLock<HashMap<FlowId, usize>>,
+}
+
+impl Default for ServerReplay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerReplay {
+    pub fn new() -> Self {
+        Self {
+            config: RwLock::new(ServerReplayConfig::default()),
+            flows: RwLock::new(vec![]),
+            hits: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> ServerReplayConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    pub fn set_config(&self, config: ServerReplayConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Replaces the recorded flows, returning how many can be replayed.
+    /// Those without a response are skipped, and so are upgrades since there
+    /// is no one to talk to afterwards, and bodies cut off by the capture
+    /// limit or a failed stream.
+    pub fn load(&self, flows: Vec<Flow>) -> usize {
+        let total = flows.len();
+        let flows: Vec<Flow> = flows
+            .into_iter()
+            .filter(|flow| {
+                flow.response
+                    .as_ref()
+                    .is_some_and(|res| res.status != StatusCode::SWITCHING_PROTOCOLS)
+                    && flow
+                        .response_body
+                        .as_ref()
+                        .is_none_or(|body| body.complete && !body.truncated)
+            })
+            .collect();
+        let count = flows.len();
+        if count < total {
+            debug!("{} of {} flows can't be replayed", total - count, total);
+        }
+        *self.flows.write().unwrap() = flows;
+        self.hits.write().unwrap().clear();
+        count
+    }
+
+    pub fn flows(&self) -> Vec<FlowId> {
+        self.flows.read().unwrap().iter().map(|f| f.id).collect()
+    }
+
+    pub fn clear(&self) {
+        self.load(vec![]);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.config.read().unwrap().enabled && !self.flows.read().unwrap().is_empty()
+    }
+
+    /// A recorded response for `req`, or `req` itself when nothing matches
+    /// and unmatched requests are forwarded. Exact matching reads up to
+    /// `limit` bytes of the body, larger ones can't match a recording.
+    pub async fn respond(&self, req: Request<Body>, limit: usize) -> RequestOrResponse {
+        let config = self.config();
+        let (parts, body) = req.into_parts();
+
+        // only exact matching needs the body, others leave it streaming
+        let (body, data) = if config.matching == ReplayMatching::Exact {
+            match buffer(body, limit).await {
+                Ok(Buffered::Whole(data, trailers)) => {
+                    (body_with_trailers(data.clone(), trailers), Some(data))
+                }
+                Ok(Buffered::Streaming(body)) => {
+                    debug!(
+                        "Request body of {} {} too large to match",
+                        parts.method, parts.uri
+                    );
+                    return self.unmatched(&config, Request::from_parts(parts, body));
+                }
+                Err(e) => {
+                    error!("Failed to read request body for replay: {}", e);
+                    return RequestOrResponse::Response(error_response(
+                        StatusCode::BAD_REQUEST,
+                        "Failed to read request body",
+                    ));
+                }
+            }
+        } else {
+            (body, None)
+        };
+
+        let found = self.find(&config, &parts.method, &parts.uri, &parts.headers, data);
+        match found {
+            Some(flow) => {
+                debug!(
+                    "Replaying flow {} for {} {}",
+                    flow.id, parts.method, parts.uri
+                );
+                match recorded_response(&flow) {
+                    Ok(res) => RequestOrResponse::Response(res),
+                    Err(e) => {
+                        error!("Failed to replay flow {}: {}", flow.id, e);
+                        RequestOrResponse::Response(error_response(
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            "Failed to replay recorded response",
+                        ))
+                    }
+                }
+            }
+            None => self.unmatched(&config, Request::from_parts(parts, body)),
+        }
+    }
+
+    fn unmatched(&self, config: &ServerReplayConfig, req: Request<Body>) -> RequestOrResponse {
+        match config.unmatched {
+            UnmatchedAction::NotFound => RequestOrResponse::Response(error_response(
+                StatusCode::NOT_FOUND,
+                "No recorded response matches",
+            )),
+            UnmatchedAction::Forward => RequestOrResponse::Request(req),
+        }
+    }
+
+    fn find(
+        &self,
+        config: &ServerReplayConfig,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+        body: Option<Bytes>,
+    ) -> Option<Flow> {
+        let flows = self.flows.read().unwrap();
+        let mut hits = self.hits.write().unwrap();
+
+        let candidates = flows.iter().filter(|flow| {
+            let request = &flow.request;
+            request.method.eq_ignore_ascii_case(method.as_str())
+                && request
+                    .uri
+                    .parse::<Uri>()
+                    .is_ok_and(|recorded| uri_matches(config.matching, &recorded, uri))
+                && config.match_headers.iter().all(|name| {
+                    let recorded = request
+                        .headers
+                        .iter()
+                        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                        .map(|(_, v)| v.as_bytes());
+                    recorded == headers.get(name.as_str()).map(|v| v.as_bytes())
+                })
+                && body.as_ref().is_none_or(|body| {
+                    let recorded = flow.request_body.as_ref().map_or(&[][..], |b| &b.data);
+                    recorded == &body[..]
+                })
+        });
+
+        // the least served one, earlier ones first
+        let flow = candidates.min_by_key(|flow| hits.get(&flow.id).copied().unwrap_or(0))?;
+        *hits.entry(flow.id).or_default() += 1;
+        Some(flow.clone())
+    }
+}
+
+fn uri_matches(matching: ReplayMatching, recorded: &Uri, uri: &Uri) -> bool {
+    let same_origin = recorded.scheme() == uri.scheme()
+        && recorded
+            .host()
+            .zip(uri.host())
+            .is_some_and(|(a, b)| a.eq_ignore_ascii_case(b))
+        && recorded.port_u16() == uri.port_u16();
+    if !same_origin || recorded.path() != uri.path() {
+        return false;
+    }
+
+    match matching {
+        ReplayMatching::Path => true,
+        ReplayMatching::Url | ReplayMatching::Exact => {
+            query_pairs(recorded.query()) == query_pairs(uri.query())
+        }
+    }
+}
+
+fn query_pairs(query: Option<&str>) -> Vec<&str> {
+    let mut pairs: Vec<&str> = query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+fn recorded_response(flow: &Flow) -> anyhow::Result<Response<Body>> {
+    let response = flow
+        .response
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Flow has no response"))?;
+    let body = flow.response_body.clone().unwrap_or_default();
+
+    let mut builder = Response::builder().status(response.status);
+    for (name, value) in &response.headers {
+        if HOP_BY_HOP.iter().any(|h| name.eq_ignore_ascii_case(h))
+            || name.eq_ignore_ascii_case(CONTENT_LENGTH.as_str())
+            || name.starts_with(':')
+        {
+            continue;
+        }
+        builder = builder.header(
+            HeaderName::from_bytes(name.as_bytes())?,
+            HeaderValue::from_str(value)?,
+        );
+    }
+    let trailers = match &body.trailers {
+        Some(pairs) => {
+            let mut trailers = HeaderMap::new();
+            for (name, value) in pairs {
+                trailers.append(
+                    HeaderName::from_bytes(name.as_bytes())?,
+                    HeaderValue::from_str(value)?,
+                );
+            }
+            Some(trailers)
+        }
+        // HTTP/1 can only send trailers with chunked framing
+        None => {
+            builder = builder.header(CONTENT_LENGTH, body.data.len());
+            None
+        }
+    };
+
+    Ok(builder.body(body_with_trailers(body.data.into(), trailers))?)
+}
@@ -0,0 +1,175 @@
+use http_body_util::BodyExt;
+use hyper::{Request, Response, StatusCode};
+
+use super::{
+    full_body, Body, BodyCapture, Flow, ReplayMatching, RequestOrResponse, ServerReplay,
+    ServerReplayConfig, UnmatchedAction,
+};
+
+fn recorded(id: u64, method: &str, uri: &str, body: &[u8], status: u16, reply: &str) -> Flow {
+    let mut flow = Flow::test(method, uri)
+        .with_request_headers(&[("accept", "application/json")])
+        .with_request_body(body)
+        .with_response(
+            status,
+            &[
+                ("content-length", "999"),
+                ("transfer-encoding", "chunked"),
+                ("x-recorded", &id.to_string()),
+            ],
+        )
+        .with_response_body(reply);
+    flow.id = id;
+    flow.imported = true;
+    flow
+}
+
+fn request(method: &str, uri: &str, body: &'static str) -> Request<Body> {
+    Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("accept", "application/json")
+        .body(full_body(body))
+        .unwrap()
+}
+
+async fn replied(result: RequestOrResponse) -> Option<(Response<()>, String)> {
+    match result {
+        RequestOrResponse::Response(res) => {
+            let (parts, body) = res.into_parts();
+            let body = body.collect().await.unwrap().to_bytes();
+            Some((
+                Response::from_parts(parts, ()),
+                String::from_utf8(body.to_vec()).unwrap(),
+            ))
+        }
+        RequestOrResponse::Request(_) => None,
+    }
+}
+
+#[tokio::test]
+async fn test_matching() {
+    let replay = ServerReplay::new();
+    assert_eq!(
+        replay.load(vec![
+            recorded(
+                1,
+                "GET",
+                "https://api.test/items?a=1&b=2",
+                b"",
+                200,
+                "first"
+            ),
+            recorded(
+                2,
+                "GET",
+                "https://api.test/items?a=1&b=2",
+                b"",
+                200,
+                "second"
+            ),
+            recorded(
+                3,
+                "POST",
+                "https://api.test/items",
+                b"{\"n\":1}",
+                201,
+                "created"
+            ),
+            recorded(4, "GET", "https://api.test/socket", b"", 101, ""),
+            // cut off by the capture limit and by a failed stream
+            Flow {
+                response_body: Some(BodyCapture {
+                    truncated: true,
+                    complete: true,
+                    ..Default::default()
+                }),
+                ..recorded(5, "GET", "https://api.test/large", b"", 200, "")
+            },
+            Flow {
+                response_body: Some(BodyCapture::default()),
+                ..recorded(6, "GET", "https://api.test/broken", b"", 200, "")
+            },
+        ]),
+        3
+    );
+    assert_eq!(replay.flows(), vec![1, 2, 3]);
+    assert!(!replay.is_active());
+    replay.set_config(ServerReplayConfig {
+        enabled: true,
+        ..Default::default()
+    });
+    assert!(replay.is_active());
+
+    // query order doesn't matter and repeated matches take turns
+    for expected in ["first", "second", "first"] {
+        let result = replay
+            .respond(request("GET", "https://api.test/items?b=2&a=1", ""), 1024)
+            .await;
+        let (res, body) = replied(result).await.unwrap();
+        assert_eq!(body, expected);
+        assert_eq!(res.headers()["content-length"], expected.len().to_string());
+        assert!(res.headers().get("transfer-encoding").is_none());
+    }
+
+    let result = replay
+        .respond(request("GET", "https://api.test/items?a=2", ""), 1024)
+        .await;
+    assert!(replied(result).await.is_none());
+
+    replay.set_config(ServerReplayConfig {
+        enabled: true,
+        matching: ReplayMatching::Path,
+        ..Default::default()
+    });
+    let result = replay
+        .respond(request("GET", "https://api.test/items?a=2", ""), 1024)
+        .await;
+    assert!(replied(result).await.is_some());
+
+    replay.set_config(ServerReplayConfig {
+        enabled: true,
+        matching: ReplayMatching::Exact,
+        match_headers: vec!["Accept".to_string()],
+        unmatched: UnmatchedAction::NotFound,
+    });
+    let result = replay
+        .respond(request("POST", "https://api.test/items", "{\"n\":1}"), 1024)
+        .await;
+    let (res, body) = replied(result).await.unwrap();
+    assert_eq!(
+        (res.status(), body.as_str()),
+        (StatusCode::CREATED, "created")
+    );
+
+    let result = replay
+        .respond(request("POST", "https://api.test/items", "{\"n\":2}"), 1024)
+        .await;
+    let (res, _) = replied(result).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // unmatched requests keep their body when forwarded
+    replay.set_config(ServerReplayConfig {
+        enabled: true,
+        matching: ReplayMatching::Exact,
+        ..Default::default()
+    });
+    let result = replay
+        .respond(request("POST", "https://api.test/items", "{\"n\":2}"), 1024)
+        .await;
+    let RequestOrResponse::Request(req) = result else {
+        panic!("expected the request to be forwarded");
+    };
+    let body = req.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(&body[..], b"{\"n\":2}");
+
+    // bodies over the limit aren't held to be compared, and go on whole
+    let result = replay
+        .respond(request("POST", "https://api.test/items", "{\"n\":1}"), 4)
+        .await;
+    let RequestOrResponse::Request(req) = result else {
+        panic!("expected the request to be forwarded");
+    };
+    let body = req.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(&body[..], b"{\"n\":1}");
+}
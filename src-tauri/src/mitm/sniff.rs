@@ -0,0 +1,221 @@
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf},
+    time::timeout,
+};
+
+/// How long to wait for the client to speak first before treating the
+/// stream as opaque.
+pub(super) const SNIFF_TIMEOUT: Duration = Duration::from_millis(500);
+
+const MAX_SNIFF_SIZE: usize = 16 * 1024 + 5;
+
+const HTTP_METHODS: [&[u8]; 10] = [
+    b"GET ",
+    b"POST ",
+    b"PUT ",
+    b"HEAD ",
+    b"DELETE ",
+    b"OPTIONS ",
+    b"PATCH ",
+    b"TRACE ",
+    b"CONNECT ",
+    b"PRI * HTTP/2.0",
+];
+
+/// What a client opened a raw stream with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sniffed {
+    Tls {
+        server_name: Option<String>,
+    },
+    Http,
+    /// Anything else, or a client waiting for the server to speak first.
+    Unknown,
+}
+
+/// Looks at the first bytes of `stream` without consuming them.
+pub(super) async fn sniff<S: AsyncRead + Unpin>(
+    mut stream: S,
+    wait: Duration,
+) -> io::Result<(Sniffed, Rewind<S>)> {
+    let mut buf = Vec::new();
+    let sniffed = loop {
+        if let Some(sniffed) = classify(&buf) {
+            break sniffed;
+        }
+
+        let mut chunk = [0; 4096];
+        match timeout(wait, stream.read(&mut chunk)).await {
+            Ok(Ok(0)) | Err(_) => break Sniffed::Unknown,
+            Ok(Ok(n)) => buf.extend_from_slice(&chunk[..n]),
+            Ok(Err(e)) => return Err(e),
+        }
+    };
+
+    Ok((sniffed, Rewind::new(buf, stream)))
+}
+
+/// `None` while more bytes are needed to tell.
+pub fn classify(buf: &[u8]) -> Option<Sniffed> {
+    if buf.is_empty() {
+        return None;
+    }
+
+    if buf[0] == 0x16 {
+        if buf.len() < 5 {
+            return None;
+        }
+        if buf[1] != 0x03 {
+            return Some(Sniffed::Unknown);
+        }
+        let len = u16::from_be_bytes([buf[3], buf[4]]) as usize;
+        if buf.len() < 5 + len && buf.len() < MAX_SNIFF_SIZE {
+            return None;
+        }
+        let record = &buf[5..buf.len().min(5 + len)];
+        return Some(Sniffed::Tls {
+            server_name: parse_sni(record),
+        });
+    }
+
+    if HTTP_METHODS.iter().any(|m| buf.starts_with(m)) {
+        return Some(Sniffed::Http);
+    }
+    if HTTP_METHODS.iter().any(|m| m.starts_with(buf)) {
+        return None;
+    }
+    Some(Sniffed::Unknown)
+}
+
+/// Reads the server name indication out of a ClientHello handshake message.
+pub fn parse_sni(handshake: &[u8]) -> Option<String> {
+    let mut reader = Reader(handshake);
+    if reader.u8()? != 0x01 {
+        return None;
+    }
+    reader.skip(3)?; // length
+    reader.skip(2 + 32)?; // version, random
+    let session_id = reader.u8()? as usize;
+    reader.skip(session_id)?;
+    let cipher_suites = reader.u16()? as usize;
+    reader.skip(cipher_suites)?;
+    let compression = reader.u8()? as usize;
+    reader.skip(compression)?;
+
+    let extensions = reader.u16()? as usize;
+    let mut extensions = Reader(reader.take(extensions)?);
+    while let (Some(kind), Some(len)) = (extensions.u16(), extensions.u16()) {
+        let data = extensions.take(len as usize)?;
+        if kind != 0x0000 {
+            continue;
+        }
+
+        let mut names = Reader(data);
+        let list = names.u16()? as usize;
+        let mut names = Reader(names.take(list)?);
+        while let Some(name_type) = names.u8() {
+            let len = names.u16()? as usize;
+            let name = names.take(len)?;
+            if name_type == 0 {
+                return String::from_utf8(name.to_vec()).ok();
+            }
+        }
+    }
+
+    None
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        self.take(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+/// Replays the sniffed bytes before reading on from the stream.
+pub struct Rewind<S> {
+    prefix: Vec<u8>,
+    pos: usize,
+    inner: S,
+}
+
+impl<S> Rewind<S> {
+    pub fn new(prefix: Vec<u8>, inner: S) -> Self {
+        Self {
+            prefix,
+            pos: 0,
+            inner,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Rewind<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.pos < self.prefix.len() {
+            let n = (self.prefix.len() - self.pos).min(buf.remaining());
+            buf.put_slice(&self.prefix[self.pos..self.pos + n]);
+            self.pos += n;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Rewind<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
@@ -0,0 +1,239 @@
+use std::{
+    io,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::TargetAddr;
+
+const SOCKS4: u8 = 0x04;
+const SOCKS5: u8 = 0x05;
+const CMD_CONNECT: u8 = 0x01;
+
+const NO_AUTH: u8 = 0x00;
+const USERNAME_PASSWORD: u8 = 0x02;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+
+const SOCKS5_SUCCEEDED: u8 = 0x00;
+const SOCKS5_GENERAL_FAILURE: u8 = 0x01;
+const SOCKS5_NETWORK_UNREACHABLE: u8 = 0x03;
+const SOCKS5_HOST_UNREACHABLE: u8 = 0x04;
+const SOCKS5_CONNECTION_REFUSED: u8 = 0x05;
+const SOCKS5_TTL_EXPIRED: u8 = 0x06;
+const SOCKS5_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const SOCKS5_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+const SOCKS4_GRANTED: u8 = 0x5a;
+const SOCKS4_REJECTED: u8 = 0x5b;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SocksCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// A CONNECT command read by [`socks_handshake`], waiting for its reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocksRequest {
+    pub target: TargetAddr,
+    version: u8,
+    /// Port and address as requested, SOCKS4 replies echo them.
+    port: u16,
+    ip: Ipv4Addr,
+}
+
+impl SocksRequest {
+    /// Reports success, or why the target couldn't be dialed.
+    pub async fn reply<S>(&self, stream: &mut S, error: Option<&io::Error>) -> anyhow::Result<()>
+    where
+        S: AsyncWrite + Unpin,
+    {
+        if self.version == SOCKS5 {
+            return socks5_reply(stream, error.map_or(SOCKS5_SUCCEEDED, socks5_status)).await;
+        }
+
+        let status = match error {
+            Some(_) => SOCKS4_REJECTED,
+            None => SOCKS4_GRANTED,
+        };
+        socks4_reply(stream, status, self.port, self.ip).await
+    }
+}
+
+/// Runs the SOCKS5 or SOCKS4a handshake up to the CONNECT command. The
+/// client is only answered through [`SocksRequest::reply`], once the target
+/// was dialed.
+pub async fn socks_handshake<S>(
+    stream: &mut S,
+    credentials: Option<&SocksCredentials>,
+) -> anyhow::Result<SocksRequest>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match stream.read_u8().await? {
+        SOCKS5 => socks5_handshake(stream, credentials).await,
+        SOCKS4 => socks4_handshake(stream, credentials).await,
+        version => bail!("Unsupported SOCKS version {}", version),
+    }
+}
+
+async fn socks5_handshake<S>(
+    stream: &mut S,
+    credentials: Option<&SocksCredentials>,
+) -> anyhow::Result<SocksRequest>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let count = stream.read_u8().await? as usize;
+    let mut methods = vec![0; count];
+    stream.read_exact(&mut methods).await?;
+
+    let method = if credentials.is_some() {
+        USERNAME_PASSWORD
+    } else {
+        NO_AUTH
+    };
+    if !methods.contains(&method) {
+        stream.write_all(&[SOCKS5, NO_ACCEPTABLE_METHOD]).await?;
+        bail!("No acceptable SOCKS5 authentication method");
+    }
+    stream.write_all(&[SOCKS5, method]).await?;
+
+    if let Some(credentials) = credentials {
+        // RFC 1929 sub-negotiation
+        let _version = stream.read_u8().await?;
+        let username = read_string(stream).await?;
+        let password = read_string(stream).await?;
+        let valid = constant_time_eq(username.as_bytes(), credentials.username.as_bytes())
+            & constant_time_eq(password.as_bytes(), credentials.password.as_bytes());
+        if !valid {
+            stream.write_all(&[0x01, 0x01]).await?;
+            bail!("Invalid SOCKS5 credentials for {:?}", username);
+        }
+        stream.write_all(&[0x01, 0x00]).await?;
+    }
+
+    let mut request = [0; 4];
+    stream.read_exact(&mut request).await?;
+    let [_, command, _, address_type] = request;
+
+    let host = match address_type {
+        0x01 => Ipv4Addr::from(stream.read_u32().await?).to_string(),
+        0x03 => read_string(stream).await?,
+        0x04 => Ipv6Addr::from(stream.read_u128().await?).to_string(),
+        _ => {
+            socks5_reply(stream, SOCKS5_ADDRESS_NOT_SUPPORTED).await?;
+            bail!("Unsupported SOCKS5 address type {}", address_type);
+        }
+    };
+    let port = stream.read_u16().await?;
+
+    if command != CMD_CONNECT {
+        socks5_reply(stream, SOCKS5_COMMAND_NOT_SUPPORTED).await?;
+        bail!("Unsupported SOCKS5 command {}", command);
+    }
+
+    Ok(SocksRequest {
+        target: TargetAddr { host, port },
+        version: SOCKS5,
+        port,
+        ip: Ipv4Addr::UNSPECIFIED,
+    })
+}
+
+async fn socks4_handshake<S>(
+    stream: &mut S,
+    credentials: Option<&SocksCredentials>,
+) -> anyhow::Result<SocksRequest>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let command = stream.read_u8().await?;
+    let port = stream.read_u16().await?;
+    let ip = Ipv4Addr::from(stream.read_u32().await?);
+    let _user_id = read_until_nul(stream).await?;
+
+    // 0.0.0.x with a non-zero x asks for SOCKS4a, the domain follows
+    let [a, b, c, d] = ip.octets();
+    let host = if a == 0 && b == 0 && c == 0 && d != 0 {
+        read_until_nul(stream).await?
+    } else {
+        ip.to_string()
+    };
+
+    // SOCKS4 has no passwords, only let it in when none are required
+    if command != CMD_CONNECT || credentials.is_some() {
+        socks4_reply(stream, SOCKS4_REJECTED, port, ip).await?;
+        match credentials {
+            Some(_) => bail!("SOCKS4 rejected, credentials are required"),
+            None => bail!("Unsupported SOCKS4 command {}", command),
+        }
+    }
+
+    Ok(SocksRequest {
+        target: TargetAddr { host, port },
+        version: SOCKS4,
+        port,
+        ip,
+    })
+}
+
+async fn socks4_reply<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    status: u8,
+    port: u16,
+    ip: Ipv4Addr,
+) -> anyhow::Result<()> {
+    let mut reply = vec![0x00, status];
+    reply.extend_from_slice(&port.to_be_bytes());
+    reply.extend_from_slice(&ip.octets());
+    stream.write_all(&reply).await?;
+    Ok(())
+}
+
+async fn socks5_reply<S: AsyncWrite + Unpin>(stream: &mut S, status: u8) -> anyhow::Result<()> {
+    // the bound address is meaningless here, report 0.0.0.0:0
+    stream
+        .write_all(&[SOCKS5, status, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+        .await?;
+    Ok(())
+}
+
+fn socks5_status(error: &io::Error) -> u8 {
+    match error.kind() {
+        io::ErrorKind::ConnectionRefused => SOCKS5_CONNECTION_REFUSED,
+        io::ErrorKind::NetworkUnreachable => SOCKS5_NETWORK_UNREACHABLE,
+        io::ErrorKind::HostUnreachable => SOCKS5_HOST_UNREACHABLE,
+        io::ErrorKind::TimedOut => SOCKS5_TTL_EXPIRED,
+        _ => SOCKS5_GENERAL_FAILURE,
+    }
+}
+
+/// Looks at every byte whatever the first difference, so the time taken
+/// tells nothing about how close a guess was.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let diff = a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y));
+    std::hint::black_box(diff) == 0 && a.len() == b.len()
+}
+
+async fn read_string<S: AsyncRead + Unpin>(stream: &mut S) -> anyhow::Result<String> {
+    let len = stream.read_u8().await? as usize;
+    let mut buf = vec![0; len];
+    stream.read_exact(&mut buf).await?;
+    Ok(String::from_utf8(buf)?)
+}
+
+async fn read_until_nul<S: AsyncRead + Unpin>(stream: &mut S) -> anyhow::Result<String> {
+    let mut buf = Vec::new();
+    loop {
+        match stream.read_u8().await? {
+            0 => break,
+            byte if buf.len() < 255 => buf.push(byte),
+            _ => bail!("SOCKS4 field too long"),
+        }
+    }
+    Ok(String::from_utf8(buf)?)
+}
@@ -0,0 +1,236 @@
+use std::{io, sync::Arc, time::Duration};
+
+use rustls::{pki_types::ServerName, ClientConfig, RootCertStore};
+use tokio::{
+    io::{duplex, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+    time::sleep,
+};
+use tokio_rustls::TlsConnector;
+
+use super::{
+    classify, socks_handshake, FlowStore, HttpHandler, MitmProxy, Sniffed, SocksCredentials,
+    TargetAddr,
+};
+
+#[tokio::test]
+async fn test_socks5_domain() {
+    let (mut client, mut server) = duplex(1024);
+    let mut request = vec![0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x03, 11];
+    request.extend_from_slice(b"example.com");
+    request.extend_from_slice(&443u16.to_be_bytes());
+    client.write_all(&request).await.unwrap();
+
+    let request = socks_handshake(&mut server, None).await.unwrap();
+    assert_eq!(
+        request.target,
+        TargetAddr {
+            host: "example.com".to_string(),
+            port: 443
+        }
+    );
+    assert_eq!(request.target.authority(443), "example.com");
+
+    // nothing but the method is answered before the reply
+    let mut method = [0; 2];
+    client.read_exact(&mut method).await.unwrap();
+    assert_eq!(method, [0x05, 0x00]);
+    let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+    request.reply(&mut server, Some(&refused)).await.unwrap();
+    let mut reply = [0; 10];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[..2], [0x05, 0x05]);
+
+    request.reply(&mut server, None).await.unwrap();
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[..2], [0x05, 0x00]);
+}
+
+#[tokio::test]
+async fn test_socks5_credentials() {
+    let credentials = SocksCredentials {
+        username: "user".to_string(),
+        password: "secret".to_string(),
+    };
+
+    let (mut client, mut server) = duplex(1024);
+    client
+        .write_all(&[0x05, 0x01, 0x02, 0x01, 4, b'u', b's', b'e', b'r', 5])
+        .await
+        .unwrap();
+    client.write_all(b"wrong").await.unwrap();
+    assert!(socks_handshake(&mut server, Some(&credentials))
+        .await
+        .is_err());
+    let mut reply = [0; 4];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, [0x05, 0x02, 0x01, 0x01]);
+
+    let (mut client, mut server) = duplex(1024);
+    client
+        .write_all(&[0x05, 0x01, 0x02, 0x01, 4, b'u', b's', b'e', b'r', 6])
+        .await
+        .unwrap();
+    client.write_all(b"secret").await.unwrap();
+    client
+        .write_all(&[0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1, 0, 80])
+        .await
+        .unwrap();
+    let request = socks_handshake(&mut server, Some(&credentials))
+        .await
+        .unwrap();
+    assert_eq!(request.target.addr(), "127.0.0.1:80");
+    let mut reply = [0; 4];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, [0x05, 0x02, 0x01, 0x00]);
+
+    // a client offering no authentication is turned away
+    let (mut client, mut server) = duplex(1024);
+    client.write_all(&[0x05, 0x01, 0x00]).await.unwrap();
+    assert!(socks_handshake(&mut server, Some(&credentials))
+        .await
+        .is_err());
+    let mut reply = [0; 2];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, [0x05, 0xff]);
+}
+
+#[tokio::test]
+async fn test_socks4a() {
+    let (mut client, mut server) = duplex(1024);
+    let mut request = vec![0x04, 0x01, 0x1f, 0x90, 0, 0, 0, 1];
+    request.extend_from_slice(b"me\0localhost\0");
+    client.write_all(&request).await.unwrap();
+
+    let request = socks_handshake(&mut server, None).await.unwrap();
+    assert_eq!(request.target.addr(), "localhost:8080");
+
+    let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+    request.reply(&mut server, Some(&refused)).await.unwrap();
+    let mut reply = [0; 8];
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply, [0x00, 0x5b, 0x1f, 0x90, 0, 0, 0, 1]);
+
+    request.reply(&mut server, None).await.unwrap();
+    client.read_exact(&mut reply).await.unwrap();
+    assert_eq!(reply[..2], [0x00, 0x5a]);
+}
+
+#[tokio::test]
+async fn test_classify() {
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    let config = ClientConfig::builder()
+        .with_root_certificates(RootCertStore::empty())
+        .with_no_client_auth();
+    let (client, mut server) = duplex(16 * 1024);
+    tokio::spawn(async move {
+        let name = ServerName::try_from("api.example.com").unwrap();
+        let _ = TlsConnector::from(Arc::new(config))
+            .connect(name, client)
+            .await;
+    });
+
+    let mut hello = vec![0; 5];
+    server.read_exact(&mut hello).await.unwrap();
+    let len = u16::from_be_bytes([hello[3], hello[4]]) as usize;
+    hello.resize(5 + len, 0);
+    server.read_exact(&mut hello[5..]).await.unwrap();
+
+    assert_eq!(classify(&hello[..3]), None);
+    assert_eq!(
+        classify(&hello),
+        Some(Sniffed::Tls {
+            server_name: Some("api.example.com".to_string())
+        })
+    );
+    assert_eq!(classify(b"GE"), None);
+    assert_eq!(classify(b"GET / HTTP/1.1\r\n"), Some(Sniffed::Http));
+    assert_eq!(classify(b"SSH-2.0-OpenSSH"), Some(Sniffed::Unknown));
+}
+
+struct PassThrough;
+
+impl HttpHandler for PassThrough {}
+
+/// Returns the reply status along with the stream.
+async fn socks5_connect(proxy: &str, port: u16) -> (TcpStream, u8) {
+    let mut stream = TcpStream::connect(proxy).await.unwrap();
+    let mut request = vec![0x05, 0x01, 0x00, 0x05, 0x01, 0x00, 0x01, 127, 0, 0, 1];
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await.unwrap();
+    let mut reply = [0; 12];
+    stream.read_exact(&mut reply).await.unwrap();
+    (stream, reply[3])
+}
+
+#[tokio::test]
+async fn test_socks_listener() {
+    // answers every request, the proxy also dials it before intercepting
+    let http = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let http_port = http.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = http.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut buf = [0; 1024];
+                if stream.read(&mut buf).await.is_ok_and(|n| n > 0) {
+                    let _ = stream
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                        .await;
+                }
+            });
+        }
+    });
+    // sends a banner first
+    let banner = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let banner_port = banner.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let (mut stream, _) = banner.accept().await.unwrap();
+        stream.write_all(b"SSH-2.0-test\r\n").await.unwrap();
+        sleep(Duration::from_millis(100)).await;
+    });
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let closed_port = closed.local_addr().unwrap().port();
+    drop(closed);
+
+    let (shutdown_tx, _) = broadcast::channel::<()>(1);
+    let flow_store = Arc::new(FlowStore::new());
+    let proxy = MitmProxy::builder()
+        .with_addr("127.0.0.1:8084")
+        .with_socks_addr("127.0.0.1:8083")
+        .with_handler(PassThrough)
+        .with_flow_store(flow_store.clone())
+        .with_shutdown(shutdown_tx.clone())
+        .build();
+    tokio::spawn(async move { proxy.start().await });
+    sleep(Duration::from_millis(200)).await;
+
+    let (mut stream, status) = socks5_connect("127.0.0.1:8083", http_port).await;
+    assert_eq!(status, 0x00);
+    stream
+        .write_all(b"GET /hello HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = vec![0; 1024];
+    let n = stream.read(&mut response).await.unwrap();
+    assert!(response[..n].ends_with(b"\r\n\r\nok"));
+
+    let flows = flow_store.list();
+    assert_eq!(
+        flows[0].request.uri,
+        format!("http://127.0.0.1:{}/hello", http_port)
+    );
+
+    let (mut stream, status) = socks5_connect("127.0.0.1:8083", banner_port).await;
+    assert_eq!(status, 0x00);
+    let mut banner = [0; 14];
+    stream.read_exact(&mut banner).await.unwrap();
+    assert_eq!(&banner, b"SSH-2.0-test\r\n");
+
+    // connection refused
+    let (_, status) = socks5_connect("127.0.0.1:8083", closed_port).await;
+    assert_eq!(status, 0x05);
+
+    let _ = shutdown_tx.send(());
+}
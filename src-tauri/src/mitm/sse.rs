@@ -0,0 +1,165 @@
+use std::mem;
+
+use hyper::{header::CONTENT_TYPE, HeaderMap};
+use serde::{Deserialize, Serialize};
+
+use super::{content_encodings, now_millis, StreamDecoder};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+    pub id: Option<String>,
+    pub retry: Option<u64>,
+    pub timestamp: u64,
+}
+
+pub fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("text/event-stream"))
+}
+
+/// Incremental `text/event-stream` parser, chunks may split lines anywhere.
+#[derive(Default)]
+pub struct SseParser {
+    line: Vec<u8>,
+    after_cr: bool,
+    started: bool,
+    event: Option<String>,
+    data: Option<String>,
+    id: Option<String>,
+    retry: Option<u64>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        let mut events = vec![];
+
+        for &byte in chunk {
+            match byte {
+                b'\n' if mem::take(&mut self.after_cr) => {}
+                b'\r' | b'\n' => {
+                    self.after_cr = byte == b'\r';
+                    let line = mem::take(&mut self.line);
+                    events.extend(self.on_line(&line));
+                }
+                _ => {
+                    self.after_cr = false;
+                    self.line.push(byte);
+                }
+            }
+        }
+
+        events
+    }
+
+    fn on_line(&mut self, line: &[u8]) -> Option<SseEvent> {
+        let mut line = String::from_utf8_lossy(line);
+        if !mem::replace(&mut self.started, true) {
+            if let Some(stripped) = line.strip_prefix('\u{feff}') {
+                line = stripped.to_string().into();
+            }
+        }
+
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_ref(), ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => match self.data.as_mut() {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_string()),
+            },
+            "id" if !value.contains('\0') => self.id = Some(value.to_string()),
+            "retry" => {
+                if let Ok(retry) = value.parse() {
+                    self.retry = Some(retry);
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        let retry = self.retry.take();
+        // like the last event id, the id sticks around for later events
+        let data = self.data.take()?;
+
+        Some(SseEvent {
+            event,
+            data,
+            id: self.id.clone(),
+            retry,
+            timestamp: now_millis(),
+        })
+    }
+}
+
+/// Parses a response body into events as it streams by, decoding it first
+/// when needed. Stops after `limit` decoded bytes.
+pub(super) struct SseRecorder {
+    decoder: Option<StreamDecoder>,
+    parser: SseParser,
+    remaining: usize,
+}
+
+impl SseRecorder {
+    pub(super) fn new(headers: &HeaderMap, limit: usize) -> Option<Self> {
+        let encodings = content_encodings(headers).ok()?;
+        let decoder = match encodings.is_empty() {
+            true => None,
+            false => Some(StreamDecoder::new(&encodings, limit).ok()?),
+        };
+
+        Some(Self {
+            decoder,
+            parser: SseParser::new(),
+            remaining: limit,
+        })
+    }
+
+    pub(super) fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        if self.remaining == 0 {
+            return vec![];
+        }
+
+        let decoded;
+        let chunk = match self.decoder.as_mut().map(|d| d.decode(chunk)) {
+            Some(Ok(data)) => {
+                decoded = data;
+                &decoded[..]
+            }
+            Some(Err(_)) => {
+                self.remaining = 0;
+                return vec![];
+            }
+            None => chunk,
+        };
+
+        let chunk = &chunk[..chunk.len().min(self.remaining)];
+        self.remaining -= chunk.len();
+        self.parser.feed(chunk)
+    }
+}
@@ -0,0 +1,69 @@
+use hyper::{header::HeaderValue, HeaderMap};
+
+use super::{encode_body, ContentEncoding, SseParser, SseRecorder, StreamDecoder};
+
+#[test]
+fn test_parse_split_chunks() {
+    let stream = "\u{feff}: keep-alive\r\nevent: tick\r\ndata: one\r\ndata:two\r\nid: 7\r\nretry: 3000\r\n\r\ndata: three\n\n";
+    let mut parser = SseParser::new();
+
+    let mut events = vec![];
+    for byte in stream.as_bytes() {
+        events.extend(parser.feed(&[*byte]));
+    }
+
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].event.as_deref(), Some("tick"));
+    assert_eq!(events[0].data, "one\ntwo");
+    assert_eq!(events[0].retry, Some(3000));
+    assert_eq!(events[1].event, None);
+    assert_eq!(events[1].data, "three");
+    assert_eq!(events[1].id.as_deref(), Some("7"));
+}
+
+#[test]
+fn test_parse_without_data() {
+    let mut parser = SseParser::new();
+    assert!(parser.feed(b"event: ignored\n\n").is_empty());
+
+    let events = parser.feed(b"data\n\n");
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].data, "");
+    assert_eq!(events[0].event, None);
+}
+
+#[test]
+fn test_stream_decoder() {
+    let encodings = [ContentEncoding::Gzip, ContentEncoding::Brotli];
+    let encoded = encode_body(b"data: compressed\n\n", &encodings).unwrap();
+
+    let mut decoder = StreamDecoder::new(&encodings, 1024).unwrap();
+    let mut decoded = vec![];
+    for chunk in encoded.chunks(3) {
+        decoded.extend(decoder.decode(chunk).unwrap());
+    }
+    assert_eq!(decoded, b"data: compressed\n\n");
+}
+
+#[test]
+fn test_stream_decoder_limit() {
+    let encoded = encode_body(&vec![b'a'; 1024 * 1024], &[ContentEncoding::Gzip]).unwrap();
+
+    // one small chunk inflates to far more than the limit
+    let mut decoder = StreamDecoder::new(&[ContentEncoding::Gzip], 1000).unwrap();
+    assert_eq!(decoder.decode(&encoded).unwrap(), vec![b'a'; 1000]);
+    assert!(decoder.decode(&encoded).is_err());
+}
+
+#[test]
+fn test_recorder_limit() {
+    let mut headers = HeaderMap::new();
+    headers.insert("content-encoding", HeaderValue::from_static("gzip"));
+    let encoded = encode_body(b"data: a\n\ndata: b\n\n", &[ContentEncoding::Gzip]).unwrap();
+
+    let mut recorder = SseRecorder::new(&headers, 10).unwrap();
+    let events = recorder.feed(&encoded);
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].data, "a");
+}
@@ -0,0 +1,333 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use hyper::body::{Body as HttpBody, Bytes, Frame, SizeHint};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{sleep, Sleep},
+};
+
+use super::{wildcard_match, Body};
+
+const MIN_RETRANSMIT_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkProfile {
+    pub request_latency_ms: u64,
+    pub response_latency_ms: u64,
+    /// `None` leaves the direction unthrottled.
+    pub download_kbps: Option<u64>,
+    pub upload_kbps: Option<u64>,
+    /// Chance in `0.0..=1.0` that a chunk is "lost" and only delivered after
+    /// a retransmission timeout.
+    #[serde(default)]
+    pub packet_loss: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NetworkPreset {
+    Regular3g,
+    Good3g,
+    Edge,
+    Satellite,
+}
+
+impl NetworkPreset {
+    pub const ALL: [NetworkPreset; 4] = [
+        NetworkPreset::Regular3g,
+        NetworkPreset::Good3g,
+        NetworkPreset::Edge,
+        NetworkPreset::Satellite,
+    ];
+
+    pub fn profile(&self) -> NetworkProfile {
+        match self {
+            Self::Regular3g => NetworkProfile {
+                request_latency_ms: 50,
+                response_latency_ms: 50,
+                download_kbps: Some(750),
+                upload_kbps: Some(250),
+                packet_loss: 0.0,
+            },
+            Self::Good3g => NetworkProfile {
+                request_latency_ms: 20,
+                response_latency_ms: 20,
+                download_kbps: Some(1_500),
+                upload_kbps: Some(750),
+                packet_loss: 0.0,
+            },
+            Self::Edge => NetworkProfile {
+                request_latency_ms: 220,
+                response_latency_ms: 200,
+                download_kbps: Some(240),
+                upload_kbps: Some(200),
+                packet_loss: 0.01,
+            },
+            Self::Satellite => NetworkProfile {
+                request_latency_ms: 300,
+                response_latency_ms: 300,
+                download_kbps: Some(1_000),
+                upload_kbps: Some(256),
+                packet_loss: 0.02,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HostProfile {
+    /// Host pattern, `*` wildcards allowed.
+    pub host: String,
+    pub profile: NetworkProfile,
+}
+
+/// Host profiles take precedence over the global one.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThrottleConfig {
+    pub enabled: bool,
+    pub global: Option<NetworkProfile>,
+    #[serde(default)]
+    pub hosts: Vec<HostProfile>,
+}
+
+/// Runtime switchable network conditions. Throttled bodies and streams look
+/// up their profile for every chunk, so changes apply to flows in progress.
+#[derive(Default)]
+pub struct Throttle {
+    config: RwLock<ThrottleConfig>,
+}
+
+impl Throttle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn config(&self) -> ThrottleConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    pub fn set_config(&self, config: ThrottleConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    pub fn profile_for(&self, host: &str) -> Option<NetworkProfile> {
+        let config = self.config.read().unwrap();
+        if !config.enabled {
+            return None;
+        }
+
+        let host = host.to_ascii_lowercase();
+        config
+            .hosts
+            .iter()
+            .find(|h| wildcard_match(&h.host.to_ascii_lowercase(), &host))
+            .map(|h| h.profile.clone())
+            .or_else(|| config.global.clone())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Direction {
+    Upload,
+    Download,
+}
+
+#[derive(Clone)]
+pub(super) struct Limiter {
+    throttle: Arc<Throttle>,
+    host: String,
+    direction: Direction,
+}
+
+impl Limiter {
+    pub(super) fn new(throttle: Arc<Throttle>, host: &str, direction: Direction) -> Self {
+        Self {
+            throttle,
+            host: host.to_string(),
+            direction,
+        }
+    }
+
+    pub(super) fn latency(&self) -> Duration {
+        self.throttle
+            .profile_for(&self.host)
+            .map(|p| match self.direction {
+                Direction::Upload => p.request_latency_ms,
+                Direction::Download => p.response_latency_ms,
+            })
+            .map(Duration::from_millis)
+            .unwrap_or_default()
+    }
+
+    /// Time it takes `len` bytes to go through at the configured rate.
+    fn delay_for(&self, len: usize) -> Option<Duration> {
+        let profile = self.throttle.profile_for(&self.host)?;
+
+        let kbps = match self.direction {
+            Direction::Upload => profile.upload_kbps,
+            Direction::Download => profile.download_kbps,
+        };
+        let mut delay = kbps
+            .filter(|kbps| *kbps > 0)
+            .map(|kbps| Duration::from_secs_f64(len as f64 * 8.0 / (kbps as f64 * 1000.0)))
+            .unwrap_or_default();
+
+        if profile.packet_loss > 0.0 && rand::random::<f64>() < profile.packet_loss {
+            let rtt =
+                Duration::from_millis(profile.request_latency_ms + profile.response_latency_ms);
+            delay += (rtt * 2).max(MIN_RETRANSMIT_TIMEOUT);
+        }
+
+        (!delay.is_zero()).then_some(delay)
+    }
+}
+
+/// Holds every data frame back for its transmission time.
+pub(super) struct ThrottledBody {
+    inner: Body,
+    limiter: Limiter,
+    delay: Option<Pin<Box<Sleep>>>,
+    pending: Option<Frame<Bytes>>,
+}
+
+impl ThrottledBody {
+    pub(super) fn new(inner: Body, limiter: Limiter) -> Self {
+        Self {
+            inner,
+            limiter,
+            delay: None,
+            pending: None,
+        }
+    }
+}
+
+impl HttpBody for ThrottledBody {
+    type Data = Bytes;
+    type Error = anyhow::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Some(delay) = self.delay.as_mut() {
+            ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+            if let Some(frame) = self.pending.take() {
+                return Poll::Ready(Some(Ok(frame)));
+            }
+        }
+
+        match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+            Some(Ok(frame)) => {
+                let len = frame.data_ref().map_or(0, |data| data.len());
+                match self.limiter.delay_for(len) {
+                    Some(delay) => {
+                        self.delay = Some(Box::pin(sleep(delay)));
+                        self.pending = Some(frame);
+                        self.poll_frame(cx)
+                    }
+                    None => Poll::Ready(Some(Ok(frame))),
+                }
+            }
+            other => Poll::Ready(other),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Paces a raw stream: reads count as upload, writes as download. Each
+/// transfer has to wait out the time the previous one would have taken.
+pub(super) struct ThrottledStream<S> {
+    inner: S,
+    read_limiter: Limiter,
+    write_limiter: Limiter,
+    read_delay: Option<Pin<Box<Sleep>>>,
+    write_delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> ThrottledStream<S> {
+    pub(super) fn new(inner: S, throttle: Arc<Throttle>, host: &str) -> Self {
+        Self {
+            inner,
+            read_limiter: Limiter::new(throttle.clone(), host, Direction::Upload),
+            write_limiter: Limiter::new(throttle, host, Direction::Download),
+            read_delay: None,
+            write_delay: None,
+        }
+    }
+}
+
+fn poll_delay(delay: &mut Option<Pin<Box<Sleep>>>, cx: &mut Context<'_>) -> Poll<()> {
+    if let Some(sleep) = delay.as_mut() {
+        ready!(sleep.as_mut().poll(cx));
+        *delay = None;
+    }
+    Poll::Ready(())
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for ThrottledStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        ready!(poll_delay(&mut this.read_delay, cx));
+
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+
+        let len = buf.filled().len() - filled;
+        this.read_delay = this
+            .read_limiter
+            .delay_for(len)
+            .map(|delay| Box::pin(sleep(delay)));
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for ThrottledStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(poll_delay(&mut this.write_delay, cx));
+
+        let len = ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        this.write_delay = this
+            .write_limiter
+            .delay_for(len)
+            .map(|delay| Box::pin(sleep(delay)));
+
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,112 @@
+use std::{sync::Arc, time::Duration};
+
+use http_body_util::BodyExt;
+use tokio::{
+    io::{duplex, AsyncReadExt, AsyncWriteExt},
+    time::Instant,
+};
+
+use super::{
+    full_body, Direction, HostProfile, Limiter, NetworkProfile, Throttle, ThrottleConfig,
+    ThrottledBody, ThrottledStream,
+};
+
+/// 1000 bytes a second both ways.
+fn slow() -> NetworkProfile {
+    NetworkProfile {
+        request_latency_ms: 100,
+        response_latency_ms: 200,
+        download_kbps: Some(8),
+        upload_kbps: Some(8),
+        packet_loss: 0.0,
+    }
+}
+
+fn throttle(global: Option<NetworkProfile>, hosts: Vec<HostProfile>) -> Arc<Throttle> {
+    let throttle = Arc::new(Throttle::new());
+    throttle.set_config(ThrottleConfig {
+        enabled: true,
+        global,
+        hosts,
+    });
+    throttle
+}
+
+#[test]
+fn test_host_profiles() {
+    let fast = NetworkProfile {
+        download_kbps: Some(10_000),
+        ..slow()
+    };
+    let throttle = throttle(
+        Some(slow()),
+        vec![HostProfile {
+            host: "*.CDN.test".to_string(),
+            profile: fast.clone(),
+        }],
+    );
+
+    assert_eq!(throttle.profile_for("img.cdn.test"), Some(fast));
+    assert_eq!(throttle.profile_for("api.test"), Some(slow()));
+
+    let mut config = throttle.config();
+    config.enabled = false;
+    throttle.set_config(config);
+    assert_eq!(throttle.profile_for("api.test"), None);
+
+    let limiter = Limiter::new(
+        self::throttle(Some(slow()), vec![]),
+        "api.test",
+        Direction::Download,
+    );
+    assert_eq!(limiter.latency(), Duration::from_millis(200));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_throttled_body() {
+    let throttle = throttle(
+        Some(slow()),
+        vec![HostProfile {
+            host: "fast.test".to_string(),
+            profile: NetworkProfile::default(),
+        }],
+    );
+
+    let start = Instant::now();
+    let limiter = Limiter::new(throttle.clone(), "api.test", Direction::Download);
+    let body = ThrottledBody::new(full_body(vec![0; 1500]), limiter);
+    let collected = body.collect().await.unwrap().to_bytes();
+    assert_eq!(collected.len(), 1500);
+    assert_eq!(start.elapsed(), Duration::from_millis(1500));
+
+    // the host profile has no rate limit
+    let start = Instant::now();
+    let limiter = Limiter::new(throttle, "fast.test", Direction::Download);
+    let body = ThrottledBody::new(full_body(vec![0; 1500]), limiter);
+    body.collect().await.unwrap();
+    assert_eq!(start.elapsed(), Duration::ZERO);
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_throttled_stream() {
+    let (client, mut server) = duplex(64 * 1024);
+    let mut stream = ThrottledStream::new(client, throttle(Some(slow()), vec![]), "api.test");
+
+    let start = Instant::now();
+    for _ in 0..3 {
+        stream.write_all(&[0; 500]).await.unwrap();
+    }
+    // each write waits out the one before it
+    assert_eq!(start.elapsed(), Duration::from_millis(1000));
+
+    let mut received = [0; 1500];
+    server.read_exact(&mut received).await.unwrap();
+
+    let start = Instant::now();
+    server.write_all(&[1; 250]).await.unwrap();
+    server.write_all(&[1; 250]).await.unwrap();
+    let mut buf = [0; 250];
+    stream.read_exact(&mut buf).await.unwrap();
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(start.elapsed(), Duration::from_millis(250));
+}
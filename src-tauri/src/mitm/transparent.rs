@@ -0,0 +1,203 @@
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::net::{lookup_host, TcpListener, TcpStream, ToSocketAddrs};
+
+const NFT_TABLE: &str = "devya";
+const TPROXY_MARK: u32 = 1;
+const TPROXY_ROUTE_TABLE: u32 = 100;
+
+/// Binds the transparent listener. On Linux the socket is also made
+/// `IP_TRANSPARENT` so that TPROXY'd connections are accepted, which needs
+/// `CAP_NET_ADMIN`; without it only `REDIRECT` works.
+pub async fn bind_transparent<A: ToSocketAddrs>(addr: A) -> io::Result<TcpListener> {
+    let addr = lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No address to bind"))?;
+
+    #[cfg(target_os = "linux")]
+    {
+        use socket2::{Domain, Socket, Type};
+
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        socket.set_reuse_address(true)?;
+        let transparent = match addr {
+            SocketAddr::V4(_) => socket.set_ip_transparent_v4(true),
+            SocketAddr::V6(_) => socket.set_ip_transparent_v6(true),
+        };
+        if let Err(e) = transparent {
+            tracing::warn!("TPROXY unavailable, only REDIRECT will work: {}", e);
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(1024)?;
+        socket.set_nonblocking(true)?;
+        TcpListener::from_std(socket.into())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    TcpListener::bind(addr).await
+}
+
+/// Where a redirected connection was headed before netfilter sent it to
+/// the proxy.
+#[cfg(target_os = "linux")]
+pub fn original_dst(stream: &TcpStream) -> io::Result<SocketAddr> {
+    let local = stream.local_addr()?;
+    let socket = socket2::SockRef::from(stream);
+    let original = match local {
+        SocketAddr::V4(_) => socket.original_dst_v4(),
+        SocketAddr::V6(_) => socket.original_dst_v6(),
+    };
+
+    match original.map(|addr| addr.as_socket()) {
+        Ok(Some(addr)) => Ok(addr),
+        // TPROXY leaves the destination alone, it is the local address
+        _ => Ok(local),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn original_dst(_stream: &TcpStream) -> io::Result<SocketAddr> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Transparent mode is only supported on Linux",
+    ))
+}
+
+/// Whether `dst` is somewhere else than the listener itself, connecting to
+/// the listener directly would make the proxy dial itself.
+pub fn is_redirected(dst: SocketAddr, listen_addr: SocketAddr) -> bool {
+    dst.port() != listen_addr.port()
+        || !(listen_addr.ip().is_unspecified() || dst.ip() == listen_addr.ip())
+}
+
+/// Describes the nftables rules sending traffic to the transparent
+/// listener, see [`TransparentRules::script`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TransparentRules {
+    /// Address the transparent listener is bound to. Traffic is sent to it
+    /// as is, only with `0.0.0.0` the address of the interface it came in
+    /// on is used.
+    pub proxy_addr: Ipv4Addr,
+    /// Port of the transparent listener.
+    pub proxy_port: u16,
+    /// Destination ports to capture.
+    pub ports: Vec<u16>,
+    /// Captures traffic routed through this interface, e.g. `docker0`.
+    pub interface: Option<String>,
+    /// Also captures traffic of local processes, except those of this
+    /// user, which the proxy must run as.
+    pub proxy_uid: Option<u32>,
+    /// Uses TPROXY instead of REDIRECT for routed traffic.
+    pub tproxy: bool,
+}
+
+impl Default for TransparentRules {
+    fn default() -> Self {
+        Self {
+            proxy_addr: Ipv4Addr::LOCALHOST,
+            proxy_port: 7779,
+            ports: vec![80, 443],
+            interface: None,
+            proxy_uid: None,
+            tproxy: false,
+        }
+    }
+}
+
+impl TransparentRules {
+    /// Shell commands installing the rules, to be run as root.
+    pub fn script(&self) -> String {
+        let ports = self
+            .ports
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let matcher = match &self.interface {
+            Some(interface) => format!("iifname \"{}\" tcp dport {{ {} }}", interface, ports),
+            None => format!("tcp dport {{ {} }}", ports),
+        };
+
+        // `redirect` picks the address of the incoming interface, a listener
+        // bound elsewhere needs its own address spelled out
+        let (redirect, tproxy) = match self.proxy_addr {
+            Ipv4Addr::UNSPECIFIED => (
+                format!("redirect to :{}", self.proxy_port),
+                format!("tproxy to :{}", self.proxy_port),
+            ),
+            addr => (
+                format!("dnat ip to {}:{}", addr, self.proxy_port),
+                format!("tproxy ip to {}:{}", addr, self.proxy_port),
+            ),
+        };
+
+        let mut lines = vec![
+            "#!/bin/sh".to_string(),
+            format!("# remove with: nft delete table ip {}", NFT_TABLE),
+        ];
+        if self.tproxy {
+            lines.push(format!(
+                "# and: ip rule del fwmark {} lookup {}; ip route flush table {}",
+                TPROXY_MARK, TPROXY_ROUTE_TABLE, TPROXY_ROUTE_TABLE
+            ));
+            lines.push(format!(
+                "ip rule add fwmark {} lookup {}",
+                TPROXY_MARK, TPROXY_ROUTE_TABLE
+            ));
+            lines.push(format!(
+                "ip route add local 0.0.0.0/0 dev lo table {}",
+                TPROXY_ROUTE_TABLE
+            ));
+        } else {
+            if self.interface.is_some() {
+                lines.push("sysctl -w net.ipv4.ip_forward=1".to_string());
+            }
+            if self.proxy_addr.is_loopback() {
+                // packets from other interfaces may not be sent to loopback
+                // addresses otherwise
+                lines.push("sysctl -w net.ipv4.conf.all.route_localnet=1".to_string());
+            }
+        }
+
+        lines.push("nft -f - <<'EOF'".to_string());
+        lines.push(format!("table ip {} {{", NFT_TABLE));
+        if self.tproxy {
+            lines.push("    chain prerouting {".to_string());
+            lines.push(
+                "        type filter hook prerouting priority mangle; policy accept;".to_string(),
+            );
+            lines.push(format!(
+                "        {} {} meta mark set {} accept",
+                matcher, tproxy, TPROXY_MARK
+            ));
+            lines.push("    }".to_string());
+        } else {
+            lines.push("    chain prerouting {".to_string());
+            lines.push(
+                "        type nat hook prerouting priority dstnat; policy accept;".to_string(),
+            );
+            lines.push(format!("        {} {}", matcher, redirect));
+            lines.push("    }".to_string());
+        }
+        if let Some(uid) = self.proxy_uid {
+            // TPROXY only sees routed packets, local ones are redirected
+            lines.push("    chain output {".to_string());
+            lines.push("        type nat hook output priority -100; policy accept;".to_string());
+            lines.push(format!(
+                "        meta skuid != {} tcp dport {{ {} }} {}",
+                uid, ports, redirect
+            ));
+            lines.push("    }".to_string());
+        }
+        lines.push("}".to_string());
+        lines.push("EOF".to_string());
+
+        lines.join("\n") + "\n"
+    }
+}
@@ -0,0 +1,112 @@
+use std::{net::Ipv4Addr, sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::broadcast,
+    time::{sleep, timeout},
+};
+
+use super::{
+    bind_transparent, is_redirected, original_dst, FlowStore, HttpHandler, MitmProxy,
+    TransparentRules,
+};
+
+#[test]
+fn test_redirect_script() {
+    let script = TransparentRules {
+        interface: Some("docker0".to_string()),
+        proxy_uid: Some(1000),
+        ..Default::default()
+    }
+    .script();
+
+    // the listener is bound to loopback by default
+    assert!(script.contains("sysctl -w net.ipv4.conf.all.route_localnet=1\n"));
+    assert!(script.contains("type nat hook prerouting priority dstnat;"));
+    assert!(
+        script.contains("iifname \"docker0\" tcp dport { 80, 443 } dnat ip to 127.0.0.1:7779\n")
+    );
+    assert!(script.contains("meta skuid != 1000 tcp dport { 80, 443 } dnat ip to 127.0.0.1:7779\n"));
+    assert!(script.ends_with("}\nEOF\n"));
+
+    let script = TransparentRules {
+        proxy_addr: Ipv4Addr::UNSPECIFIED,
+        ..Default::default()
+    }
+    .script();
+    assert!(!script.contains("route_localnet"));
+    assert!(script.contains("tcp dport { 80, 443 } redirect to :7779\n"));
+}
+
+#[test]
+fn test_tproxy_script() {
+    let script = TransparentRules {
+        proxy_port: 8000,
+        ports: vec![443],
+        tproxy: true,
+        ..Default::default()
+    }
+    .script();
+
+    assert!(script.contains("ip rule add fwmark 1 lookup 100\n"));
+    assert!(
+        script.contains("tcp dport { 443 } tproxy ip to 127.0.0.1:8000 meta mark set 1 accept\n")
+    );
+    assert!(!script.contains("chain output"));
+}
+
+#[tokio::test]
+async fn test_direct_connection() {
+    let listener = bind_transparent("127.0.0.1:0").await.unwrap();
+    let listen_addr = listener.local_addr().unwrap();
+    let _client = TcpStream::connect(listen_addr).await.unwrap();
+    let (stream, _) = listener.accept().await.unwrap();
+
+    // nothing redirected it, the destination is the listener itself
+    if let Ok(dst) = original_dst(&stream) {
+        assert_eq!(dst, listen_addr);
+        assert!(!is_redirected(dst, listen_addr));
+    }
+    let wildcard = format!("0.0.0.0:{}", listen_addr.port()).parse().unwrap();
+    assert!(!is_redirected(listen_addr, wildcard));
+    assert!(is_redirected(
+        "93.184.216.34:443".parse().unwrap(),
+        listen_addr
+    ));
+}
+
+struct PassThrough;
+
+impl HttpHandler for PassThrough {}
+
+#[tokio::test]
+async fn test_transparent_listener() {
+    let (shutdown_tx, _) = broadcast::channel::<()>(1);
+    let flow_store = Arc::new(FlowStore::new());
+    let proxy = MitmProxy::builder()
+        .with_addr("127.0.0.1:8090")
+        .with_transparent_addr("127.0.0.1:8091")
+        .with_handler(PassThrough)
+        .with_flow_store(flow_store.clone())
+        .with_shutdown(shutdown_tx.clone())
+        .build();
+    tokio::spawn(async move { proxy.start().await });
+    sleep(Duration::from_millis(200)).await;
+
+    // without a redirect the destination is the listener, which the proxy
+    // must not dial, and there is no destination to find outside Linux
+    let mut stream = TcpStream::connect("127.0.0.1:8091").await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: 127.0.0.1:8091\r\n\r\n")
+        .await
+        .unwrap();
+    let mut buf = [0; 64];
+    let read = timeout(Duration::from_secs(2), stream.read(&mut buf))
+        .await
+        .unwrap();
+    assert!(matches!(read, Ok(0) | Err(_)));
+    assert!(flow_store.list().is_empty());
+
+    let _ = shutdown_tx.send(());
+}
@@ -0,0 +1,160 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    time::sleep,
+};
+use tracing::info;
+
+use super::{Direction, Limiter, Throttle, ThrottledStream, TunnelStats};
+
+/// Where a client asked to be connected, through CONNECT or SOCKS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetAddr {
+    /// Domain name or IP address, IPv6 without brackets.
+    pub host: String,
+    pub port: u16,
+}
+
+impl TargetAddr {
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host_for_uri(), self.port)
+    }
+
+    /// Leaves out the port when it is the default one.
+    pub fn authority(&self, default_port: u16) -> String {
+        if self.port == default_port {
+            self.host_for_uri()
+        } else {
+            self.addr()
+        }
+    }
+
+    fn host_for_uri(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        }
+    }
+}
+
+/// Bytes moved through a [`CountingStream`] so far.
+#[derive(Debug, Clone, Default)]
+pub struct ByteCounts {
+    read: Arc<AtomicU64>,
+    written: Arc<AtomicU64>,
+}
+
+impl ByteCounts {
+    /// Seen from the client side: what it sent and what it got back.
+    pub fn stats(&self) -> TunnelStats {
+        TunnelStats {
+            bytes_sent: self.read.load(Ordering::Relaxed),
+            bytes_received: self.written.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct CountingStream<S> {
+    inner: S,
+    counts: ByteCounts,
+}
+
+impl<S> CountingStream<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            counts: ByteCounts::default(),
+        }
+    }
+
+    pub fn counts(&self) -> ByteCounts {
+        self.counts.clone()
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let n = buf.filled().len() - filled;
+        self.counts.read.fetch_add(n as u64, Ordering::Relaxed);
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CountingStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            self.counts.written.fetch_add(n as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Relays bytes between the client and `addr` untouched, over `server`
+/// when it is already connected.
+pub(super) async fn tunnel<I>(
+    mut client: I,
+    addr: String,
+    host: &str,
+    server: Option<TcpStream>,
+    throttle: Option<Arc<Throttle>>,
+) -> anyhow::Result<()>
+where
+    I: AsyncRead + AsyncWrite + Unpin,
+{
+    info!("Tunneling to {}", addr);
+    let connect = |addr| async move {
+        match server {
+            Some(server) => Ok(server),
+            None => TcpStream::connect(addr).await,
+        }
+    };
+
+    let Some(throttle) = throttle else {
+        let mut server = connect(addr).await?;
+        tokio::io::copy_bidirectional(&mut client, &mut server).await?;
+        return Ok(());
+    };
+
+    // one round trip for connecting, the stream is paced from there on
+    sleep(
+        Limiter::new(throttle.clone(), host, Direction::Upload).latency()
+            + Limiter::new(throttle.clone(), host, Direction::Download).latency(),
+    )
+    .await;
+
+    let mut server = connect(addr).await?;
+    let mut client = ThrottledStream::new(client, throttle, host);
+    tokio::io::copy_bidirectional(&mut client, &mut server).await?;
+
+    Ok(())
+}
@@ -0,0 +1,781 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::pending,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use flate2::{Decompress, FlushDecompress, Status};
+use hyper::{
+    header::{HeaderValue, CONNECTION, SEC_WEBSOCKET_EXTENSIONS, UPGRADE},
+    upgrade::Upgraded,
+    HeaderMap, Request, Uri,
+};
+use hyper_util::rt::TokioIo;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+use tracing::{error, warn};
+
+use super::{
+    apply_ws_action, find_ws_action, now_millis, FlowId, FlowStore, HttpHandler, Rule, WsDirection,
+    WsMessage, WsMessageKind,
+};
+
+/// Frames above this size are forwarded but not recorded.
+const MAX_RECORDED_FRAME: u64 = 16 * 1024 * 1024;
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+pub(super) const OPCODE_CONTINUATION: u8 = 0x0;
+pub(super) const OPCODE_TEXT: u8 = 0x1;
+pub(super) const OPCODE_BINARY: u8 = 0x2;
+pub(super) const OPCODE_CLOSE: u8 = 0x8;
+pub(super) const OPCODE_PING: u8 = 0x9;
+pub(super) const OPCODE_PONG: u8 = 0xa;
+
+pub(super) fn is_websocket_upgrade<B>(req: &Request<B>) -> bool {
+    let has_token = |value: &[u8], token: &str| {
+        String::from_utf8_lossy(value)
+            .split(',')
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    };
+
+    req.headers()
+        .get_all(CONNECTION)
+        .iter()
+        .any(|v| has_token(v.as_bytes(), "upgrade"))
+        && req
+            .headers()
+            .get(UPGRADE)
+            .is_some_and(|v| has_token(v.as_bytes(), "websocket"))
+}
+
+/// The permessage-deflate parameters the server agreed to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct DeflateParams {
+    pub client_no_context_takeover: bool,
+    pub server_no_context_takeover: bool,
+}
+
+impl DeflateParams {
+    pub(super) fn negotiated(headers: &HeaderMap) -> Option<Self> {
+        headers
+            .get_all(SEC_WEBSOCKET_EXTENSIONS)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .find_map(|extension| {
+                let mut params = extension.split(';').map(str::trim);
+                if !params.next()?.eq_ignore_ascii_case("permessage-deflate") {
+                    return None;
+                }
+
+                let mut deflate = DeflateParams::default();
+                for param in params {
+                    let name = param.split('=').next().unwrap_or_default().trim();
+                    if name.eq_ignore_ascii_case("client_no_context_takeover") {
+                        deflate.client_no_context_takeover = true;
+                    } else if name.eq_ignore_ascii_case("server_no_context_takeover") {
+                        deflate.server_no_context_takeover = true;
+                    }
+                }
+                Some(deflate)
+            })
+    }
+}
+
+/// Asks the server for permessage-deflate without context takeover in both
+/// directions, so a message a relay changes or drops can't leave the
+/// receiver's inflater out of step with the sender's compressor.
+pub(super) fn offer_no_context_takeover(headers: &mut HeaderMap) {
+    add_deflate_params(
+        headers,
+        &["client_no_context_takeover", "server_no_context_takeover"],
+    );
+}
+
+/// Tells the client to compress every message on its own, which the server
+/// may have left out of its answer.
+pub(super) fn require_client_no_context_takeover(headers: &mut HeaderMap) {
+    add_deflate_params(headers, &["client_no_context_takeover"]);
+}
+
+fn add_deflate_params(headers: &mut HeaderMap, names: &[&str]) {
+    let values: Vec<String> = headers
+        .get_all(SEC_WEBSOCKET_EXTENSIONS)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .map(|extension| {
+                    let mut extension = extension.trim().to_string();
+                    let mut params = extension.split(';').map(str::trim);
+                    if !params
+                        .next()
+                        .is_some_and(|name| name.eq_ignore_ascii_case("permessage-deflate"))
+                    {
+                        return extension;
+                    }
+                    let params: Vec<&str> = params.collect();
+                    let missing: Vec<&str> = names
+                        .iter()
+                        .filter(|name| !params.iter().any(|p| p.eq_ignore_ascii_case(name)))
+                        .copied()
+                        .collect();
+                    for name in missing {
+                        extension.push_str("; ");
+                        extension.push_str(name);
+                    }
+                    extension
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .collect();
+
+    headers.remove(SEC_WEBSOCKET_EXTENSIONS);
+    for value in values {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.append(SEC_WEBSOCKET_EXTENSIONS, value);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct FrameHeader {
+    pub fin: bool,
+    pub rsv1: bool,
+    pub opcode: u8,
+    pub mask: Option<[u8; 4]>,
+    pub header_len: usize,
+    pub payload_len: u64,
+}
+
+impl FrameHeader {
+    /// `None` until the whole header is buffered.
+    pub(super) fn parse(buf: &[u8]) -> Option<Self> {
+        let [b0, b1, ..] = *buf else {
+            return None;
+        };
+
+        let (payload_len, mut header_len) = match b1 & 0x7f {
+            126 => (
+                u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?) as u64,
+                4,
+            ),
+            127 => (u64::from_be_bytes(buf.get(2..10)?.try_into().ok()?), 10),
+            len => (len as u64, 2),
+        };
+
+        let mask = if b1 & 0x80 != 0 {
+            let mask = buf.get(header_len..header_len + 4)?.try_into().ok()?;
+            header_len += 4;
+            Some(mask)
+        } else {
+            None
+        };
+
+        Some(Self {
+            fin: b0 & 0x80 != 0,
+            rsv1: b0 & 0x40 != 0,
+            opcode: b0 & 0x0f,
+            mask,
+            header_len,
+            payload_len,
+        })
+    }
+}
+
+pub(super) fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// Encodes a message as a single uncompressed frame.
+pub(super) fn encode_message(message: &WsMessage, mask: Option<[u8; 4]>) -> Vec<u8> {
+    let opcode = match message.kind {
+        WsMessageKind::Text => OPCODE_TEXT,
+        WsMessageKind::Binary => OPCODE_BINARY,
+        WsMessageKind::Close => OPCODE_CLOSE,
+        WsMessageKind::Ping => OPCODE_PING,
+        WsMessageKind::Pong => OPCODE_PONG,
+    };
+
+    let len = message.payload.len();
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    let mut frame = Vec::with_capacity(len + 14);
+    frame.push(0x80 | opcode);
+    if len < 126 {
+        frame.push(mask_bit | len as u8);
+    } else if len <= u16::MAX as usize {
+        frame.push(mask_bit | 126);
+        frame.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        frame.push(mask_bit | 127);
+        frame.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+
+    let payload_start = frame.len() + mask.map_or(0, |m| m.len());
+    if let Some(mask) = mask {
+        frame.extend_from_slice(&mask);
+    }
+    frame.extend_from_slice(&message.payload);
+    if let Some(mask) = mask {
+        apply_mask(&mut frame[payload_start..], mask);
+    }
+
+    frame
+}
+
+/// What a rewriting relay gets out of the bytes it reads.
+pub(super) enum Relayed {
+    /// A whole message and the frames it came in.
+    Message(WsMessage, Vec<u8>),
+    /// Frames to forward as they are, too large or not decodable, with the
+    /// message to record if there is one.
+    Raw(Vec<u8>, Option<WsMessage>),
+}
+
+/// Reassembles the messages of one direction out of raw frame bytes.
+pub(super) struct MessageAssembler {
+    direction: WsDirection,
+    buf: Vec<u8>,
+    skip: u64,
+    opcode: u8,
+    compressed: bool,
+    fragments: Option<Vec<u8>>,
+    inflater: Option<Decompress>,
+    deflate: Option<DeflateParams>,
+    /// Keeps the frames of every message for [`Self::relay`].
+    keep_frames: bool,
+    frames: Vec<u8>,
+    /// The frames of the current message are forwarded as they come.
+    passthrough: bool,
+}
+
+impl MessageAssembler {
+    pub(super) fn new(direction: WsDirection, deflate: Option<DeflateParams>) -> Self {
+        Self {
+            direction,
+            buf: vec![],
+            skip: 0,
+            opcode: OPCODE_BINARY,
+            compressed: false,
+            fragments: None,
+            inflater: None,
+            deflate,
+            keep_frames: false,
+            frames: vec![],
+            passthrough: false,
+        }
+    }
+
+    /// An assembler for a relay that rewrites messages.
+    pub(super) fn relaying(mut self) -> Self {
+        self.keep_frames = true;
+        self
+    }
+
+    /// Whether the bytes fed so far end on a message boundary.
+    pub(super) fn at_boundary(&self) -> bool {
+        self.buf.is_empty() && self.skip == 0 && self.fragments.is_none()
+    }
+
+    /// Whether another message can be written without splitting one that
+    /// is being forwarded. A rewriting relay writes whole messages except
+    /// for the ones going through as they are.
+    pub(super) fn can_interleave(&self) -> bool {
+        match self.keep_frames {
+            true => !self.passthrough && self.skip == 0,
+            false => self.at_boundary(),
+        }
+    }
+
+    /// Whether compressed messages of this direction build on the ones
+    /// before them, so none of them can be changed or dropped on the way.
+    pub(super) fn shares_context(&self) -> bool {
+        self.deflate.is_some_and(|d| match self.direction {
+            WsDirection::ClientToServer => !d.client_no_context_takeover,
+            WsDirection::ServerToClient => !d.server_no_context_takeover,
+        })
+    }
+
+    pub(super) fn feed(&mut self, data: &[u8]) -> Vec<WsMessage> {
+        self.process(data)
+            .into_iter()
+            .filter_map(|relayed| match relayed {
+                Relayed::Message(message, _) => Some(message),
+                Relayed::Raw(_, message) => message,
+            })
+            .collect()
+    }
+
+    /// Like [`Self::feed`], along with the frames each message came in.
+    /// Only for assemblers created with [`Self::relaying`].
+    pub(super) fn relay(&mut self, data: &[u8]) -> Vec<Relayed> {
+        debug_assert!(self.keep_frames);
+        self.process(data)
+    }
+
+    fn process(&mut self, mut data: &[u8]) -> Vec<Relayed> {
+        let mut relayed = vec![];
+        if self.skip > 0 {
+            let skipped = self.skip.min(data.len() as u64) as usize;
+            self.skip -= skipped as u64;
+            if self.keep_frames {
+                relayed.push(Relayed::Raw(data[..skipped].to_vec(), None));
+            }
+            data = &data[skipped..];
+        }
+        self.buf.extend_from_slice(data);
+
+        while let Some(header) = FrameHeader::parse(&self.buf) {
+            let frame_len = header.header_len as u64 + header.payload_len;
+            let assembled = match header.opcode {
+                OPCODE_CONTINUATION => self.fragments.as_ref().map_or(0, Vec::len) as u64,
+                _ => 0,
+            };
+
+            if assembled + header.payload_len > MAX_RECORDED_FRAME {
+                let buffered = self.buf.len() as u64;
+                let taken = frame_len.min(buffered) as usize;
+                if self.keep_frames {
+                    let mut frames = std::mem::take(&mut self.frames);
+                    frames.extend_from_slice(&self.buf[..taken]);
+                    relayed.push(Relayed::Raw(frames, None));
+                    // the rest of the message follows it as it is
+                    self.passthrough = header.opcode < OPCODE_CLOSE && !header.fin;
+                }
+                self.buf.drain(..taken);
+                self.skip = frame_len.saturating_sub(buffered);
+                self.fragments = None;
+                continue;
+            }
+
+            if (self.buf.len() as u64) < frame_len {
+                break;
+            }
+
+            let frame_len = frame_len as usize;
+            let mut payload = self.buf[header.header_len..frame_len].to_vec();
+            let frame = self.keep_frames.then(|| self.buf[..frame_len].to_vec());
+            self.buf.drain(..frame_len);
+            if let Some(mask) = header.mask {
+                apply_mask(&mut payload, mask);
+            }
+
+            if let Some(message) = self.on_frame(&header, payload, frame) {
+                relayed.push(message);
+            }
+        }
+
+        relayed
+    }
+
+    fn on_frame(
+        &mut self,
+        header: &FrameHeader,
+        payload: Vec<u8>,
+        frame: Option<Vec<u8>>,
+    ) -> Option<Relayed> {
+        let raw = |frame: Option<Vec<u8>>| frame.map(|frame| Relayed::Raw(frame, None));
+        match header.opcode {
+            OPCODE_CLOSE | OPCODE_PING | OPCODE_PONG => {
+                let message = self.message(header.opcode, payload, false);
+                return Some(Relayed::Message(message, frame.unwrap_or_default()));
+            }
+            OPCODE_TEXT | OPCODE_BINARY => {
+                self.opcode = header.opcode;
+                self.compressed = header.rsv1 && self.deflate.is_some();
+                self.fragments = Some(payload);
+                self.frames.clear();
+                self.passthrough = false;
+            }
+            OPCODE_CONTINUATION if self.passthrough => {
+                self.passthrough = !header.fin;
+                return raw(frame);
+            }
+            OPCODE_CONTINUATION => match self.fragments.as_mut() {
+                Some(fragments) => fragments.extend_from_slice(&payload),
+                None => return raw(frame),
+            },
+            _ => return raw(frame),
+        }
+        if let Some(frame) = frame {
+            self.frames.extend_from_slice(&frame);
+        }
+
+        if !header.fin {
+            return None;
+        }
+
+        let payload = self.fragments.take()?;
+        let frames = std::mem::take(&mut self.frames);
+        if !self.compressed {
+            return Some(Relayed::Message(
+                self.message(self.opcode, payload, false),
+                frames,
+            ));
+        }
+
+        match self.inflate(&payload) {
+            Ok(inflated) => Some(Relayed::Message(
+                self.message(self.opcode, inflated, true),
+                frames,
+            )),
+            // the context is lost, keep what was on the wire
+            Err(_) => Some(Relayed::Raw(
+                frames,
+                Some(self.message(self.opcode, payload, true)),
+            )),
+        }
+    }
+
+    fn message(&self, opcode: u8, payload: Vec<u8>, compressed: bool) -> WsMessage {
+        let kind = match opcode {
+            OPCODE_TEXT => WsMessageKind::Text,
+            OPCODE_CLOSE => WsMessageKind::Close,
+            OPCODE_PING => WsMessageKind::Ping,
+            OPCODE_PONG => WsMessageKind::Pong,
+            _ => WsMessageKind::Binary,
+        };
+
+        WsMessage {
+            direction: self.direction,
+            kind,
+            payload,
+            compressed,
+            injected: false,
+            timestamp: now_millis(),
+        }
+    }
+
+    fn inflate(&mut self, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let mut inflater = self
+            .inflater
+            .take()
+            .unwrap_or_else(|| Decompress::new(false));
+        let result = inflate_message(&mut inflater, payload);
+        if self.shares_context() && result.is_ok() {
+            self.inflater = Some(inflater);
+        }
+
+        result
+    }
+}
+
+fn inflate_message(inflater: &mut Decompress, payload: &[u8]) -> io::Result<Vec<u8>> {
+    let input = [payload, &DEFLATE_TAIL].concat();
+    let mut output = Vec::with_capacity(input.len() * 4);
+    let mut consumed = 0;
+
+    loop {
+        if output.len() == output.capacity() {
+            output.reserve(output.capacity().max(1024));
+        }
+
+        let total_in = inflater.total_in();
+        let status = inflater
+            .decompress_vec(&input[consumed..], &mut output, FlushDecompress::Sync)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        consumed += (inflater.total_in() - total_in) as usize;
+
+        if output.len() as u64 > MAX_RECORDED_FRAME {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Inflated message too large",
+            ));
+        }
+
+        let done = consumed >= input.len() && output.len() < output.capacity();
+        if done || status == Status::StreamEnd {
+            return Ok(output);
+        }
+        if status == Status::BufError && output.len() < output.capacity() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Truncated deflate stream",
+            ));
+        }
+    }
+}
+
+struct WsSender {
+    to_server: UnboundedSender<WsMessage>,
+    to_client: UnboundedSender<WsMessage>,
+}
+
+/// Open WebSocket connections that messages can be injected into.
+#[derive(Default)]
+pub struct WsInjector {
+    sessions: Mutex<HashMap<FlowId, WsSender>>,
+}
+
+impl WsInjector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sessions(&self) -> Vec<FlowId> {
+        let mut ids: Vec<_> = self.sessions.lock().unwrap().keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn inject(
+        &self,
+        id: FlowId,
+        direction: WsDirection,
+        kind: WsMessageKind,
+        payload: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let sessions = self.sessions.lock().unwrap();
+        let sender = sessions
+            .get(&id)
+            .ok_or_else(|| anyhow!("No open WebSocket for flow {}", id))?;
+
+        let message = WsMessage {
+            direction,
+            kind,
+            payload,
+            compressed: false,
+            injected: true,
+            timestamp: now_millis(),
+        };
+        let sender = match direction {
+            WsDirection::ClientToServer => &sender.to_server,
+            WsDirection::ServerToClient => &sender.to_client,
+        };
+        sender
+            .send(message)
+            .map_err(|_| anyhow!("WebSocket for flow {} is closing", id))
+    }
+
+    pub(super) fn register(&self, id: FlowId) -> WsSession<'_> {
+        let (to_server, to_server_rx) = unbounded_channel();
+        let (to_client, to_client_rx) = unbounded_channel();
+        self.sessions.lock().unwrap().insert(
+            id,
+            WsSender {
+                to_server,
+                to_client,
+            },
+        );
+
+        WsSession {
+            injector: self,
+            id,
+            to_server: Some(to_server_rx),
+            to_client: Some(to_client_rx),
+        }
+    }
+}
+
+/// Unregisters the connection once the bridge is done.
+pub(super) struct WsSession<'a> {
+    injector: &'a WsInjector,
+    id: FlowId,
+    pub to_server: Option<UnboundedReceiver<WsMessage>>,
+    pub to_client: Option<UnboundedReceiver<WsMessage>>,
+}
+
+impl Drop for WsSession<'_> {
+    fn drop(&mut self) {
+        self.injector.sessions.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Passes every message through the WebSocket rules of the connection and
+/// then the handler.
+pub(super) struct WsRewriter<'a, H> {
+    pub rules: Vec<Rule>,
+    pub handler: Option<(&'a H, &'a Uri)>,
+    /// Where the rules that act are recorded.
+    pub flow: Option<&'a (Arc<FlowStore>, FlowId)>,
+}
+
+impl<H: HttpHandler + Sync> WsRewriter<'_, H> {
+    async fn apply(&self, message: WsMessage) -> io::Result<Option<WsMessage>> {
+        let action = find_ws_action(&self.rules, &message);
+        if let (Some(action), Some((store, id))) = (action, self.flow) {
+            store.add_rule_hit(*id, action.clone());
+        }
+        let Some(message) = apply_ws_action(action, message) else {
+            return Ok(None);
+        };
+        let Some((handler, uri)) = self.handler else {
+            return Ok(Some(message));
+        };
+        handler.handle_ws_message(uri, message).await.map_err(|e| {
+            error!("Failed to handle WebSocket message: {}", e);
+            io::Error::other(e)
+        })
+    }
+}
+
+/// Relays messages between both upgraded connections while recording them
+/// on the flow. Without a rewriter the bytes go through untouched. With one
+/// every message is passed to it, the ones it changes are re-encoded
+/// uncompressed and the rest keep their frames. Messages too large to
+/// record or that can't be inflated go through as they are.
+///
+/// The proxy negotiates permessage-deflate without context takeover for
+/// rewritten connections. Should a peer keep its context anyway, compressed
+/// messages of that direction are forwarded unchanged, as the ones after
+/// them can only be inflated by a receiver that saw them.
+pub(super) async fn bridge<H: HttpHandler + Sync>(
+    client: Upgraded,
+    upstream: Upgraded,
+    deflate: Option<DeflateParams>,
+    rewriter: Option<&WsRewriter<'_, H>>,
+    flow: Option<&(Arc<FlowStore>, FlowId)>,
+    injector: Option<&WsInjector>,
+) -> io::Result<()> {
+    let (client_read, client_write) = tokio::io::split(TokioIo::new(client));
+    let (upstream_read, upstream_write) = tokio::io::split(TokioIo::new(upstream));
+
+    let mut session = injector.zip(flow).map(|(i, (_, id))| i.register(*id));
+    let (to_server, to_client) = match session.as_mut() {
+        Some(session) => (session.to_server.take(), session.to_client.take()),
+        None => (None, None),
+    };
+    let assembler = |direction| {
+        let assembler = MessageAssembler::new(direction, deflate);
+        match rewriter {
+            Some(_) => assembler.relaying(),
+            None => assembler,
+        }
+    };
+
+    tokio::try_join!(
+        pump(
+            client_read,
+            upstream_write,
+            assembler(WsDirection::ClientToServer),
+            to_server,
+            rewriter,
+            flow,
+        ),
+        pump(
+            upstream_read,
+            client_write,
+            assembler(WsDirection::ServerToClient),
+            to_client,
+            rewriter,
+            flow,
+        ),
+    )?;
+
+    Ok(())
+}
+
+pub(super) async fn pump<R, W, H>(
+    mut reader: R,
+    mut writer: W,
+    mut assembler: MessageAssembler,
+    mut injected: Option<UnboundedReceiver<WsMessage>>,
+    rewriter: Option<&WsRewriter<'_, H>>,
+    flow: Option<&(Arc<FlowStore>, FlowId)>,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    H: HttpHandler + Sync,
+{
+    // frames sent by the client have to be masked
+    let direction = assembler.direction;
+    let mask = || (direction == WsDirection::ClientToServer).then(rand::random);
+    let record = |message: WsMessage| {
+        if let Some((store, id)) = flow {
+            store.push_ws_message(*id, message);
+        }
+    };
+
+    let mut buf = vec![0u8; 16 * 1024];
+    let mut queued = VecDeque::new();
+
+    loop {
+        tokio::select! {
+            n = reader.read(&mut buf) => {
+                let n = n?;
+                if n == 0 {
+                    writer.shutdown().await?;
+                    return Ok(());
+                }
+
+                match rewriter {
+                    None => {
+                        writer.write_all(&buf[..n]).await?;
+                        assembler.feed(&buf[..n]).into_iter().for_each(record);
+                    }
+                    Some(rewriter) => {
+                        for relayed in assembler.relay(&buf[..n]) {
+                            let (message, frames) = match relayed {
+                                Relayed::Message(message, frames) => (message, frames),
+                                Relayed::Raw(frames, message) => {
+                                    writer.write_all(&frames).await?;
+                                    if let Some(message) = message {
+                                        record(message);
+                                    }
+                                    continue;
+                                }
+                            };
+
+                            let original = message.clone();
+                            let rewritten = rewriter.apply(message).await?;
+                            let unchanged = rewritten.as_ref().is_some_and(|message| {
+                                message.kind == original.kind
+                                    && message.payload == original.payload
+                            });
+                            match rewritten {
+                                Some(message) if unchanged => {
+                                    writer.write_all(&frames).await?;
+                                    record(message);
+                                }
+                                _ if original.compressed && assembler.shares_context() => {
+                                    warn!("Forwarding a compressed WebSocket message as it is, the peer keeps its context");
+                                    writer.write_all(&frames).await?;
+                                    record(original);
+                                }
+                                Some(message) => {
+                                    writer.write_all(&encode_message(&message, mask())).await?;
+                                    record(message);
+                                }
+                                None => {}
+                            }
+                        }
+                    }
+                }
+
+                if assembler.can_interleave() {
+                    while let Some(message) = queued.pop_front() {
+                        writer.write_all(&encode_message(&message, mask())).await?;
+                        record(message);
+                    }
+                }
+            }
+            Some(message) = recv_injected(&mut injected) => {
+                if assembler.can_interleave() {
+                    writer.write_all(&encode_message(&message, mask())).await?;
+                    record(message);
+                } else {
+                    queued.push_back(message);
+                }
+            }
+        }
+    }
+}
+
+async fn recv_injected(rx: &mut Option<UnboundedReceiver<WsMessage>>) -> Option<WsMessage> {
+    match rx {
+        Some(rx) => match rx.recv().await {
+            Some(message) => Some(message),
+            None => pending().await,
+        },
+        None => pending().await,
+    }
+}
@@ -0,0 +1,346 @@
+use flate2::{Compress, Compression, FlushCompress};
+use hyper::{header::HeaderValue, HeaderMap, Request};
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+use super::{
+    apply_mask, encode_message, is_websocket_upgrade, offer_no_context_takeover, pump,
+    require_client_no_context_takeover, DeflateParams, FrameHeader, HttpHandler, MessageAssembler,
+    Relayed, RequestMatcher, Rule, RuleAction, WsDirection, WsInjector, WsMessage, WsMessageKind,
+    WsRewriter,
+};
+
+struct PassThrough;
+
+impl HttpHandler for PassThrough {}
+
+fn frame(fin: bool, rsv1: bool, opcode: u8, mask: Option<[u8; 4]>, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![(fin as u8) << 7 | (rsv1 as u8) << 6 | opcode];
+    let mask_bit = (mask.is_some() as u8) << 7;
+
+    match payload.len() {
+        len if len < 126 => frame.push(mask_bit | len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(mask_bit | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(mask_bit | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+
+    let mut payload = payload.to_vec();
+    if let Some(mask) = mask {
+        frame.extend_from_slice(&mask);
+        apply_mask(&mut payload, mask);
+    }
+    frame.extend_from_slice(&payload);
+    frame
+}
+
+fn deflate(compress: &mut Compress, data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() + 64);
+    compress
+        .compress_vec(data, &mut output, FlushCompress::Sync)
+        .unwrap();
+    assert!(output.ends_with(&[0x00, 0x00, 0xff, 0xff]));
+    output.truncate(output.len() - 4);
+    output
+}
+
+#[test]
+fn test_frame_header() {
+    let data = frame(true, false, 0x2, Some([1, 2, 3, 4]), &[0u8; 300]);
+    let header = FrameHeader::parse(&data).unwrap();
+
+    assert_eq!(header.opcode, 0x2);
+    assert_eq!(header.payload_len, 300);
+    assert_eq!(header.header_len, 8);
+    assert_eq!(header.mask, Some([1, 2, 3, 4]));
+    assert!(FrameHeader::parse(&data[..3]).is_none());
+}
+
+#[test]
+fn test_fragmented_masked_message() {
+    let mut data = frame(false, false, 0x1, Some([9, 8, 7, 6]), b"hello ");
+    data.extend(frame(true, false, 0x9, Some([1, 1, 1, 1]), b"ping"));
+    data.extend(frame(true, false, 0x0, Some([5, 5, 5, 5]), b"devya"));
+
+    let mut assembler = MessageAssembler::new(WsDirection::ClientToServer, None);
+    let mut messages = vec![];
+    // feed byte by byte to exercise partial frames
+    for byte in &data {
+        messages.extend(assembler.feed(&[*byte]));
+    }
+
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].kind, WsMessageKind::Ping);
+    assert_eq!(messages[1].kind, WsMessageKind::Text);
+    assert_eq!(messages[1].payload, b"hello devya");
+}
+
+#[test]
+fn test_relay_keeps_frames() {
+    let first = frame(false, false, 0x1, Some([9, 8, 7, 6]), b"hello ");
+    let ping = frame(true, false, 0x9, Some([1, 1, 1, 1]), b"ping");
+    let last = frame(true, false, 0x0, Some([5, 5, 5, 5]), b"devya");
+
+    let mut assembler = MessageAssembler::new(WsDirection::ClientToServer, None).relaying();
+    let mut relayed = assembler.relay(&[first.clone(), ping.clone()].concat());
+    // fragments are held back, the ping in between is not
+    assert!(assembler.can_interleave());
+    relayed.extend(assembler.relay(&last));
+
+    let [Relayed::Message(control, control_frames), Relayed::Message(text, text_frames)] =
+        &relayed[..]
+    else {
+        panic!("expected two messages");
+    };
+    assert_eq!(control.kind, WsMessageKind::Ping);
+    assert_eq!(control_frames, &ping);
+    assert_eq!(text.payload, b"hello devya");
+    assert_eq!(text_frames, &[first, last].concat());
+}
+
+#[test]
+fn test_relay_passes_oversized_messages() {
+    let mut data = frame(false, false, 0x2, None, &vec![7; 16 * 1024 * 1024 + 1]);
+    data.extend(frame(true, false, 0x0, None, b"tail"));
+    let passed = data.len();
+    data.extend(frame(true, false, 0x1, None, b"next"));
+
+    let mut assembler = MessageAssembler::new(WsDirection::ServerToClient, None).relaying();
+    let mut raw = vec![];
+    let mut messages = vec![];
+    for chunk in data.chunks(64 * 1024) {
+        for relayed in assembler.relay(chunk) {
+            match relayed {
+                Relayed::Raw(frames, message) => {
+                    assert!(message.is_none());
+                    raw.extend(frames);
+                }
+                Relayed::Message(message, _) => messages.push(message),
+            }
+        }
+        if raw.len() < passed {
+            assert!(!assembler.can_interleave());
+        }
+    }
+
+    assert_eq!(raw, data[..passed]);
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].payload, b"next");
+    assert!(assembler.can_interleave());
+}
+
+#[test]
+fn test_relay_passes_undecodable_messages() {
+    let data = frame(true, true, 0x1, None, &[0xff; 8]);
+
+    let mut assembler =
+        MessageAssembler::new(WsDirection::ServerToClient, Some(DeflateParams::default()))
+            .relaying();
+    let relayed = assembler.relay(&data);
+
+    let [Relayed::Raw(frames, Some(message))] = &relayed[..] else {
+        panic!("expected the frames as they are");
+    };
+    assert_eq!(frames, &data);
+    assert!(message.compressed);
+    assert_eq!(message.payload, [0xff; 8]);
+}
+
+#[test]
+fn test_permessage_deflate_context_takeover() {
+    let mut compress = Compress::new(Compression::default(), false);
+    let first = deflate(&mut compress, b"{\"event\":\"tick\",\"value\":1}");
+    let second = deflate(&mut compress, b"{\"event\":\"tick\",\"value\":2}");
+
+    let mut data = frame(true, true, 0x1, None, &first);
+    data.extend(frame(true, true, 0x1, None, &second));
+
+    let mut assembler =
+        MessageAssembler::new(WsDirection::ServerToClient, Some(DeflateParams::default()));
+    let messages = assembler.feed(&data);
+
+    assert_eq!(messages.len(), 2);
+    assert!(messages.iter().all(|m| m.compressed));
+    assert_eq!(messages[1].payload, b"{\"event\":\"tick\",\"value\":2}");
+}
+
+#[test]
+fn test_negotiated_deflate() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "sec-websocket-extensions",
+        HeaderValue::from_static("permessage-deflate; client_no_context_takeover"),
+    );
+
+    assert_eq!(
+        DeflateParams::negotiated(&headers),
+        Some(DeflateParams {
+            client_no_context_takeover: true,
+            server_no_context_takeover: false,
+        })
+    );
+    assert_eq!(DeflateParams::negotiated(&HeaderMap::new()), None);
+}
+
+#[test]
+fn test_no_context_takeover() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "sec-websocket-extensions",
+        HeaderValue::from_static(
+            "permessage-deflate; client_max_window_bits, x-webkit-deflate-frame",
+        ),
+    );
+    offer_no_context_takeover(&mut headers);
+    assert_eq!(
+        headers["sec-websocket-extensions"],
+        "permessage-deflate; client_max_window_bits; client_no_context_takeover; server_no_context_takeover, x-webkit-deflate-frame"
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "sec-websocket-extensions",
+        HeaderValue::from_static("permessage-deflate; server_no_context_takeover"),
+    );
+    require_client_no_context_takeover(&mut headers);
+    assert_eq!(
+        DeflateParams::negotiated(&headers),
+        Some(DeflateParams {
+            client_no_context_takeover: true,
+            server_no_context_takeover: true,
+        })
+    );
+}
+
+/// Relays two compressed messages from the server through a relay that
+/// drops the first, and returns what the client can make of the rest.
+async fn relay_compressed(params: DeflateParams) -> Vec<WsMessage> {
+    let mut compress = Compress::new(Compression::default(), false);
+    let first = deflate(&mut compress, b"{\"event\":\"tick\",\"value\":1}");
+    if params.server_no_context_takeover {
+        compress.reset();
+    }
+    let second = deflate(&mut compress, b"{\"event\":\"tick\",\"value\":2}");
+
+    let rewriter = WsRewriter::<PassThrough> {
+        rules: vec![Rule {
+            matcher: RequestMatcher::default(),
+            action: RuleAction::WsMessage {
+                direction: None,
+                find: "\"value\":1".to_string(),
+                replace: None,
+            },
+            probability: 1.0,
+        }],
+        handler: None,
+        flow: None,
+    };
+    let (mut server, relay_in) = duplex(64 * 1024);
+    let (relay_out, mut client) = duplex(64 * 1024);
+    server
+        .write_all(&frame(true, true, 0x1, None, &first))
+        .await
+        .unwrap();
+    server
+        .write_all(&frame(true, true, 0x1, None, &second))
+        .await
+        .unwrap();
+    drop(server);
+
+    let assembler = MessageAssembler::new(WsDirection::ServerToClient, Some(params)).relaying();
+    pump(relay_in, relay_out, assembler, None, Some(&rewriter), None)
+        .await
+        .unwrap();
+
+    let mut received = vec![];
+    client.read_to_end(&mut received).await.unwrap();
+    MessageAssembler::new(WsDirection::ServerToClient, Some(params)).feed(&received)
+}
+
+#[tokio::test]
+async fn test_relay_without_context_takeover() {
+    let messages = relay_compressed(DeflateParams {
+        client_no_context_takeover: true,
+        server_no_context_takeover: true,
+    })
+    .await;
+
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].payload, b"{\"event\":\"tick\",\"value\":2}");
+}
+
+#[tokio::test]
+async fn test_relay_keeps_shared_context() {
+    // dropping the first message would leave the second undecodable
+    let messages = relay_compressed(DeflateParams::default()).await;
+
+    assert_eq!(messages.len(), 2);
+    assert!(messages.iter().all(|m| m.compressed));
+    assert_eq!(messages[1].payload, b"{\"event\":\"tick\",\"value\":2}");
+}
+
+#[test]
+fn test_websocket_upgrade() {
+    let req = Request::builder()
+        .header("connection", "keep-alive, Upgrade")
+        .header("upgrade", "websocket")
+        .body(())
+        .unwrap();
+    assert!(is_websocket_upgrade(&req));
+
+    let req = Request::builder()
+        .header("upgrade", "websocket")
+        .body(())
+        .unwrap();
+    assert!(!is_websocket_upgrade(&req));
+}
+
+#[test]
+fn test_encode_message_round_trip() {
+    let message = WsMessage {
+        direction: WsDirection::ClientToServer,
+        kind: WsMessageKind::Binary,
+        payload: (0..=255).cycle().take(70_000).collect(),
+        compressed: false,
+        injected: false,
+        timestamp: 0,
+    };
+    let frame = encode_message(&message, Some([0xde, 0xad, 0xbe, 0xef]));
+
+    let mut assembler = MessageAssembler::new(WsDirection::ClientToServer, None);
+    let messages = assembler.feed(&frame);
+
+    assert!(assembler.at_boundary());
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].payload, message.payload);
+}
+
+#[test]
+fn test_injector_sessions() {
+    let injector = WsInjector::new();
+    assert!(injector
+        .inject(1, WsDirection::ServerToClient, WsMessageKind::Text, vec![])
+        .is_err());
+
+    let mut session = injector.register(1);
+    injector
+        .inject(
+            1,
+            WsDirection::ServerToClient,
+            WsMessageKind::Text,
+            b"hi".to_vec(),
+        )
+        .unwrap();
+    assert_eq!(injector.sessions(), vec![1]);
+
+    let message = session.to_client.as_mut().unwrap().try_recv().unwrap();
+    assert!(message.injected);
+    assert_eq!(message.payload, b"hi");
+
+    drop(session);
+    assert!(injector.sessions().is_empty());
+}
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+
+use hyper::{StatusCode, Uri};
+
+use super::TnetValue;
+use crate::mitm::{
+    BodyCapture, Connection, ConnectionId, Flow, FlowKind, WsDirection, WsMessage, WsMessageKind,
+};
+
+/// The flow format of mitmproxy 11, older releases can't read it but newer
+/// ones migrate it on load.
+pub const MITMPROXY_FLOW_VERSION: i64 = 21;
+
+/// A `.flows` dump of the HTTP and WebSocket flows, tunnels have no
+/// counterpart and are left out. `connections` fills in the client side,
+/// flows without a known connection get an anonymous one.
+pub fn export_mitmproxy(flows: &[Flow], connections: &[Connection]) -> Vec<u8> {
+    let connections: HashMap<ConnectionId, &Connection> = connections
+        .iter()
+        .map(|connection| (connection.id, connection))
+        .collect();
+    // flows of one connection share its id like they do in mitmproxy
+    let mut connection_ids = HashMap::new();
+
+    let mut out = vec![];
+    for flow in flows.iter().filter(|flow| flow.kind == FlowKind::Http) {
+        let connection = flow
+            .connection_id
+            .and_then(|id| connections.get(&id).copied());
+        let client_id = match flow.connection_id {
+            Some(id) => connection_ids.entry(id).or_insert_with(uuid).clone(),
+            None => uuid(),
+        };
+        mitmproxy_state(flow, connection, client_id).dump(&mut out);
+    }
+    out
+}
+
+pub fn mitmproxy_state(
+    flow: &Flow,
+    connection: Option<&Connection>,
+    client_id: String,
+) -> TnetValue {
+    let target = Target::of(flow);
+    let tls = target.scheme == "https" || target.scheme == "wss";
+
+    TnetValue::dict([
+        ("id", TnetValue::String(uuid())),
+        ("type", TnetValue::String("http".to_string())),
+        ("version", TnetValue::Int(MITMPROXY_FLOW_VERSION)),
+        ("timestamp_created", seconds(flow.request.timestamp)),
+        (
+            "error",
+            match &flow.error {
+                Some(message) => TnetValue::dict([
+                    ("msg", TnetValue::String(message.clone())),
+                    (
+                        "timestamp",
+                        seconds(flow.finished_at.unwrap_or(flow.request.timestamp)),
+                    ),
+                ]),
+                None => TnetValue::Null,
+            },
+        ),
+        ("client_conn", client_conn(flow, connection, client_id, tls)),
+        ("server_conn", server_conn(flow, &target, tls)),
+        ("intercepted", TnetValue::Bool(false)),
+        (
+            "is_replay",
+            match flow.replay_of {
+                Some(_) => TnetValue::String("request".to_string()),
+                None => TnetValue::Null,
+            },
+        ),
+        ("marked", TnetValue::String(String::new())),
+        ("metadata", TnetValue::Dict(vec![])),
+        ("comment", TnetValue::String(String::new())),
+        ("request", request(flow, &target)),
+        ("response", response(flow)),
+        ("websocket", websocket(flow)),
+    ])
+}
+
+/// Where a request went, split up the way mitmproxy keeps it.
+struct Target {
+    scheme: String,
+    host: String,
+    port: u16,
+    authority: String,
+    path: String,
+}
+
+impl Target {
+    fn of(flow: &Flow) -> Self {
+        let request = &flow.request;
+        let uri: Uri = request.uri.parse().unwrap_or_default();
+        let scheme = uri.scheme_str().unwrap_or("http").to_ascii_lowercase();
+        let host_header = request
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("host"))
+            .and_then(|(_, value)| value.parse::<Uri>().ok());
+        let authority = uri
+            .authority()
+            .or(host_header.as_ref().and_then(|host| host.authority()));
+
+        let default_port = match scheme.as_str() {
+            "https" | "wss" => 443,
+            _ => 80,
+        };
+        Self {
+            host: authority.map_or("", |a| a.host()).to_string(),
+            port: authority.and_then(|a| a.port_u16()).unwrap_or(default_port),
+            // only HTTP/2 requests carry one, it is `:authority`
+            authority: match (request.version.as_str(), authority) {
+                ("HTTP/2.0", Some(authority)) => authority.to_string(),
+                _ => String::new(),
+            },
+            path: uri
+                .path_and_query()
+                .map_or("/".to_string(), |path| path.to_string()),
+            scheme,
+        }
+    }
+}
+
+fn client_conn(flow: &Flow, connection: Option<&Connection>, id: String, tls: bool) -> TnetValue {
+    let peername = connection
+        .and_then(|connection| connection.client_addr.parse::<std::net::SocketAddr>().ok())
+        .map_or_else(
+            || address("0.0.0.0", 0),
+            |addr| address(&addr.ip().to_string(), addr.port()),
+        );
+
+    TnetValue::dict([
+        ("id", TnetValue::String(id)),
+        ("peername", peername),
+        ("sockname", address("0.0.0.0", 0)),
+        ("error", TnetValue::Null),
+        ("tls", TnetValue::Bool(tls)),
+        ("certificate_list", TnetValue::List(vec![])),
+        (
+            "alpn",
+            connection
+                .and_then(|connection| connection.alpn.clone())
+                .map_or(TnetValue::Null, |alpn| TnetValue::Bytes(alpn.into_bytes())),
+        ),
+        ("alpn_offers", TnetValue::List(vec![])),
+        (
+            "cipher",
+            connection
+                .and_then(|connection| connection.cipher.clone())
+                .map_or(TnetValue::Null, TnetValue::String),
+        ),
+        ("cipher_list", TnetValue::List(vec![])),
+        (
+            "tls_version",
+            connection
+                .and_then(|connection| connection.tls_version.clone())
+                .map_or(TnetValue::Null, TnetValue::String),
+        ),
+        (
+            "sni",
+            connection
+                .and_then(|connection| connection.server_name.clone())
+                .map_or(TnetValue::Null, TnetValue::String),
+        ),
+        (
+            "timestamp_start",
+            seconds(connection.map_or(flow.request.timestamp, |c| c.opened_at)),
+        ),
+        (
+            "timestamp_end",
+            optional_seconds(connection.and_then(|c| c.closed_at)),
+        ),
+        ("timestamp_tls_setup", TnetValue::Null),
+        ("transport_protocol", TnetValue::String("tcp".to_string())),
+        ("mitmcert", TnetValue::Null),
+        ("proxy_mode", TnetValue::String("regular".to_string())),
+    ])
+}
+
+fn server_conn(flow: &Flow, target: &Target, tls: bool) -> TnetValue {
+    let alpn = match flow.request.version.as_str() {
+        "HTTP/2.0" => TnetValue::Bytes(b"h2".to_vec()),
+        _ if tls => TnetValue::Bytes(b"http/1.1".to_vec()),
+        _ => TnetValue::Null,
+    };
+
+    TnetValue::dict([
+        ("id", TnetValue::String(uuid())),
+        ("peername", TnetValue::Null),
+        ("sockname", TnetValue::Null),
+        ("error", TnetValue::Null),
+        ("tls", TnetValue::Bool(tls)),
+        ("certificate_list", TnetValue::List(vec![])),
+        ("alpn", alpn),
+        ("alpn_offers", TnetValue::List(vec![])),
+        ("cipher", TnetValue::Null),
+        ("cipher_list", TnetValue::List(vec![])),
+        ("tls_version", TnetValue::Null),
+        (
+            "sni",
+            if tls {
+                TnetValue::String(target.host.clone())
+            } else {
+                TnetValue::Null
+            },
+        ),
+        ("timestamp_start", seconds(flow.request.timestamp)),
+        ("timestamp_end", optional_seconds(flow.finished_at)),
+        ("timestamp_tls_setup", TnetValue::Null),
+        ("transport_protocol", TnetValue::String("tcp".to_string())),
+        ("address", address(&target.host, target.port)),
+        ("timestamp_tcp_setup", TnetValue::Null),
+        ("via", TnetValue::Null),
+    ])
+}
+
+fn request(flow: &Flow, target: &Target) -> TnetValue {
+    let request = &flow.request;
+    TnetValue::dict([
+        ("host", TnetValue::String(target.host.clone())),
+        ("port", TnetValue::Int(target.port.into())),
+        (
+            "method",
+            TnetValue::Bytes(request.method.clone().into_bytes()),
+        ),
+        (
+            "scheme",
+            TnetValue::Bytes(target.scheme.clone().into_bytes()),
+        ),
+        (
+            "authority",
+            TnetValue::Bytes(target.authority.clone().into_bytes()),
+        ),
+        ("path", TnetValue::Bytes(target.path.clone().into_bytes())),
+        (
+            "http_version",
+            TnetValue::Bytes(request.version.clone().into_bytes()),
+        ),
+        ("headers", headers(&request.headers)),
+        ("content", content(flow.request_body.as_ref())),
+        ("trailers", trailers(flow.request_body.as_ref())),
+        ("timestamp_start", seconds(request.timestamp)),
+        ("timestamp_end", seconds(request.timestamp)),
+    ])
+}
+
+fn response(flow: &Flow) -> TnetValue {
+    let Some(response) = &flow.response else {
+        return TnetValue::Null;
+    };
+    let reason = StatusCode::from_u16(response.status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or_default();
+    // an upgraded response ends with its head, the messages come after
+    let end = match flow.websocket {
+        Some(_) => Some(response.timestamp),
+        None => flow.finished_at,
+    };
+
+    TnetValue::dict([
+        (
+            "http_version",
+            TnetValue::Bytes(response.version.clone().into_bytes()),
+        ),
+        ("status_code", TnetValue::Int(response.status.into())),
+        ("reason", TnetValue::Bytes(reason.as_bytes().to_vec())),
+        ("headers", headers(&response.headers)),
+        ("content", content(flow.response_body.as_ref())),
+        ("trailers", trailers(flow.response_body.as_ref())),
+        ("timestamp_start", seconds(response.timestamp)),
+        ("timestamp_end", optional_seconds(end)),
+    ])
+}
+
+/// mitmproxy only lists data messages, a close frame becomes the close
+/// code and reason and pings are dropped.
+fn websocket(flow: &Flow) -> TnetValue {
+    let Some(messages) = &flow.websocket else {
+        return TnetValue::Null;
+    };
+
+    let mut close: Option<&WsMessage> = None;
+    let mut data = vec![];
+    for message in messages {
+        let opcode = match message.kind {
+            WsMessageKind::Text => 1,
+            WsMessageKind::Binary => 2,
+            WsMessageKind::Close => {
+                close = close.or(Some(message));
+                continue;
+            }
+            WsMessageKind::Ping | WsMessageKind::Pong => continue,
+        };
+        data.push(TnetValue::List(vec![
+            TnetValue::Int(opcode),
+            TnetValue::Bool(message.direction == WsDirection::ClientToServer),
+            TnetValue::Bytes(message.payload.clone()),
+            seconds(message.timestamp),
+            TnetValue::Bool(false),
+            TnetValue::Bool(message.injected),
+        ]));
+    }
+
+    let (code, reason) = match close.map(|message| message.payload.as_slice()) {
+        Some([high, low, reason @ ..]) => (
+            TnetValue::Int(u16::from_be_bytes([*high, *low]).into()),
+            TnetValue::String(String::from_utf8_lossy(reason).into_owned()),
+        ),
+        // a close frame without a body means 1005, no status received
+        Some(_) => (TnetValue::Int(1005), TnetValue::String(String::new())),
+        None => (TnetValue::Null, TnetValue::Null),
+    };
+
+    TnetValue::dict([
+        ("messages", TnetValue::List(data)),
+        (
+            "closed_by_client",
+            close.map_or(TnetValue::Null, |message| {
+                TnetValue::Bool(message.direction == WsDirection::ClientToServer)
+            }),
+        ),
+        ("close_code", code),
+        ("close_reason", reason),
+        ("timestamp_end", optional_seconds(flow.finished_at)),
+    ])
+}
+
+fn headers(headers: &[(String, String)]) -> TnetValue {
+    TnetValue::List(
+        headers
+            .iter()
+            .map(|(name, value)| {
+                TnetValue::List(vec![
+                    TnetValue::Bytes(name.clone().into_bytes()),
+                    TnetValue::Bytes(value.clone().into_bytes()),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// mitmproxy has no notion of a partial body, a truncated capture is
+/// written as missing content.
+fn content(body: Option<&BodyCapture>) -> TnetValue {
+    match body {
+        Some(body) if !body.truncated => TnetValue::Bytes(body.data.clone()),
+        Some(_) => TnetValue::Null,
+        None => TnetValue::Bytes(vec![]),
+    }
+}
+
+fn trailers(body: Option<&BodyCapture>) -> TnetValue {
+    match body.and_then(|body| body.trailers.as_ref()) {
+        Some(trailers) => headers(trailers),
+        None => TnetValue::Null,
+    }
+}
+
+fn address(host: &str, port: u16) -> TnetValue {
+    TnetValue::List(vec![
+        TnetValue::String(host.to_string()),
+        TnetValue::Int(port.into()),
+    ])
+}
+
+fn seconds(millis: u64) -> TnetValue {
+    TnetValue::Float(millis as f64 / 1000.0)
+}
+
+fn optional_seconds(millis: Option<u64>) -> TnetValue {
+    millis.map_or(TnetValue::Null, seconds)
+}
+
+/// mitmproxy identifies flows and connections by UUID.
+fn uuid() -> String {
+    // version 4, variant 1
+    let bits = (rand::random::<u128>() & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+    let hex = format!("{:032x}", bits);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
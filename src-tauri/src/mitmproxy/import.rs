@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context};
+
+use super::TnetValue;
+use crate::mitm::{
+    BodyCapture, Connection, ConnectionId, Flow, FlowId, FlowKind, FlowRequest, FlowResponse,
+    FlowStore, FlowTimings, WsDirection, WsMessage, WsMessageKind,
+};
+
+/// What a `.flows` dump holds. Connection ids are only meaningful within
+/// the import until [`load_mitmproxy`] adds it to a store.
+#[derive(Debug, Default)]
+pub struct MitmproxyImport {
+    pub connections: Vec<Connection>,
+    pub flows: Vec<Flow>,
+}
+
+/// Reads the HTTP flows of a dump, WebSocket messages included. TCP, UDP
+/// and DNS flows have no counterpart and are skipped. Fields are looked up
+/// leniently so dumps of older mitmproxy releases load too.
+pub fn import_mitmproxy(mut data: &[u8]) -> anyhow::Result<MitmproxyImport> {
+    let mut import = MitmproxyImport::default();
+    let mut connection_ids: HashMap<String, ConnectionId> = HashMap::new();
+
+    while !data.is_empty() {
+        let (state, rest) = TnetValue::parse(data)?;
+        data = rest;
+
+        if state.get("type").and_then(TnetValue::as_str).as_deref() != Some("http") {
+            continue;
+        }
+        let mut flow = mitmproxy_flow(&state)?;
+
+        if let Some(client) = state.get("client_conn") {
+            let key = client.get("id").and_then(TnetValue::as_str);
+            let known = key.as_ref().and_then(|key| connection_ids.get(key));
+            let id = match known {
+                Some(&id) => id,
+                None => {
+                    let id = import.connections.len() as ConnectionId + 1;
+                    import.connections.push(connection(client, id));
+                    if let Some(key) = key {
+                        connection_ids.insert(key, id);
+                    }
+                    id
+                }
+            };
+            flow.connection_id = Some(id);
+        }
+        import.flows.push(flow);
+    }
+
+    Ok(import)
+}
+
+/// Imports a dump into `flow_store`, returning the new flow ids.
+pub fn load_mitmproxy(data: &[u8], flow_store: &FlowStore) -> anyhow::Result<Vec<FlowId>> {
+    let import = import_mitmproxy(data)?;
+    let connection_ids: HashMap<ConnectionId, ConnectionId> = import
+        .connections
+        .into_iter()
+        .map(|connection| (connection.id, flow_store.import_connection(connection)))
+        .collect();
+
+    Ok(import
+        .flows
+        .into_iter()
+        .map(|mut flow| {
+            flow.connection_id = flow
+                .connection_id
+                .and_then(|id| connection_ids.get(&id).copied());
+            flow_store.import(flow)
+        })
+        .collect())
+}
+
+pub fn mitmproxy_flow(state: &TnetValue) -> anyhow::Result<Flow> {
+    let request = state
+        .get("request")
+        .ok_or_else(|| anyhow!("mitmproxy flow has no request"))?;
+    let text = |value: &TnetValue, key: &str| value.get(key).and_then(TnetValue::as_str);
+
+    let method = text(request, "method").context("mitmproxy request has no method")?;
+    let scheme = text(request, "scheme").unwrap_or_else(|| "http".to_string());
+    let host = text(request, "host").unwrap_or_default();
+    let port = request.get("port").and_then(TnetValue::as_i64);
+    // IPv6 addresses are bracketed in URLs
+    let host = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host
+    };
+    let authority = match (scheme.as_str(), port) {
+        (_, None) | ("http", Some(80)) | ("https", Some(443)) => host,
+        (_, Some(port)) => format!("{}:{}", host, port),
+    };
+
+    let kind = if method.eq_ignore_ascii_case("CONNECT") {
+        FlowKind::Tunnel
+    } else {
+        FlowKind::Http
+    };
+    let uri = match kind {
+        FlowKind::Tunnel => authority,
+        _ => format!(
+            "{}://{}{}",
+            scheme,
+            authority,
+            text(request, "path").unwrap_or_else(|| "/".to_string())
+        ),
+    };
+
+    let mut flow = Flow {
+        id: 0,
+        kind,
+        imported: true,
+        replay_of: None,
+        connection_id: None,
+        request: FlowRequest {
+            method,
+            uri,
+            version: text(request, "http_version").unwrap_or_else(|| "HTTP/1.1".to_string()),
+            headers: headers(request.get("headers")),
+            raw_head: None,
+            timestamp: millis(request.get("timestamp_start")),
+        },
+        response: None,
+        request_body: body(request),
+        response_body: None,
+        events: None,
+        websocket: None,
+        tunnel: None,
+        rule_hits: vec![],
+        breakpoints: vec![],
+        timings: FlowTimings::default(),
+        error: state
+            .get("error")
+            .and_then(|error| error.get("msg"))
+            .and_then(TnetValue::as_str),
+        finished_at: None,
+    };
+
+    let response = state.get("response");
+    if let Some(response) = response {
+        flow.response = Some(FlowResponse {
+            status: response
+                .get("status_code")
+                .and_then(TnetValue::as_i64)
+                .and_then(|status| u16::try_from(status).ok())
+                .context("mitmproxy response has no status")?,
+            version: text(response, "http_version").unwrap_or_else(|| "HTTP/1.1".to_string()),
+            headers: headers(response.get("headers")),
+            timestamp: millis(response.get("timestamp_start")),
+        });
+        flow.response_body = body(response);
+    }
+
+    let websocket = state.get("websocket");
+    flow.websocket = websocket.map(websocket_messages);
+
+    flow.finished_at = websocket
+        .and_then(|ws| ws.get("timestamp_end"))
+        .or(response.and_then(|response| response.get("timestamp_end")))
+        .or(state.get("error").and_then(|error| error.get("timestamp")))
+        .map(|at| millis(Some(at)));
+    Ok(flow)
+}
+
+fn connection(client: &TnetValue, id: ConnectionId) -> Connection {
+    // `address` in older dumps
+    let peer = client
+        .get("peername")
+        .or(client.get("address"))
+        .and_then(TnetValue::as_list);
+    let client_addr = match peer {
+        Some([host, port, ..]) => {
+            let host = host.as_str().unwrap_or_default();
+            let port = port.as_i64().unwrap_or_default();
+            if host.contains(':') {
+                format!("[{}]:{}", host, port)
+            } else {
+                format!("{}:{}", host, port)
+            }
+        }
+        _ => String::new(),
+    };
+
+    Connection {
+        id,
+        client_addr,
+        server_name: client.get("sni").and_then(TnetValue::as_str),
+        alpn: client.get("alpn").and_then(TnetValue::as_str),
+        tls_version: client.get("tls_version").and_then(TnetValue::as_str),
+        cipher: client.get("cipher").and_then(TnetValue::as_str),
+        opened_at: millis(client.get("timestamp_start")),
+        closed_at: client.get("timestamp_end").map(|at| millis(Some(at))),
+    }
+}
+
+fn websocket_messages(websocket: &TnetValue) -> Vec<WsMessage> {
+    let direction = |from_client: bool| {
+        if from_client {
+            WsDirection::ClientToServer
+        } else {
+            WsDirection::ServerToClient
+        }
+    };
+
+    let entries = websocket
+        .get("messages")
+        .and_then(TnetValue::as_list)
+        .unwrap_or_default();
+    let mut messages: Vec<WsMessage> = entries
+        .iter()
+        .filter_map(TnetValue::as_list)
+        .filter_map(|entry| match entry {
+            [opcode, from_client, content, timestamp, rest @ ..] => Some(WsMessage {
+                direction: direction(from_client.as_bool().unwrap_or_default()),
+                kind: match opcode.as_i64() {
+                    Some(1) => WsMessageKind::Text,
+                    _ => WsMessageKind::Binary,
+                },
+                payload: content.as_bytes().unwrap_or_default().to_vec(),
+                compressed: false,
+                // after `dropped`
+                injected: rest.get(1).and_then(TnetValue::as_bool).unwrap_or_default(),
+                timestamp: millis(Some(timestamp)),
+            }),
+            _ => None,
+        })
+        .collect();
+
+    // mitmproxy keeps the close frame as code and reason
+    if let Some(code) = websocket.get("close_code").and_then(TnetValue::as_i64) {
+        let mut payload = vec![];
+        if code != 1005 {
+            payload.extend_from_slice(&(code as u16).to_be_bytes());
+            let reason = websocket.get("close_reason").and_then(TnetValue::as_str);
+            payload.extend_from_slice(reason.unwrap_or_default().as_bytes());
+        }
+        let closed_at = websocket.get("timestamp_end");
+        messages.push(WsMessage {
+            direction: direction(
+                websocket
+                    .get("closed_by_client")
+                    .and_then(TnetValue::as_bool)
+                    .unwrap_or_default(),
+            ),
+            kind: WsMessageKind::Close,
+            payload,
+            compressed: false,
+            injected: false,
+            timestamp: match closed_at {
+                Some(at) => millis(Some(at)),
+                None => messages.last().map_or(0, |message| message.timestamp),
+            },
+        });
+    }
+    messages
+}
+
+fn headers(headers: Option<&TnetValue>) -> Vec<(String, String)> {
+    headers
+        .and_then(TnetValue::as_list)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|header| match header.as_list()? {
+            [name, value] => Some((name.as_str()?, value.as_str()?)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `content` is missing when mitmproxy streamed the body without keeping it.
+fn body(message: &TnetValue) -> Option<BodyCapture> {
+    let data = message.get("content")?.as_bytes()?.to_vec();
+    let trailers = message
+        .get("trailers")
+        .map(|trailers| headers(Some(trailers)));
+    Some(BodyCapture {
+        size: data.len() as u64,
+        data,
+        truncated: false,
+        trailers,
+        complete: true,
+    })
+}
+
+fn millis(seconds: Option<&TnetValue>) -> u64 {
+    match seconds.and_then(TnetValue::as_f64) {
+        Some(seconds) if seconds > 0.0 => (seconds * 1000.0).round() as u64,
+        _ => 0,
+    }
+}
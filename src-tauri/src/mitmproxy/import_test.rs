@@ -0,0 +1,266 @@
+use super::{export_mitmproxy, import_mitmproxy, load_mitmproxy, TnetValue};
+use crate::mitm::{
+    BodyCapture, Connection, FlowKind, FlowRequest, FlowResponse, FlowStore, WsDirection,
+    WsMessage, WsMessageKind,
+};
+
+fn text(value: &str) -> TnetValue {
+    TnetValue::String(value.to_string())
+}
+
+fn bytes(value: &[u8]) -> TnetValue {
+    TnetValue::Bytes(value.to_vec())
+}
+
+fn header(name: &str, value: &str) -> TnetValue {
+    TnetValue::List(vec![bytes(name.as_bytes()), bytes(value.as_bytes())])
+}
+
+fn client_conn(id: &str) -> TnetValue {
+    TnetValue::dict([
+        ("id", text(id)),
+        (
+            "peername",
+            TnetValue::List(vec![text("::1"), TnetValue::Int(51000)]),
+        ),
+        ("sni", text("example.com")),
+        ("alpn", bytes(b"h2")),
+        ("timestamp_start", TnetValue::Float(1700000000.0)),
+        ("timestamp_end", TnetValue::Null),
+    ])
+}
+
+fn http_flow(path: &str, response: TnetValue, websocket: TnetValue) -> TnetValue {
+    TnetValue::dict([
+        ("id", text("flow")),
+        ("type", text("http")),
+        ("version", TnetValue::Int(21)),
+        ("client_conn", client_conn("client-1")),
+        ("error", TnetValue::Null),
+        (
+            "request",
+            TnetValue::dict([
+                ("host", text("example.com")),
+                ("port", TnetValue::Int(8443)),
+                ("method", bytes(b"POST")),
+                ("scheme", bytes(b"https")),
+                ("authority", bytes(b"")),
+                ("path", bytes(path.as_bytes())),
+                ("http_version", bytes(b"HTTP/2.0")),
+                ("headers", TnetValue::List(vec![header("x-a", "1")])),
+                ("content", bytes(&[0, 0xff])),
+                ("trailers", TnetValue::Null),
+                ("timestamp_start", TnetValue::Float(1700000000.25)),
+                ("timestamp_end", TnetValue::Float(1700000000.5)),
+            ]),
+        ),
+        ("response", response),
+        ("websocket", websocket),
+    ])
+}
+
+#[test]
+fn test_import_dump() {
+    let response = TnetValue::dict([
+        ("http_version", bytes(b"HTTP/2.0")),
+        ("status_code", TnetValue::Int(201)),
+        ("reason", bytes(b"")),
+        ("headers", TnetValue::List(vec![header("grpc-status", "0")])),
+        ("content", bytes(b"ok")),
+        (
+            "trailers",
+            TnetValue::List(vec![header("grpc-message", "done")]),
+        ),
+        ("timestamp_start", TnetValue::Float(1700000001.0)),
+        ("timestamp_end", TnetValue::Float(1700000002.0)),
+    ]);
+    let websocket = TnetValue::dict([
+        (
+            "messages",
+            TnetValue::List(vec![
+                TnetValue::List(vec![
+                    TnetValue::Int(1),
+                    TnetValue::Bool(true),
+                    bytes(b"hi"),
+                    TnetValue::Float(1700000003.0),
+                    TnetValue::Bool(false),
+                    TnetValue::Bool(true),
+                ]),
+                // before `dropped` and `injected` were added
+                TnetValue::List(vec![
+                    TnetValue::Int(2),
+                    TnetValue::Bool(false),
+                    bytes(&[1, 2]),
+                    TnetValue::Float(1700000004.0),
+                ]),
+            ]),
+        ),
+        ("closed_by_client", TnetValue::Bool(false)),
+        ("close_code", TnetValue::Int(1000)),
+        ("close_reason", text("bye")),
+        ("timestamp_end", TnetValue::Float(1700000005.0)),
+    ]);
+
+    let mut data = vec![];
+    http_flow("/upload?x=1", response, TnetValue::Null).dump(&mut data);
+    TnetValue::dict([("type", text("tcp"))]).dump(&mut data);
+    http_flow("/ws", TnetValue::Null, websocket).dump(&mut data);
+
+    let import = import_mitmproxy(&data).unwrap();
+    assert_eq!(import.connections.len(), 1);
+    let connection = &import.connections[0];
+    assert_eq!(connection.client_addr, "[::1]:51000");
+    assert_eq!(connection.alpn.as_deref(), Some("h2"));
+    assert_eq!(connection.opened_at, 1700000000000);
+    assert_eq!(connection.closed_at, None);
+
+    let [upload, socket] = &import.flows[..] else {
+        panic!("expected two flows, got {:?}", import.flows);
+    };
+    assert_eq!(upload.connection_id, Some(connection.id));
+    assert_eq!(upload.request.method, "POST");
+    assert_eq!(upload.request.uri, "https://example.com:8443/upload?x=1");
+    assert_eq!(upload.request.version, "HTTP/2.0");
+    assert_eq!(upload.request.timestamp, 1700000000250);
+    assert_eq!(upload.request_body.as_ref().unwrap().data, [0, 0xff]);
+    let response = upload.response.as_ref().unwrap();
+    assert_eq!(response.status, 201);
+    assert_eq!(response.timestamp, 1700000001000);
+    let body = upload.response_body.as_ref().unwrap();
+    assert_eq!(body.data, b"ok");
+    assert_eq!(
+        body.trailers,
+        Some(vec![("grpc-message".to_string(), "done".to_string())])
+    );
+    assert_eq!(upload.finished_at, Some(1700000002000));
+
+    let messages = socket.websocket.as_ref().unwrap();
+    assert_eq!(messages.len(), 3);
+    assert_eq!(messages[0].kind, WsMessageKind::Text);
+    assert_eq!(messages[0].direction, WsDirection::ClientToServer);
+    assert!(messages[0].injected);
+    assert_eq!(messages[1].kind, WsMessageKind::Binary);
+    assert_eq!(messages[1].payload, [1, 2]);
+    assert_eq!(messages[2].kind, WsMessageKind::Close);
+    assert_eq!(messages[2].direction, WsDirection::ServerToClient);
+    assert_eq!(messages[2].payload, b"\x03\xe8bye");
+    assert_eq!(socket.finished_at, Some(1700000005000));
+
+    assert!(import_mitmproxy(b"12:not a flow").is_err());
+}
+
+#[test]
+fn test_export_round_trip() {
+    let store = FlowStore::new();
+    let connection = store.open_connection(
+        "10.0.0.2:40000".parse().unwrap(),
+        Some("api.test".to_string()),
+        Some("http/1.1".to_string()),
+        Some((
+            "TLSv1.3".to_string(),
+            "TLS13_AES_128_GCM_SHA256".to_string(),
+        )),
+    );
+
+    let request = |uri: &str| FlowRequest {
+        method: "PUT".to_string(),
+        uri: uri.to_string(),
+        version: "HTTP/1.1".to_string(),
+        headers: vec![("content-type".to_string(), "text/plain".to_string())],
+        raw_head: None,
+        timestamp: 1700000000123,
+    };
+    let upload = store.insert(request("https://api.test/items/1?q=a"), Some(connection));
+    store.set_request_body(upload, BodyCapture::complete(b"hello"));
+    store.set_response(
+        upload,
+        FlowResponse {
+            status: 404,
+            version: "HTTP/1.1".to_string(),
+            headers: vec![("x-id".to_string(), "7".to_string())],
+            timestamp: 1700000000200,
+        },
+    );
+    store.finish(upload);
+
+    let socket = store.insert(request("ws://plain.test:8080/ws"), Some(connection));
+    for (kind, payload) in [
+        (WsMessageKind::Binary, vec![0xff]),
+        (WsMessageKind::Ping, vec![]),
+        (WsMessageKind::Close, b"\x0f\xa0gone".to_vec()),
+    ] {
+        store.push_ws_message(
+            socket,
+            WsMessage {
+                direction: WsDirection::ClientToServer,
+                kind,
+                payload,
+                compressed: false,
+                injected: false,
+                timestamp: 1700000000300,
+            },
+        );
+    }
+    store.set_error(socket, "Connection reset".to_string());
+
+    let tunnel = store.insert_tunnel(
+        FlowRequest::tunnel("api.test:443".to_string(), "HTTP/1.1"),
+        None,
+    );
+    store.finish(tunnel);
+
+    let flows = store.list();
+    let data = export_mitmproxy(&flows, &store.connections());
+
+    let (first, _) = TnetValue::parse(&data).unwrap();
+    assert_eq!(first.get("version"), Some(&TnetValue::Int(21)));
+    let exported = first.get("request").unwrap();
+    assert_eq!(exported.get("scheme"), Some(&bytes(b"https")));
+    assert_eq!(exported.get("path"), Some(&bytes(b"/items/1?q=a")));
+    assert_eq!(exported.get("port"), Some(&TnetValue::Int(443)));
+    assert_eq!(
+        first.get("response").unwrap().get("reason"),
+        Some(&bytes(b"Not Found"))
+    );
+
+    let target = FlowStore::new();
+    let ids = load_mitmproxy(&data, &target).unwrap();
+    assert_eq!(ids.len(), 2);
+
+    let connections: Vec<Connection> = target.connections();
+    assert_eq!(connections.len(), 1);
+    assert_eq!(connections[0].client_addr, "10.0.0.2:40000");
+    assert_eq!(connections[0].server_name.as_deref(), Some("api.test"));
+    assert_eq!(connections[0].tls_version.as_deref(), Some("TLSv1.3"));
+    assert_eq!(
+        connections[0].cipher.as_deref(),
+        Some("TLS13_AES_128_GCM_SHA256")
+    );
+
+    for (before, id) in flows.iter().zip(&ids) {
+        let after = target.get(*id).unwrap();
+        assert_eq!(after.kind, FlowKind::Http);
+        assert_eq!(after.connection_id, Some(connections[0].id));
+        assert_eq!(after.request.uri, before.request.uri);
+        assert_eq!(after.request.method, before.request.method);
+        assert_eq!(after.request.headers, before.request.headers);
+        assert_eq!(after.request.timestamp, before.request.timestamp);
+        assert_eq!(
+            after.response.as_ref().map(|r| (r.status, r.timestamp)),
+            before.response.as_ref().map(|r| (r.status, r.timestamp))
+        );
+        assert_eq!(after.error, before.error);
+    }
+
+    let upload = target.get(ids[0]).unwrap();
+    assert_eq!(upload.request_body.unwrap().data, b"hello");
+    assert_eq!(upload.finished_at, flows[0].finished_at);
+
+    // the ping has no place in mitmproxy's model
+    let messages = target.get(ids[1]).unwrap().websocket.unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].payload, [0xff]);
+    assert_eq!(messages[1].kind, WsMessageKind::Close);
+    assert_eq!(messages[1].payload, b"\x0f\xa0gone");
+    assert_eq!(messages[1].direction, WsDirection::ClientToServer);
+}
@@ -0,0 +1,12 @@
+mod export;
+mod import;
+mod tnetstring;
+
+#[cfg(test)]
+mod import_test;
+#[cfg(test)]
+mod tnetstring_test;
+
+pub use export::*;
+pub use import::*;
+pub use tnetstring::*;
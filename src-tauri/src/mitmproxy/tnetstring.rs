@@ -0,0 +1,189 @@
+use anyhow::{anyhow, bail, Context};
+
+/// Values of the tnetstring dialect mitmproxy writes, which tells bytes
+/// (`,`) and text (`;`) apart.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TnetValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Bytes(Vec<u8>),
+    String(String),
+    List(Vec<TnetValue>),
+    Dict(Vec<(String, TnetValue)>),
+}
+
+/// Deep enough for any flow, shallow enough not to overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+impl TnetValue {
+    pub fn dict<'a>(entries: impl IntoIterator<Item = (&'a str, TnetValue)>) -> Self {
+        TnetValue::Dict(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Entry `key` of a dict, a null entry counts as missing.
+    pub fn get(&self, key: &str) -> Option<&TnetValue> {
+        match self {
+            TnetValue::Dict(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value)
+                .filter(|value| **value != TnetValue::Null),
+            _ => None,
+        }
+    }
+
+    /// Text of either string kind, bytes are read as UTF-8 lossily.
+    pub fn as_str(&self) -> Option<String> {
+        match self {
+            TnetValue::String(text) => Some(text.clone()),
+            TnetValue::Bytes(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            TnetValue::Bytes(bytes) => Some(bytes),
+            TnetValue::String(text) => Some(text.as_bytes()),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            TnetValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            TnetValue::Float(value) => Some(*value),
+            TnetValue::Int(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            TnetValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[TnetValue]> {
+        match self {
+            TnetValue::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn dump(&self, out: &mut Vec<u8>) {
+        let (payload, tag) = match self {
+            TnetValue::Null => (vec![], b'~'),
+            TnetValue::Bool(value) => (value.to_string().into_bytes(), b'!'),
+            TnetValue::Int(value) => (value.to_string().into_bytes(), b'#'),
+            // Python reads Rust's shortest round-trip form
+            TnetValue::Float(value) => (format!("{:?}", value).into_bytes(), b'^'),
+            TnetValue::Bytes(bytes) => (bytes.clone(), b','),
+            TnetValue::String(text) => (text.clone().into_bytes(), b';'),
+            TnetValue::List(items) => {
+                let mut payload = vec![];
+                for item in items {
+                    item.dump(&mut payload);
+                }
+                (payload, b']')
+            }
+            TnetValue::Dict(entries) => {
+                let mut payload = vec![];
+                for (key, value) in entries {
+                    TnetValue::String(key.clone()).dump(&mut payload);
+                    value.dump(&mut payload);
+                }
+                (payload, b'}')
+            }
+        };
+        out.extend_from_slice(payload.len().to_string().as_bytes());
+        out.push(b':');
+        out.extend_from_slice(&payload);
+        out.push(tag);
+    }
+
+    /// Reads one value off the front of `data` and returns the rest.
+    pub fn parse(data: &[u8]) -> anyhow::Result<(TnetValue, &[u8])> {
+        parse_value(data, 0)
+    }
+}
+
+fn parse_value(data: &[u8], depth: usize) -> anyhow::Result<(TnetValue, &[u8])> {
+    if depth > MAX_DEPTH {
+        bail!("tnetstring nested too deeply");
+    }
+
+    let colon = data
+        .iter()
+        .take(12)
+        .position(|&b| b == b':')
+        .ok_or_else(|| anyhow!("Not a tnetstring"))?;
+    let len: usize = std::str::from_utf8(&data[..colon])
+        .ok()
+        .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|digits| digits.parse().ok())
+        .ok_or_else(|| anyhow!("Invalid tnetstring length"))?;
+
+    let rest = &data[colon + 1..];
+    if rest.len() <= len {
+        bail!("tnetstring is truncated");
+    }
+    let (payload, tag, rest) = (&rest[..len], rest[len], &rest[len + 1..]);
+    let text = || std::str::from_utf8(payload).context("Invalid tnetstring text");
+
+    let value = match tag {
+        b'~' if payload.is_empty() => TnetValue::Null,
+        b'!' => match payload {
+            b"true" => TnetValue::Bool(true),
+            b"false" => TnetValue::Bool(false),
+            _ => bail!("Invalid tnetstring boolean"),
+        },
+        b'#' => TnetValue::Int(text()?.parse().context("Invalid tnetstring integer")?),
+        b'^' => TnetValue::Float(text()?.parse().context("Invalid tnetstring float")?),
+        b',' => TnetValue::Bytes(payload.to_vec()),
+        b';' => TnetValue::String(text()?.to_string()),
+        b']' => {
+            let mut items = vec![];
+            let mut remaining = payload;
+            while !remaining.is_empty() {
+                let (item, next) = parse_value(remaining, depth + 1)?;
+                items.push(item);
+                remaining = next;
+            }
+            TnetValue::List(items)
+        }
+        b'}' => {
+            let mut entries = vec![];
+            let mut remaining = payload;
+            while !remaining.is_empty() {
+                let (key, next) = parse_value(remaining, depth + 1)?;
+                let key = key
+                    .as_str()
+                    .ok_or_else(|| anyhow!("tnetstring dict key is not a string"))?;
+                if next.is_empty() {
+                    bail!("tnetstring dict key has no value");
+                }
+                let (value, next) = parse_value(next, depth + 1)?;
+                entries.push((key, value));
+                remaining = next;
+            }
+            TnetValue::Dict(entries)
+        }
+        _ => bail!("Unknown tnetstring type {:?}", tag as char),
+    };
+    Ok((value, rest))
+}
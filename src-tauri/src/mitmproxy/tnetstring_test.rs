@@ -0,0 +1,70 @@
+use super::TnetValue;
+
+#[test]
+fn test_dump_and_parse() {
+    let value = TnetValue::dict([
+        ("content", TnetValue::Bytes(vec![0, 0xff, b':'])),
+        ("host", TnetValue::String("h\u{e9}".to_string())),
+        ("port", TnetValue::Int(-8080)),
+        ("timestamp", TnetValue::Float(1700000000.125)),
+        ("tls", TnetValue::Bool(true)),
+        ("error", TnetValue::Null),
+        (
+            "headers",
+            TnetValue::List(vec![TnetValue::List(vec![]), TnetValue::List(vec![])]),
+        ),
+    ]);
+
+    let dumped = |value: TnetValue| {
+        let mut out = vec![];
+        value.dump(&mut out);
+        out
+    };
+    assert_eq!(dumped(TnetValue::Bytes(vec![0, b':'])), b"2:\x00:,");
+    assert_eq!(
+        dumped(TnetValue::String("\u{e9}".to_string())),
+        b"2:\xc3\xa9;"
+    );
+    assert_eq!(dumped(TnetValue::Float(1.0)), b"3:1.0^");
+    assert_eq!(dumped(TnetValue::Bool(false)), b"5:false!");
+    assert_eq!(
+        dumped(TnetValue::dict([(
+            "a",
+            TnetValue::List(vec![TnetValue::Null])
+        )])),
+        b"10:1:a;3:0:~]}"
+    );
+
+    let mut out = dumped(value.clone());
+
+    out.extend_from_slice(b"0:~");
+    let (parsed, rest) = TnetValue::parse(&out).unwrap();
+    assert_eq!(parsed, value);
+    assert_eq!(rest, b"0:~");
+    assert_eq!(parsed.get("tls"), Some(&TnetValue::Bool(true)));
+    assert_eq!(parsed.get("error"), None);
+    assert_eq!(parsed.get("host").unwrap().as_str().unwrap(), "h\u{e9}");
+}
+
+#[test]
+fn test_parse_errors() {
+    for data in [
+        &b""[..],
+        b"abc",
+        b"5:abc,",
+        b"3:abc?",
+        b"01234567890123:x,",
+        b"4:maybe!",
+        b"2:1x#",
+        b"4:1:a,}",
+        b"4:1:a#}",
+    ] {
+        assert!(TnetValue::parse(data).is_err(), "{:?}", data);
+    }
+
+    let mut nested = b"0:]".to_vec();
+    for _ in 0..100 {
+        nested = [format!("{}:", nested.len()).as_bytes(), &nested, b"]"].concat();
+    }
+    assert!(TnetValue::parse(&nested).is_err());
+}
@@ -0,0 +1,390 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::sync::Mutex;
+
+use hyper::Uri;
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+use super::{Filter, Flag, Query, Scope, Term};
+use crate::mitm::{BodyCapture, Flow, FlowEvent, FlowId, FlowKind, FlowStore, WsDirection};
+
+/// Decoded body text searched per body, the rest is left out.
+pub const MAX_INDEXED_BODY: usize = 256 * 1024;
+
+/// Filter bits per distinct trigram of a body, for about 5% false
+/// positives.
+const BITS_PER_TRIGRAM: usize = 8;
+
+/// Searchable facts of every flow in a [`FlowStore`], kept up to date from
+/// its events. Bodies aren't copied: every body gets a bloom filter of its
+/// trigrams, and only flows whose filters can contain the words have their
+/// bodies read from the store and scanned.
+pub struct SearchIndex {
+    state: Mutex<IndexState>,
+}
+
+struct IndexState {
+    events: broadcast::Receiver<FlowEvent>,
+    entries: BTreeMap<FlowId, Entry>,
+    dirty: BTreeSet<FlowId>,
+    /// `false` until the flows already in the store are indexed, and again
+    /// after missing events.
+    synced: bool,
+}
+
+/// Text fields are ASCII lowercased.
+struct Entry {
+    kind: FlowKind,
+    method: String,
+    url: String,
+    host: String,
+    path: String,
+    status: Option<u16>,
+    duration: Option<u64>,
+    size: Option<u64>,
+    content_type: String,
+    request_headers: String,
+    response_headers: String,
+    request_body: TrigramFilter,
+    response_body: TrigramFilter,
+    error: Option<String>,
+    websocket: bool,
+    sse: bool,
+    replay: bool,
+    imported: bool,
+    /// Nothing changes a finished flow, it is only indexed again on events.
+    finished: bool,
+}
+
+/// Bloom filter of the trigrams in one text, sized to their number so it
+/// doesn't fill up for large bodies. At most [`BITS_PER_TRIGRAM`] times
+/// [`MAX_INDEXED_BODY`] bits.
+pub(super) struct TrigramFilter {
+    bits: Box<[u64]>,
+}
+
+/// The body text of one flow, read from the store the first time a term
+/// needs it.
+struct Bodies<'a> {
+    flow_store: &'a FlowStore,
+    id: FlowId,
+    text: Option<(String, String)>,
+}
+
+impl SearchIndex {
+    pub fn new(flow_store: &FlowStore) -> Self {
+        Self {
+            state: Mutex::new(IndexState {
+                events: flow_store.subscribe(),
+                entries: BTreeMap::new(),
+                dirty: BTreeSet::new(),
+                synced: false,
+            }),
+        }
+    }
+
+    /// Ids of the flows matching `query` in store order. `flow_store` has
+    /// to be the store the index was created for.
+    pub fn search(&self, flow_store: &FlowStore, query: &Query) -> Vec<FlowId> {
+        let mut state = self.state.lock().unwrap();
+        state.refresh(flow_store);
+
+        state
+            .entries
+            .iter()
+            .filter(|(&id, entry)| {
+                let mut bodies = Bodies {
+                    flow_store,
+                    id,
+                    text: None,
+                };
+                query.clauses.is_empty()
+                    || query
+                        .clauses
+                        .iter()
+                        .any(|clause| clause.iter().all(|term| entry.matches(term, &mut bodies)))
+            })
+            .map(|(&id, _)| id)
+            .collect()
+    }
+}
+
+impl IndexState {
+    fn refresh(&mut self, flow_store: &FlowStore) {
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => self.apply(event),
+                Err(TryRecvError::Lagged(_)) => self.synced = false,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+            }
+        }
+
+        if !self.synced {
+            let ids: BTreeSet<FlowId> = flow_store.ids().into_iter().collect();
+            self.entries.retain(|id, _| ids.contains(id));
+            for id in ids {
+                if !self.entries.get(&id).is_some_and(|entry| entry.finished) {
+                    self.dirty.insert(id);
+                }
+            }
+            self.synced = true;
+        }
+
+        for id in std::mem::take(&mut self.dirty) {
+            match flow_store.get(id) {
+                Some(flow) => self.entries.insert(id, Entry::new(&flow)),
+                None => self.entries.remove(&id),
+            };
+        }
+    }
+
+    fn apply(&mut self, event: FlowEvent) {
+        let id = match event {
+            FlowEvent::Created { flow } => flow.id,
+            FlowEvent::Response { id, .. }
+            | FlowEvent::RequestBody { id, .. }
+            | FlowEvent::ResponseBody { id, .. }
+            | FlowEvent::ServerSentEvent { id, .. }
+            | FlowEvent::WebSocketMessage { id, .. }
+            | FlowEvent::Tunnel { id, .. }
+            | FlowEvent::Error { id, .. }
+            | FlowEvent::Finished { id, .. } => id,
+            FlowEvent::Cleared => {
+                self.entries.clear();
+                self.dirty.clear();
+                return;
+            }
+            FlowEvent::RuleHit { .. }
+            | FlowEvent::Breakpoint { .. }
+            | FlowEvent::Timings { .. }
+            | FlowEvent::ConnectionOpened { .. }
+            | FlowEvent::ConnectionClosed { .. } => return,
+        };
+        self.dirty.insert(id);
+    }
+}
+
+impl Entry {
+    fn new(flow: &Flow) -> Self {
+        let request = &flow.request;
+        let uri: Uri = request.uri.parse().unwrap_or_default();
+        let response = flow.response.as_ref();
+
+        let (request_body, response_body) = body_texts(flow);
+
+        Self {
+            kind: flow.kind,
+            method: request.method.to_ascii_lowercase(),
+            url: request.uri.to_ascii_lowercase(),
+            host: uri.host().unwrap_or_default().to_ascii_lowercase(),
+            path: uri.path().to_ascii_lowercase(),
+            status: response.map(|response| response.status),
+            duration: flow
+                .finished_at
+                .map(|end| end.saturating_sub(request.timestamp)),
+            size: flow.response_body.as_ref().map(|body| body.size),
+            content_type: response
+                .and_then(|response| header(&response.headers, "content-type"))
+                .unwrap_or_default()
+                .to_ascii_lowercase(),
+            request_headers: header_text(&request.headers),
+            response_headers: response.map_or(String::new(), |r| header_text(&r.headers)),
+            request_body: TrigramFilter::new(&request_body),
+            response_body: TrigramFilter::new(&response_body),
+            error: flow.error.as_ref().map(|error| error.to_ascii_lowercase()),
+            websocket: flow.websocket.is_some(),
+            sse: flow.events.is_some(),
+            replay: flow.replay_of.is_some(),
+            imported: flow.imported,
+            finished: flow.finished_at.is_some() || flow.imported,
+        }
+    }
+
+    fn matches(&self, term: &Term, bodies: &mut Bodies) -> bool {
+        self.matches_filter(&term.filter, bodies) != term.negated
+    }
+
+    fn matches_filter(&self, filter: &Filter, bodies: &mut Bodies) -> bool {
+        let scoped = |scope: Scope, request: &str, response: &str, needle: &str| match scope {
+            Scope::Any => request.contains(needle) || response.contains(needle),
+            Scope::Request => request.contains(needle),
+            Scope::Response => response.contains(needle),
+        };
+
+        match filter {
+            Filter::Text(needle) => {
+                self.url.contains(needle.as_str())
+                    || scoped(
+                        Scope::Any,
+                        &self.request_headers,
+                        &self.response_headers,
+                        needle,
+                    )
+                    || self
+                        .error
+                        .as_ref()
+                        .is_some_and(|error| error.contains(needle.as_str()))
+                    || self.body_contains(Scope::Any, needle, bodies)
+            }
+            Filter::Host(pattern) => pattern.matches(&self.host),
+            Filter::Url(pattern) => pattern.matches(&self.url),
+            Filter::Path(pattern) => pattern.matches(&self.path),
+            Filter::Method(method) => self.method == *method,
+            Filter::Status(range) => self
+                .status
+                .is_some_and(|status| range.contains(status.into())),
+            Filter::Duration(range) => self.duration.is_some_and(|ms| range.contains(ms)),
+            Filter::Size(range) => self.size.is_some_and(|size| range.contains(size)),
+            Filter::Header(scope, needle) => scoped(
+                *scope,
+                &self.request_headers,
+                &self.response_headers,
+                needle,
+            ),
+            Filter::Body(scope, needle) => self.body_contains(*scope, needle, bodies),
+            Filter::ContentType(needle) => self.content_type.contains(needle.as_str()),
+            Filter::Error(needle) => self
+                .error
+                .as_ref()
+                .is_some_and(|error| error.contains(needle.as_str())),
+            Filter::Is(flag) => match flag {
+                Flag::WebSocket => self.websocket,
+                Flag::Tunnel => self.kind == FlowKind::Tunnel,
+                Flag::Sse => self.sse,
+                Flag::Error => self.error.is_some(),
+                Flag::Replay => self.replay,
+                Flag::Imported => self.imported,
+                Flag::Pending => self.status.is_none() && self.error.is_none(),
+            },
+        }
+    }
+
+    /// Scans the bodies in `scope` whose filters don't rule `needle` out.
+    fn body_contains(&self, scope: Scope, needle: &str, bodies: &mut Bodies) -> bool {
+        let request = scope != Scope::Response && self.request_body.may_contain(needle);
+        let response = scope != Scope::Request && self.response_body.may_contain(needle);
+        if !request && !response {
+            return false;
+        }
+        let (request_text, response_text) = bodies.text();
+        (request && request_text.contains(needle)) || (response && response_text.contains(needle))
+    }
+}
+
+impl TrigramFilter {
+    pub(super) fn new(text: &str) -> Self {
+        let trigrams: HashSet<u32> = trigrams(text).collect();
+        let words = (trigrams.len() * BITS_PER_TRIGRAM).div_ceil(64);
+        let mut filter = Self {
+            bits: vec![0; words].into_boxed_slice(),
+        };
+        for trigram in trigrams {
+            for bit in filter.positions(trigram) {
+                filter.bits[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        filter
+    }
+
+    /// `false` only when the text can't contain `needle`.
+    pub(super) fn may_contain(&self, needle: &str) -> bool {
+        if self.bits.is_empty() {
+            return needle.len() < 3;
+        }
+        trigrams(needle).all(|trigram| {
+            self.positions(trigram)
+                .into_iter()
+                .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+        })
+    }
+
+    fn positions(&self, trigram: u32) -> [usize; 2] {
+        let len = self.bits.len() * 64;
+        let trigram = u64::from(trigram);
+        [
+            (trigram.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) as usize % len,
+            (trigram.wrapping_mul(0xc2b2_ae3d_27d4_eb4f) >> 32) as usize % len,
+        ]
+    }
+}
+
+impl Bodies<'_> {
+    fn text(&mut self) -> &(String, String) {
+        self.text.get_or_insert_with(|| {
+            self.flow_store
+                .get(self.id)
+                .map(|flow| body_texts(&flow))
+                .unwrap_or_default()
+        })
+    }
+}
+
+fn trigrams(text: &str) -> impl Iterator<Item = u32> + '_ {
+    text.as_bytes()
+        .windows(3)
+        .map(|trigram| u32::from_le_bytes([trigram[0], trigram[1], trigram[2], 0]))
+}
+
+fn header(headers: &[(String, String)], name: &str) -> Option<String> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.clone())
+}
+
+/// One `name: value` line per header.
+fn header_text(headers: &[(String, String)]) -> String {
+    let mut text = String::new();
+    for (name, value) in headers {
+        text.push_str(name);
+        text.push_str(": ");
+        text.push_str(value);
+        text.push('\n');
+    }
+    text.to_ascii_lowercase()
+}
+
+/// The searched text of both sides of `flow`, WebSocket messages and
+/// server-sent events count as the body of their side.
+fn body_texts(flow: &Flow) -> (String, String) {
+    let mut request_body = body_text(flow.request_body.as_ref(), &flow.request.headers);
+    let mut response_body = match &flow.response {
+        Some(response) => body_text(flow.response_body.as_ref(), &response.headers),
+        None => String::new(),
+    };
+    for message in flow.websocket.iter().flatten() {
+        let text = match message.direction {
+            WsDirection::ClientToServer => &mut request_body,
+            WsDirection::ServerToClient => &mut response_body,
+        };
+        append_text(text, &message.payload);
+    }
+    for event in flow.events.iter().flatten() {
+        append_text(&mut response_body, event.data.as_bytes());
+    }
+    (request_body, response_body)
+}
+
+/// The start of the decoded body as text, or the bytes as captured when
+/// they can't be decoded.
+fn body_text(body: Option<&BodyCapture>, headers: &[(String, String)]) -> String {
+    let mut text = String::new();
+    if let Some(body) = body {
+        match body.decoded_prefix(headers, MAX_INDEXED_BODY) {
+            Ok(data) => append_text(&mut text, &data),
+            Err(_) => append_text(&mut text, &body.data),
+        }
+    }
+    text
+}
+
+fn append_text(text: &mut String, data: &[u8]) {
+    let room = MAX_INDEXED_BODY.saturating_sub(text.len());
+    if room == 0 {
+        return;
+    }
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    let data = &data[..data.len().min(room)];
+    text.push_str(&String::from_utf8_lossy(data).to_ascii_lowercase());
+}
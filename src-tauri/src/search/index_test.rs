@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+
+use super::{parse_query, SearchIndex, TrigramFilter, MAX_INDEXED_BODY};
+use crate::mitm::{
+    encode_body, now_millis, BodyCapture, ContentEncoding, FlowId, FlowRequest, FlowResponse,
+    FlowStore,
+};
+
+fn request(method: &str, uri: &str, timestamp: u64) -> FlowRequest {
+    FlowRequest {
+        method: method.to_string(),
+        uri: uri.to_string(),
+        version: "HTTP/1.1".to_string(),
+        headers: vec![("authorization".to_string(), "Bearer abc123".to_string())],
+        raw_head: None,
+        timestamp,
+    }
+}
+
+fn respond(store: &FlowStore, id: FlowId, status: u16, headers: &[(&str, &str)], body: &[u8]) {
+    store.set_response(
+        id,
+        FlowResponse {
+            status,
+            version: "HTTP/1.1".to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            timestamp: now_millis(),
+        },
+    );
+    store.set_response_body(id, BodyCapture::complete(body));
+    store.finish(id);
+}
+
+#[test]
+fn test_search_flows() {
+    let store = FlowStore::new();
+    let slow = store.insert(
+        request("POST", "https://api.example.test/login", now_millis() - 800),
+        None,
+    );
+    respond(
+        &store,
+        slow,
+        401,
+        &[("content-type", "application/json")],
+        br#"{"error":"Invalid Token"}"#,
+    );
+
+    let index = SearchIndex::new(&store);
+    let search = |query: &str| index.search(&store, &parse_query(query).unwrap());
+
+    let fast = store.insert(
+        request("GET", "https://www.example.test/index.html", now_millis()),
+        None,
+    );
+    let gzipped = encode_body(b"<h1>Welcome</h1>", &[ContentEncoding::Gzip]).unwrap();
+    respond(
+        &store,
+        fast,
+        200,
+        &[("content-type", "text/html"), ("content-encoding", "gzip")],
+        &gzipped,
+    );
+    let pending = store.insert(request("GET", "http://plain.test/", now_millis()), None);
+
+    assert_eq!(search(""), [slow, fast, pending]);
+    assert_eq!(
+        search(r#"host:api.* status:>=400 method:POST body:"token" duration:>500ms"#),
+        [slow]
+    );
+    assert_eq!(search("welcome"), [fast]);
+    assert_eq!(search("resbody:welcome type:html size:<1kb"), [fast]);
+    assert_eq!(search("reqheader:bearer -status:4xx"), [fast, pending]);
+    assert_eq!(search("is:pending OR status:401"), [slow, pending]);
+    assert_eq!(search("nothing-like-this"), Vec::<FlowId>::new());
+
+    store.set_error(pending, "connection refused".to_string());
+    assert_eq!(search("error:refused"), [pending]);
+    assert_eq!(search("is:pending"), Vec::<FlowId>::new());
+
+    store.clear();
+    assert_eq!(search(""), Vec::<FlowId>::new());
+}
+
+#[test]
+fn test_search_large_bodies() {
+    let store = FlowStore::new();
+    let index = SearchIndex::new(&store);
+    let search = |query: &str| index.search(&store, &parse_query(query).unwrap());
+
+    let mut text = String::new();
+    for n in 0..5_000 {
+        text.push_str(&format!("{{\"item\":{},\"name\":\"entry{}\"}}", n, n * 7));
+    }
+    let id = store.insert(request("GET", "https://api.test/items", now_millis()), None);
+    respond(&store, id, 200, &[], text.as_bytes());
+    assert_eq!(search("entry34993"), [id]);
+    assert_eq!(search("-resbody:entry34993"), Vec::<FlowId>::new());
+    assert_eq!(search("resbody:missing"), Vec::<FlowId>::new());
+
+    // only the start of a large body is decoded and searched
+    let mut large = vec![b'a'; 1024 * 1024];
+    large.extend_from_slice(b"needle");
+    let gzipped = encode_body(&large, &[ContentEncoding::Gzip]).unwrap();
+    let id = store.insert(request("GET", "https://api.test/large", now_millis()), None);
+    respond(&store, id, 200, &[("content-encoding", "gzip")], &gzipped);
+    assert_eq!(search("resbody:aaaa"), [id]);
+    assert_eq!(search("needle"), Vec::<FlowId>::new());
+}
+
+#[test]
+fn test_trigram_filter_large_text() {
+    // printable ASCII from a fixed sequence, nearly every trigram distinct
+    let mut seed = 1u64;
+    let mut next = move || {
+        seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        char::from(b'!' + (seed >> 33) as u8 % 94)
+    };
+    let text: String = (0..MAX_INDEXED_BODY).map(|_| next()).collect();
+    let filter = TrigramFilter::new(&text);
+    let present: HashSet<&str> = (0..text.len() - 2).map(|i| &text[i..i + 3]).collect();
+    assert!(present.iter().all(|trigram| filter.may_contain(trigram)));
+
+    let absent: Vec<String> = (0..10_000)
+        .map(|_| (0..3).map(|_| next()).collect::<String>())
+        .filter(|needle| !present.contains(needle.as_str()))
+        .collect();
+    let positives = absent
+        .iter()
+        .filter(|needle| filter.may_contain(needle))
+        .count();
+    assert!(
+        positives * 10 < absent.len(),
+        "{} of {}",
+        positives,
+        absent.len()
+    );
+}
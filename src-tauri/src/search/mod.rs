@@ -0,0 +1,10 @@
+mod index;
+mod query;
+
+#[cfg(test)]
+mod index_test;
+#[cfg(test)]
+mod query_test;
+
+pub use index::*;
+pub use query::*;
@@ -0,0 +1,342 @@
+use anyhow::{anyhow, bail};
+
+/// A parsed search. Terms are separated by spaces and must all match,
+/// `OR` between terms starts an alternative:
+///
+/// `host:api.* status:>=400 method:POST body:"token" duration:>500ms`
+///
+/// A leading `-` negates a term, words without a known field are looked
+/// for anywhere in the URL, headers, bodies and error.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    /// Alternatives, each a list of terms that all have to match. No
+    /// alternatives at all matches everything.
+    pub clauses: Vec<Vec<Term>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub negated: bool,
+    pub filter: Filter,
+}
+
+/// Text is kept lowercased, matching ignores ASCII case.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Text(String),
+    Host(Pattern),
+    Url(Pattern),
+    Path(Pattern),
+    Method(String),
+    Status(Range),
+    /// Milliseconds from the request to the end of the flow.
+    Duration(Range),
+    /// Bytes of the response body.
+    Size(Range),
+    Header(Scope, String),
+    Body(Scope, String),
+    /// The response `Content-Type`.
+    ContentType(String),
+    Error(String),
+    Is(Flag),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Any,
+    Request,
+    Response,
+}
+
+/// `*` and `?` make a pattern match the whole value, without them it
+/// matches anywhere in it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Contains(String),
+    Glob(String),
+}
+
+/// Inclusive bounds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Range {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    WebSocket,
+    Tunnel,
+    Sse,
+    Error,
+    Replay,
+    Imported,
+    /// No response yet.
+    Pending,
+}
+
+pub fn parse_query(input: &str) -> anyhow::Result<Query> {
+    let mut query = Query::default();
+    let mut clause = vec![];
+
+    for token in tokenize(input)? {
+        if !token.quoted && token.text == "OR" {
+            if !clause.is_empty() {
+                query.clauses.push(std::mem::take(&mut clause));
+            }
+            continue;
+        }
+        clause.push(term(token)?);
+    }
+    if !clause.is_empty() {
+        query.clauses.push(clause);
+    }
+    Ok(query)
+}
+
+struct Token {
+    text: String,
+    /// Where an unquoted `:` splits off a field name.
+    colon: Option<usize>,
+    quoted: bool,
+}
+
+/// Splits on whitespace outside of double quotes, `\"` is a literal quote.
+fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(tokens);
+        }
+
+        let mut token = Token {
+            text: String::new(),
+            colon: None,
+            quoted: false,
+        };
+        let mut in_quotes = false;
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    in_quotes = !in_quotes;
+                    token.quoted = true;
+                }
+                '\\' if in_quotes && matches!(chars.peek(), Some('"' | '\\')) => {
+                    token.text.extend(chars.next());
+                }
+                ':' if !in_quotes && token.colon.is_none() && !token.quoted => {
+                    token.colon = Some(token.text.len());
+                    token.text.push(c);
+                }
+                c if c.is_whitespace() && !in_quotes => break,
+                c => token.text.push(c),
+            }
+        }
+        if in_quotes {
+            bail!("Unterminated quote in search");
+        }
+        tokens.push(token);
+    }
+}
+
+fn term(token: Token) -> anyhow::Result<Term> {
+    let negated = token.text.starts_with('-') && token.text.len() > 1;
+    let offset = usize::from(negated);
+    let text = &token.text[offset..];
+
+    let Some((field, value)) = token
+        .colon
+        .map(|colon| (&token.text[offset..colon], &token.text[colon + 1..]))
+    else {
+        return Ok(Term {
+            negated,
+            filter: Filter::Text(text.to_ascii_lowercase()),
+        });
+    };
+
+    let lower = value.to_ascii_lowercase();
+    let filter = match field.to_ascii_lowercase().as_str() {
+        "host" | "domain" => Filter::Host(pattern(lower)),
+        "url" => Filter::Url(pattern(lower)),
+        "path" => Filter::Path(pattern(lower)),
+        "method" => Filter::Method(lower),
+        "status" | "code" => Filter::Status(status_range(value)?),
+        "duration" | "time" => Filter::Duration(range(value, duration_unit)?),
+        "size" => Filter::Size(range(value, size_unit)?),
+        "header" => Filter::Header(Scope::Any, lower),
+        "reqheader" => Filter::Header(Scope::Request, lower),
+        "resheader" => Filter::Header(Scope::Response, lower),
+        "body" => Filter::Body(Scope::Any, lower),
+        "reqbody" => Filter::Body(Scope::Request, lower),
+        "resbody" => Filter::Body(Scope::Response, lower),
+        "type" => Filter::ContentType(lower),
+        "error" => Filter::Error(lower),
+        "is" => Filter::Is(flag(&lower)?),
+        // `https://…` and the like are plain text
+        _ => Filter::Text(text.to_ascii_lowercase()),
+    };
+    if value.is_empty() && !matches!(filter, Filter::Text(_)) {
+        bail!("Missing value for {}:", field);
+    }
+    Ok(Term { negated, filter })
+}
+
+fn pattern(value: String) -> Pattern {
+    if value.contains(['*', '?']) {
+        Pattern::Glob(value)
+    } else {
+        Pattern::Contains(value)
+    }
+}
+
+fn flag(value: &str) -> anyhow::Result<Flag> {
+    Ok(match value {
+        "websocket" | "ws" => Flag::WebSocket,
+        "tunnel" => Flag::Tunnel,
+        "sse" => Flag::Sse,
+        "error" | "failed" => Flag::Error,
+        "replay" => Flag::Replay,
+        "imported" => Flag::Imported,
+        "pending" => Flag::Pending,
+        _ => bail!("Unknown flag is:{}", value),
+    })
+}
+
+/// `404`, `4xx`, or a comparison like `>=400`.
+fn status_range(value: &str) -> anyhow::Result<Range> {
+    if let Some(class) = value
+        .strip_suffix("xx")
+        .or_else(|| value.strip_suffix("XX"))
+        .and_then(|class| class.parse::<u64>().ok())
+        .filter(|class| (1..=9).contains(class))
+    {
+        return Ok(Range {
+            min: Some(class * 100),
+            max: Some(class * 100 + 99),
+        });
+    }
+    range(value, |unit| unit.is_empty().then_some(1))
+}
+
+fn duration_unit(unit: &str) -> Option<u64> {
+    match unit {
+        "" | "ms" => Some(1),
+        "s" => Some(1000),
+        "m" | "min" => Some(60_000),
+        _ => None,
+    }
+}
+
+fn size_unit(unit: &str) -> Option<u64> {
+    match unit {
+        "" | "b" => Some(1),
+        "k" | "kb" => Some(1 << 10),
+        "m" | "mb" => Some(1 << 20),
+        "g" | "gb" => Some(1 << 30),
+        _ => None,
+    }
+}
+
+/// A number with an optional comparison in front and unit after, or
+/// `low..high`.
+fn range(value: &str, unit: fn(&str) -> Option<u64>) -> anyhow::Result<Range> {
+    if let Some((low, high)) = value.split_once("..") {
+        return Ok(Range {
+            min: Some(number(low, unit)?),
+            max: Some(number(high, unit)?),
+        });
+    }
+
+    let (op, number_text) = match value.as_bytes() {
+        [b'>', b'=', ..] | [b'<', b'=', ..] => value.split_at(2),
+        [b'>' | b'<' | b'=', ..] => value.split_at(1),
+        _ => ("=", value),
+    };
+    let n = number(number_text, unit)?;
+    Ok(match op {
+        ">" => Range {
+            min: Some(n.saturating_add(1)),
+            max: None,
+        },
+        ">=" => Range {
+            min: Some(n),
+            max: None,
+        },
+        "<" => Range {
+            min: None,
+            max: Some(
+                n.checked_sub(1)
+                    .ok_or_else(|| anyhow!("Nothing is below 0"))?,
+            ),
+        },
+        "<=" => Range {
+            min: None,
+            max: Some(n),
+        },
+        _ => Range {
+            min: Some(n),
+            max: Some(n),
+        },
+    })
+}
+
+fn number(text: &str, unit: fn(&str) -> Option<u64>) -> anyhow::Result<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (digits, suffix) = text.split_at(split);
+    let multiplier =
+        unit(&suffix.to_ascii_lowercase()).ok_or_else(|| anyhow!("Unknown unit in {}", text))?;
+    let value: f64 = digits
+        .parse()
+        .map_err(|_| anyhow!("Not a number: {}", text))?;
+    Ok((value * multiplier as f64).round() as u64)
+}
+
+impl Pattern {
+    /// `text` has to be lowercase already.
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            Pattern::Contains(needle) => text.contains(needle.as_str()),
+            Pattern::Glob(glob) => glob_match(glob.as_bytes(), text.as_bytes()),
+        }
+    }
+}
+
+impl Range {
+    pub fn contains(&self, value: u64) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+}
+
+/// `*` matches any run of bytes and `?` any one byte.
+fn glob_match(glob: &[u8], text: &[u8]) -> bool {
+    let (mut g, mut t) = (0, 0);
+    // where the last `*` was and the text position it is tried from
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match glob.get(g) {
+            Some(b'*') => {
+                star = Some((g, t));
+                g += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_g, star_t)) => {
+                    g = star_g + 1;
+                    t = star_t + 1;
+                    star = Some((star_g, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == b'*')
+}
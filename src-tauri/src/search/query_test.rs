@@ -0,0 +1,105 @@
+use super::{parse_query, Filter, Flag, Pattern, Range, Scope, Term};
+
+fn filters(input: &str) -> Vec<Vec<(bool, Filter)>> {
+    parse_query(input)
+        .unwrap()
+        .clauses
+        .into_iter()
+        .map(|clause| {
+            clause
+                .into_iter()
+                .map(|Term { negated, filter }| (negated, filter))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_query() {
+    assert_eq!(
+        filters(r#"host:API.* status:>=400 method:POST body:"Bearer token" duration:>500ms"#),
+        [vec![
+            (false, Filter::Host(Pattern::Glob("api.*".to_string()))),
+            (
+                false,
+                Filter::Status(Range {
+                    min: Some(400),
+                    max: None
+                })
+            ),
+            (false, Filter::Method("post".to_string())),
+            (false, Filter::Body(Scope::Any, "bearer token".to_string())),
+            (
+                false,
+                Filter::Duration(Range {
+                    min: Some(501),
+                    max: None
+                })
+            ),
+        ]]
+    );
+
+    assert_eq!(
+        filters(r#"status:5xx -is:ws OR size:1kb..2mb "a \"b\":c" https://x.test"#),
+        [
+            vec![
+                (
+                    false,
+                    Filter::Status(Range {
+                        min: Some(500),
+                        max: Some(599)
+                    })
+                ),
+                (true, Filter::Is(Flag::WebSocket)),
+            ],
+            vec![
+                (
+                    false,
+                    Filter::Size(Range {
+                        min: Some(1024),
+                        max: Some(2 << 20)
+                    })
+                ),
+                (false, Filter::Text(r#"a "b":c"#.to_string())),
+                (false, Filter::Text("https://x.test".to_string())),
+            ],
+        ]
+    );
+
+    assert_eq!(
+        filters("time:<1.5s resheader:x-cache"),
+        [vec![
+            (
+                false,
+                Filter::Duration(Range {
+                    min: None,
+                    max: Some(1499)
+                })
+            ),
+            (
+                false,
+                Filter::Header(Scope::Response, "x-cache".to_string())
+            ),
+        ]]
+    );
+    assert!(parse_query("").unwrap().clauses.is_empty());
+
+    for invalid in [
+        "body:\"open",
+        "status:abc",
+        "size:3parsecs",
+        "is:nothing",
+        "host:",
+    ] {
+        assert!(parse_query(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn test_pattern_matches() {
+    let glob = Pattern::Glob("api.*.test".to_string());
+    assert!(glob.matches("api.example.test"));
+    assert!(!glob.matches("www.api.example.test"));
+    assert!(Pattern::Glob("*.t?st".to_string()).matches("a.test"));
+    assert!(Pattern::Contains("api".to_string()).matches("www.api.test"));
+}
@@ -0,0 +1,412 @@
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::mitm::{now_millis, BreakpointRule, Connection, Flow, FlowId, FlowKind, Rule};
+
+/// Starts every session file, followed by the format version as a
+/// little-endian `u16`. The rest is a sequence of zstd frames, together a
+/// stream of records, then a trailer.
+///
+/// A session holds what the flow store records: connections with their
+/// SNI, ALPN and TLS parameters, flows with raw request heads, bodies, SSE
+/// events, WebSocket messages, rule hits, breakpoint records and timings,
+/// and the rule and breakpoint rule lists.
+///
+/// Every record is a zstd frame of its own, so a flow can be read on its
+/// own from the offset the index gives. The index and the end frame come
+/// last, followed by a zstd skippable frame holding the index offset as a
+/// little-endian `u64`; plain zstd readers pass over it.
+pub const SESSION_MAGIC: &[u8; 8] = b"DEVYASES";
+pub const SESSION_VERSION: u16 = 1;
+
+/// Magic of the skippable frame that ends a session.
+const TRAILER_MAGIC: [u8; 4] = [0x50, 0x2a, 0x4d, 0x18];
+const TRAILER_LEN: usize = 16;
+
+/// Frames are a kind byte, a little-endian `u32` length and the payload.
+/// Readers skip kinds they don't know, so new ones don't need a version.
+mod frame {
+    pub const END: u8 = 0;
+    pub const META: u8 = 1;
+    pub const CONNECTION: u8 = 2;
+    /// Flow JSON without its binary parts, those follow as blobs.
+    pub const FLOW: u8 = 3;
+    pub const BLOB: u8 = 4;
+    pub const RULES: u8 = 5;
+    pub const BREAKPOINT_RULES: u8 = 6;
+    /// A [`super::SessionIndex`], right before the end.
+    pub const INDEX: u8 = 7;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMeta {
+    pub app_version: String,
+    pub created_at: u64,
+}
+
+/// A flow as listed in the index, enough to show it without reading it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionEntry {
+    pub id: FlowId,
+    pub kind: FlowKind,
+    pub method: String,
+    pub uri: String,
+    pub status: Option<u16>,
+    pub timestamp: u64,
+    /// Where the record of the flow starts in the file.
+    pub offset: u64,
+}
+
+/// The connections of a session and its flows in file order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionIndex {
+    pub connections: Vec<Connection>,
+    pub flows: Vec<SessionEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub enum SessionRecord {
+    Meta(SessionMeta),
+    Connection(Connection),
+    Flow(Box<Flow>),
+    Rules(Vec<Rule>),
+    BreakpointRules(Vec<BreakpointRule>),
+}
+
+/// Writes a session one record at a time, nothing is buffered beyond the
+/// record being written and the index.
+pub struct SessionWriter<W: Write> {
+    writer: W,
+    compressor: zstd::bulk::Compressor<'static>,
+    /// Frames of the record being written.
+    record: Vec<u8>,
+    /// Bytes written so far.
+    offset: u64,
+    index: SessionIndex,
+}
+
+impl<W: Write> SessionWriter<W> {
+    pub fn new(mut writer: W) -> anyhow::Result<Self> {
+        writer.write_all(SESSION_MAGIC)?;
+        writer.write_all(&SESSION_VERSION.to_le_bytes())?;
+
+        let mut session = Self {
+            writer,
+            compressor: zstd::bulk::Compressor::new(0)?,
+            record: vec![],
+            offset: (SESSION_MAGIC.len() + 2) as u64,
+            index: SessionIndex::default(),
+        };
+        session.write_json(
+            frame::META,
+            &SessionMeta {
+                app_version: env!("CARGO_PKG_VERSION").to_string(),
+                created_at: now_millis(),
+            },
+        )?;
+        session.end_record()?;
+        Ok(session)
+    }
+
+    pub fn write_connection(&mut self, connection: &Connection) -> anyhow::Result<()> {
+        self.write_json(frame::CONNECTION, connection)?;
+        self.end_record()?;
+        self.index.connections.push(connection.clone());
+        Ok(())
+    }
+
+    pub fn write_flow(&mut self, flow: &Flow) -> anyhow::Result<()> {
+        let mut stripped = flow.clone();
+        let mut blobs = vec![];
+        if let Some(raw_head) = &mut stripped.request.raw_head {
+            blobs.push(std::mem::take(raw_head));
+        }
+        for body in [&mut stripped.request_body, &mut stripped.response_body]
+            .into_iter()
+            .flatten()
+        {
+            blobs.push(std::mem::take(&mut body.data));
+        }
+        for message in stripped.websocket.iter_mut().flatten() {
+            blobs.push(std::mem::take(&mut message.payload));
+        }
+        for record in &mut stripped.breakpoints {
+            if let Some(request) = &mut record.original_request {
+                blobs.push(std::mem::take(&mut request.body));
+            }
+            if let Some(response) = &mut record.original_response {
+                blobs.push(std::mem::take(&mut response.body));
+            }
+        }
+
+        self.write_json(frame::FLOW, &stripped)?;
+        for blob in blobs {
+            self.write_frame(frame::BLOB, &blob)?;
+        }
+        self.index.flows.push(SessionEntry {
+            id: flow.id,
+            kind: flow.kind,
+            method: flow.request.method.clone(),
+            uri: flow.request.uri.clone(),
+            status: flow.response.as_ref().map(|response| response.status),
+            timestamp: flow.request.timestamp,
+            offset: self.offset,
+        });
+        self.end_record()
+    }
+
+    pub fn write_rules(&mut self, rules: &[Rule]) -> anyhow::Result<()> {
+        self.write_json(frame::RULES, &rules)?;
+        self.end_record()
+    }
+
+    pub fn write_breakpoint_rules(&mut self, rules: &[BreakpointRule]) -> anyhow::Result<()> {
+        self.write_json(frame::BREAKPOINT_RULES, &rules)?;
+        self.end_record()
+    }
+
+    /// Writes the index and marks the session complete, a file without the
+    /// end frame was cut short.
+    pub fn finish(mut self) -> anyhow::Result<W> {
+        let index_offset = self.offset;
+        let index = std::mem::take(&mut self.index);
+        self.write_json(frame::INDEX, &index)?;
+        self.write_frame(frame::END, &[])?;
+        self.end_record()?;
+
+        self.writer.write_all(&TRAILER_MAGIC)?;
+        self.writer.write_all(&8u32.to_le_bytes())?;
+        self.writer.write_all(&index_offset.to_le_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_json<T: Serialize>(&mut self, kind: u8, value: &T) -> anyhow::Result<()> {
+        self.write_frame(kind, &serde_json::to_vec(value)?)
+    }
+
+    fn write_frame(&mut self, kind: u8, payload: &[u8]) -> anyhow::Result<()> {
+        let len = u32::try_from(payload.len()).context("Session frame too large")?;
+        self.record.push(kind);
+        self.record.extend_from_slice(&len.to_le_bytes());
+        self.record.extend_from_slice(payload);
+        Ok(())
+    }
+
+    /// Compresses the frames written since the last record into a zstd
+    /// frame of their own.
+    fn end_record(&mut self) -> anyhow::Result<()> {
+        let compressed = self.compressor.compress(&self.record)?;
+        self.writer.write_all(&compressed)?;
+        self.offset += compressed.len() as u64;
+        self.record.clear();
+        Ok(())
+    }
+}
+
+/// Reads a session back record by record, holding one flow at a time.
+pub struct SessionReader<R: Read> {
+    decoder: zstd::stream::read::Decoder<'static, BufReader<R>>,
+    version: u16,
+    ended: bool,
+}
+
+impl<R: Read> SessionReader<R> {
+    pub fn new(mut reader: R) -> anyhow::Result<Self> {
+        let version = read_header(&mut reader)?;
+        Self::at_record(reader, version)
+    }
+
+    /// Reads on from `reader`, which has to be at the start of a record.
+    fn at_record(reader: R, version: u16) -> anyhow::Result<Self> {
+        Ok(Self {
+            decoder: zstd::stream::read::Decoder::new(reader)?,
+            version,
+            ended: false,
+        })
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    fn read_record(&mut self) -> anyhow::Result<Option<SessionRecord>> {
+        loop {
+            let Some((kind, payload)) = self.read_frame()? else {
+                bail!("Session file is truncated");
+            };
+            let record = match kind {
+                frame::END => {
+                    self.ended = true;
+                    return Ok(None);
+                }
+                frame::META => SessionRecord::Meta(serde_json::from_slice(&payload)?),
+                frame::CONNECTION => SessionRecord::Connection(serde_json::from_slice(&payload)?),
+                frame::FLOW => {
+                    let flow: Flow = serde_json::from_slice(&payload)?;
+                    SessionRecord::Flow(Box::new(self.read_blobs(flow)?))
+                }
+                frame::RULES => SessionRecord::Rules(serde_json::from_slice(&payload)?),
+                frame::BREAKPOINT_RULES => {
+                    SessionRecord::BreakpointRules(serde_json::from_slice(&payload)?)
+                }
+                frame::BLOB => bail!("Session has data without a flow"),
+                // only read through `SessionArchive`
+                frame::INDEX => continue,
+                _ => continue,
+            };
+            return Ok(Some(record));
+        }
+    }
+
+    /// Puts the binary parts back in the order [`SessionWriter::write_flow`]
+    /// took them out.
+    fn read_blobs(&mut self, mut flow: Flow) -> anyhow::Result<Flow> {
+        if let Some(raw_head) = &mut flow.request.raw_head {
+            *raw_head = self.read_blob()?;
+        }
+        for body in [&mut flow.request_body, &mut flow.response_body]
+            .into_iter()
+            .flatten()
+        {
+            body.data = self.read_blob()?;
+        }
+        let messages = flow.websocket.iter_mut().flatten();
+        for message in messages {
+            message.payload = self.read_blob()?;
+        }
+        for record in &mut flow.breakpoints {
+            if let Some(request) = &mut record.original_request {
+                request.body = self.read_blob()?;
+            }
+            if let Some(response) = &mut record.original_response {
+                response.body = self.read_blob()?;
+            }
+        }
+        Ok(flow)
+    }
+
+    fn read_blob(&mut self) -> anyhow::Result<Vec<u8>> {
+        match self.read_frame()? {
+            Some((frame::BLOB, payload)) => Ok(payload),
+            Some(_) => bail!("Session flow is missing its data"),
+            None => bail!("Session file is truncated"),
+        }
+    }
+
+    fn read_frame(&mut self) -> anyhow::Result<Option<(u8, Vec<u8>)>> {
+        let mut head = [0; 5];
+        match self.decoder.read_exact(&mut head) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let len = u32::from_le_bytes([head[1], head[2], head[3], head[4]]) as usize;
+
+        // read through `take` so a corrupt length can't allocate gigabytes
+        let mut payload = Vec::new();
+        (&mut self.decoder)
+            .take(len as u64)
+            .read_to_end(&mut payload)?;
+        if payload.len() != len {
+            return Err(anyhow!("Session file is truncated"));
+        }
+        Ok(Some((head[0], payload)))
+    }
+}
+
+impl<R: Read> Iterator for SessionReader<R> {
+    type Item = anyhow::Result<SessionRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ended {
+            return None;
+        }
+        let record = self.read_record();
+        if record.is_err() {
+            self.ended = true;
+        }
+        record.transpose()
+    }
+}
+
+/// Reads single flows of a session from the offsets in its index, without
+/// going through the rest of the file.
+pub struct SessionArchive<R: Read + Seek> {
+    reader: R,
+    version: u16,
+    index: SessionIndex,
+}
+
+impl<R: Read + Seek> SessionArchive<R> {
+    pub fn open(mut reader: R) -> anyhow::Result<Self> {
+        let version = read_header(&mut reader)?;
+
+        let mut trailer = [0; TRAILER_LEN];
+        reader.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+        reader.read_exact(&mut trailer)?;
+        if trailer[..4] != TRAILER_MAGIC || trailer[4..8] != 8u32.to_le_bytes() {
+            bail!("Session has no index, it can only be loaded whole");
+        }
+        let offset = u64::from_le_bytes(trailer[8..].try_into().unwrap());
+
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut frames = SessionReader::at_record(&mut reader, version)?;
+        let index = match frames.read_frame()? {
+            Some((frame::INDEX, payload)) => serde_json::from_slice(&payload)?,
+            _ => bail!("Session index is damaged"),
+        };
+
+        Ok(Self {
+            reader,
+            version,
+            index,
+        })
+    }
+
+    pub fn index(&self) -> &SessionIndex {
+        &self.index
+    }
+
+    /// Reads the flow saved with `id`, its ids are the ones in the index.
+    pub fn flow(&mut self, id: FlowId) -> anyhow::Result<Flow> {
+        let entry = self
+            .index
+            .flows
+            .iter()
+            .find(|entry| entry.id == id)
+            .ok_or_else(|| anyhow!("No flow {} in the session", id))?;
+
+        self.reader.seek(SeekFrom::Start(entry.offset))?;
+        match SessionReader::at_record(&mut self.reader, self.version)?.read_record()? {
+            Some(SessionRecord::Flow(flow)) if flow.id == id => Ok(*flow),
+            _ => bail!("Session index is damaged"),
+        }
+    }
+}
+
+/// Checks the magic and returns the format version.
+fn read_header<R: Read>(reader: &mut R) -> anyhow::Result<u16> {
+    let mut magic = [0; 8];
+    reader
+        .read_exact(&mut magic)
+        .context("Not a devya session")?;
+    if &magic != SESSION_MAGIC {
+        bail!("Not a devya session");
+    }
+    let mut version = [0; 2];
+    reader.read_exact(&mut version)?;
+    let version = u16::from_le_bytes(version);
+    if version > SESSION_VERSION {
+        bail!(
+            "Session format {} is newer than this version supports",
+            version
+        );
+    }
+    Ok(version)
+}
@@ -0,0 +1,224 @@
+use super::{load_session, save_session, SessionArchive, SessionReader, SessionRecord};
+use crate::mitm::{
+    BodyCapture, BreakpointOutcome, BreakpointPhase, BreakpointRecord, EditableRequest,
+    FlowRequest, FlowResponse, FlowStore, RequestMatcher, Rule, RuleAction, WsDirection, WsMessage,
+    WsMessageKind,
+};
+
+fn request(uri: &str) -> FlowRequest {
+    FlowRequest {
+        method: "POST".to_string(),
+        uri: uri.to_string(),
+        version: "HTTP/1.1".to_string(),
+        headers: vec![("content-type".to_string(), "image/png".to_string())],
+        raw_head: Some(b"POST /upload HTTP/1.1\r\n\r\n".to_vec()),
+        timestamp: 1,
+    }
+}
+
+fn saved_store() -> (FlowStore, Vec<u8>) {
+    let store = FlowStore::new();
+    let connection = store.open_connection(
+        "127.0.0.1:5000".parse().unwrap(),
+        Some("example.com".to_string()),
+        Some("http/1.1".to_string()),
+        Some((
+            "TLSv1.2".to_string(),
+            "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256".to_string(),
+        )),
+    );
+
+    let upload = store.insert(request("https://example.com/upload"), Some(connection));
+    store.add_breakpoint(
+        upload,
+        BreakpointRecord {
+            phase: BreakpointPhase::Request,
+            outcome: BreakpointOutcome::Edited,
+            paused_at: 1,
+            resumed_at: 2,
+            original_request: Some(EditableRequest {
+                method: "POST".to_string(),
+                uri: "https://example.com/upload".to_string(),
+                headers: vec![],
+                body: vec![0xff, 0],
+                trailers: vec![],
+            }),
+            original_response: None,
+        },
+    );
+    store.add_rule_hit(upload, RuleAction::Block { status: 503 });
+    store.set_request_body(upload, BodyCapture::complete([0, 0xff, 0x89, b'P']));
+    store.mark_sent(upload);
+    store.mark_response_started(upload);
+    store.set_response(
+        upload,
+        FlowResponse {
+            status: 201,
+            version: "HTTP/1.1".to_string(),
+            headers: vec![],
+            timestamp: 2,
+        },
+    );
+    store.set_response_body(upload, BodyCapture::complete(b""));
+    store.finish(upload);
+
+    let socket = store.insert(request("wss://example.com/ws"), Some(connection));
+    for (direction, payload) in [
+        (WsDirection::ClientToServer, b"ping".to_vec()),
+        (WsDirection::ServerToClient, vec![1, 2, 0xfe]),
+    ] {
+        store.push_ws_message(
+            socket,
+            WsMessage {
+                direction,
+                kind: WsMessageKind::Binary,
+                payload,
+                compressed: false,
+                injected: false,
+                timestamp: 3,
+            },
+        );
+    }
+
+    store.insert_replay(request("https://example.com/upload"), Some(upload));
+
+    let rules = [Rule {
+        matcher: RequestMatcher {
+            host: Some("example.com".to_string()),
+            ..Default::default()
+        },
+        action: RuleAction::Block { status: 503 },
+        probability: 0.5,
+    }];
+    let mut file = vec![];
+    assert_eq!(save_session(&mut file, &store, &rules, &[]).unwrap(), 3);
+    (store, file)
+}
+
+#[test]
+fn test_round_trip() {
+    let (original, file) = saved_store();
+
+    let store = FlowStore::new();
+    // ids in the new store are taken already
+    store.insert(request("https://other.test/"), None);
+    let loaded = load_session(&file[..], &store).unwrap();
+    assert_eq!(loaded.flows, [2, 3, 4]);
+    assert_eq!(loaded.rules.unwrap()[0].probability, 0.5);
+    assert_eq!(loaded.breakpoint_rules.unwrap().len(), 0);
+
+    for (before, id) in original.list().into_iter().zip(loaded.flows) {
+        let after = store.get(id).unwrap();
+        assert!(after.imported);
+        assert_eq!(after.request.raw_head, before.request.raw_head);
+        assert_eq!(
+            after.request_body.map(|body| body.data),
+            before.request_body.map(|body| body.data)
+        );
+        assert_eq!(
+            after.response_body.map(|body| (body.data, body.complete)),
+            before.response_body.map(|body| (body.data, body.complete))
+        );
+        assert_eq!(
+            after
+                .websocket
+                .map(|messages| messages.into_iter().map(|m| m.payload).collect::<Vec<_>>()),
+            before
+                .websocket
+                .map(|messages| messages.into_iter().map(|m| m.payload).collect())
+        );
+        assert_eq!(
+            after.response.map(|r| r.status),
+            before.response.map(|r| r.status)
+        );
+        assert_eq!(after.finished_at, before.finished_at);
+        assert_eq!(after.rule_hits, before.rule_hits);
+        assert_eq!(after.timings, before.timings);
+        assert_eq!(
+            after
+                .breakpoints
+                .iter()
+                .map(|record| (
+                    record.outcome,
+                    record.original_request.clone().map(|r| r.body)
+                ))
+                .collect::<Vec<_>>(),
+            before
+                .breakpoints
+                .iter()
+                .map(|record| (
+                    record.outcome,
+                    record.original_request.clone().map(|r| r.body)
+                ))
+                .collect::<Vec<_>>()
+        );
+    }
+    let upload = store.get(2).unwrap();
+    assert_eq!(upload.breakpoints.len(), 1);
+    assert!(upload.timings.response_started_at.is_some());
+
+    let connections = store.connections();
+    assert_eq!(connections.len(), 1);
+    assert_eq!(connections[0].server_name.as_deref(), Some("example.com"));
+    assert_eq!(connections[0].tls_version.as_deref(), Some("TLSv1.2"));
+    assert_eq!(store.get(2).unwrap().connection_id, Some(connections[0].id));
+    assert_eq!(store.get(4).unwrap().replay_of, Some(2));
+}
+
+#[test]
+fn test_archive() {
+    let (original, file) = saved_store();
+    let mut archive = SessionArchive::open(std::io::Cursor::new(&file)).unwrap();
+
+    let index = archive.index().clone();
+    assert_eq!(index.connections.len(), 1);
+    assert_eq!(
+        index.flows.iter().map(|entry| entry.id).collect::<Vec<_>>(),
+        [1, 2, 3]
+    );
+    assert_eq!(index.flows[0].status, Some(201));
+    assert_eq!(index.flows[1].uri, "wss://example.com/ws");
+
+    // any flow can be read, in any order
+    let socket = archive.flow(2).unwrap();
+    assert_eq!(socket.websocket.unwrap()[1].payload, [1, 2, 0xfe]);
+    let upload = archive.flow(1).unwrap();
+    assert_eq!(
+        upload.request_body.unwrap().data,
+        original.get(1).unwrap().request_body.unwrap().data
+    );
+    assert_eq!(
+        upload.breakpoints[0]
+            .original_request
+            .as_ref()
+            .unwrap()
+            .body,
+        [0xff, 0]
+    );
+    assert!(archive.flow(7).is_err());
+
+    // a session written as one stream can only be loaded
+    let mut unindexed = file.clone();
+    unindexed.truncate(file.len() - 16);
+    assert!(SessionArchive::open(std::io::Cursor::new(&unindexed)).is_err());
+}
+
+#[test]
+fn test_reject_bad_files() {
+    let (_, file) = saved_store();
+
+    let records = SessionReader::new(&file[..])
+        .unwrap()
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap();
+    assert!(matches!(records[0], SessionRecord::Meta(_)));
+
+    assert!(SessionReader::new(&b"{\"log\":{}}"[..]).is_err());
+
+    let mut newer = file.clone();
+    newer[8] = 0xff;
+    assert!(SessionReader::new(&newer[..]).is_err());
+
+    let truncated = &file[..file.len() - 20];
+    assert!(load_session(truncated, &FlowStore::new()).is_err());
+}
@@ -0,0 +1,8 @@
+mod archive;
+mod restore;
+
+#[cfg(test)]
+mod archive_test;
+
+pub use archive::*;
+pub use restore::*;
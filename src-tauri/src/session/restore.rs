@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+
+use super::{SessionArchive, SessionIndex, SessionReader, SessionRecord, SessionWriter};
+use crate::mitm::{BreakpointRule, Flow, FlowId, FlowStore, Rule};
+
+#[derive(Debug, Default)]
+pub struct LoadedSession {
+    /// Ids the flows got in the store, in file order.
+    pub flows: Vec<FlowId>,
+    pub rules: Option<Vec<Rule>>,
+    pub breakpoint_rules: Option<Vec<BreakpointRule>>,
+}
+
+/// Writes every connection and flow of `flow_store` and the given rules.
+/// Flows are fetched one at a time so the store isn't copied whole.
+/// Returns the number of flows written.
+pub fn save_session<W: Write>(
+    writer: W,
+    flow_store: &FlowStore,
+    rules: &[Rule],
+    breakpoint_rules: &[BreakpointRule],
+) -> anyhow::Result<usize> {
+    let mut session = SessionWriter::new(writer)?;
+    for connection in flow_store.connections() {
+        session.write_connection(&connection)?;
+    }
+    let mut count = 0;
+    for id in flow_store.ids() {
+        // cleared while saving
+        let Some(flow) = flow_store.get(id) else {
+            continue;
+        };
+        session.write_flow(&flow)?;
+        count += 1;
+    }
+    session.write_rules(rules)?;
+    session.write_breakpoint_rules(breakpoint_rules)?;
+    session.finish()?;
+    Ok(count)
+}
+
+/// Adds the flows of a session to `flow_store` next to the ones it has.
+/// Everything gets new ids, links between flows and to connections are
+/// rewritten to match.
+///
+/// The store keeps flows in memory, bodies included, so a session has to
+/// fit there to be opened this way. Larger ones can be browsed through
+/// [`OpenSession`], which reads flows from the file as they are asked for.
+pub fn load_session<R: Read>(reader: R, flow_store: &FlowStore) -> anyhow::Result<LoadedSession> {
+    let mut loaded = LoadedSession::default();
+    let mut connection_ids = HashMap::new();
+    let mut flow_ids = HashMap::new();
+
+    for record in SessionReader::new(reader)? {
+        match record? {
+            SessionRecord::Meta(_) => {}
+            SessionRecord::Connection(connection) => {
+                let old_id = connection.id;
+                connection_ids.insert(old_id, flow_store.import_connection(connection));
+            }
+            SessionRecord::Flow(flow) => {
+                let mut flow = *flow;
+                let old_id = flow.id;
+                flow.connection_id = flow
+                    .connection_id
+                    .and_then(|id| connection_ids.get(&id).copied());
+                // replays are always saved after the flow they repeat
+                flow.replay_of = flow.replay_of.and_then(|id| flow_ids.get(&id).copied());
+                let id = flow_store.import(flow);
+                flow_ids.insert(old_id, id);
+                loaded.flows.push(id);
+            }
+            SessionRecord::Rules(rules) => loaded.rules = Some(rules),
+            SessionRecord::BreakpointRules(rules) => loaded.breakpoint_rules = Some(rules),
+        }
+    }
+
+    Ok(loaded)
+}
+
+/// A session file browsed in place, its flows are read on demand and never
+/// enter the flow store.
+#[derive(Default)]
+pub struct OpenSession {
+    archive: Mutex<Option<SessionArchive<BufReader<File>>>>,
+}
+
+impl OpenSession {
+    /// Opens the session at `path` in place of the one open before and
+    /// returns its index.
+    pub fn open(&self, path: &Path) -> anyhow::Result<SessionIndex> {
+        let archive = SessionArchive::open(BufReader::new(File::open(path)?))?;
+        let index = archive.index().clone();
+        *self.archive.lock().unwrap() = Some(archive);
+        Ok(index)
+    }
+
+    pub fn flow(&self, id: FlowId) -> anyhow::Result<Flow> {
+        self.archive
+            .lock()
+            .unwrap()
+            .as_mut()
+            .ok_or_else(|| anyhow!("No session is open"))?
+            .flow(id)
+    }
+
+    pub fn close(&self) {
+        self.archive.lock().unwrap().take();
+    }
+}
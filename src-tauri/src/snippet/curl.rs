@@ -0,0 +1,426 @@
+use anyhow::{anyhow, bail, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+
+use crate::mitm::EditableRequest;
+
+/// What `--compressed` asks for.
+const ACCEPT_ENCODING: &str = "deflate, gzip, br, zstd";
+
+/// Options that don't change the request.
+const IGNORED_FLAGS: [&str; 24] = [
+    "-s",
+    "--silent",
+    "-S",
+    "--show-error",
+    "-v",
+    "--verbose",
+    "-i",
+    "--include",
+    "-L",
+    "--location",
+    "-f",
+    "--fail",
+    "-N",
+    "--no-buffer",
+    "-#",
+    "--progress-bar",
+    "--http1.1",
+    "--http2",
+    "--http2-prior-knowledge",
+    "--tlsv1.2",
+    "--tlsv1.3",
+    "--globoff",
+    "-k",
+    "--insecure",
+];
+
+/// Ignored like [`IGNORED_FLAGS`], but followed by a value.
+const IGNORED_OPTIONS: [&str; 15] = [
+    "-o",
+    "--output",
+    "-m",
+    "--max-time",
+    "--connect-timeout",
+    "-w",
+    "--write-out",
+    "--retry",
+    "-x",
+    "--proxy",
+    "--resolve",
+    "--cacert",
+    "-c",
+    "--cookie-jar",
+    "--max-redirs",
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CurlCommand {
+    pub request: EditableRequest,
+    /// Options that were left out because they don't shape the request.
+    /// `-k` is one of them, replays verify upstream certificates all the same.
+    pub ignored: Vec<String>,
+}
+
+/// Parses a `curl` command line as copied from a browser or shell history.
+/// `@file` arguments are read relative to the working directory.
+pub fn parse_curl(command: &str) -> anyhow::Result<CurlCommand> {
+    let words = shell_words(command)?;
+    let mut args = words.iter().map(Vec::as_slice).peekable();
+    if args.peek() == Some(&&b"curl"[..]) {
+        args.next();
+    }
+
+    let mut url = None;
+    let mut method = None;
+    let mut headers: Vec<(String, String)> = vec![];
+    let mut data: Vec<Vec<u8>> = vec![];
+    let mut form_data = false;
+    let mut get = false;
+    let mut ignored = vec![];
+
+    while let Some(arg) = args.next() {
+        let (option, attached) = split_option(arg);
+        let option = text(option)?;
+        // bodies may be binary, everything else has to be text
+        let mut value = || match attached {
+            Some(value) => Ok(value),
+            None => args
+                .next()
+                .ok_or_else(|| anyhow!("{} needs a value", option)),
+        };
+
+        match option {
+            "-X" | "--request" => method = Some(text(value()?)?.to_string()),
+            "-H" | "--header" => {
+                if let Some(header) = parse_header(text(value()?)?) {
+                    headers.push(header);
+                }
+            }
+            "-d" | "--data" | "--data-ascii" => {
+                data.push(read_data(value()?, true)?);
+                form_data = true;
+            }
+            "--data-raw" => {
+                data.push(value()?.to_vec());
+                form_data = true;
+            }
+            "--data-binary" => {
+                data.push(read_data(value()?, false)?);
+                form_data = true;
+            }
+            "--data-urlencode" => {
+                data.push(url_encode_data(value()?)?.into_bytes());
+                form_data = true;
+            }
+            "--json" => {
+                data.push(read_data(value()?, false)?);
+                set_default(&mut headers, "content-type", "application/json");
+                set_default(&mut headers, "accept", "application/json");
+            }
+            "-u" | "--user" => {
+                let mut credentials = value()?.to_vec();
+                // curl would prompt for the missing password, send an empty one
+                if !credentials.contains(&b':') {
+                    credentials.push(b':');
+                }
+                let credentials = STANDARD.encode(credentials);
+                headers.push((
+                    "authorization".to_string(),
+                    format!("Basic {}", credentials),
+                ));
+            }
+            "-A" | "--user-agent" => {
+                headers.push(("user-agent".to_string(), text(value()?)?.to_string()))
+            }
+            "-e" | "--referer" => {
+                headers.push(("referer".to_string(), text(value()?)?.to_string()))
+            }
+            "-b" | "--cookie" => {
+                let cookie = text(value()?)?;
+                if cookie.contains('=') {
+                    headers.push(("cookie".to_string(), cookie.to_string()));
+                } else {
+                    ignored.push(format!("{} {}", option, cookie));
+                }
+            }
+            "--compressed" => set_default(&mut headers, "accept-encoding", ACCEPT_ENCODING),
+            "-G" | "--get" => get = true,
+            "-I" | "--head" => method = Some("HEAD".to_string()),
+            "--url" => url = Some(text(value()?)?.to_string()),
+            "-F" | "--form" => bail!("Multipart forms (-F) are not supported"),
+            _ if IGNORED_FLAGS.contains(&option) => ignored.push(option.to_string()),
+            _ if IGNORED_OPTIONS.contains(&option) => {
+                ignored.push(format!("{} {}", option, text(value()?)?))
+            }
+            // grouped flags, as in -sSLk
+            _ if option.len() > 2 && option.starts_with('-') && !option.starts_with("--") => {
+                for letter in option[1..].chars() {
+                    match letter {
+                        'G' => get = true,
+                        'I' => method = Some("HEAD".to_string()),
+                        _ if IGNORED_FLAGS.contains(&format!("-{}", letter).as_str()) => {
+                            ignored.push(format!("-{}", letter))
+                        }
+                        _ => bail!("Unknown option -{} in {}", letter, option),
+                    }
+                }
+            }
+            _ if option.starts_with('-') && option.len() > 1 => bail!("Unknown option {}", option),
+            _ if url.is_none() => url = Some(option.to_string()),
+            _ => bail!("Only one URL is supported, got {}", option),
+        }
+    }
+
+    let mut url = url.ok_or_else(|| anyhow!("No URL in the command"))?;
+    if !url.contains("://") {
+        url = format!("http://{}", url);
+    }
+
+    let mut body = data.join(&b'&');
+    if get && !body.is_empty() {
+        let separator = if url.contains('?') { '&' } else { '?' };
+        url = format!("{}{}{}", url, separator, String::from_utf8_lossy(&body));
+        body.clear();
+    }
+    if form_data && !body.is_empty() {
+        set_default(
+            &mut headers,
+            "content-type",
+            "application/x-www-form-urlencoded",
+        );
+    }
+
+    let method = match method {
+        Some(method) => method,
+        None if !body.is_empty() => "POST".to_string(),
+        None => "GET".to_string(),
+    };
+
+    Ok(CurlCommand {
+        request: EditableRequest {
+            method,
+            uri: url,
+            headers,
+            body,
+            trailers: vec![],
+        },
+        ignored,
+    })
+}
+
+/// Short options may carry their value, as in `-XPOST`.
+fn split_option(arg: &[u8]) -> (&[u8], Option<&[u8]>) {
+    match arg {
+        [b'-', short, _, ..] if b"XHdubAeomwxc".contains(short) => (&arg[..2], Some(&arg[2..])),
+        _ => (arg, None),
+    }
+}
+
+fn text(word: &[u8]) -> anyhow::Result<&str> {
+    std::str::from_utf8(word).map_err(|_| anyhow!("Only bodies can be binary"))
+}
+
+/// `Name: value`, `Name;` for an empty value. `Name:` only removes a
+/// header curl would add by itself, which there is none of here.
+fn parse_header(header: &str) -> Option<(String, String)> {
+    match header.split_once(':') {
+        Some((name, value)) if !value.trim().is_empty() => {
+            Some((name.trim().to_string(), value.trim().to_string()))
+        }
+        Some(_) => None,
+        None => header
+            .strip_suffix(';')
+            .map(|name| (name.trim().to_string(), String::new())),
+    }
+}
+
+fn set_default(headers: &mut Vec<(String, String)>, name: &str, value: &str) {
+    if !headers.iter().any(|(n, _)| n.eq_ignore_ascii_case(name)) {
+        headers.push((name.to_string(), value.to_string()));
+    }
+}
+
+/// Like curl, `strip_newlines` drops the line breaks of `@file` contents,
+/// as `--data` does.
+fn read_data(value: &[u8], strip_newlines: bool) -> anyhow::Result<Vec<u8>> {
+    match value.strip_prefix(b"@") {
+        Some(b"-") => bail!("Data from stdin can't be imported"),
+        Some(path) => {
+            let path = text(path)?;
+            let mut data =
+                std::fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+            if strip_newlines {
+                data.retain(|b| *b != b'\r' && *b != b'\n');
+            }
+            Ok(data)
+        }
+        None => Ok(value.to_vec()),
+    }
+}
+
+/// `content`, `=content`, `name=content`, `@file` or `name@file`.
+fn url_encode_data(value: &[u8]) -> anyhow::Result<String> {
+    let (name, content) = match value.iter().position(|b| *b == b'=' || *b == b'@') {
+        Some(at) if value[at] == b'=' => (&value[..at], value[at + 1..].to_vec()),
+        Some(at) => (&value[..at], read_data(&value[at..], false)?),
+        None => (&b""[..], value.to_vec()),
+    };
+    let encoded = percent_encode(&content);
+    Ok(if name.is_empty() {
+        encoded
+    } else {
+        format!("{}={}", text(name)?, encoded)
+    })
+}
+
+fn percent_encode(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    for &byte in data {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Splits `command` into words the way a POSIX shell would, with bash's
+/// `$'...'` strings that browsers use for bodies with control characters.
+fn shell_words(command: &str) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut words = vec![];
+    let mut word: Option<Vec<u8>> = None;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' | '\r' => {
+                if let Some(word) = word.take() {
+                    words.push(word);
+                }
+            }
+            '\\' => match chars.next() {
+                // a line continuation
+                Some('\n') => {}
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                Some(c) => push_char(word.get_or_insert_with(Vec::new), c),
+                None => bail!("Trailing backslash"),
+            },
+            '\'' => {
+                let word = word.get_or_insert_with(Vec::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => push_char(word, c),
+                        None => bail!("Unterminated single quote"),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(Vec::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => push_char(word, c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                word.push(b'\\');
+                                push_char(word, c);
+                            }
+                            None => bail!("Unterminated double quote"),
+                        },
+                        Some(c) => push_char(word, c),
+                        None => bail!("Unterminated double quote"),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                ansi_c_string(&mut chars, word.get_or_insert_with(Vec::new))?;
+            }
+            c => push_char(word.get_or_insert_with(Vec::new), c),
+        }
+    }
+    if let Some(word) = word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+fn ansi_c_string(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    word: &mut Vec<u8>,
+) -> anyhow::Result<()> {
+    loop {
+        let c = chars
+            .next()
+            .ok_or_else(|| anyhow!("Unterminated $' string"))?;
+        if c == '\'' {
+            return Ok(());
+        }
+        if c != '\\' {
+            push_char(word, c);
+            continue;
+        }
+
+        let escaped = chars
+            .next()
+            .ok_or_else(|| anyhow!("Unterminated $' string"))?;
+        match escaped {
+            'n' => word.push(b'\n'),
+            'r' => word.push(b'\r'),
+            't' => word.push(b'\t'),
+            'e' | 'E' => word.push(0x1b),
+            'x' => word.push(take_digits(chars, 16, 2)? as u8),
+            'u' | 'U' => {
+                let digits = if escaped == 'u' { 4 } else { 8 };
+                let code = take_digits(chars, 16, digits)?;
+                let c = char::from_u32(code).ok_or_else(|| anyhow!("Invalid \\u escape"))?;
+                push_char(word, c);
+            }
+            '0'..='7' => {
+                let mut code = escaped.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                word.push(code as u8);
+            }
+            c => push_char(word, c),
+        }
+    }
+}
+
+fn take_digits(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    radix: u32,
+    max: usize,
+) -> anyhow::Result<u32> {
+    let mut value = None;
+    for _ in 0..max {
+        match chars.peek().and_then(|c| c.to_digit(radix)) {
+            Some(digit) => {
+                value = Some(value.unwrap_or(0) * radix + digit);
+                chars.next();
+            }
+            None => break,
+        }
+    }
+    value.ok_or_else(|| anyhow!("Escape without digits"))
+}
+
+fn push_char(word: &mut Vec<u8>, c: char) {
+    let mut buf = [0; 4];
+    word.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
@@ -0,0 +1,114 @@
+use super::{generate_snippet, parse_curl, SnippetFormat};
+use crate::mitm::EditableRequest;
+
+fn header<'a>(request: &'a EditableRequest, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+#[test]
+fn test_parse_browser_command() {
+    let command = parse_curl(
+        "curl 'https://api.test/items' \\\n  \
+         -H 'accept: application/json' \\\n  \
+         -H 'x-empty;' \\\n  \
+         -H 'x-removed:' \\\n  \
+         -b 'sid=1' \\\n  \
+         --data-raw $'{\"note\":\"it\\'s\\\\n\\x41\"}' \\\n  \
+         --compressed -sSLk",
+    )
+    .unwrap();
+
+    let request = &command.request;
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.uri, "https://api.test/items");
+    assert_eq!(request.body, b"{\"note\":\"it's\\nA\"}");
+    assert_eq!(header(request, "accept"), Some("application/json"));
+    assert_eq!(header(request, "x-empty"), Some(""));
+    assert_eq!(header(request, "x-removed"), None);
+    assert_eq!(header(request, "cookie"), Some("sid=1"));
+    assert_eq!(
+        header(request, "content-type"),
+        Some("application/x-www-form-urlencoded")
+    );
+    assert!(header(request, "accept-encoding").is_some());
+    assert_eq!(command.ignored, ["-s", "-S", "-L", "-k"]);
+}
+
+#[test]
+fn test_parse_options() {
+    let path = std::env::temp_dir().join("devya_curl_test.bin");
+    std::fs::write(&path, [0u8, 0xff, b'\n']).unwrap();
+
+    let command = parse_curl(&format!(
+        "curl -XPUT -u user:pass --data-binary @{} example.com:8080/upload -o out.txt",
+        path.display()
+    ))
+    .unwrap();
+    let request = &command.request;
+    assert_eq!(request.method, "PUT");
+    assert_eq!(request.uri, "http://example.com:8080/upload");
+    assert_eq!(request.body, [0, 0xff, b'\n']);
+    assert_eq!(header(request, "authorization"), Some("Basic dXNlcjpwYXNz"));
+    assert_eq!(command.ignored, ["-o out.txt"]);
+
+    let command = parse_curl("curl -u user https://example.com").unwrap();
+    assert_eq!(
+        header(&command.request, "authorization"),
+        Some("Basic dXNlcjo=")
+    );
+
+    // --data drops the line breaks of files
+    std::fs::write(&path, "a=1\r\nb=2\n").unwrap();
+    let command = parse_curl(&format!("curl -d @{} https://example.com", path.display())).unwrap();
+    assert_eq!(command.request.body, b"a=1b=2");
+    // only file contents lose them
+    let command = parse_curl("curl -d $'a=1\\nb=2' https://example.com").unwrap();
+    assert_eq!(command.request.body, b"a=1\nb=2");
+    std::fs::remove_file(&path).unwrap();
+
+    let command = parse_curl(
+        "curl -G \"https://example.com/search?x=1\" -d q=a --data-urlencode 'name=a b&c'",
+    )
+    .unwrap();
+    let request = &command.request;
+    assert_eq!(request.method, "GET");
+    assert!(request.body.is_empty());
+    assert_eq!(
+        request.uri,
+        "https://example.com/search?x=1&q=a&name=a%20b%26c"
+    );
+
+    assert!(parse_curl("curl -F file=@a.txt https://example.com").is_err());
+    assert!(parse_curl("curl --bogus https://example.com").is_err());
+    assert!(parse_curl("curl -H 'unterminated https://example.com").is_err());
+    assert!(parse_curl("curl -d x=1").is_err());
+}
+
+#[test]
+fn test_snippet_round_trip() {
+    let request = EditableRequest {
+        method: "PATCH".to_string(),
+        uri: "https://api.test/items/1?q=it's".to_string(),
+        headers: vec![
+            (
+                "content-type".to_string(),
+                "application/octet-stream".to_string(),
+            ),
+            ("x-quote".to_string(), "a 'b' \"c\"".to_string()),
+        ],
+        body: b"line 1\nit's \"quoted\" $HOME `x`".to_vec(),
+        trailers: vec![],
+    };
+
+    let parsed = parse_curl(&generate_snippet(&request, SnippetFormat::Curl))
+        .unwrap()
+        .request;
+    assert_eq!(parsed.method, request.method);
+    assert_eq!(parsed.uri, request.uri);
+    assert_eq!(parsed.headers, request.headers);
+    assert_eq!(parsed.body, request.body);
+}
@@ -0,0 +1,336 @@
+use std::fmt::Write;
+
+use hyper::Uri;
+use serde::{Deserialize, Serialize};
+
+use crate::mitm::EditableRequest;
+
+/// Headers the tools add on their own, or that describe the connection the
+/// request was captured on.
+const SKIPPED_HEADERS: [&str; 6] = [
+    "host",
+    "content-length",
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SnippetFormat {
+    Curl,
+    Httpie,
+    Fetch,
+    PythonRequests,
+    RawHttp,
+}
+
+pub fn generate_snippet(request: &EditableRequest, format: SnippetFormat) -> String {
+    match format {
+        SnippetFormat::Curl => curl(request),
+        SnippetFormat::Httpie => httpie(request),
+        SnippetFormat::Fetch => fetch(request),
+        SnippetFormat::PythonRequests => python_requests(request),
+        SnippetFormat::RawHttp => raw_http(request),
+    }
+}
+
+fn curl(request: &EditableRequest) -> String {
+    let method = request.method.to_ascii_uppercase();
+    let has_body = !request.body.is_empty();
+    let mut args = vec![shell_quote(&request.uri)];
+
+    match method.as_str() {
+        "HEAD" => args.push("--head".to_string()),
+        "GET" if !has_body => {}
+        "POST" if has_body => {}
+        _ => args.push(format!("-X {}", shell_quote(&method))),
+    }
+
+    let mut compressed = false;
+    for (name, value) in headers(request) {
+        // curl only decodes what it asked for itself
+        if name.eq_ignore_ascii_case("accept-encoding") {
+            compressed = true;
+            continue;
+        }
+        args.push(format!(
+            "-H {}",
+            shell_quote(&header_arg(name, value, ": "))
+        ));
+    }
+    if compressed {
+        args.push("--compressed".to_string());
+    }
+
+    match body_text(&request.body) {
+        Some("") => format!("curl {}", args.join(" \\\n  ")),
+        Some(text) => {
+            args.push(format!("--data-raw {}", shell_quote(text)));
+            format!("curl {}", args.join(" \\\n  "))
+        }
+        None => {
+            args.push("--data-binary @-".to_string());
+            format!(
+                "{} | curl {}",
+                shell_printf(&request.body),
+                args.join(" \\\n  ")
+            )
+        }
+    }
+}
+
+fn httpie(request: &EditableRequest) -> String {
+    let mut args = vec![format!(
+        "{} {}",
+        shell_quote(&request.method.to_ascii_uppercase()),
+        shell_quote(&request.uri)
+    )];
+    for (name, value) in headers(request) {
+        args.push(shell_quote(&header_arg(name, value, ":")));
+    }
+
+    match body_text(&request.body) {
+        Some(text) if !text.is_empty() => {
+            args.push(format!("--raw {}", shell_quote(text)));
+            format!("http {}", args.join(" \\\n  "))
+        }
+        Some(_) => format!("http {}", args.join(" \\\n  ")),
+        // httpie sends whatever is piped in as the body
+        None => format!(
+            "{} | http {}",
+            shell_printf(&request.body),
+            args.join(" \\\n  ")
+        ),
+    }
+}
+
+fn fetch(request: &EditableRequest) -> String {
+    let mut options = vec![format!(
+        "  \"method\": {}",
+        json_string(&request.method.to_ascii_uppercase())
+    )];
+
+    let headers = merged_headers(request);
+    if !headers.is_empty() {
+        let entries: Vec<String> = headers
+            .iter()
+            .map(|(name, value)| format!("    {}: {}", json_string(name), json_string(value)))
+            .collect();
+        options.push(format!("  \"headers\": {{\n{}\n  }}", entries.join(",\n")));
+    }
+
+    if !request.body.is_empty() {
+        let body = match body_text(&request.body) {
+            Some(text) => json_string(text),
+            None => format!(
+                "new Uint8Array([{}])",
+                request
+                    .body
+                    .iter()
+                    .map(u8::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        options.push(format!("  \"body\": {}", body));
+    }
+
+    format!(
+        "fetch({}, {{\n{}\n}});",
+        json_string(&request.uri),
+        options.join(",\n")
+    )
+}
+
+fn python_requests(request: &EditableRequest) -> String {
+    let mut snippet = format!("import requests\n\nurl = {}\n", json_string(&request.uri));
+    let mut arguments = String::new();
+
+    let headers = merged_headers(request);
+    if !headers.is_empty() {
+        snippet.push_str("headers = {\n");
+        for (name, value) in &headers {
+            let _ = writeln!(
+                snippet,
+                "    {}: {},",
+                json_string(name),
+                json_string(value)
+            );
+        }
+        snippet.push_str("}\n");
+        arguments.push_str(", headers=headers");
+    }
+
+    if !request.body.is_empty() {
+        // a str body goes out as Latin-1, anything else has to be bytes
+        let data = match body_text(&request.body) {
+            Some(text) if text.is_ascii() => json_string(text),
+            Some(text) => format!("{}.encode()", json_string(text)),
+            None => python_bytes(&request.body),
+        };
+        let _ = writeln!(snippet, "data = {}", data);
+        arguments.push_str(", data=data");
+    }
+
+    let _ = write!(
+        snippet,
+        "\nresponse = requests.request({}, url{})\n",
+        json_string(&request.method.to_ascii_uppercase()),
+        arguments
+    );
+    snippet
+}
+
+/// The request as an HTTP/1.1 message. Binary bodies can't be pasted as
+/// text and are shown with `\xHH` escapes.
+fn raw_http(request: &EditableRequest) -> String {
+    let uri = request.uri.parse::<Uri>().ok();
+    let target = uri
+        .as_ref()
+        .and_then(|uri| uri.path_and_query())
+        .map_or(request.uri.as_str(), |path| path.as_str());
+
+    let mut message = format!("{} {} HTTP/1.1\r\n", request.method, target);
+    let has_header = |header: &str| {
+        request
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(header))
+    };
+    // HTTP/2 requests carry neither
+    if let Some(authority) = uri.as_ref().and_then(Uri::authority) {
+        if !has_header("host") {
+            let _ = write!(message, "Host: {}\r\n", authority);
+        }
+    }
+    for (name, value) in request.headers.iter().filter(|(n, _)| !n.starts_with(':')) {
+        let _ = write!(message, "{}: {}\r\n", name, value);
+    }
+    if !request.body.is_empty() && !has_header("content-length") && !has_header("transfer-encoding")
+    {
+        let _ = write!(message, "Content-Length: {}\r\n", request.body.len());
+    }
+    message.push_str("\r\n");
+
+    match std::str::from_utf8(&request.body) {
+        Ok(text) => message.push_str(text),
+        Err(_) => {
+            for chunk in request.body.utf8_chunks() {
+                message.push_str(chunk.valid());
+                for byte in chunk.invalid() {
+                    let _ = write!(message, "\\x{:02x}", byte);
+                }
+            }
+        }
+    }
+    message
+}
+
+fn headers(request: &EditableRequest) -> impl Iterator<Item = (&str, &str)> {
+    request
+        .headers
+        .iter()
+        .filter(|(name, _)| {
+            !name.starts_with(':') && !SKIPPED_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h))
+        })
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+}
+
+/// Headers for maps that can't repeat a name, repeated ones are joined the
+/// way a single header line would carry them.
+fn merged_headers(request: &EditableRequest) -> Vec<(String, String)> {
+    let mut merged: Vec<(String, String)> = vec![];
+    for (name, value) in headers(request) {
+        match merged
+            .iter_mut()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        {
+            Some((_, joined)) => {
+                joined.push_str(if name.eq_ignore_ascii_case("cookie") {
+                    "; "
+                } else {
+                    ", "
+                });
+                joined.push_str(value);
+            }
+            None => merged.push((name.to_string(), value.to_string())),
+        }
+    }
+    merged
+}
+
+/// `Name;` is how both curl and httpie send a header without a value.
+fn header_arg(name: &str, value: &str, separator: &str) -> String {
+    if value.is_empty() {
+        format!("{};", name)
+    } else {
+        format!("{}{}{}", name, separator, value)
+    }
+}
+
+/// The body when it can be written as text, without NULs or control
+/// characters that don't survive a paste.
+fn body_text(body: &[u8]) -> Option<&str> {
+    let text = std::str::from_utf8(body).ok()?;
+    text.chars()
+        .all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+        .then_some(text)
+}
+
+/// Quotes `value` for a POSIX shell, plain words are left alone.
+fn shell_quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_./:=@,+%".contains(&b));
+    if plain {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+/// A `printf` writing `data` byte for byte. Octal escapes are the only ones
+/// POSIX `printf` knows, and unlike arguments they can produce NULs.
+fn shell_printf(data: &[u8]) -> String {
+    let mut format = String::from("printf '");
+    for &byte in data {
+        match byte {
+            b'%' => format.push_str("%%"),
+            b'\\' => format.push_str("\\\\"),
+            b'\'' => format.push_str(r"'\''"),
+            b' '..=b'~' => format.push(byte as char),
+            _ => {
+                let _ = write!(format, "\\{:03o}", byte);
+            }
+        }
+    }
+    format.push('\'');
+    format
+}
+
+/// JSON string literals are valid JavaScript and Python too.
+fn json_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn python_bytes(data: &[u8]) -> String {
+    let mut literal = String::from("b\"");
+    for &byte in data {
+        match byte {
+            b'\\' => literal.push_str("\\\\"),
+            b'"' => literal.push_str("\\\""),
+            b'\n' => literal.push_str("\\n"),
+            b'\r' => literal.push_str("\\r"),
+            b'\t' => literal.push_str("\\t"),
+            b' '..=b'~' => literal.push(byte as char),
+            _ => {
+                let _ = write!(literal, "\\x{:02x}", byte);
+            }
+        }
+    }
+    literal.push('"');
+    literal
+}
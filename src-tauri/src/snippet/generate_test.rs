@@ -0,0 +1,120 @@
+use super::{generate_snippet, SnippetFormat};
+use crate::mitm::EditableRequest;
+
+fn request(method: &str, headers: &[(&str, &str)], body: &[u8]) -> EditableRequest {
+    EditableRequest {
+        method: method.to_string(),
+        uri: "https://api.test/items?q=it's&x=1".to_string(),
+        headers: headers
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+        body: body.to_vec(),
+        trailers: vec![],
+    }
+}
+
+#[test]
+fn test_shell_commands() {
+    let post = request(
+        "POST",
+        &[
+            ("host", "api.test"),
+            ("content-type", "application/json"),
+            ("accept-encoding", "gzip, br"),
+            ("x-empty", ""),
+            ("content-length", "16"),
+        ],
+        b"{\"name\":\"o'neil\"}",
+    );
+    assert_eq!(
+        generate_snippet(&post, SnippetFormat::Curl),
+        "curl 'https://api.test/items?q=it'\\''s&x=1' \\\n  \
+         -H 'content-type: application/json' \\\n  \
+         -H 'x-empty;' \\\n  \
+         --compressed \\\n  \
+         --data-raw '{\"name\":\"o'\\''neil\"}'"
+    );
+    assert_eq!(
+        generate_snippet(&post, SnippetFormat::Httpie),
+        "http POST 'https://api.test/items?q=it'\\''s&x=1' \\\n  \
+         content-type:application/json \\\n  \
+         'accept-encoding:gzip, br' \\\n  \
+         'x-empty;' \\\n  \
+         --raw '{\"name\":\"o'\\''neil\"}'"
+    );
+
+    let binary = request("PUT", &[], &[0, b'%', b'\\', 0xff, b'a']);
+    assert_eq!(
+        generate_snippet(&binary, SnippetFormat::Curl),
+        "printf '\\000%%\\\\\\377a' | curl 'https://api.test/items?q=it'\\''s&x=1' \\\n  \
+         -X PUT \\\n  \
+         --data-binary @-"
+    );
+    assert!(generate_snippet(&binary, SnippetFormat::Httpie)
+        .starts_with("printf '\\000%%\\\\\\377a' | http PUT "));
+
+    let head = request("HEAD", &[], b"");
+    assert_eq!(
+        generate_snippet(&head, SnippetFormat::Curl),
+        "curl 'https://api.test/items?q=it'\\''s&x=1' \\\n  --head"
+    );
+}
+
+#[test]
+fn test_code_snippets() {
+    let post = request(
+        "post",
+        &[
+            ("cookie", "a=1"),
+            ("cookie", "b=2"),
+            ("content-length", "7"),
+        ],
+        "caf\u{e9}\n\"".as_bytes(),
+    );
+    assert_eq!(
+        generate_snippet(&post, SnippetFormat::Fetch),
+        "fetch(\"https://api.test/items?q=it's&x=1\", {\n  \
+         \"method\": \"POST\",\n  \
+         \"headers\": {\n    \"cookie\": \"a=1; b=2\"\n  },\n  \
+         \"body\": \"caf\u{e9}\\n\\\"\"\n});"
+    );
+    assert_eq!(
+        generate_snippet(&post, SnippetFormat::PythonRequests),
+        "import requests\n\n\
+         url = \"https://api.test/items?q=it's&x=1\"\n\
+         headers = {\n    \"cookie\": \"a=1; b=2\",\n}\n\
+         data = \"caf\u{e9}\\n\\\"\".encode()\n\n\
+         response = requests.request(\"POST\", url, headers=headers, data=data)\n"
+    );
+
+    let binary = request("POST", &[], &[0, b'"', 0xff]);
+    assert!(generate_snippet(&binary, SnippetFormat::Fetch)
+        .contains("\"body\": new Uint8Array([0, 34, 255])"));
+    assert!(generate_snippet(&binary, SnippetFormat::PythonRequests)
+        .contains("data = b\"\\x00\\\"\\xff\"\n"));
+}
+
+#[test]
+fn test_raw_http() {
+    let h2 = request("POST", &[("content-type", "text/plain")], b"hi");
+    assert_eq!(
+        generate_snippet(&h2, SnippetFormat::RawHttp),
+        "POST /items?q=it's&x=1 HTTP/1.1\r\n\
+         Host: api.test\r\n\
+         content-type: text/plain\r\n\
+         Content-Length: 2\r\n\
+         \r\n\
+         hi"
+    );
+
+    let binary = request(
+        "PUT",
+        &[("Host", "api.test"), ("Content-Length", "3")],
+        &[b'a', 0xff, b'b'],
+    );
+    assert_eq!(
+        generate_snippet(&binary, SnippetFormat::RawHttp),
+        "PUT /items?q=it's&x=1 HTTP/1.1\r\nHost: api.test\r\nContent-Length: 3\r\n\r\na\\xffb"
+    );
+}
@@ -0,0 +1,10 @@
+mod curl;
+mod generate;
+
+#[cfg(test)]
+mod curl_test;
+#[cfg(test)]
+mod generate_test;
+
+pub use curl::*;
+pub use generate::*;
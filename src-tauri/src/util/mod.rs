@@ -0,0 +1,6 @@
+mod percent;
+
+#[cfg(test)]
+mod percent_test;
+
+pub use percent::*;
@@ -0,0 +1,19 @@
+use super::{form_decode, percent_decode};
+
+#[test]
+fn test_percent_decode() {
+    assert_eq!(percent_decode("not%20found%3A%20%E2%9C%93"), "not found: ✓");
+    assert_eq!(percent_decode("100%"), "100%");
+}
+
+#[test]
+fn test_form_decode() {
+    assert_eq!(
+        form_decode("q=a+b%26c&flag&&name=%E2%9C%93"),
+        [
+            ("q".to_string(), "a b&c".to_string()),
+            ("flag".to_string(), String::new()),
+            ("name".to_string(), "✓".to_string()),
+        ]
+    );
+}